@@ -0,0 +1,21 @@
+//! Verifies the compressed proofs of many batches of the batch program and commits their
+//! public values together, so the contract settles all of them with one verification.
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+use alloy_sol_types::SolType;
+use fibonacci_lib::{aggregate_values, AggregateValuesStruct};
+use sha2::{Digest, Sha256};
+
+pub fn main() {
+    // `hash_u32` of the batch program vkey, every proof is verified against it
+    let batch_vkey = sp1_zkvm::io::read::<[u32; 8]>();
+    // the public values of every batch, in the order the proofs were written
+    let batches = sp1_zkvm::io::read::<Vec<Vec<u8>>>();
+    for pv in &batches {
+        // the proof itself is deferred, the prover checks it matches these public values
+        sp1_zkvm::lib::verify::verify_sp1_proof(&batch_vkey, &Sha256::digest(pv).into());
+    }
+    let pv = aggregate_values(&batch_vkey, batches);
+    sp1_zkvm::io::commit_slice(&AggregateValuesStruct::abi_encode(&pv));
+}
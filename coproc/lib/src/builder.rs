@@ -0,0 +1,788 @@
+//! Host side construction of the program input.
+//! Every tx is checked the way `BatchProcessor` will check it, so a batch that builds is one the
+//! program accepts, and the delta indices are assigned here instead of by the caller.
+//!
+//! Deltas are keyed by (address, token, channel): a sender gets one per channel it sends on,
+//! credits and the fee sink of each token land on channel 0. Indices are assigned in order of
+//! first appearance (from, to, fee sink per tx), so the same txs added in the same order always
+//! serialize to the same input.
+use crate::ds::{
+    Eip712Domain, FeeSplit, InputToSer, NoncePolicy, PriorBalances, Receipt, SenderKeys, SigScheme,
+    TransferPolicy, TxKind, TxToSer,
+};
+use crate::{key_to_adr, nonce_range, recover_key, signer_checked, ProcessError};
+use std::collections::{BTreeMap, HashMap};
+
+/// (address, token, channel) of a delta
+type Key = ([u8; 20], [u8; 20], u32);
+
+pub struct BatchBuilder {
+    fee_recipient: [u8; 20],
+    timestamp: u64,
+    domain: Eip712Domain,
+    /// `domain` separator, computed once per batch
+    domain_separator: [u8; 32],
+    nonce_policy: NoncePolicy,
+    compressed: bool,
+    fee_splits: Vec<FeeSplit>,
+    transfer_policy: TransferPolicy,
+    min_atoms: u64,
+    batch_nonce: u64,
+    sig_scheme: SigScheme,
+    idx: HashMap<Key, u32>,
+    /// (nonce, tx) sent from each delta idx, spacing is only checked in `build`.
+    /// ordered so the first gap reported is the one the program would report
+    nonces: BTreeMap<u32, Vec<(u64, u32)>>,
+    /// sec1 compressed key of every secp256k1 sending delta, recovered once
+    keys: BTreeMap<u32, [u8; 33]>,
+    total_fee: u64,
+    /// one per tx, the leaves of the receipt tree the program commits when asked
+    receipts: Vec<Receipt>,
+    txs: Vec<TxToSer>,
+}
+
+impl BatchBuilder {
+    /// strict nonces, no fee splits, no colliding recipients, batch nonce 0, secp256k1
+    /// until set otherwise
+    pub fn new(fee_recipient: [u8; 20], timestamp: u64, domain: Eip712Domain) -> Self {
+        Self {
+            fee_recipient,
+            timestamp,
+            domain,
+            domain_separator: domain.separator(),
+            nonce_policy: NoncePolicy::default(),
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::default(),
+            idx: HashMap::new(),
+            nonces: BTreeMap::new(),
+            keys: BTreeMap::new(),
+            total_fee: 0,
+            receipts: vec![],
+            txs: vec![],
+        }
+    }
+
+    pub fn nonce_policy(mut self, nonce_policy: NoncePolicy) -> Self {
+        self.nonce_policy = nonce_policy;
+        self
+    }
+
+    pub fn compressed(mut self, compressed: bool) -> Self {
+        self.compressed = compressed;
+        self
+    }
+
+    /// panics on more than `FeeSplit::MAX`, an over 100% total is rejected by `build`
+    pub fn fee_splits(mut self, fee_splits: Vec<FeeSplit>) -> Self {
+        assert!(fee_splits.len() <= FeeSplit::MAX, "too many fee splits");
+        self.fee_splits = fee_splits;
+        self
+    }
+
+    pub fn transfer_policy(mut self, transfer_policy: TransferPolicy) -> Self {
+        self.transfer_policy = transfer_policy;
+        self
+    }
+
+    pub fn min_atoms(mut self, min_atoms: u64) -> Self {
+        self.min_atoms = min_atoms;
+        self
+    }
+
+    pub fn batch_nonce(mut self, batch_nonce: u64) -> Self {
+        self.batch_nonce = batch_nonce;
+        self
+    }
+
+    /// every tx added has to carry a pubkey iff `Ed25519`
+    pub fn sig_scheme(mut self, sig_scheme: SigScheme) -> Self {
+        self.sig_scheme = sig_scheme;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+
+    /// sum of the fees of the txs added so far
+    pub fn total_fee(&self) -> u64 {
+        self.total_fee
+    }
+
+    /// of the txs added so far, in order. `Processed::receipt_root` is the `merkle` root of
+    /// their leaves, a sender gets its receipt with a proof from here
+    pub fn receipts(&self) -> &[Receipt] {
+        &self.receipts
+    }
+
+    /// the key of every sender so far, to hand the program next to the input,
+    /// empty for an ed25519 batch
+    pub fn sender_keys(&self) -> SenderKeys {
+        SenderKeys {
+            keys: self.keys.iter().map(|(idx, key)| (*idx, *key)).collect(),
+        }
+    }
+
+    /// `balance(address, token)` of every sending delta so far, `None` leaves it unbounded.
+    /// every channel of an (address, token) gets the same balance, as the program requires
+    pub fn prior_balances(
+        &self,
+        balance: impl Fn(&[u8; 20], &[u8; 20]) -> Option<u128>,
+    ) -> PriorBalances {
+        let mut balances: Vec<_> = self
+            .idx
+            .iter()
+            .filter(|(_, idx)| self.nonces.contains_key(idx))
+            .filter_map(|((a, token, _), idx)| Some((*idx, balance(a, token)?)))
+            .collect();
+        balances.sort_unstable();
+        PriorBalances { balances }
+    }
+
+    fn idx(&mut self, key: Key) -> u32 {
+        let next = self.idx.len() as u32;
+        *self.idx.entry(key).or_insert(next)
+    }
+
+    /// validates `tx` and assigns its delta indices, the ones it came with are ignored.
+    /// a rejected tx leaves the batch as it was, the error carries the position it would have had
+    pub fn add(&mut self, mut tx: TxToSer) -> Result<(), ProcessError> {
+        let offset = self.txs.len() as u32;
+        if tx.kind != TxKind::Deposit {
+            return self.add_run(vec![tx]);
+        }
+        check_memo(&tx, offset)?;
+        if tx.atoms <= 0 {
+            return Err(ProcessError::AtomsNotAboveFee {
+                tx: offset,
+                atoms: tx.atoms,
+            });
+        }
+        tx.from_idx = 0;
+        // unsigned, the pubkey slot only pads it to the tx size of the batch
+        let ed25519 = self.sig_scheme == SigScheme::Ed25519;
+        tx.pubkey = ed25519.then(|| tx.pubkey.unwrap_or_default());
+        tx.to_idx = self.idx((tx.to, tx.token, 0));
+        tx.fee_idx = self.idx((self.fee_recipient, tx.token, 0));
+        self.receipts.push(Receipt {
+            digest: tx.keccak(&self.domain_separator),
+            from: [0; 20],
+            to: tx.to,
+            atoms: tx.atoms,
+            status: tx.kind,
+        });
+        self.txs.push(tx);
+        Ok(())
+    }
+
+    /// a run of txs of one sender authorized by a single signature, see `ds::RunHasher`.
+    /// the last tx carries the signature over `TxToSer::run_digest` of the run, the ones before
+    /// it have an all zero signature slot. all or nothing, like `add`
+    pub fn add_run(&mut self, run: Vec<TxToSer>) -> Result<(), ProcessError> {
+        let start = self.txs.len() as u32;
+        let Some((last, rest)) = run.split_last() else {
+            return Ok(());
+        };
+        for (i, tx) in run.iter().enumerate() {
+            let offset = start + i as u32;
+            check_memo(tx, offset)?;
+            if tx.kind == TxKind::Deposit {
+                return Err(ProcessError::OpenRun { tx: start });
+            }
+        }
+        if last.joins_run() || !rest.iter().all(TxToSer::joins_run) {
+            return Err(ProcessError::OpenRun { tx: start });
+        }
+        let offset = start + rest.len() as u32;
+        let digest = TxToSer::run_digest(&run, &self.domain_separator);
+        let (from, key) = match (self.sig_scheme, last.pubkey) {
+            (SigScheme::Secp256k1, None) => {
+                let key = recover_key(last.sig_r, last.sig_s, last.v, &digest)
+                    .map_err(|e| e.at_tx(offset))?;
+                (key_to_adr(&key), Some(key))
+            }
+            (scheme, pubkey) => {
+                let from = signer_checked(scheme, last.sig_r, last.sig_s, last.v, pubkey, &digest)
+                    .map_err(|e| e.at_tx(offset))?;
+                (from, None)
+            }
+        };
+        for (i, tx) in run.iter().enumerate() {
+            self.check_signed(tx, start + i as u32, &from)?;
+        }
+        for mut tx in run {
+            let offset = self.txs.len() as u32;
+            tx.from_idx = self.idx((from, tx.token, tx.channel));
+            if let Some(key) = &key {
+                let sec1 = key.to_encoded_point(true);
+                let sec1 = sec1.as_bytes().try_into().unwrap();
+                self.keys.entry(tx.from_idx).or_insert(sec1);
+            }
+            tx.to_idx = match tx.kind {
+                // the payout leaves the batch, the recipient has no delta
+                TxKind::Withdrawal => 0,
+                // an allowed self transfer has to land on the sending delta
+                _ if tx.to == from => tx.from_idx,
+                _ => self.idx((tx.to, tx.token, 0)),
+            };
+            tx.fee_idx = self.idx((self.fee_recipient, tx.token, 0));
+            self.nonces
+                .entry(tx.from_idx)
+                .or_default()
+                .push((tx.nonce, offset));
+            self.total_fee += tx.fee as u64;
+            self.receipts.push(Receipt {
+                digest: tx.keccak(&self.domain_separator),
+                from,
+                to: tx.to,
+                atoms: tx.atoms,
+                status: tx.kind,
+            });
+            self.txs.push(tx);
+        }
+        Ok(())
+    }
+
+    /// the checks of `BatchProcessor` once the signer is known
+    fn check_signed(&self, tx: &TxToSer, offset: u32, from: &[u8; 20]) -> Result<(), ProcessError> {
+        let policy = self.transfer_policy;
+        if tx.kind == TxKind::Transfer {
+            if tx.to == *from && !policy.allow_self {
+                return Err(ProcessError::SelfTransfer { tx: offset });
+            }
+            if tx.to != *from && tx.to == self.fee_recipient && !policy.allow_fee_recipient {
+                return Err(ProcessError::PaysFeeRecipient { tx: offset });
+            }
+        }
+        if self.timestamp > tx.valid_until {
+            return Err(ProcessError::Expired {
+                tx: offset,
+                valid_until: tx.valid_until,
+            });
+        }
+        if tx.atoms <= tx.fee as i128 {
+            return Err(ProcessError::AtomsNotAboveFee {
+                tx: offset,
+                atoms: tx.atoms,
+            });
+        }
+        if tx.atoms < self.min_atoms as i128 {
+            return Err(ProcessError::BelowMinAtoms {
+                tx: offset,
+                atoms: tx.atoms,
+            });
+        }
+        Ok(())
+    }
+
+    /// checks the nonces of every sender against the policy and the fee split total
+    pub fn build(mut self) -> Result<InputToSer, ProcessError> {
+        let bps: u32 = self.fee_splits.iter().map(|x| x.bps as u32).sum();
+        if bps > FeeSplit::TOTAL_BPS as u32 {
+            return Err(crate::ds::InputError::BadFeeSplits { bps }.into());
+        }
+        for nonces in self.nonces.values_mut() {
+            nonce_range(nonces, self.nonce_policy)?;
+        }
+        Ok(InputToSer {
+            state_deltas: self.idx.len() as u32,
+            fee_recipient: self.fee_recipient,
+            timestamp: self.timestamp,
+            nonce_policy: self.nonce_policy,
+            compressed: self.compressed,
+            fee_splits: self.fee_splits,
+            transfer_policy: self.transfer_policy,
+            min_atoms: self.min_atoms,
+            domain: self.domain,
+            batch_nonce: self.batch_nonce,
+            sig_scheme: self.sig_scheme,
+            tx: self.txs,
+        })
+    }
+}
+
+/// A sender delta that stops at `end_nonce` in batch `from` and picks up at `start_nonce`
+/// in batch `to`, the next batch it sends in. The contract records the end nonce when it
+/// applies `from` and checks the start against it when it applies `to`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handoff {
+    pub v: [u8; 20],
+    pub token: [u8; 20],
+    pub channel: u32,
+    pub from: usize,
+    pub end_nonce: u64,
+    pub to: usize,
+    pub start_nonce: u64,
+}
+
+pub struct Split {
+    pub batches: Vec<InputToSer>,
+    /// ordered by `to`, then by (v, token, channel)
+    pub handoffs: Vec<Handoff>,
+}
+
+/// Cuts `txs` into batches of at most `max_txs` each, in order, the k-th built on
+/// `new_batch(k)` (so it can set the batch nonce). A run is never cut, one longer than
+/// `max_txs` gets a batch of its own.
+/// A sender spread over batches has to pick up in the next one where it stopped, as the
+/// nonce policy of the next batch allows, or it is a `NonceGap` at its first tx there.
+/// Errors carry the tx position within its batch, not within `txs`
+pub fn split(
+    txs: Vec<TxToSer>,
+    max_txs: usize,
+    mut new_batch: impl FnMut(usize) -> BatchBuilder,
+) -> Result<Split, ProcessError> {
+    assert!(max_txs > 0, "empty batches");
+    let mut batches = vec![];
+    let mut handoffs = vec![];
+    // where every sender so far stopped, (batch, end nonce)
+    let mut last: HashMap<Key, (usize, u64)> = HashMap::new();
+    let mut finish = |batch: BatchBuilder, k: usize| -> Result<InputToSer, ProcessError> {
+        let mut senders: Vec<(Key, u32)> = batch
+            .idx
+            .iter()
+            .filter(|(_, idx)| batch.nonces.contains_key(idx))
+            .map(|(key, idx)| (*key, *idx))
+            .collect();
+        senders.sort_unstable();
+        for (key, idx) in senders {
+            let mut nonces = batch.nonces[&idx].clone();
+            nonces.sort_unstable();
+            let ((start, tx), end) = (nonces[0], nonces[nonces.len() - 1].0);
+            if let Some((from, end_nonce)) = last.insert(key, (k, end)) {
+                if !batch.nonce_policy.accepts(end_nonce, start) {
+                    return Err(ProcessError::NonceGap {
+                        tx,
+                        expected: end_nonce.saturating_add(1),
+                        got: start,
+                    });
+                }
+                handoffs.push(Handoff {
+                    v: key.0,
+                    token: key.1,
+                    channel: key.2,
+                    from,
+                    end_nonce,
+                    to: k,
+                    start_nonce: start,
+                });
+            }
+        }
+        batch.build()
+    };
+    let mut batch = new_batch(0);
+    let mut unit = vec![];
+    for tx in txs {
+        let joins = tx.kind != TxKind::Deposit && tx.joins_run();
+        unit.push(tx);
+        if joins {
+            continue;
+        }
+        if !batch.is_empty() && batch.len() + unit.len() > max_txs {
+            let k = batches.len();
+            batches.push(finish(batch, k)?);
+            batch = new_batch(k + 1);
+        }
+        let unit = std::mem::take(&mut unit);
+        match unit[0].kind {
+            TxKind::Deposit => batch.add(unit.into_iter().next().unwrap())?,
+            _ => batch.add_run(unit)?,
+        }
+    }
+    if !unit.is_empty() {
+        return Err(ProcessError::OpenRun {
+            tx: batch.len() as u32,
+        });
+    }
+    if !batch.is_empty() || batches.is_empty() {
+        let k = batches.len();
+        batches.push(finish(batch, k)?);
+    }
+    Ok(Split { batches, handoffs })
+}
+
+fn check_memo(tx: &TxToSer, offset: u32) -> Result<(), ProcessError> {
+    if tx.memo.len() > TxToSer::MAX_MEMO {
+        return Err(ProcessError::BadMemo {
+            tx: offset,
+            len: tx.memo.len().min(u8::MAX as usize) as u8,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{merkle, process_txs_checked, BatchProcessor, Processed};
+    use k256::ecdsa::SigningKey;
+
+    const DOMAIN: Eip712Domain = Eip712Domain {
+        chain_id: 1,
+        verifying_contract: [0xDD; 20],
+    };
+    const TOKEN: [u8; 20] = [0xC0; 20];
+    const FEE_RECIPIENT: [u8; 20] = [0xFE; 20];
+    const NOW: u64 = 1_700_000_000;
+
+    fn tx(sk: &SigningKey, to: [u8; 20], atoms: i128, nonce: u64, kind: TxKind) -> TxToSer {
+        let mut tx = TxToSer {
+            to,
+            token: TOKEN,
+            atoms,
+            nonce,
+            channel: 0,
+            fee: 10,
+            valid_until: NOW,
+            kind,
+            memo: vec![],
+            sig_r: [0; 32],
+            sig_s: [0; 32],
+            v: 0,
+            from_idx: 0,
+            to_idx: 0,
+            fee_idx: 0,
+            pubkey: None,
+        };
+        let (sig, rec) = sk
+            .sign_prehash_recoverable(&tx.keccak(&DOMAIN.separator()))
+            .unwrap();
+        tx.sig_r = sig.r().to_bytes().into();
+        tx.sig_s = sig.s().to_bytes().into();
+        tx.v = rec.to_byte();
+        tx
+    }
+
+    fn addr(sk: &SigningKey) -> [u8; 20] {
+        let t = tx(sk, [0; 20], 100, 0, TxKind::Transfer);
+        crate::try_recover(t.sig_r, t.sig_s, t.v, &t.keccak(&DOMAIN.separator())).unwrap()
+    }
+
+    #[test]
+    fn test_builder_accepted_by_program() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = SigningKey::from_bytes(&[0x22; 32].into()).unwrap();
+        let (a, b) = (addr(&alice), addr(&bob));
+        let build = || {
+            let mut batch = BatchBuilder::new(FEE_RECIPIENT, NOW, DOMAIN).batch_nonce(3);
+            // out of nonce order on purpose, only the spacing matters
+            batch.add(tx(&alice, b, 100, 6, TxKind::Transfer)).unwrap();
+            batch.add(tx(&alice, b, 50, 5, TxKind::Transfer)).unwrap();
+            batch.add(tx(&bob, a, 30, 0, TxKind::Transfer)).unwrap();
+            batch
+                .add(tx(&bob, [0xAB; 20], 40, 1, TxKind::Withdrawal))
+                .unwrap();
+            assert!(batch.add(tx(&alice, a, 0, 0, TxKind::Deposit)).is_err());
+            let mut deposit = tx(&alice, a, 25, 9, TxKind::Deposit);
+            deposit.sig_r = [0; 32];
+            batch.add(deposit).unwrap();
+            assert_eq!((batch.len(), batch.total_fee()), (5, 40));
+            batch.build().unwrap()
+        };
+        let inp = build();
+        assert_eq!(inp.state_deltas, 3, "alice, bob and the fee sink");
+        assert_eq!(inp.ser(), build().ser(), "deterministic");
+        let idx: Vec<_> = inp
+            .tx
+            .iter()
+            .map(|t| (t.from_idx, t.to_idx, t.fee_idx))
+            .collect();
+        assert_eq!(
+            idx,
+            vec![(0, 1, 2), (0, 1, 2), (1, 0, 2), (1, 0, 2), (0, 0, 2)]
+        );
+
+        let Processed {
+            n,
+            total_fee,
+            batch_nonce,
+            ..
+        } = process_txs_checked(&inp.ser()).unwrap();
+        assert_eq!((total_fee, batch_nonce), (40, 3));
+        let deltas: Vec<_> = n.iter().map(|d| (d.v.0 .0, d.delta)).collect();
+        // the fee sink takes all of a withdrawal and pays out the deposit
+        let mut expected = vec![(a, -150 + 20 + 25), (b, 130 - 30 - 40)];
+        expected.sort();
+        expected.insert(0, (FEE_RECIPIENT, 30 + 40 - 25));
+        assert_eq!(deltas, expected);
+    }
+
+    #[test]
+    fn test_builder_rejects() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let a = addr(&alice);
+        let bob = [0xB0; 20];
+        let mut batch = BatchBuilder::new(FEE_RECIPIENT, NOW, DOMAIN).min_atoms(20);
+        let mut add = |t| batch.add(t);
+        assert_eq!(
+            add(tx(&alice, a, 100, 0, TxKind::Transfer)),
+            Err(ProcessError::SelfTransfer { tx: 0 })
+        );
+        assert_eq!(
+            add(tx(&alice, FEE_RECIPIENT, 100, 0, TxKind::Transfer)),
+            Err(ProcessError::PaysFeeRecipient { tx: 0 })
+        );
+        assert_eq!(
+            add(tx(&alice, bob, 10, 0, TxKind::Transfer)),
+            Err(ProcessError::AtomsNotAboveFee { tx: 0, atoms: 10 })
+        );
+        assert_eq!(
+            add(tx(&alice, bob, 15, 0, TxKind::Transfer)),
+            Err(ProcessError::BelowMinAtoms { tx: 0, atoms: 15 })
+        );
+        let mut stale = tx(&alice, bob, 100, 0, TxKind::Transfer);
+        stale.valid_until = NOW - 1;
+        assert!(matches!(
+            add(stale),
+            Err(ProcessError::Expired { tx: 0, .. })
+        ));
+        let mut bad = tx(&alice, bob, 100, 0, TxKind::Transfer);
+        bad.v = 2;
+        assert_eq!(add(bad), Err(ProcessError::BadRecoveryId { tx: 0, v: 2 }));
+        assert!(batch.is_empty(), "rejected txs are not added");
+
+        // the nonce spacing is only known once every tx is in
+        batch
+            .add(tx(&alice, bob, 100, 0, TxKind::Transfer))
+            .unwrap();
+        batch
+            .add(tx(&alice, bob, 100, 2, TxKind::Transfer))
+            .unwrap();
+        assert_eq!(
+            batch.build().err(),
+            Some(ProcessError::NonceGap {
+                tx: 1,
+                expected: 1,
+                got: 2
+            })
+        );
+
+        let over = vec![
+            FeeSplit {
+                recipient: bob,
+                bps: 6_000
+            };
+            2
+        ];
+        assert!(BatchBuilder::new(FEE_RECIPIENT, NOW, DOMAIN)
+            .fee_splits(over)
+            .build()
+            .is_err());
+
+        // allowed collisions land on the one delta the program expects
+        let mut batch =
+            BatchBuilder::new(FEE_RECIPIENT, NOW, DOMAIN).transfer_policy(TransferPolicy {
+                allow_self: true,
+                allow_fee_recipient: true,
+            });
+        batch.add(tx(&alice, a, 100, 0, TxKind::Transfer)).unwrap();
+        batch
+            .add(tx(&alice, FEE_RECIPIENT, 100, 1, TxKind::Transfer))
+            .unwrap();
+        let inp = batch.build().unwrap();
+        assert!(process_txs_checked(&inp.ser()).is_ok());
+    }
+
+    #[test]
+    fn test_builder_run() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = [0xB0; 20];
+        let mut run: Vec<_> = (0..3)
+            .map(|x| tx(&alice, bob, 100, x, TxKind::Transfer))
+            .collect();
+        let mut batch = BatchBuilder::new(FEE_RECIPIENT, NOW, DOMAIN);
+        // still signed one by one
+        assert_eq!(
+            batch.add_run(run.clone()),
+            Err(ProcessError::OpenRun { tx: 0 })
+        );
+        for tx in run.iter_mut() {
+            (tx.sig_r, tx.sig_s, tx.v) = ([0; 32], [0; 32], 0);
+        }
+        assert_eq!(
+            batch.add(run[0].clone()),
+            Err(ProcessError::OpenRun { tx: 0 })
+        );
+        let sign = |mut run: Vec<TxToSer>| {
+            let digest = TxToSer::run_digest(&run, &DOMAIN.separator());
+            let (sig, rec) = alice.sign_prehash_recoverable(&digest).unwrap();
+            let last = run.last_mut().unwrap();
+            last.sig_r = sig.r().to_bytes().into();
+            last.sig_s = sig.s().to_bytes().into();
+            last.v = rec.to_byte();
+            run
+        };
+        // one bad tx rejects the whole run
+        let mut low = run.clone();
+        low[0].atoms = 10;
+        assert_eq!(
+            batch.add_run(sign(low)),
+            Err(ProcessError::AtomsNotAboveFee { tx: 0, atoms: 10 })
+        );
+        let run = sign(run);
+        assert!(batch.is_empty());
+        batch.add_run(run).unwrap();
+        batch
+            .add(tx(&alice, bob, 100, 3, TxKind::Transfer))
+            .unwrap();
+        let keys = batch.sender_keys();
+        // only the sending deltas get one
+        let balances = batch.prior_balances(|_, _| Some(400));
+        assert_eq!(balances.balances, [(0, 400)]);
+        let inp = batch.build().unwrap();
+        assert_eq!(inp.state_deltas, 3);
+        let Processed { n, .. } = process_txs_checked(&inp.ser()).unwrap();
+        assert_eq!((n[1].v, n[1].delta), (addr(&alice).into(), -400));
+
+        // alice sends from delta 0, her key verifies the run and the tx after it
+        assert_eq!(keys.keys.len(), 1);
+        assert_eq!(keys.keys[0].0, 0);
+        let keyed = crate::process_txs_keyed(&inp.ser(), &keys.ser()).unwrap();
+        assert_eq!(keyed.n[1].delta, -400);
+    }
+
+    #[test]
+    fn test_builder_split() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let carol = SigningKey::from_bytes(&[0x33; 32].into()).unwrap();
+        let bob = [0xB0; 20];
+        let mut run: Vec<_> = (0..3)
+            .map(|x| tx(&carol, bob, 100, x, TxKind::Transfer))
+            .collect();
+        for tx in run.iter_mut() {
+            (tx.sig_r, tx.sig_s, tx.v) = ([0; 32], [0; 32], 0);
+        }
+        let digest = TxToSer::run_digest(&run, &DOMAIN.separator());
+        let (sig, rec) = carol.sign_prehash_recoverable(&digest).unwrap();
+        let last = run.last_mut().unwrap();
+        (last.sig_r, last.sig_s) = (sig.r().to_bytes().into(), sig.s().to_bytes().into());
+        last.v = rec.to_byte();
+
+        // alice 0 1 | 2 carol's run (too long to cut) | 3 4
+        let mut txs: Vec<_> = (0..3)
+            .map(|x| tx(&alice, bob, 100, x, TxKind::Transfer))
+            .collect();
+        txs.extend(run);
+        txs.extend((3..5).map(|x| tx(&alice, bob, 100, x, TxKind::Transfer)));
+        let new_batch =
+            |k: usize| BatchBuilder::new(FEE_RECIPIENT, NOW, DOMAIN).batch_nonce(7 + k as u64);
+        let Split { batches, handoffs } = split(txs.clone(), 2, new_batch).unwrap();
+        let lens: Vec<_> = batches.iter().map(|x| x.tx.len()).collect();
+        assert_eq!(lens, [2, 1, 3, 2]);
+        let handoff = |from, end_nonce, to| Handoff {
+            v: addr(&alice),
+            token: TOKEN,
+            channel: 0,
+            from,
+            end_nonce,
+            to,
+            start_nonce: end_nonce + 1,
+        };
+        assert_eq!(handoffs, [handoff(0, 1, 1), handoff(1, 2, 3)]);
+        for (k, batch) in batches.iter().enumerate() {
+            let p = process_txs_checked(&batch.ser()).unwrap();
+            assert_eq!(p.batch_nonce, 7 + k as u64);
+        }
+        let all = split(txs.clone(), 100, new_batch).unwrap();
+        assert_eq!((all.batches.len(), all.handoffs.len()), (1, 0));
+
+        // alice's 2 comes last, the batch with her 3 and 4 does not pick up after 1
+        let two = txs.remove(2);
+        txs.push(two);
+        assert_eq!(
+            split(txs, 2, new_batch).err(),
+            Some(ProcessError::NonceGap {
+                tx: 0,
+                expected: 2,
+                got: 3
+            })
+        );
+
+        // nothing follows u64::MAX, a repeat in the next batch is a gap, not an overflow
+        let at_max = vec![
+            tx(&alice, bob, 100, u64::MAX, TxKind::Transfer),
+            tx(&alice, bob, 100, u64::MAX, TxKind::Transfer),
+        ];
+        assert_eq!(
+            split(at_max, 1, new_batch).err(),
+            Some(ProcessError::NonceGap {
+                tx: 0,
+                expected: u64::MAX,
+                got: u64::MAX
+            })
+        );
+    }
+
+    #[test]
+    fn test_builder_receipts() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = [0xB0; 20];
+        let mut run: Vec<_> = (0..3)
+            .map(|x| tx(&alice, bob, 100, x, TxKind::Transfer))
+            .collect();
+        for tx in run.iter_mut() {
+            (tx.sig_r, tx.sig_s, tx.v) = ([0; 32], [0; 32], 0);
+        }
+        let digest = TxToSer::run_digest(&run, &DOMAIN.separator());
+        let (sig, rec) = alice.sign_prehash_recoverable(&digest).unwrap();
+        let last = run.last_mut().unwrap();
+        (last.sig_r, last.sig_s) = (sig.r().to_bytes().into(), sig.s().to_bytes().into());
+        last.v = rec.to_byte();
+        let mut deposit = tx(&alice, bob, 500, 77, TxKind::Deposit);
+        (deposit.sig_r, deposit.sig_s, deposit.v) = ([0; 32], [0; 32], 0);
+
+        let mut batch = BatchBuilder::new(FEE_RECIPIENT, NOW, DOMAIN);
+        batch.add(deposit).unwrap();
+        batch.add_run(run).unwrap();
+        batch
+            .add(tx(&alice, [0xCA; 20], 100, 3, TxKind::Withdrawal))
+            .unwrap();
+        let receipts = batch.receipts().to_vec();
+        let statuses: Vec<_> = receipts.iter().map(|x| x.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                TxKind::Deposit,
+                TxKind::Transfer,
+                TxKind::Transfer,
+                TxKind::Transfer,
+                TxKind::Withdrawal
+            ]
+        );
+        assert_eq!(receipts[0].from, [0; 20], "a deposit has no signer");
+        assert!(receipts[1..].iter().all(|x| x.from == addr(&alice)));
+        let leaves: Vec<_> = receipts.iter().map(|x| x.leaf()).collect();
+
+        // the run straddles the chunks, its receipts only land once it is signed
+        let inp = batch.build().unwrap();
+        let process = |receipts: bool| {
+            let mut p = BatchProcessor::new(&inp.ser_header()).unwrap();
+            if receipts {
+                p.receipts();
+            }
+            for chunk in inp.ser_chunks(2) {
+                p.process_chunk(&chunk).unwrap();
+            }
+            p.finish().unwrap()
+        };
+        let Processed {
+            receipt_root,
+            tx_root,
+            ..
+        } = process(true);
+        assert_eq!(receipt_root, merkle::root(&leaves));
+        let digests: Vec<_> = receipts.iter().map(|x| x.digest).collect();
+        assert_eq!(tx_root, merkle::root(&digests));
+        let proof = merkle::proof(&leaves, 2).unwrap();
+        assert!(merkle::verify(&proof, &receipt_root, &leaves[2]));
+        assert_eq!(process(false).receipt_root, [0; 32]);
+    }
+}
@@ -0,0 +1,142 @@
+//! Calldata of the settlement contract function a proof is posted to, from its Solidity
+//! signature with the parameters named after what they take: `vkey` (bytes32), `publicValues`
+//! and `proof` (bytes), ex. `settle(bytes publicValues, bytes proof)`. A leading underscore of
+//! the name and the data location are ignored, it's pasted from the contract as is.
+use std::str::FromStr;
+use tiny_keccak::{Hasher, Keccak};
+
+/// `ISP1Verifier.verifyProof`, to post to the verifier directly
+pub const VERIFY_PROOF: &str = "verifyProof(bytes32 vkey, bytes publicValues, bytes proof)";
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Arg {
+    Vkey,
+    PublicValues,
+    Proof,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Function {
+    pub selector: [u8; 4],
+    pub args: Vec<Arg>,
+}
+
+impl FromStr for Function {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (name, params) = s
+            .trim()
+            .strip_suffix(')')
+            .and_then(|x| x.split_once('('))
+            .ok_or_else(|| format!("expected name(type name, ..), got {s}"))?;
+        let mut args = vec![];
+        let mut types = vec![];
+        for param in params.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            // the data location is not part of the signature
+            let words: Vec<_> = param
+                .split_whitespace()
+                .filter(|x| !["calldata", "memory"].contains(x))
+                .collect();
+            let [ty, arg] = words[..] else {
+                return Err(format!("expected type and name, got {param}"));
+            };
+            let arg = match arg.trim_start_matches('_') {
+                "vkey" => Arg::Vkey,
+                "publicValues" => Arg::PublicValues,
+                "proof" => Arg::Proof,
+                x => return Err(format!("unknown parameter {x}")),
+            };
+            let expected = if arg == Arg::Vkey { "bytes32" } else { "bytes" };
+            if ty != expected {
+                return Err(format!("{arg:?} is {expected}, got {ty}"));
+            }
+            if args.contains(&arg) {
+                return Err(format!("{arg:?} taken twice"));
+            }
+            args.push(arg);
+            types.push(ty);
+        }
+        let mut hash = [0; 32];
+        let mut k = Keccak::v256();
+        k.update(format!("{}({})", name.trim(), types.join(",")).as_bytes());
+        k.finalize(&mut hash);
+        Ok(Self {
+            selector: hash[..4].try_into().unwrap(),
+            args,
+        })
+    }
+}
+
+impl Function {
+    /// the selector and the ABI encoded arguments
+    pub fn encode(&self, vkey: &[u8; 32], public_values: &[u8], proof: &[u8]) -> Vec<u8> {
+        let heads = 32 * self.args.len();
+        let mut out = self.selector.to_vec();
+        let mut tail = vec![];
+        for arg in &self.args {
+            let v = match arg {
+                Arg::Vkey => {
+                    out.extend_from_slice(vkey);
+                    continue;
+                }
+                Arg::PublicValues => public_values,
+                Arg::Proof => proof,
+            };
+            out.extend_from_slice(&word(heads + tail.len()));
+            tail.extend_from_slice(&word(v.len()));
+            tail.extend_from_slice(v);
+            tail.resize(tail.len().next_multiple_of(32), 0);
+        }
+        out.extend(tail);
+        out
+    }
+}
+
+fn word(x: usize) -> [u8; 32] {
+    let mut out = [0; 32];
+    out[24..].copy_from_slice(&(x as u64).to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_sol_types::{sol, SolCall};
+
+    sol! {
+        function verifyProof(bytes32 programVKey, bytes publicValues, bytes proofBytes);
+        function settle(bytes _publicValues, bytes _proof);
+    }
+
+    #[test]
+    fn test_calldata() {
+        let (vkey, public_values, proof) = ([7; 32], vec![1; 33], vec![2; 260]);
+        let f: Function = VERIFY_PROOF.parse().unwrap();
+        let expected = verifyProofCall {
+            programVKey: vkey.into(),
+            publicValues: public_values.clone().into(),
+            proofBytes: proof.clone().into(),
+        };
+        assert_eq!(
+            f.encode(&vkey, &public_values, &proof),
+            expected.abi_encode()
+        );
+
+        let f: Function = "settle(bytes calldata _publicValues, bytes calldata _proof)"
+            .parse()
+            .unwrap();
+        let expected = settleCall {
+            _publicValues: public_values.clone().into(),
+            _proof: vec![].into(),
+        };
+        assert_eq!(f.encode(&vkey, &public_values, &[]), expected.abi_encode());
+
+        assert!("settle(bytes vkey)".parse::<Function>().is_err());
+        assert!("settle(bytes proof, bytes proof)"
+            .parse::<Function>()
+            .is_err());
+        assert!("settle(bytes calldata)".parse::<Function>().is_err());
+        assert!("settle".parse::<Function>().is_err());
+    }
+}
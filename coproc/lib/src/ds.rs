@@ -1,73 +1,864 @@
+use crate::inspect::Hex;
+use ruzstd::decoding::{BlockDecodingStrategy, FrameDecoder};
+use ruzstd::encoding::{compress_to_vec, CompressionLevel};
 use tiny_keccak::Hasher;
 
+pub const DOMAIN_NAME: &str = "ddm";
+pub const DOMAIN_VERSION: &str = "1";
+
+/// The EIP-712 domain txs are signed under, has to match the settlement contract
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Eip712Domain {
+    pub chain_id: u64,
+    pub verifying_contract: [u8; 20],
+}
+
+impl std::fmt::Display for Eip712Domain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "chain_id={} verifying_contract={}",
+            self.chain_id,
+            Hex(&self.verifying_contract)
+        )
+    }
+}
+
+impl Eip712Domain {
+    /// keccak256("EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)")
+    pub const TYPEHASH: [u8; 32] = [
+        0x8b, 0x73, 0xc3, 0xc6, 0x9b, 0xb8, 0xfe, 0x3d, 0x51, 0x2e, 0xcc, 0x4c, 0xf7, 0x59, 0xcc,
+        0x79, 0x23, 0x9f, 0x7b, 0x17, 0x9b, 0x0f, 0xfa, 0xca, 0xa9, 0xa7, 0x5d, 0x52, 0x2b, 0x39,
+        0x40, 0x0f,
+    ];
+
+    /// computed once per batch
+    pub fn separator(&self) -> [u8; 32] {
+        let mut s = tiny_keccak::Keccak::v256();
+        s.update(&Self::TYPEHASH);
+        s.update(&keccak256(DOMAIN_NAME.as_bytes()));
+        s.update(&keccak256(DOMAIN_VERSION.as_bytes()));
+        s.update(&[0; 24]);
+        s.update(&self.chain_id.to_be_bytes());
+        s.update(&[0; 12]);
+        s.update(&self.verifying_contract);
+        let mut out = [0; 32];
+        s.finalize(&mut out);
+        out
+    }
+}
+
+fn keccak256(v: &[u8]) -> [u8; 32] {
+    let mut s = tiny_keccak::Keccak::v256();
+    s.update(v);
+    let mut out = [0; 32];
+    s.finalize(&mut out);
+    out
+}
+
+/// keccak256("\x19\x01" ‖ domainSeparator ‖ hashStruct(Tx)), the fields are abi encoded words.
+/// `signed` is the signed prefix of a serialized tx, see `TxToSer::SIGNED_SIZE`
+/// In the guest the permutations run on the sp1 keccak precompile, see the tiny-keccak patch
+fn eip712_digest(domain_separator: &[u8; 32], signed: &[u8], out: &mut [u8; 32]) {
+    let atoms_be = &signed[40..56];
+    // int128 is sign extended to 32 bytes
+    let atoms_pad = if atoms_be[0] & 0x80 != 0 {
+        [0xff; 16]
+    } else {
+        [0; 16]
+    };
+    let mut s = tiny_keccak::Keccak::v256();
+    s.update(&TxToSer::TYPEHASH);
+    s.update(&[0; 12]);
+    s.update(&signed[0..20]); // to
+    s.update(&[0; 12]);
+    s.update(&signed[20..40]); // token
+    s.update(&atoms_pad);
+    s.update(atoms_be);
+    s.update(&[0; 24]);
+    s.update(&signed[56..64]); // nonce
+    s.update(&[0; 28]);
+    s.update(&signed[64..68]); // channel
+    s.update(&[0; 28]);
+    s.update(&signed[68..72]); // fee
+    s.update(&[0; 24]);
+    s.update(&signed[72..80]); // valid_until
+    s.update(&[0; 31]);
+    s.update(&signed[80..81]); // kind
+                               // bytes are hashed, only the used part of the memo slot
+    let memo_len = (signed[81] as usize).min(TxToSer::MAX_MEMO);
+    s.update(&keccak256(&signed[82..82 + memo_len])); // memo
+    let mut hash_struct = [0; 32];
+    s.finalize(&mut hash_struct);
+
+    let mut s = tiny_keccak::Keccak::v256();
+    s.update(b"\x19\x01");
+    s.update(domain_separator);
+    s.update(&hash_struct);
+    s.finalize(out);
+}
+
+/// The eip-712 digest a sender signs once for a run of its txs, the typed struct
+/// `TxRun(bytes32[] digests)` over the tx digests of the run in input order, same domain.
+/// Only the last tx of a run is signed, the ones before it have an all zero signature slot,
+/// see `Tx::joins_run`. The digests are hashed as they come, nothing is buffered
+pub struct RunHasher {
+    digests: tiny_keccak::Keccak,
+    len: u32,
+}
+
+impl Default for RunHasher {
+    fn default() -> Self {
+        Self {
+            digests: tiny_keccak::Keccak::v256(),
+            len: 0,
+        }
+    }
+}
+
+impl RunHasher {
+    /// keccak256("TxRun(bytes32[] digests)")
+    pub const TYPEHASH: [u8; 32] = [
+        0x53, 0xc7, 0x4e, 0xfc, 0xac, 0x07, 0xfc, 0x10, 0x2c, 0x19, 0x8d, 0x7c, 0xdb, 0x6d, 0x31,
+        0x89, 0x50, 0x00, 0xa5, 0x4e, 0x5a, 0xb8, 0xe9, 0xf9, 0x89, 0x4b, 0x2d, 0xe7, 0x55, 0xa1,
+        0xb7, 0xa6,
+    ];
+
+    pub fn update(&mut self, digest: &[u8; 32]) {
+        self.digests.update(digest);
+        self.len += 1;
+    }
+
+    /// digests hashed so far
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// the run digest, resets for the next run
+    pub fn finalize(&mut self, domain_separator: &[u8; 32]) -> [u8; 32] {
+        let mut array = [0; 32];
+        std::mem::take(self).digests.finalize(&mut array);
+        let mut s = tiny_keccak::Keccak::v256();
+        s.update(&Self::TYPEHASH);
+        s.update(&array);
+        let mut hash_struct = [0; 32];
+        s.finalize(&mut hash_struct);
+        let mut s = tiny_keccak::Keccak::v256();
+        s.update(b"\x19\x01");
+        s.update(domain_separator);
+        s.update(&hash_struct);
+        let mut out = [0; 32];
+        s.finalize(&mut out);
+        out
+    }
+}
+
 /// serialization does not need to be efficient
+#[derive(Debug)]
 pub struct InputToSer {
     pub state_deltas: u32,
-    pub fee_atoms: u16, // the fee charged for batching, enforced onchain, but modifiable
     pub fee_recipient: [u8; 20], // fee recipient, the batch contract itself, enforced onchain
+    pub timestamp: u64, // unix seconds the batch is settled at, committed and checked onchain
+    pub nonce_policy: NoncePolicy,
+    /// every tx chunk is a zstd frame, the program inflates them before parsing
+    pub compressed: bool,
+    /// at most `FeeSplit::MAX`, shares of the collected fees paid on from the fee sink
+    pub fee_splits: Vec<FeeSplit>,
+    pub transfer_policy: TransferPolicy,
+    /// signed txs below it are dust and rejected, committed for the contract to check its policy
+    pub min_atoms: u64,
+    /// the deployment the txs are signed for and the batch settles on, mixed into every digest
+    pub domain: Eip712Domain,
+    /// sequence number of the batch, committed for the contract to apply batches in order
+    pub batch_nonce: u64,
+    /// how every tx of the batch is signed, `TxToSer::pubkey` is set iff `Ed25519`
+    pub sig_scheme: SigScheme,
     pub tx: Vec<TxToSer>,
 }
 
+/// The signature every tx of a batch carries, one scheme per batch.
+/// secp256k1 is what EVM wallets sign with, ed25519 is a lot cheaper to check in the zkvm
+/// (no pubkey recovery, curve ops have precompiles) but the tx has to carry the signer pubkey,
+/// the signer address is `keccak256(pubkey)[12..]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum SigScheme {
+    /// `v`, `sig_r`, `sig_s` recover the signer
+    #[default]
+    Secp256k1 = 0,
+    /// `sig_r`, `sig_s` are R and S of the signature over the eip-712 digest, `v` is 0 and
+    /// the pubkey trails the tx
+    Ed25519 = 1,
+}
+
+impl SigScheme {
+    pub fn from_u8(tag: u8) -> Result<Self, InputError> {
+        match tag {
+            0 => Ok(Self::Secp256k1),
+            1 => Ok(Self::Ed25519),
+            tag => Err(InputError::BadSigScheme { tag }),
+        }
+    }
+
+    /// serialized size of one tx of a batch signed under this scheme
+    pub fn tx_size(&self) -> usize {
+        match self {
+            Self::Secp256k1 => TxToSer::SIZE,
+            Self::Ed25519 => TxToSer::ED25519_SIZE,
+        }
+    }
+}
+
+impl std::fmt::Display for SigScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Secp256k1 => write!(f, "secp256k1"),
+            Self::Ed25519 => write!(f, "ed25519"),
+        }
+    }
+}
+
+/// Which transfers with a colliding recipient the program accepts, both are rejected by default.
+/// An accepted collision always lands on a single delta, so its effect is fixed:
+/// - self transfer (`to` is the signer): `to_idx == from_idx`, nets to `-fee` and uses up the
+///   nonce, ex. to cancel a signed tx that is still floating around
+/// - paying the fee recipient: `to_idx == fee_idx`, the fee sink is credited all of `atoms`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TransferPolicy {
+    pub allow_self: bool,
+    pub allow_fee_recipient: bool,
+}
+
+impl TransferPolicy {
+    /// bit 0 `allow_self`, bit 1 `allow_fee_recipient`, committed as is
+    pub fn flags(&self) -> u8 {
+        self.allow_self as u8 | (self.allow_fee_recipient as u8) << 1
+    }
+
+    pub fn from_flags(flags: u8) -> Result<Self, InputError> {
+        if flags > 0b11 {
+            return Err(InputError::BadTransferPolicy { flags });
+        }
+        Ok(Self {
+            allow_self: flags & 1 != 0,
+            allow_fee_recipient: flags & 2 != 0,
+        })
+    }
+}
+
+/// A share of every fee the fee sink collects, paid on to `recipient` in the state deltas.
+/// whatever the splits don't take (rounding included) stays with the fee sink
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FeeSplit {
+    pub recipient: [u8; 20],
+    /// basis points, the splits of a batch sum to at most 10_000
+    pub bps: u16,
+}
+
+impl FeeSplit {
+    pub const SIZE: usize = 20 + 2;
+    /// the header always holds this many, unused slots are zeroed
+    pub const MAX: usize = 4;
+    pub const TOTAL_BPS: u16 = 10_000;
+
+    /// the part of `fees` that goes to `recipient`, rounded down
+    pub fn share(&self, fees: u64) -> u64 {
+        (fees as u128 * self.bps as u128 / Self::TOTAL_BPS as u128) as u64
+    }
+}
+
 impl InputToSer {
+    /// compressed, the txs are a single frame
     pub fn ser(&self) -> Vec<u8> {
+        let mut out = self.ser_header();
+        for chunk in self.ser_chunks(self.tx.len()) {
+            out.extend_from_slice(&chunk);
+        }
+
+        out
+    }
+
+    /// inverse of `ser`, for tooling that inspects or edits a persisted batch.
+    /// fee split slots with 0 bps are dropped, the program ignores them too
+    pub fn deser(v: &[u8]) -> Result<Self, InputError> {
+        let header = v
+            .get(..Input::HEADER_SIZE)
+            .ok_or(InputError::TooShort { len: v.len() })?;
+        let inp = Input::new(header);
+        let compressed = inp.try_compressed()?;
+        let sig_scheme = inp.try_sig_scheme()?;
+        let body = v.len() - Input::HEADER_SIZE;
+        let expected = (inp.total_tx() as usize)
+            .checked_mul(sig_scheme.tx_size())
+            .ok_or(InputError::LengthMismatch {
+                expected: usize::MAX,
+                got: body,
+            })?;
+        let raw = if compressed {
+            decompress(&v[Input::HEADER_SIZE..], expected)?
+        } else {
+            v[Input::HEADER_SIZE..].to_vec()
+        };
+        if raw.len() != expected {
+            return Err(InputError::LengthMismatch {
+                expected,
+                got: raw.len(),
+            });
+        }
+        Ok(Self {
+            state_deltas: inp.state_deltas(),
+            fee_recipient: inp.fee_recipient().try_into().unwrap(),
+            timestamp: inp.timestamp(),
+            nonce_policy: inp.try_nonce_policy()?,
+            compressed,
+            fee_splits: inp.try_fee_splits()?,
+            transfer_policy: inp.try_transfer_policy()?,
+            min_atoms: inp.min_atoms(),
+            domain: inp.domain(),
+            batch_nonce: inp.batch_nonce(),
+            sig_scheme,
+            tx: raw
+                .chunks(sig_scheme.tx_size())
+                .map(TxToSer::deser)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// the `Input::HEADER_SIZE` leading bytes of `ser`
+    pub fn ser_header(&self) -> Vec<u8> {
         let mut out = vec![];
         out.extend_from_slice(&self.state_deltas.to_be_bytes());
-        out.extend_from_slice(&self.fee_atoms.to_be_bytes());
         out.extend_from_slice(&self.fee_recipient);
+        out.extend_from_slice(&self.timestamp.to_be_bytes());
         let txs = self.tx.len() as u32;
         out.extend_from_slice(&txs.to_be_bytes());
-        for tx in &self.tx {
-            out.extend_from_slice(&tx.ser());
+        out.extend_from_slice(&self.nonce_policy.ser());
+        out.push(self.compressed as u8);
+        assert!(
+            self.fee_splits.len() <= FeeSplit::MAX,
+            "too many fee splits"
+        );
+        for i in 0..FeeSplit::MAX {
+            let split = self.fee_splits.get(i).copied().unwrap_or_default();
+            out.extend_from_slice(&split.recipient);
+            out.extend_from_slice(&split.bps.to_be_bytes());
+        }
+        out.push(self.transfer_policy.flags());
+        out.extend_from_slice(&self.min_atoms.to_be_bytes());
+        out.extend_from_slice(&self.domain.chain_id.to_be_bytes());
+        out.extend_from_slice(&self.domain.verifying_contract);
+        out.extend_from_slice(&self.batch_nonce.to_be_bytes());
+        out.push(self.sig_scheme as u8);
+        out
+    }
+
+    /// the txs of `ser` split into segments of at most `chunk_txs` txs,
+    /// for the program to read one at a time after the header.
+    /// compressed, each segment is its own zstd frame
+    pub fn ser_chunks(&self, chunk_txs: usize) -> Vec<Vec<u8>> {
+        let ed25519 = self.sig_scheme == SigScheme::Ed25519;
+        assert!(
+            self.tx.iter().all(|tx| tx.pubkey.is_some() == ed25519),
+            "tx pubkey does not match the sig scheme"
+        );
+        self.tx
+            .chunks(chunk_txs.max(1))
+            .map(|c| {
+                let raw: Vec<u8> = c.iter().flat_map(|tx| tx.ser()).collect();
+                if self.compressed {
+                    compress_to_vec(raw.as_slice(), CompressionLevel::Fastest)
+                } else {
+                    raw
+                }
+            })
+            .collect()
+    }
+}
+
+/// inflates one zstd compressed chunk (or several concatenated frames), `max_len` bounds the
+/// output so a frame can't blow up the program memory past the txs the header has left.
+/// nothing is reserved up front, the output only grows with what the frames inflate to
+/// (and at most one block past `max_len` before it is rejected)
+pub fn decompress(frame: &[u8], max_len: usize) -> Result<Vec<u8>, InputError> {
+    let bad = InputError::BadFrame { len: frame.len() };
+    let mut src = frame;
+    let mut dec = FrameDecoder::new();
+    let mut out = Vec::new();
+    while !src.is_empty() {
+        dec.init(&mut src).map_err(|_| bad.clone())?;
+        let left = max_len - out.len();
+        // stops once past what is left, an unfinished frame is then too big
+        let finished = dec
+            .decode_blocks(
+                &mut src,
+                BlockDecodingStrategy::UptoBytes(left.saturating_add(1)),
+            )
+            .map_err(|_| bad.clone())?;
+        let inflated = dec.collect().unwrap_or_default();
+        if !finished || inflated.len() > left {
+            return Err(bad);
+        }
+        out.extend_from_slice(&inflated);
+    }
+    Ok(out)
+}
+
+/// why an input buffer can't be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputError {
+    /// shorter than the header
+    TooShort { len: usize },
+    /// header claims `total_tx` txs but the buffer holds a different amount of bytes
+    LengthMismatch { expected: usize, got: usize },
+    /// a streamed chunk is empty or does not hold a whole number of txs
+    BadChunk { len: usize },
+    /// the header nonce policy tag is none of `NoncePolicy`
+    BadNoncePolicy { tag: u8 },
+    /// the header compressed flag is neither 0 nor 1
+    BadCompressedFlag { flag: u8 },
+    /// a compressed chunk is not a zstd frame or inflates past the txs left
+    BadFrame { len: usize },
+    /// the header fee splits sum past `FeeSplit::TOTAL_BPS`
+    BadFeeSplits { bps: u32 },
+    /// unknown bits set in the header transfer policy
+    BadTransferPolicy { flags: u8 },
+    /// a tx kind byte is none of `TxKind`, only owned parsing checks it
+    BadTxKind { kind: u8 },
+    /// a tx memo is longer than `TxToSer::MAX_MEMO` or its slot is not zero padded
+    BadMemo { len: u8 },
+    /// the header sig scheme tag is none of `SigScheme`
+    BadSigScheme { tag: u8 },
+    /// the sender key table is not whole `SenderKeys` entries, or the batch txs carry their keys
+    BadSenderKeys { len: usize },
+    /// a sender key is not a curve point, points past `state_deltas` or repeats a delta
+    BadSenderKey { idx: u32 },
+    /// the prior balance table is not whole `PriorBalances` entries
+    BadPriorBalances { len: usize },
+    /// a prior balance points past `state_deltas`, repeats a delta or disagrees with the one
+    /// given for another channel of the same (address, token)
+    BadPriorBalance { idx: u32 },
+    /// a stdin segment is not a `frame::frame` of the version this program reads
+    BadFrameVersion { version: u8 },
+    /// a stdin segment is shorter or longer than its frame says
+    BadFrameLength { expected: usize, got: usize },
+}
+
+impl std::fmt::Display for InputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooShort { len } => write!(f, "input of {len} bytes is shorter than the header"),
+            Self::LengthMismatch { expected, got } => {
+                write!(f, "expected {expected} bytes, got {got}")
+            }
+            Self::BadChunk { len } => write!(f, "chunk of {len} bytes is not whole txs"),
+            Self::BadNoncePolicy { tag } => write!(f, "bad nonce policy tag {tag}"),
+            Self::BadCompressedFlag { flag } => write!(f, "bad compressed flag {flag}"),
+            Self::BadFrame { len } => write!(f, "bad zstd frame of {len} bytes"),
+            Self::BadFeeSplits { bps } => write!(f, "fee splits sum to {bps}bps"),
+            Self::BadTransferPolicy { flags } => write!(f, "bad transfer policy flags {flags:#b}"),
+            Self::BadTxKind { kind } => write!(f, "bad tx kind {kind}"),
+            Self::BadMemo { len } => write!(f, "bad memo len={len}"),
+            Self::BadSigScheme { tag } => write!(f, "bad sig scheme tag {tag}"),
+            Self::BadSenderKeys { len } => write!(f, "bad sender key table of {len} bytes"),
+            Self::BadSenderKey { idx } => write!(f, "bad sender key for delta idx={idx}"),
+            Self::BadPriorBalances { len } => write!(f, "bad prior balance table of {len} bytes"),
+            Self::BadPriorBalance { idx } => write!(f, "bad prior balance for delta idx={idx}"),
+            Self::BadFrameVersion { version } => write!(f, "bad frame version {version}"),
+            Self::BadFrameLength { expected, got } => {
+                write!(f, "frame of {got} bytes, expected {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
+
+/// Signer keys the host hands the program next to the input, at most one per sending delta.
+/// The signatures sent from a keyed delta are verified against its key instead of recovering
+/// the key of every one. Only a hint, a wrong key fails the signatures and the delta address
+/// is still derived from the key, so it is not part of the input hash.
+/// secp256k1 only, an ed25519 tx carries its key
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SenderKeys {
+    /// (delta idx, sec1 compressed pubkey)
+    pub keys: Vec<(u32, [u8; 33])>,
+}
+
+impl SenderKeys {
+    pub const ENTRY_SIZE: usize = 4 + 33;
+
+    pub fn ser(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.keys.len() * Self::ENTRY_SIZE);
+        for (idx, key) in &self.keys {
+            out.extend_from_slice(&idx.to_be_bytes());
+            out.extend_from_slice(key);
+        }
+        out
+    }
+
+    pub fn deser(v: &[u8]) -> Result<Self, InputError> {
+        if v.len() / Self::ENTRY_SIZE * Self::ENTRY_SIZE != v.len() {
+            return Err(InputError::BadSenderKeys { len: v.len() });
+        }
+        Ok(Self {
+            keys: v
+                .chunks(Self::ENTRY_SIZE)
+                .map(|x| {
+                    let idx = u32::from_be_bytes(x[..4].try_into().unwrap());
+                    (idx, x[4..].try_into().unwrap())
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Onchain balances before the batch the host hands the program next to the input, at most one
+/// per delta. The net delta of the (address, token), all its channels together, may not take
+/// more than the balance, the deltas it checked are committed `solvent`.
+/// Unlike `SenderKeys` it decides validity, so it is committed as `Processed::balances_hash`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PriorBalances {
+    /// (delta idx, balance)
+    pub balances: Vec<(u32, u128)>,
+}
+
+impl PriorBalances {
+    pub const ENTRY_SIZE: usize = 4 + 16;
+
+    pub fn ser(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.balances.len() * Self::ENTRY_SIZE);
+        for (idx, balance) in &self.balances {
+            out.extend_from_slice(&idx.to_be_bytes());
+            out.extend_from_slice(&balance.to_be_bytes());
         }
+        out
+    }
+
+    pub fn deser(v: &[u8]) -> Result<Self, InputError> {
+        if v.len() / Self::ENTRY_SIZE * Self::ENTRY_SIZE != v.len() {
+            return Err(InputError::BadPriorBalances { len: v.len() });
+        }
+        Ok(Self {
+            balances: v
+                .chunks(Self::ENTRY_SIZE)
+                .map(|x| {
+                    let idx = u32::from_be_bytes(x[..4].try_into().unwrap());
+                    (idx, u128::from_be_bytes(x[4..].try_into().unwrap()))
+                })
+                .collect(),
+        })
+    }
+}
+
+/// How the nonces a sender uses within one batch may be spaced, checked once sorted.
+/// Anything looser than `Strict` lets a subset of a sender's signed txs settle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoncePolicy {
+    /// every nonce from start to end, exactly once
+    #[default]
+    Strict,
+    /// strictly increasing, any gap
+    Monotonic,
+    /// strictly increasing, consecutive nonces at most `window` apart, `Windowed(1)` is `Strict`
+    Windowed(u32),
+}
+
+impl NoncePolicy {
+    /// tag u8 followed by the window u32, 0 unless `Windowed`
+    pub const SIZE: usize = 1 + 4;
 
+    /// committed as `StateDelta::nonce_policy`
+    pub fn tag(&self) -> u8 {
+        match self {
+            Self::Strict => 0,
+            Self::Monotonic => 1,
+            Self::Windowed(_) => 2,
+        }
+    }
+
+    pub fn ser(&self) -> [u8; Self::SIZE] {
+        let window = match self {
+            Self::Windowed(w) => *w,
+            _ => 0,
+        };
+        let mut out = [0; Self::SIZE];
+        out[0] = self.tag();
+        out[1..].copy_from_slice(&window.to_be_bytes());
         out
     }
+
+    pub fn from_bytes(v: &[u8; Self::SIZE]) -> Result<Self, InputError> {
+        let window = u32::from_be_bytes(v[1..].try_into().unwrap());
+        match v[0] {
+            0 => Ok(Self::Strict),
+            1 => Ok(Self::Monotonic),
+            2 => Ok(Self::Windowed(window)),
+            tag => Err(InputError::BadNoncePolicy { tag }),
+        }
+    }
+
+    /// whether `next` may follow `prev` in a sender's sorted nonces
+    pub fn accepts(&self, prev: u64, next: u64) -> bool {
+        match self {
+            Self::Strict => prev.checked_add(1) == Some(next),
+            Self::Monotonic => next > prev,
+            Self::Windowed(w) => next > prev && next - prev <= *w as u64,
+        }
+    }
+}
+
+impl std::fmt::Display for NoncePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Strict => write!(f, "strict"),
+            Self::Monotonic => write!(f, "monotonic"),
+            Self::Windowed(w) => write!(f, "windowed({w})"),
+        }
+    }
 }
 
 /// to be as efficient as possible we will borrow everything from the input vector
 pub struct Input<'a> {
     /// the entire input buffer
     /// state_deltas_u32 would give 2**31 max txs worst case
-    /// (state_deltas_u32, total_tx_u32, txs[])
+    /// (state_deltas_u32, fee_recipient, timestamp_u64, total_tx_u32, nonce_policy, compressed,
+    /// fee_splits[FeeSplit::MAX], transfer_policy, min_atoms_u64, chain_id_u64, verifying_contract,
+    /// batch_nonce_u64, sig_scheme, txs[])
+    /// a compressed input can only be viewed up to the header, it has to go through `BatchProcessor`
     pub v: &'a [u8],
 }
 impl<'a> Input<'a> {
-    pub const HEADER_SIZE: usize = 4 + 2 + 20 + 4; // 30
+    pub const HEADER_SIZE: usize = 4
+        + 20
+        + 8
+        + 4
+        + NoncePolicy::SIZE
+        + 1
+        + FeeSplit::MAX * FeeSplit::SIZE
+        + 1
+        + 8
+        + 8
+        + 20
+        + 8
+        + 1; // 176
+    /// unchecked, accessors panic on a truncated buffer, see `try_new`
     pub fn new(v: &'a [u8]) -> Self {
         Self { v }
     }
+    /// checks `HEADER_SIZE + total_tx * SigScheme::tx_size == len` and the header tags,
+    /// after which none of the accessors can go out of bounds or panic
+    pub fn try_new(v: &'a [u8]) -> Result<Self, InputError> {
+        if v.len() < Self::HEADER_SIZE {
+            return Err(InputError::TooShort { len: v.len() });
+        }
+        let inp = Self { v };
+        inp.try_nonce_policy()?;
+        inp.try_compressed()?;
+        inp.try_fee_splits()?;
+        inp.try_transfer_policy()?;
+        let sig_scheme = inp.try_sig_scheme()?;
+        let expected = (inp.total_tx() as usize)
+            .checked_mul(sig_scheme.tx_size())
+            .and_then(|x| x.checked_add(Self::HEADER_SIZE));
+        if expected != Some(v.len()) {
+            return Err(InputError::LengthMismatch {
+                expected: expected.unwrap_or(usize::MAX),
+                got: v.len(),
+            });
+        }
+        Ok(inp)
+    }
     pub fn state_deltas(&self) -> u32 {
         u32::from_be_bytes(self.v[..4].try_into().unwrap())
     }
-    pub fn fee_atoms(&self) -> u16 {
-        u16::from_be_bytes(self.v[4..6].try_into().unwrap())
-    }
     pub fn fee_recipient(&self) -> &'a [u8] {
-        &self.v[6..26]
+        &self.v[4..24]
+    }
+    pub fn timestamp(&self) -> u64 {
+        u64::from_be_bytes(self.v[24..32].try_into().unwrap())
     }
     pub fn total_tx(&self) -> u32 {
-        u32::from_be_bytes(self.v[26..Self::HEADER_SIZE].try_into().unwrap())
+        u32::from_be_bytes(self.v[32..36].try_into().unwrap())
+    }
+    pub fn try_nonce_policy(&self) -> Result<NoncePolicy, InputError> {
+        NoncePolicy::from_bytes(self.v[36..41].try_into().unwrap())
+    }
+    pub fn nonce_policy(&self) -> NoncePolicy {
+        self.try_nonce_policy().unwrap()
+    }
+    pub fn try_compressed(&self) -> Result<bool, InputError> {
+        match self.v[41] {
+            0 => Ok(false),
+            1 => Ok(true),
+            flag => Err(InputError::BadCompressedFlag { flag }),
+        }
+    }
+    pub fn compressed(&self) -> bool {
+        self.try_compressed().unwrap()
+    }
+    /// the used slots, ones with 0 bps are skipped
+    pub fn try_fee_splits(&self) -> Result<Vec<FeeSplit>, InputError> {
+        let splits: Vec<FeeSplit> = (0..FeeSplit::MAX)
+            .map(|i| {
+                let x = &self.v[42 + i * FeeSplit::SIZE..][..FeeSplit::SIZE];
+                FeeSplit {
+                    recipient: x[..20].try_into().unwrap(),
+                    bps: u16::from_be_bytes(x[20..].try_into().unwrap()),
+                }
+            })
+            .filter(|x| x.bps != 0)
+            .collect();
+        let bps: u32 = splits.iter().map(|x| x.bps as u32).sum();
+        if bps > FeeSplit::TOTAL_BPS as u32 {
+            return Err(InputError::BadFeeSplits { bps });
+        }
+        Ok(splits)
+    }
+    pub fn fee_splits(&self) -> Vec<FeeSplit> {
+        self.try_fee_splits().unwrap()
+    }
+    pub fn try_transfer_policy(&self) -> Result<TransferPolicy, InputError> {
+        TransferPolicy::from_flags(self.v[130])
+    }
+    pub fn transfer_policy(&self) -> TransferPolicy {
+        self.try_transfer_policy().unwrap()
+    }
+    pub fn min_atoms(&self) -> u64 {
+        u64::from_be_bytes(self.v[131..139].try_into().unwrap())
+    }
+    pub fn domain(&self) -> Eip712Domain {
+        Eip712Domain {
+            chain_id: u64::from_be_bytes(self.v[139..147].try_into().unwrap()),
+            verifying_contract: self.v[147..167].try_into().unwrap(),
+        }
+    }
+    pub fn batch_nonce(&self) -> u64 {
+        u64::from_be_bytes(self.v[167..175].try_into().unwrap())
+    }
+    pub fn try_sig_scheme(&self) -> Result<SigScheme, InputError> {
+        SigScheme::from_u8(self.v[175])
+    }
+    pub fn sig_scheme(&self) -> SigScheme {
+        self.try_sig_scheme().unwrap()
     }
     pub fn tx_at(&self, idx: u32) -> Tx<'a> {
         let idx = idx as usize;
-        let start = idx * TxToSer::SIZE;
-        let end = start + TxToSer::SIZE;
+        let size = self.sig_scheme().tx_size();
+        let start = idx * size;
+        let end = start + size;
         let region = &self.v[Self::HEADER_SIZE..];
         Tx {
             v: &region[start..end],
         }
     }
+    /// `None` if idx is past the end of the buffer
+    pub fn get_tx(&self, idx: u32) -> Option<Tx<'a>> {
+        let size = self.try_sig_scheme().ok()?.tx_size();
+        let start = (idx as usize).checked_mul(size)?;
+        let end = start.checked_add(size)?;
+        let region = self.v.get(Self::HEADER_SIZE..)?;
+        Tx::try_new(region.get(start..end)?)
+    }
+}
+
+/// what a tx does to the state deltas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TxKind {
+    /// signer pays `to`
+    Transfer = 0,
+    /// `to` deposited onchain, nonce is the deposit id of the contract.
+    /// not signed by anyone, the contract checks every committed deposit against its records
+    Deposit = 1,
+    /// signer asks the contract to pay out `atoms - fee` to `to` onchain
+    Withdrawal = 2,
+}
+
+impl TxKind {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Self::Transfer),
+            1 => Some(Self::Deposit),
+            2 => Some(Self::Withdrawal),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for TxKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Transfer => write!(f, "transfer"),
+            Self::Deposit => write!(f, "deposit"),
+            Self::Withdrawal => write!(f, "withdrawal"),
+        }
+    }
 }
 
-/// want to make it eip-712 compatible for ez integration
+/// what a valid batch did with one of its txs, `leaf` of it is in the receipt tree.
+/// Every tx settles or the whole batch fails, so the status is the `TxKind` it settled as.
+/// `from` is the signer, zero for a deposit, `atoms` are the signed ones, fee included
+#[derive(Debug, Clone, PartialEq)]
+pub struct Receipt {
+    /// the eip-712 digest of the tx, its `tx_root` leaf
+    pub digest: [u8; 32],
+    pub from: [u8; 20],
+    pub to: [u8; 20],
+    pub atoms: i128,
+    pub status: TxKind,
+}
+
+impl Receipt {
+    pub const SIZE: usize = 32 + 20 + 20 + 16 + 1;
+
+    pub fn ser(&self) -> [u8; Self::SIZE] {
+        let mut out = [0; Self::SIZE];
+        out[..32].copy_from_slice(&self.digest);
+        out[32..52].copy_from_slice(&self.from);
+        out[52..72].copy_from_slice(&self.to);
+        out[72..88].copy_from_slice(&self.atoms.to_be_bytes());
+        out[88] = self.status as u8;
+        out
+    }
+
+    /// keccak256(`ser`)
+    pub fn leaf(&self) -> [u8; 32] {
+        let mut h = tiny_keccak::Keccak::v256();
+        h.update(&self.ser());
+        let mut out = [0; 32];
+        h.finalize(&mut out);
+        out
+    }
+}
+
+/// eip-712 compatible for ez integration, wallets sign the typed struct
+/// `Tx(address to,address token,int128 atoms,uint64 nonce,uint32 channel,uint32 fee,uint64 validUntil,uint8 kind,bytes memo)`
 /// sign(keccak256("\x19\x01" ‖ domainSeparator ‖ hashStruct(message)))
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct TxToSer {
     pub to: [u8; 20],
-    /// max payment size is 2**63, type is kept as i64 to add to sub in state deltas,
-    /// non positive values are invalid and are asserted in the program
-    pub atoms: i64,
+    /// erc20 being transferred, deltas are kept per (address, token)
+    pub token: [u8; 20],
+    /// max payment size is 2**127, wide enough for 18 decimal tokens. signed to add to sub
+    /// in state deltas, non positive values are invalid and are asserted in the program
+    pub atoms: i128,
     pub nonce: u64,
+    /// nonces are tracked per (sender, token, channel), so a client can keep
+    /// independent payment streams, ex. one per vendor, without one shared counter
+    pub channel: u32,
+    /// charged for batching, taken out of atoms and credited to the fee sink of `token`,
+    /// signed so the batcher can't raise it, 0 for no fee
+    pub fee: u32,
+    /// unix seconds, the tx can't be settled in a batch with a later timestamp
+    pub valid_until: u64,
+    pub kind: TxKind,
+    /// at most `MAX_MEMO` bytes, ex. an invoice id. signed, serialized as a length byte and a
+    /// zero padded slot so every tx keeps the same size
+    pub memo: Vec<u8>,
     pub sig_r: [u8; 32],
     pub sig_s: [u8; 32],
     pub v: u8,
@@ -75,84 +866,243 @@ pub struct TxToSer {
     /// helpers for the program to idx the state diff arr
     pub from_idx: u32,
     pub to_idx: u32,
+    /// the fee sink delta of `token`, one per token in the batch
+    pub fee_idx: u32,
+    /// ed25519 signer, serialized after the helpers. `None` in a secp256k1 batch
+    pub pubkey: Option<[u8; 32]>,
 }
 
 impl TxToSer {
-    pub const SIZE: usize = 20 + 8 + 8 + 32 + 32 + 1 + 4 + 4;
+    /// keccak256("Tx(address to,address token,int128 atoms,uint64 nonce,uint32 channel,uint32 fee,uint64 validUntil,uint8 kind,bytes memo)")
+    pub const TYPEHASH: [u8; 32] = [
+        0x64, 0x5a, 0xb2, 0xf9, 0x52, 0xd6, 0x8c, 0x24, 0x55, 0x23, 0x0a, 0x8e, 0x9d, 0xdb, 0xa6,
+        0x3b, 0xc9, 0xd6, 0xcb, 0xd7, 0x3d, 0x83, 0x33, 0xb3, 0x26, 0xf0, 0x35, 0xb9, 0xdf, 0x13,
+        0x67, 0xd6,
+    ];
+    pub const MAX_MEMO: usize = 32;
+    /// the fields covered by the signature lead the serialized tx
+    pub const SIGNED_SIZE: usize = 20 + 20 + 16 + 8 + 4 + 4 + 8 + 1 + 1 + Self::MAX_MEMO;
+    pub const SIZE: usize = Self::SIGNED_SIZE + 32 + 32 + 1 + 4 + 4 + 4;
+    /// a tx of an ed25519 batch, `SIZE` followed by the pubkey
+    pub const ED25519_SIZE: usize = Self::SIZE + 32;
 
     pub fn ser(&self) -> Vec<u8> {
         let mut out = vec![];
         out.extend_from_slice(&self.to);
+        out.extend_from_slice(&self.token);
         out.extend_from_slice(&self.atoms.to_be_bytes());
         out.extend_from_slice(&self.nonce.to_be_bytes());
+        out.extend_from_slice(&self.channel.to_be_bytes());
+        out.extend_from_slice(&self.fee.to_be_bytes());
+        out.extend_from_slice(&self.valid_until.to_be_bytes());
+        out.push(self.kind as u8);
+        assert!(self.memo.len() <= Self::MAX_MEMO, "memo too long");
+        out.push(self.memo.len() as u8);
+        out.extend_from_slice(&self.memo);
+        out.resize(Self::SIGNED_SIZE, 0);
         out.extend_from_slice(&self.sig_r);
         out.extend_from_slice(&self.sig_s);
         out.push(self.v);
         // helpers
         out.extend_from_slice(&self.from_idx.to_be_bytes());
         out.extend_from_slice(&self.to_idx.to_be_bytes());
+        out.extend_from_slice(&self.fee_idx.to_be_bytes());
+        if let Some(pubkey) = &self.pubkey {
+            out.extend_from_slice(pubkey);
+        }
         out
     }
 
-    pub fn keccak(&self) -> [u8; 32] {
-        let mut s = tiny_keccak::Keccak::v256();
-        s.update(&self.to);
-        s.update(&self.atoms.to_be_bytes());
-        s.update(&self.nonce.to_be_bytes());
+    /// inverse of `ser`, `v` is exactly one serialized tx of either scheme
+    pub fn deser(v: &[u8]) -> Result<Self, InputError> {
+        let tx = Tx::try_new(v).ok_or(InputError::LengthMismatch {
+            expected: Self::SIZE,
+            got: v.len(),
+        })?;
+        Ok(Self {
+            to: tx.to().try_into().unwrap(),
+            token: tx.token().try_into().unwrap(),
+            atoms: tx.atoms(),
+            nonce: tx.nonce(),
+            channel: tx.channel(),
+            fee: tx.fee(),
+            valid_until: tx.valid_until(),
+            kind: TxKind::from_u8(tx.kind()).ok_or(InputError::BadTxKind { kind: tx.kind() })?,
+            memo: tx
+                .memo()
+                .ok_or(InputError::BadMemo { len: tx.memo_len() })?
+                .to_vec(),
+            sig_r: tx.sig_r(),
+            sig_s: tx.sig_s(),
+            v: tx.v(),
+            from_idx: tx.from_idx(),
+            to_idx: tx.to_idx(),
+            fee_idx: tx.fee_idx(),
+            pubkey: tx.pubkey(),
+        })
+    }
+
+    /// the eip-712 digest that is signed, see `Eip712Domain::separator`
+    pub fn keccak(&self, domain_separator: &[u8; 32]) -> [u8; 32] {
         let mut out = [0; 32];
-        s.finalize(&mut out);
+        eip712_digest(domain_separator, &self.ser()[..Self::SIGNED_SIZE], &mut out);
         out
     }
+
+    /// the digest the last tx of `run` is signed over, see `RunHasher`.
+    /// a run of one is a plain signed tx, its digest is `keccak`
+    pub fn run_digest(run: &[TxToSer], domain_separator: &[u8; 32]) -> [u8; 32] {
+        if let [tx] = run {
+            return tx.keccak(domain_separator);
+        }
+        let mut h = RunHasher::default();
+        for tx in run {
+            h.update(&tx.keccak(domain_separator));
+        }
+        h.finalize(domain_separator)
+    }
+
+    /// see `Tx::joins_run`
+    pub fn joins_run(&self) -> bool {
+        self.sig_r == [0; 32]
+            && self.sig_s == [0; 32]
+            && self.v == 0
+            && self.pubkey.is_none_or(|x| x == [0; 32])
+    }
 }
 
 pub struct Tx<'a> {
     pub v: &'a [u8],
 }
+/// the fields as parsed, a malformed memo shows as `None`
+impl std::fmt::Debug for Tx<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tx")
+            .field("to", &Hex(self.to()))
+            .field("token", &Hex(self.token()))
+            .field("atoms", &self.atoms())
+            .field("nonce", &self.nonce())
+            .field("channel", &self.channel())
+            .field("fee", &self.fee())
+            .field("valid_until", &self.valid_until())
+            .field("kind", &self.kind())
+            .field("memo", &self.memo().map(Hex))
+            .field("sig_r", &Hex(&self.sig_r()))
+            .field("sig_s", &Hex(&self.sig_s()))
+            .field("v", &self.v())
+            .field("from_idx", &self.from_idx())
+            .field("to_idx", &self.to_idx())
+            .field("fee_idx", &self.fee_idx())
+            .field("pubkey", &self.pubkey().as_ref().map(|x| Hex(x)))
+            .finish()
+    }
+}
+
 impl<'a> Tx<'a> {
+    /// `None` unless `v` is exactly one serialized tx (`TxToSer::SIZE` or `ED25519_SIZE`),
+    /// then accessors can't go out of bounds
+    pub fn try_new(v: &'a [u8]) -> Option<Self> {
+        if v.len() != TxToSer::SIZE && v.len() != TxToSer::ED25519_SIZE {
+            return None;
+        }
+        Some(Self { v })
+    }
     pub fn to(&self) -> &'a [u8] {
         &self.v[0..20]
     }
+    pub fn token(&self) -> &'a [u8] {
+        &self.v[20..40]
+    }
 
     pub fn atoms_slice(&self) -> &'a [u8] {
-        &self.v[20..28]
+        &self.v[40..56]
     }
-    pub fn atoms(&self) -> i64 {
-        let bytes: [u8; 8] = self.atoms_slice().try_into().unwrap();
-        i64::from_be_bytes(bytes)
+    pub fn atoms(&self) -> i128 {
+        let bytes: [u8; 16] = self.atoms_slice().try_into().unwrap();
+        i128::from_be_bytes(bytes)
     }
     pub fn nonce_slice(&self) -> &'a [u8] {
-        &self.v[28..36]
+        &self.v[56..64]
     }
     pub fn nonce(&self) -> u64 {
         let bytes: [u8; 8] = self.nonce_slice().try_into().unwrap();
         u64::from_be_bytes(bytes)
     }
 
+    pub fn channel(&self) -> u32 {
+        u32::from_be_bytes(self.v[64..68].try_into().unwrap())
+    }
+
+    pub fn fee_slice(&self) -> &'a [u8] {
+        &self.v[68..72]
+    }
+    pub fn fee(&self) -> u32 {
+        u32::from_be_bytes(self.fee_slice().try_into().unwrap())
+    }
+
+    pub fn valid_until(&self) -> u64 {
+        u64::from_be_bytes(self.v[72..80].try_into().unwrap())
+    }
+
+    /// raw, see `TxKind::from_u8`
+    pub fn kind(&self) -> u8 {
+        self.v[80]
+    }
+
+    /// raw, see `memo`
+    pub fn memo_len(&self) -> u8 {
+        self.v[81]
+    }
+
+    /// `None` if the length is past `TxToSer::MAX_MEMO` or the rest of the slot is not zero,
+    /// so a memo has exactly one encoding
+    pub fn memo(&self) -> Option<&'a [u8]> {
+        let slot = &self.v[82..TxToSer::SIGNED_SIZE];
+        let len = self.memo_len() as usize;
+        if len > TxToSer::MAX_MEMO || slot[len..].iter().any(|&b| b != 0) {
+            return None;
+        }
+        Some(&slot[..len])
+    }
+
     pub fn sig_r(&self) -> [u8; 32] {
-        self.v[36..68].try_into().unwrap()
+        self.v[114..146].try_into().unwrap()
     }
 
     pub fn sig_s(&self) -> [u8; 32] {
-        self.v[68..100].try_into().unwrap()
+        self.v[146..178].try_into().unwrap()
     }
 
     pub fn v(&self) -> u8 {
-        self.v[100]
+        self.v[178]
     }
 
     pub fn from_idx(&self) -> u32 {
-        u32::from_be_bytes(self.v[101..105].try_into().unwrap())
+        u32::from_be_bytes(self.v[179..183].try_into().unwrap())
     }
     pub fn to_idx(&self) -> u32 {
-        u32::from_be_bytes(self.v[105..109].try_into().unwrap())
+        u32::from_be_bytes(self.v[183..187].try_into().unwrap())
+    }
+    pub fn fee_idx(&self) -> u32 {
+        u32::from_be_bytes(self.v[187..191].try_into().unwrap())
+    }
+    /// the trailing ed25519 signer, `None` for a secp256k1 tx
+    pub fn pubkey(&self) -> Option<[u8; 32]> {
+        self.v.get(191..223).map(|x| x.try_into().unwrap())
     }
 
-    pub fn keccak(&self, out: &mut [u8; 32]) {
-        let mut s = tiny_keccak::Keccak::v256();
-        s.update(self.to());
-        s.update(self.atoms_slice());
-        s.update(self.nonce_slice());
-        s.finalize(out);
+    /// the signature slot (and pubkey) is all zero, no valid signature of either scheme is.
+    /// the tx is authorized by the signature of the next signed tx, see `RunHasher`
+    pub fn joins_run(&self) -> bool {
+        self.v[114..179]
+            .iter()
+            .chain(self.v.get(191..).unwrap_or_default())
+            .all(|&b| b == 0)
+    }
+
+    /// same digest as `TxToSer::keccak` hashed straight from the input buffer
+    pub fn keccak(&self, domain_separator: &[u8; 32], out: &mut [u8; 32]) {
+        eip712_digest(domain_separator, &self.v[..TxToSer::SIGNED_SIZE], out);
     }
 }
 
@@ -160,17 +1110,34 @@ impl<'a> Tx<'a> {
 mod tests {
     use super::*;
 
+    const TEST_DOMAIN: Eip712Domain = Eip712Domain {
+        chain_id: 1,
+        verifying_contract: [9u8; 20],
+    };
+
+    fn test_domain() -> [u8; 32] {
+        TEST_DOMAIN.separator()
+    }
+
     /// Helper to create a test TxToSer with known values
     fn create_test_tx() -> TxToSer {
         TxToSer {
             to: [1u8; 20],
+            token: [6u8; 20],
             atoms: 1000,
             nonce: 42,
+            channel: 3,
+            fee: 20,
+            valid_until: 1_700_000_000,
+            kind: TxKind::Transfer,
+            memo: b"invoice 42".to_vec(),
             sig_r: [2u8; 32],
             sig_s: [3u8; 32],
             v: 27,
             from_idx: 5,
             to_idx: 10,
+            fee_idx: 0,
+            pubkey: None,
         }
     }
 
@@ -178,13 +1145,21 @@ mod tests {
     fn create_max_tx() -> TxToSer {
         TxToSer {
             to: [0xFF; 20],
-            atoms: i64::MAX,
+            token: [0xFF; 20],
+            atoms: i128::MAX,
             nonce: u64::MAX,
+            channel: u32::MAX,
+            fee: u32::MAX,
+            valid_until: u64::MAX,
+            kind: TxKind::Withdrawal,
+            memo: vec![0xFF; TxToSer::MAX_MEMO],
             sig_r: [0xFF; 32],
             sig_s: [0xFF; 32],
             v: 255,
             from_idx: u32::MAX,
             to_idx: u32::MAX,
+            fee_idx: u32::MAX,
+            pubkey: None,
         }
     }
 
@@ -192,13 +1167,21 @@ mod tests {
     fn create_min_tx() -> TxToSer {
         TxToSer {
             to: [0u8; 20],
+            token: [0u8; 20],
             atoms: 1, // positive non-zero as per the requirement
             nonce: 0,
+            channel: 0,
+            fee: 0,
+            valid_until: 0,
+            kind: TxKind::Transfer,
+            memo: vec![],
             sig_r: [0u8; 32],
             sig_s: [0u8; 32],
             v: 0,
             from_idx: 0,
             to_idx: 0,
+            fee_idx: 0,
+            pubkey: None,
         }
     }
 
@@ -214,8 +1197,8 @@ mod tests {
         );
         assert_eq!(
             TxToSer::SIZE,
-            109,
-            "TxToSer::SIZE should be 109 bytes (20+8+8+32+32+1+4+4)"
+            191,
+            "TxToSer::SIZE should be 191 bytes (20+20+16+8+4+4+8+1+1+32+32+32+1+4+4+4)"
         );
     }
 
@@ -227,8 +1210,17 @@ mod tests {
 
         // Verify all fields round-trip correctly
         assert_eq!(tx.to(), &original.to, "to field should match");
+        assert_eq!(tx.token(), &original.token, "token field should match");
         assert_eq!(tx.atoms(), original.atoms, "atoms field should match");
         assert_eq!(tx.nonce(), original.nonce, "nonce field should match");
+        assert_eq!(tx.channel(), original.channel, "channel field should match");
+        assert_eq!(tx.fee(), original.fee, "fee field should match");
+        assert_eq!(
+            tx.valid_until(),
+            original.valid_until,
+            "valid_until field should match"
+        );
+        assert_eq!(tx.kind(), original.kind as u8, "kind field should match");
         assert_eq!(tx.sig_r(), original.sig_r, "sig_r field should match");
         assert_eq!(tx.sig_s(), original.sig_s, "sig_s field should match");
         assert_eq!(tx.v(), original.v, "v field should match");
@@ -238,6 +1230,29 @@ mod tests {
             "from_idx field should match"
         );
         assert_eq!(tx.to_idx(), original.to_idx, "to_idx field should match");
+        assert_eq!(tx.fee_idx(), original.fee_idx, "fee_idx field should match");
+    }
+
+    #[test]
+    fn test_tx_deser() {
+        for original in [create_test_tx(), create_max_tx(), create_min_tx()] {
+            let serialized = original.ser();
+            assert_eq!(TxToSer::deser(&serialized).unwrap().ser(), serialized);
+        }
+        let serialized = create_test_tx().ser();
+        assert_eq!(
+            TxToSer::deser(&serialized[1..]).err(),
+            Some(InputError::LengthMismatch {
+                expected: TxToSer::SIZE,
+                got: TxToSer::SIZE - 1
+            })
+        );
+        let mut bad = serialized;
+        bad[80] = 3;
+        assert_eq!(
+            TxToSer::deser(&bad).err(),
+            Some(InputError::BadTxKind { kind: 3 })
+        );
     }
 
     #[test]
@@ -246,8 +1261,15 @@ mod tests {
         let serialized = original.ser();
         let tx = Tx { v: &serialized };
 
-        assert_eq!(tx.atoms(), i64::MAX, "should handle max i64 value");
+        assert_eq!(tx.atoms(), i128::MAX, "should handle max i128 value");
         assert_eq!(tx.nonce(), u64::MAX, "should handle max u64 value");
+        assert_eq!(tx.channel(), u32::MAX, "should handle max u32 channel");
+        assert_eq!(tx.fee(), u32::MAX, "should handle max u32 fee");
+        assert_eq!(
+            tx.valid_until(),
+            u64::MAX,
+            "should handle max u64 valid_until"
+        );
         assert_eq!(
             tx.from_idx(),
             u32::MAX,
@@ -287,12 +1309,12 @@ mod tests {
     #[test]
     fn test_tx_keccak_consistency() {
         let tx_to_ser = create_test_tx();
-        let hash1 = tx_to_ser.keccak();
+        let hash1 = tx_to_ser.keccak(&test_domain());
 
         let serialized = tx_to_ser.ser();
         let tx = Tx { v: &serialized };
         let mut hash2 = [0u8; 32];
-        tx.keccak(&mut hash2);
+        tx.keccak(&test_domain(), &mut hash2);
 
         assert_eq!(
             hash1, hash2,
@@ -303,8 +1325,8 @@ mod tests {
     #[test]
     fn test_tx_keccak_deterministic() {
         let tx = create_test_tx();
-        let hash1 = tx.keccak();
-        let hash2 = tx.keccak();
+        let hash1 = tx.keccak(&test_domain());
+        let hash2 = tx.keccak(&test_domain());
 
         assert_eq!(hash1, hash2, "Keccak hash should be deterministic");
     }
@@ -315,21 +1337,167 @@ mod tests {
         let mut tx2 = create_test_tx();
         tx2.nonce = 43; // Change one field
 
-        let hash1 = tx1.keccak();
-        let hash2 = tx2.keccak();
+        let hash1 = tx1.keccak(&test_domain());
+        let hash2 = tx2.keccak(&test_domain());
 
         assert_ne!(
             hash1, hash2,
             "Different transactions should produce different hashes"
         );
+
+        let mut tx3 = create_test_tx();
+        tx3.token = [7u8; 20];
+        assert_ne!(
+            hash1,
+            tx3.keccak(&test_domain()),
+            "Signatures should not replay across tokens"
+        );
+
+        let mut tx4 = create_test_tx();
+        tx4.fee = 21;
+        assert_ne!(
+            hash1,
+            tx4.keccak(&test_domain()),
+            "The batcher should not be able to change the signed fee"
+        );
+
+        let mut tx5 = create_test_tx();
+        tx5.valid_until += 1;
+        assert_ne!(
+            hash1,
+            tx5.keccak(&test_domain()),
+            "The batcher should not be able to extend the deadline"
+        );
+
+        let mut tx6 = create_test_tx();
+        tx6.kind = TxKind::Withdrawal;
+        assert_ne!(
+            hash1,
+            tx6.keccak(&test_domain()),
+            "A transfer signature should not authorize a withdrawal"
+        );
+
+        let mut tx7 = create_test_tx();
+        tx7.channel = 4;
+        assert_ne!(
+            hash1,
+            tx7.keccak(&test_domain()),
+            "Signatures should not replay across channels"
+        );
+    }
+
+    #[test]
+    fn test_tx_keccak_different_for_different_domain() {
+        let tx = create_test_tx();
+        let other = Eip712Domain {
+            chain_id: 2,
+            verifying_contract: [9u8; 20],
+        };
+        assert_ne!(
+            tx.keccak(&test_domain()),
+            tx.keccak(&other.separator()),
+            "Signatures should not replay across chains"
+        );
+    }
+
+    #[test]
+    fn test_eip712_typehashes() {
+        assert_eq!(
+            Eip712Domain::TYPEHASH,
+            keccak256(
+                b"EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)"
+            )
+        );
+        assert_eq!(
+            TxToSer::TYPEHASH,
+            keccak256(
+                b"Tx(address to,address token,int128 atoms,uint64 nonce,uint32 channel,uint32 fee,uint64 validUntil,uint8 kind,bytes memo)"
+            )
+        );
+        assert_eq!(RunHasher::TYPEHASH, keccak256(b"TxRun(bytes32[] digests)"));
+    }
+
+    #[test]
+    fn test_eip712_matches_alloy() {
+        use alloy_sol_types::{eip712_domain, sol, SolStruct};
+        sol! {
+            struct Tx {
+                address to;
+                address token;
+                int128 atoms;
+                uint64 nonce;
+                uint32 channel;
+                uint32 fee;
+                uint64 validUntil;
+                uint8 kind;
+                bytes memo;
+            }
+        }
+        let domain = eip712_domain! {
+            name: DOMAIN_NAME,
+            version: DOMAIN_VERSION,
+            chain_id: 1,
+            verifying_contract: [9u8; 20].into(),
+        };
+        for atoms in [1000, -500, i64::MAX as i128, i128::MAX, i128::MIN] {
+            let mut tx = create_test_tx();
+            tx.atoms = atoms;
+            let alloy_tx = Tx {
+                to: tx.to.into(),
+                token: tx.token.into(),
+                atoms: tx.atoms,
+                nonce: tx.nonce,
+                channel: tx.channel,
+                fee: tx.fee,
+                validUntil: tx.valid_until,
+                kind: tx.kind as u8,
+                memo: tx.memo.clone().into(),
+            };
+            assert_eq!(
+                tx.keccak(&test_domain()),
+                alloy_tx.eip712_signing_hash(&domain).0,
+                "digest should match a wallet's eth_signTypedData for atoms={}",
+                atoms
+            );
+        }
+
+        sol! {
+            struct TxRun {
+                bytes32[] digests;
+            }
+        }
+        let run = vec![create_test_tx(), create_max_tx(), create_min_tx()];
+        let alloy_run = TxRun {
+            digests: run
+                .iter()
+                .map(|x| x.keccak(&test_domain()).into())
+                .collect(),
+        };
+        assert_eq!(
+            TxToSer::run_digest(&run, &test_domain()),
+            alloy_run.eip712_signing_hash(&domain).0
+        );
+        assert_eq!(
+            TxToSer::run_digest(&run[..1], &test_domain()),
+            run[0].keccak(&test_domain()),
+            "a run of one is a signed tx"
+        );
     }
 
     #[test]
     fn test_input_header_size_constant() {
         let input = InputToSer {
             state_deltas: 10,
-            fee_atoms: 100,
             fee_recipient: [4u8; 20],
+            timestamp: 1_700_000_000,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
             tx: vec![],
         };
         let serialized = input.ser();
@@ -342,8 +1510,8 @@ mod tests {
         );
         assert_eq!(
             Input::HEADER_SIZE,
-            30,
-            "Input::HEADER_SIZE should be 30 bytes (4+2+20+4)"
+            176,
+            "Input::HEADER_SIZE should be 176 bytes (4+20+8+4+5+1+4*22+1+8+8+20+8+1)"
         );
     }
 
@@ -351,8 +1519,16 @@ mod tests {
     fn test_input_round_trip_no_tx() {
         let original = InputToSer {
             state_deltas: 10,
-            fee_atoms: 100,
             fee_recipient: [4u8; 20],
+            timestamp: 1_700_000_000,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
             tx: vec![],
         };
 
@@ -364,16 +1540,16 @@ mod tests {
             original.state_deltas,
             "state_deltas should match"
         );
-        assert_eq!(
-            input.fee_atoms(),
-            original.fee_atoms,
-            "fee_atoms should match"
-        );
         assert_eq!(
             input.fee_recipient(),
             &original.fee_recipient,
             "fee_recipient should match"
         );
+        assert_eq!(
+            input.timestamp(),
+            original.timestamp,
+            "timestamp should match"
+        );
         assert_eq!(
             input.total_tx(),
             0,
@@ -386,8 +1562,16 @@ mod tests {
         let tx = create_test_tx();
         let original = InputToSer {
             state_deltas: 5,
-            fee_atoms: 50,
             fee_recipient: [7u8; 20],
+            timestamp: 0,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
             tx: vec![tx],
         };
 
@@ -395,7 +1579,6 @@ mod tests {
         let input = Input::new(&serialized);
 
         assert_eq!(input.state_deltas(), 5);
-        assert_eq!(input.fee_atoms(), 50);
         assert_eq!(input.fee_recipient(), &[7u8; 20]);
         assert_eq!(input.total_tx(), 1);
 
@@ -422,8 +1605,16 @@ mod tests {
 
         let original = InputToSer {
             state_deltas: 20,
-            fee_atoms: 75,
             fee_recipient: [8u8; 20],
+            timestamp: 0,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
             tx: vec![tx1, tx2, tx3],
         };
 
@@ -431,7 +1622,6 @@ mod tests {
         let input = Input::new(&serialized);
 
         assert_eq!(input.state_deltas(), 20);
-        assert_eq!(input.fee_atoms(), 75);
         assert_eq!(input.total_tx(), 3);
 
         // Verify each transaction
@@ -464,8 +1654,16 @@ mod tests {
         let max_tx = create_max_tx();
         let original = InputToSer {
             state_deltas: u32::MAX,
-            fee_atoms: u16::MAX,
             fee_recipient: [0xFF; 20],
+            timestamp: u64::MAX,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
             tx: vec![max_tx],
         };
 
@@ -478,9 +1676,9 @@ mod tests {
             "should handle max u32 state_deltas"
         );
         assert_eq!(
-            input.fee_atoms(),
-            u16::MAX,
-            "should handle max u16 fee_atoms"
+            input.timestamp(),
+            u64::MAX,
+            "should handle max u64 timestamp"
         );
         assert_eq!(input.total_tx(), 1);
     }
@@ -491,8 +1689,16 @@ mod tests {
         let tx2 = create_min_tx();
         let original = InputToSer {
             state_deltas: 100,
-            fee_atoms: 200,
             fee_recipient: [9u8; 20],
+            timestamp: 0,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
             tx: vec![tx1, tx2],
         };
 
@@ -506,56 +1712,274 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_input_ser_chunks() {
+        let original = InputToSer {
+            state_deltas: 100,
+            fee_recipient: [9u8; 20],
+            timestamp: 0,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
+            tx: vec![create_test_tx(), create_min_tx(), create_max_tx()],
+        };
+        let chunks = original.ser_chunks(2);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].len(), 2 * TxToSer::SIZE);
+        assert_eq!(chunks[1].len(), TxToSer::SIZE);
+
+        let header = original.ser_header();
+        assert_eq!(header.len(), Input::HEADER_SIZE);
+        assert_eq!(
+            [header, chunks.concat()].concat(),
+            original.ser(),
+            "header followed by the chunks should be the whole input"
+        );
+    }
+
+    #[test]
+    fn test_input_ser_chunks_compressed() {
+        let raw = InputToSer {
+            state_deltas: 100,
+            fee_recipient: [9u8; 20],
+            timestamp: 0,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
+            tx: (0..40).map(|_| create_test_tx()).collect(),
+        };
+        let compressed = InputToSer {
+            compressed: true,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
+            tx: raw.tx.clone(),
+            ..raw
+        };
+        let header = compressed.ser_header();
+        assert!(Input::try_new(&header[..Input::HEADER_SIZE - 1]).is_err());
+        assert!(Input::new(&header).compressed());
+
+        let frames = compressed.ser_chunks(16);
+        assert_eq!(frames.len(), 3);
+        let raw_chunks = raw.ser_chunks(16);
+        for (frame, chunk) in frames.iter().zip(&raw_chunks) {
+            assert!(
+                frame.len() < chunk.len() / 4,
+                "repetitive txs should compress"
+            );
+            assert_eq!(&decompress(frame, chunk.len()).unwrap(), chunk);
+        }
+        assert_eq!(
+            decompress(&frames[0], raw_chunks[0].len() - 1),
+            Err(InputError::BadFrame {
+                len: frames[0].len()
+            }),
+            "inflating past max_len is rejected"
+        );
+        assert!(decompress(&raw_chunks[0], raw_chunks[0].len()).is_err());
+        let two = [frames[0].clone(), frames[1].clone()].concat();
+        let both = raw_chunks[0].len() + raw_chunks[1].len();
+        assert_eq!(decompress(&two, both).unwrap().len(), both);
+        assert!(
+            decompress(&two, both - 1).is_err(),
+            "max_len bounds all the frames together"
+        );
+        // a max_len far past what the frame inflates to reserves nothing up front
+        assert_eq!(&decompress(&frames[0], usize::MAX).unwrap(), &raw_chunks[0]);
+        assert_eq!(
+            compressed.ser().len(),
+            Input::HEADER_SIZE + compressed.ser_chunks(40)[0].len()
+        );
+
+        let mut bad = header.clone();
+        bad[41] = 2;
+        assert_eq!(
+            Input::new(&bad).try_compressed(),
+            Err(InputError::BadCompressedFlag { flag: 2 })
+        );
+    }
+
+    #[test]
+    fn test_input_deser() {
+        let original = InputToSer {
+            state_deltas: 7,
+            fee_recipient: [9u8; 20],
+            timestamp: 1_700_000_000,
+            nonce_policy: NoncePolicy::Windowed(4),
+            compressed: false,
+            fee_splits: vec![FeeSplit {
+                recipient: [3; 20],
+                bps: 250,
+            }],
+            transfer_policy: TransferPolicy {
+                allow_self: true,
+                allow_fee_recipient: false,
+            },
+            min_atoms: 50,
+            domain: TEST_DOMAIN,
+            batch_nonce: 12,
+            sig_scheme: SigScheme::Secp256k1,
+            tx: vec![create_test_tx(), create_max_tx(), create_min_tx()],
+        };
+        let serialized = original.ser();
+        let back = InputToSer::deser(&serialized).unwrap();
+        assert_eq!(back.ser(), serialized);
+        assert_eq!(back.tx.len(), 3);
+        assert_eq!(back.fee_splits, original.fee_splits);
+
+        // edit and re-serialize, ex. to bump the batch nonce of a persisted batch
+        let bumped = InputToSer {
+            batch_nonce: 13,
+            ..back
+        };
+        assert_eq!(Input::new(&bumped.ser()).batch_nonce(), 13);
+
+        let compressed = InputToSer {
+            compressed: true,
+            ..InputToSer::deser(&serialized).unwrap()
+        };
+        let back = InputToSer::deser(&compressed.ser()).unwrap();
+        assert!(back.compressed);
+        assert_eq!(back.ser_chunks(3)[0], compressed.ser_chunks(3)[0]);
+        // several frames, as streamed to the program
+        let frames: Vec<u8> = compressed.ser_chunks(1).concat();
+        let streamed = [compressed.ser_header(), frames].concat();
+        assert_eq!(InputToSer::deser(&streamed).unwrap().tx.len(), 3);
+
+        assert_eq!(
+            InputToSer::deser(&serialized[..serialized.len() - 1]).err(),
+            Some(InputError::LengthMismatch {
+                expected: 3 * TxToSer::SIZE,
+                got: 3 * TxToSer::SIZE - 1
+            })
+        );
+        assert_eq!(
+            InputToSer::deser(&serialized[..10]).err(),
+            Some(InputError::TooShort { len: 10 })
+        );
+    }
+
     #[test]
     fn test_tx_byte_order_big_endian() {
         let tx = TxToSer {
             to: [0; 20],
-            atoms: 0x0102030405060708i64,
+            token: [0; 20],
+            atoms: 0x0102030405060708_1112131415161718i128,
             nonce: 0x090A0B0C0D0E0F10u64,
+            channel: 0x292A2B2Cu32,
+            fee: 0x1D1E1F20u32,
+            valid_until: 0x2122232425262728u64,
+            kind: TxKind::Deposit,
+            memo: vec![0x31, 0x32],
             sig_r: [0; 32],
             sig_s: [0; 32],
             v: 0,
             from_idx: 0x11121314u32,
             to_idx: 0x15161718u32,
+            fee_idx: 0x191A1B1Cu32,
+            pubkey: None,
         };
 
         let serialized = tx.ser();
 
-        // Check atoms (big-endian i64)
+        // Check atoms (big-endian i128)
         assert_eq!(
-            &serialized[20..28],
-            &[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08],
+            &serialized[40..56],
+            &[
+                0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16,
+                0x17, 0x18
+            ],
             "atoms should be big-endian"
         );
 
         // Check nonce (big-endian u64)
         assert_eq!(
-            &serialized[28..36],
+            &serialized[56..64],
             &[0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F, 0x10],
             "nonce should be big-endian"
         );
 
+        // Check channel (big-endian u32)
+        assert_eq!(
+            &serialized[64..68],
+            &[0x29, 0x2A, 0x2B, 0x2C],
+            "channel should be big-endian"
+        );
+
+        // Check fee (big-endian u32)
+        assert_eq!(
+            &serialized[68..72],
+            &[0x1D, 0x1E, 0x1F, 0x20],
+            "fee should be big-endian"
+        );
+
+        // Check valid_until (big-endian u64)
+        assert_eq!(
+            &serialized[72..80],
+            &[0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28],
+            "valid_until should be big-endian"
+        );
+        assert_eq!(serialized[80], TxKind::Deposit as u8, "kind");
+        assert_eq!(
+            &serialized[81..84],
+            &[2, 0x31, 0x32],
+            "memo length then bytes"
+        );
+        assert!(
+            serialized[84..114].iter().all(|&b| b == 0),
+            "memo slot padding"
+        );
+
         // Check from_idx (big-endian u32)
         assert_eq!(
-            &serialized[101..105],
+            &serialized[179..183],
             &[0x11, 0x12, 0x13, 0x14],
             "from_idx should be big-endian"
         );
 
         // Check to_idx (big-endian u32)
         assert_eq!(
-            &serialized[105..109],
+            &serialized[183..187],
             &[0x15, 0x16, 0x17, 0x18],
             "to_idx should be big-endian"
         );
+
+        // Check fee_idx (big-endian u32)
+        assert_eq!(
+            &serialized[187..191],
+            &[0x19, 0x1A, 0x1B, 0x1C],
+            "fee_idx should be big-endian"
+        );
     }
 
     #[test]
     fn test_input_byte_order_big_endian() {
         let input = InputToSer {
             state_deltas: 0x01020304u32,
-            fee_atoms: 0x0506u16,
             fee_recipient: [0; 20],
+            timestamp: 0x292A2B2C2D2E2F30u64,
+            nonce_policy: NoncePolicy::Windowed(0x31323334),
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0x4142434445464748u64,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0x5152535455565758u64,
+            sig_scheme: SigScheme::Secp256k1,
             tx: vec![],
         };
 
@@ -568,19 +1992,287 @@ mod tests {
             "state_deltas should be big-endian"
         );
 
-        // Check fee_atoms (big-endian u16)
+        // Check timestamp (big-endian u64)
         assert_eq!(
-            &serialized[4..6],
-            &[0x05, 0x06],
-            "fee_atoms should be big-endian"
+            &serialized[24..32],
+            &[0x29, 0x2A, 0x2B, 0x2C, 0x2D, 0x2E, 0x2F, 0x30],
+            "timestamp should be big-endian"
         );
 
         // Check total_tx (big-endian u32)
         assert_eq!(
-            &serialized[26..30],
+            &serialized[32..36],
             &[0x00, 0x00, 0x00, 0x00],
             "total_tx should be big-endian (0 txs)"
         );
+
+        // Check nonce policy (tag, big-endian u32 window)
+        assert_eq!(
+            &serialized[36..41],
+            &[0x02, 0x31, 0x32, 0x33, 0x34],
+            "nonce policy window should be big-endian"
+        );
+
+        // Check min_atoms (big-endian u64)
+        assert_eq!(
+            &serialized[131..139],
+            &[0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48],
+            "min_atoms should be big-endian"
+        );
+        assert_eq!(Input::new(&serialized).min_atoms(), 0x4142434445464748);
+
+        // Check chain_id (big-endian u64) and the contract that follows
+        assert_eq!(
+            &serialized[139..147],
+            &TEST_DOMAIN.chain_id.to_be_bytes(),
+            "chain_id should be big-endian"
+        );
+        assert_eq!(&serialized[147..167], &TEST_DOMAIN.verifying_contract);
+        assert_eq!(Input::new(&serialized).domain(), TEST_DOMAIN);
+
+        // Check batch_nonce (big-endian u64)
+        assert_eq!(
+            &serialized[167..175],
+            &[0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58],
+            "batch_nonce should be big-endian"
+        );
+        assert_eq!(Input::new(&serialized).batch_nonce(), 0x5152535455565758);
+    }
+
+    #[test]
+    fn test_nonce_policy() {
+        for p in [
+            NoncePolicy::Strict,
+            NoncePolicy::Monotonic,
+            NoncePolicy::Windowed(3),
+        ] {
+            assert_eq!(NoncePolicy::from_bytes(&p.ser()), Ok(p));
+            assert!(p.accepts(4, 5), "{p:?} should accept +1");
+            assert!(!p.accepts(5, 5), "{p:?} should reject a duplicate");
+            assert!(!p.accepts(5, 4), "{p:?} should reject going back");
+        }
+        assert!(!NoncePolicy::Strict.accepts(4, 6));
+        assert!(!NoncePolicy::Strict.accepts(u64::MAX, 0));
+        assert!(NoncePolicy::Monotonic.accepts(4, u64::MAX));
+        assert!(NoncePolicy::Windowed(3).accepts(4, 7));
+        assert!(!NoncePolicy::Windowed(3).accepts(4, 8));
+        assert_eq!(
+            NoncePolicy::from_bytes(&[3, 0, 0, 0, 0]),
+            Err(InputError::BadNoncePolicy { tag: 3 })
+        );
+
+        let mut header = InputToSer {
+            state_deltas: 0,
+            fee_recipient: [0; 20],
+            timestamp: 0,
+            nonce_policy: NoncePolicy::Monotonic,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
+            tx: vec![],
+        }
+        .ser();
+        assert_eq!(
+            Input::try_new(&header).unwrap().nonce_policy(),
+            NoncePolicy::Monotonic
+        );
+        header[36] = 9;
+        assert_eq!(
+            Input::try_new(&header).err(),
+            Some(InputError::BadNoncePolicy { tag: 9 })
+        );
+    }
+
+    #[test]
+    fn test_fee_splits() {
+        let splits = vec![
+            FeeSplit {
+                recipient: [1; 20],
+                bps: 2500,
+            },
+            FeeSplit {
+                recipient: [2; 20],
+                bps: 0x1234,
+            },
+        ];
+        let mut input = InputToSer {
+            state_deltas: 0,
+            fee_recipient: [0; 20],
+            timestamp: 0,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: splits.clone(),
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
+            tx: vec![],
+        };
+        let header = input.ser_header();
+        assert_eq!(&header[42..62], &[1; 20]);
+        assert_eq!(&header[84..86], &[0x12, 0x34], "bps should be big-endian");
+        assert!(
+            header[86..130].iter().all(|b| *b == 0),
+            "unused slots zeroed"
+        );
+        assert_eq!(Input::try_new(&header).unwrap().fee_splits(), splits);
+
+        input.fee_splits[1].bps = 7501;
+        assert_eq!(
+            Input::try_new(&input.ser_header()).err(),
+            Some(InputError::BadFeeSplits { bps: 10_001 })
+        );
+
+        let split = FeeSplit {
+            recipient: [1; 20],
+            bps: 2500,
+        };
+        assert_eq!(
+            (split.share(30), split.share(3), split.share(u64::MAX)),
+            (7, 0, u64::MAX / 4)
+        );
+    }
+
+    #[test]
+    fn test_transfer_policy() {
+        for flags in 0..4 {
+            let p = TransferPolicy::from_flags(flags).unwrap();
+            assert_eq!(p.flags(), flags);
+        }
+        let p = TransferPolicy {
+            allow_self: false,
+            allow_fee_recipient: true,
+        };
+        assert_eq!(p.flags(), 0b10);
+        assert_eq!(
+            TransferPolicy::from_flags(4),
+            Err(InputError::BadTransferPolicy { flags: 4 })
+        );
+
+        let mut header = InputToSer {
+            state_deltas: 0,
+            fee_recipient: [0; 20],
+            timestamp: 0,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: p,
+            min_atoms: 0,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
+            tx: vec![],
+        }
+        .ser_header();
+        assert_eq!(header[130], 0b10);
+        assert_eq!(Input::try_new(&header).unwrap().transfer_policy(), p);
+        header[130] = 0xFF;
+        assert!(Input::try_new(&header).is_err());
+    }
+
+    #[test]
+    fn test_sender_keys() {
+        let keys = SenderKeys {
+            keys: vec![(0, [2; 33]), (u32::MAX, [3; 33])],
+        };
+        let ser = keys.ser();
+        assert_eq!(ser.len(), 2 * SenderKeys::ENTRY_SIZE);
+        assert_eq!(ser[..4], [0, 0, 0, 0]);
+        assert_eq!(ser[SenderKeys::ENTRY_SIZE..][..4], [0xFF; 4]);
+        assert_eq!(SenderKeys::deser(&ser), Ok(keys));
+        assert_eq!(SenderKeys::deser(&[]), Ok(SenderKeys::default()));
+        assert_eq!(
+            SenderKeys::deser(&ser[1..]),
+            Err(InputError::BadSenderKeys {
+                len: 2 * SenderKeys::ENTRY_SIZE - 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_prior_balances() {
+        let balances = PriorBalances {
+            balances: vec![(1, 500), (u32::MAX, u128::MAX)],
+        };
+        let ser = balances.ser();
+        assert_eq!(ser.len(), 2 * PriorBalances::ENTRY_SIZE);
+        assert_eq!(ser[4..PriorBalances::ENTRY_SIZE], 500u128.to_be_bytes());
+        assert_eq!(PriorBalances::deser(&ser), Ok(balances));
+        assert_eq!(
+            PriorBalances::deser(&ser[..5]),
+            Err(InputError::BadPriorBalances { len: 5 })
+        );
+    }
+
+    #[test]
+    fn test_sig_scheme() {
+        let mut ed = create_test_tx();
+        ed.pubkey = Some([0xAB; 32]);
+        let mut input = InputToSer {
+            state_deltas: 0,
+            fee_recipient: [0; 20],
+            timestamp: 0,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Ed25519,
+            tx: vec![ed.clone(), ed.clone()],
+        };
+        let serialized = input.ser();
+        assert_eq!(serialized[175], 1);
+        assert_eq!(
+            serialized.len(),
+            Input::HEADER_SIZE + 2 * TxToSer::ED25519_SIZE
+        );
+        let inp = Input::try_new(&serialized).unwrap();
+        assert_eq!(inp.sig_scheme(), SigScheme::Ed25519);
+        let tx = inp.get_tx(1).unwrap();
+        assert_eq!(tx.pubkey(), Some([0xAB; 32]));
+        // the secp256k1 prefix is unchanged, the pubkey trails it
+        assert_eq!(tx.from_idx(), 5);
+        assert_eq!(tx.v[..TxToSer::SIZE], create_test_tx().ser());
+        assert!(inp.get_tx(2).is_none());
+
+        let back = InputToSer::deser(&serialized).unwrap();
+        assert_eq!(back.sig_scheme, SigScheme::Ed25519);
+        assert_eq!(back.ser(), serialized);
+        input.compressed = true;
+        assert_eq!(
+            InputToSer::deser(&input.ser()).unwrap().tx[0].pubkey,
+            ed.pubkey
+        );
+
+        // a secp256k1 sized tx region does not add up under the ed25519 header
+        let mut secp = input.ser_header();
+        secp[41] = 0;
+        secp.extend(create_test_tx().ser());
+        secp.extend(create_test_tx().ser());
+        assert_eq!(
+            Input::try_new(&secp).err(),
+            Some(InputError::LengthMismatch {
+                expected: Input::HEADER_SIZE + 2 * TxToSer::ED25519_SIZE,
+                got: Input::HEADER_SIZE + 2 * TxToSer::SIZE,
+            })
+        );
+        secp[175] = 2;
+        assert_eq!(
+            Input::try_new(&secp).err(),
+            Some(InputError::BadSigScheme { tag: 2 })
+        );
+        assert_eq!(create_test_tx().pubkey, None);
+        assert_eq!(
+            TxToSer::deser(&create_test_tx().ser()).unwrap().pubkey,
+            None
+        );
     }
 
     #[test]
@@ -590,7 +2282,11 @@ mod tests {
         let tx_ref = Tx { v: &serialized };
 
         // Test slice accessors return correct slices
-        assert_eq!(tx_ref.atoms_slice().len(), 8, "atoms_slice should be 8 bytes");
+        assert_eq!(
+            tx_ref.atoms_slice().len(),
+            16,
+            "atoms_slice should be 16 bytes"
+        );
         assert_eq!(
             tx_ref.nonce_slice().len(),
             8,
@@ -598,7 +2294,7 @@ mod tests {
         );
 
         // Verify slice contents match the value methods
-        let atoms_from_slice = i64::from_be_bytes(tx_ref.atoms_slice().try_into().unwrap());
+        let atoms_from_slice = i128::from_be_bytes(tx_ref.atoms_slice().try_into().unwrap());
         assert_eq!(
             atoms_from_slice,
             tx_ref.atoms(),
@@ -613,6 +2309,70 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_input_try_new() {
+        let original = InputToSer {
+            state_deltas: 3,
+            fee_recipient: [1; 20],
+            timestamp: 0,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
+            tx: vec![create_test_tx(), create_min_tx()],
+        };
+        let serialized = original.ser();
+        let input = Input::try_new(&serialized).unwrap();
+        assert_eq!(input.total_tx(), 2);
+        assert!(input.get_tx(1).is_some());
+        assert!(input.get_tx(2).is_none(), "past the last tx");
+        assert!(input.get_tx(u32::MAX).is_none());
+
+        assert_eq!(
+            Input::try_new(&serialized[..10]).err(),
+            Some(InputError::TooShort { len: 10 })
+        );
+        assert_eq!(
+            Input::try_new(&serialized[..serialized.len() - 1]).err(),
+            Some(InputError::LengthMismatch {
+                expected: serialized.len(),
+                got: serialized.len() - 1
+            }),
+            "truncated last tx"
+        );
+
+        // header claiming more txs than the buffer holds
+        let mut lying = serialized.clone();
+        lying[32..36].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            Input::try_new(&lying),
+            Err(InputError::LengthMismatch { .. })
+        ));
+        assert!(Input::new(&lying).get_tx(2).is_none());
+    }
+
+    #[test]
+    fn test_tx_kind() {
+        for k in [TxKind::Transfer, TxKind::Deposit, TxKind::Withdrawal] {
+            let mut tx = create_test_tx();
+            tx.kind = k;
+            let serialized = tx.ser();
+            assert_eq!(TxKind::from_u8(Tx { v: &serialized }.kind()), Some(k));
+        }
+        assert_eq!(TxKind::from_u8(3), None);
+    }
+
+    #[test]
+    fn test_tx_try_new() {
+        let serialized = create_test_tx().ser();
+        assert!(Tx::try_new(&serialized).is_some());
+        assert!(Tx::try_new(&serialized[1..]).is_none());
+    }
+
     #[test]
     fn test_many_transactions() {
         // Test with a larger number of transactions
@@ -620,7 +2380,7 @@ mod tests {
         for i in 0..100 {
             let mut tx = create_test_tx();
             tx.nonce = i as u64;
-            tx.atoms = 1000 + (i as i64 * 10);
+            tx.atoms = 1000 + (i as i128 * 10);
             tx.from_idx = i;
             tx.to_idx = (i + 1) % 100;
             txs.push(tx);
@@ -628,8 +2388,16 @@ mod tests {
 
         let original = InputToSer {
             state_deltas: 200,
-            fee_atoms: 10,
             fee_recipient: [0xAB; 20],
+            timestamp: 0,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: TEST_DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
             tx: txs,
         };
 
@@ -0,0 +1,75 @@
+//! Framing of the byte segments the host writes to the program stdin: a version byte and the
+//! big endian u32 payload length ahead of the payload. The host writes the frames raw
+//! (`SP1Stdin::write_vec`), so the program reads back exactly what `frame` produced and the
+//! layout is defined here rather than by the serializer of the sdk.
+use crate::ds::InputError;
+
+/// bumped whenever the layout of a frame changes
+pub const FRAME_VERSION: u8 = 1;
+
+/// version byte and payload length
+pub const FRAME_HEADER: usize = 1 + 4;
+
+pub fn frame(payload: &[u8]) -> Vec<u8> {
+    let len = u32::try_from(payload.len()).expect("frame payload past u32::MAX bytes");
+    let mut out = Vec::with_capacity(FRAME_HEADER + payload.len());
+    out.push(FRAME_VERSION);
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+/// the payload of a `frame`, which has to fill `v` exactly
+pub fn unframe(v: &[u8]) -> Result<&[u8], InputError> {
+    let (head, payload) = v
+        .split_at_checked(FRAME_HEADER)
+        .ok_or(InputError::BadFrameLength {
+            expected: FRAME_HEADER,
+            got: v.len(),
+        })?;
+    if head[0] != FRAME_VERSION {
+        return Err(InputError::BadFrameVersion { version: head[0] });
+    }
+    let len = u32::from_be_bytes(head[1..].try_into().unwrap()) as usize;
+    if payload.len() != len {
+        return Err(InputError::BadFrameLength {
+            expected: FRAME_HEADER + len,
+            got: v.len(),
+        });
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame() {
+        let v = frame(b"abc");
+        assert_eq!(v, [FRAME_VERSION, 0, 0, 0, 3, b'a', b'b', b'c']);
+        assert_eq!(unframe(&v), Ok(&b"abc"[..]));
+        assert_eq!(unframe(&frame(&[])), Ok(&[][..]));
+
+        assert_eq!(
+            unframe(&v[..7]),
+            Err(InputError::BadFrameLength {
+                expected: 8,
+                got: 7
+            })
+        );
+        assert_eq!(
+            unframe(&v[..2]),
+            Err(InputError::BadFrameLength {
+                expected: FRAME_HEADER,
+                got: 2
+            })
+        );
+        let mut bad = v.clone();
+        bad[0] = 9;
+        assert_eq!(
+            unframe(&bad),
+            Err(InputError::BadFrameVersion { version: 9 })
+        );
+    }
+}
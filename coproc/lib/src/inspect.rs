@@ -0,0 +1,219 @@
+//! Human readable dump of a serialized batch, to troubleshoot a malformed one before it goes to
+//! the prover. Never panics, whatever can't be parsed is printed as the error that rejects it:
+//! the header field by field, then a row per tx with its digest and signer. The signer of a run
+//! is recovered once the run is signed and shown on every tx of it.
+use crate::ds::{decompress, Input, RunHasher, SigScheme, Tx, TxKind};
+use crate::signer_checked;
+use std::fmt::Write;
+
+/// `0x` and lowercase hex
+pub struct Hex<'a>(pub &'a [u8]);
+
+impl std::fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("0x")?;
+        for b in self.0 {
+            write!(f, "{b:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Hex<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(self, f)
+    }
+}
+
+/// the header and tx table of the serialized input `v`, compressed or not
+pub fn inspect(v: &[u8]) -> String {
+    let mut out = String::new();
+    if v.len() < Input::HEADER_SIZE {
+        let _ = writeln!(out, "input too short, {} bytes", v.len());
+        return out;
+    }
+    let inp = Input::new(v);
+    let _ = header(&mut out, &inp);
+    let Ok(scheme) = inp.try_sig_scheme() else {
+        return out;
+    };
+    let body = &v[Input::HEADER_SIZE..];
+    let raw = match inp.try_compressed() {
+        Ok(true) => match decompress(body, inp.total_tx() as usize * scheme.tx_size()) {
+            Ok(raw) => raw,
+            Err(e) => {
+                let _ = writeln!(out, "txs: {e}");
+                return out;
+            }
+        },
+        Ok(false) => body.to_vec(),
+        Err(_) => return out,
+    };
+    let _ = txs(&mut out, &inp, scheme, &raw);
+    out
+}
+
+fn header(out: &mut String, inp: &Input<'_>) -> std::fmt::Result {
+    let field = |x: Result<String, crate::ds::InputError>| x.unwrap_or_else(|e| e.to_string());
+    writeln!(out, "header")?;
+    writeln!(out, "  state_deltas    {}", inp.state_deltas())?;
+    writeln!(out, "  fee_recipient   {}", Hex(inp.fee_recipient()))?;
+    writeln!(out, "  timestamp       {}", inp.timestamp())?;
+    writeln!(out, "  total_tx        {}", inp.total_tx())?;
+    let policy = inp.try_nonce_policy().map(|x| x.to_string());
+    writeln!(out, "  nonce_policy    {}", field(policy))?;
+    let compressed = inp.try_compressed().map(|x| x.to_string());
+    writeln!(out, "  compressed      {}", field(compressed))?;
+    let splits = inp.try_fee_splits().map(|x| {
+        let x: Vec<_> = x
+            .iter()
+            .map(|s| format!("{} {}bps", Hex(&s.recipient), s.bps))
+            .collect();
+        format!("[{}]", x.join(", "))
+    });
+    writeln!(out, "  fee_splits      {}", field(splits))?;
+    let transfer = inp.try_transfer_policy().map(|x| format!("{x:?}"));
+    writeln!(out, "  transfer_policy {}", field(transfer))?;
+    writeln!(out, "  min_atoms       {}", inp.min_atoms())?;
+    writeln!(out, "  domain          {}", inp.domain())?;
+    writeln!(out, "  batch_nonce     {}", inp.batch_nonce())?;
+    let scheme = inp.try_sig_scheme().map(|x| x.to_string());
+    writeln!(out, "  sig_scheme      {}", field(scheme))?;
+    Ok(())
+}
+
+fn txs(out: &mut String, inp: &Input<'_>, scheme: SigScheme, raw: &[u8]) -> std::fmt::Result {
+    let size = scheme.tx_size();
+    let expected = inp.total_tx() as usize * size;
+    if raw.len() != expected {
+        writeln!(out, "txs: {} bytes, header implies {expected}", raw.len())?;
+    }
+    let separator = inp.domain().separator();
+    // the signer column of every row, runs are filled in once their signed tx is reached
+    let mut signers: Vec<String> = vec![];
+    let mut run = RunHasher::default();
+    writeln!(out, "txs")?;
+    for (i, v) in raw.chunks_exact(size).enumerate() {
+        let tx = Tx { v };
+        let mut digest = [0; 32];
+        tx.keccak(&separator, &mut digest);
+        writeln!(
+            out,
+            "  #{i} {} idx={}/{}/{} to={} token={} atoms={} fee={} nonce={} channel={} \
+             valid_until={} memo_len={} digest={}",
+            TxKind::from_u8(tx.kind()).map_or(format!("kind({})", tx.kind()), |x| x.to_string()),
+            tx.from_idx(),
+            tx.to_idx(),
+            tx.fee_idx(),
+            Hex(tx.to()),
+            Hex(tx.token()),
+            tx.atoms(),
+            tx.fee(),
+            tx.nonce(),
+            tx.channel(),
+            tx.valid_until(),
+            tx.memo_len(),
+            Hex(&digest),
+        )?;
+        if tx.kind() == TxKind::Deposit as u8 {
+            signers.push("unsigned deposit".into());
+            continue;
+        }
+        run.update(&digest);
+        if tx.joins_run() {
+            signers.push(String::new());
+            continue;
+        }
+        let len = run.len() as usize;
+        let digest = if len == 1 {
+            std::mem::take(&mut run);
+            digest
+        } else {
+            run.finalize(&separator)
+        };
+        let signer = signer_checked(scheme, tx.sig_r(), tx.sig_s(), tx.v(), tx.pubkey(), &digest)
+            .map_or_else(|e| format!("{e:?}"), |x| Hex(&x).to_string());
+        let start = signers.len() + 1 - len;
+        signers.truncate(start);
+        signers.extend((0..len).map(|_| signer.clone()));
+    }
+    let open = signers.len() - run.len() as usize;
+    for x in &mut signers[open..] {
+        *x = "open run, never signed".into();
+    }
+    writeln!(out, "signers")?;
+    for (i, x) in signers.iter().enumerate() {
+        writeln!(out, "  #{i} {x}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ds::{Eip712Domain, InputToSer, NoncePolicy, TransferPolicy, TxToSer};
+    use k256::ecdsa::SigningKey;
+
+    #[test]
+    fn test_inspect() {
+        let domain = Eip712Domain {
+            chain_id: 1,
+            verifying_contract: [0xDD; 20],
+        };
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let mut tx = TxToSer {
+            to: [0xB0; 20],
+            token: [0xC0; 20],
+            atoms: 100,
+            nonce: 0,
+            channel: 0,
+            fee: 10,
+            valid_until: 5,
+            kind: TxKind::Transfer,
+            memo: vec![],
+            sig_r: [0; 32],
+            sig_s: [0; 32],
+            v: 0,
+            from_idx: 1,
+            to_idx: 2,
+            fee_idx: 0,
+            pubkey: None,
+        };
+        let joined = tx.clone();
+        tx.nonce = 1;
+        let digest = TxToSer::run_digest(&[joined.clone(), tx.clone()], &domain.separator());
+        let (sig, rec) = alice.sign_prehash_recoverable(&digest).unwrap();
+        (tx.sig_r, tx.sig_s) = (sig.r().to_bytes().into(), sig.s().to_bytes().into());
+        tx.v = rec.to_byte();
+        let mut inp = InputToSer {
+            state_deltas: 3,
+            fee_recipient: [0xFE; 20],
+            timestamp: 0,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
+            tx: vec![joined.clone(), tx, joined],
+        };
+        let signer = crate::key_to_adr(alice.verifying_key());
+        let out = inspect(&inp.ser());
+        assert!(out.contains("  #0 transfer idx=1/2/0"), "{out}");
+        assert!(out.contains(&format!("  #0 {}", Hex(&signer))), "{out}");
+        assert!(out.contains(&format!("  #1 {}", Hex(&signer))), "{out}");
+        assert!(out.contains("  #2 open run, never signed"), "{out}");
+
+        inp.compressed = true;
+        assert_eq!(
+            inspect(&inp.ser()),
+            out.replace("compressed      false", "compressed      true")
+        );
+        let mut bad = inp.ser();
+        bad[41] = 7;
+        assert!(inspect(&bad).contains("compressed      bad compressed flag 7"));
+        assert_eq!(inspect(&[0; 3]), "input too short, 3 bytes\n");
+    }
+}
@@ -1,30 +1,164 @@
+pub mod builder;
+pub mod calldata;
 pub mod ds;
+pub mod frame;
+pub mod inspect;
+pub mod merkle;
+pub mod packed;
+#[cfg(feature = "ddm")]
+pub mod pay;
 use crate::ds::*;
+use crate::merkle::MerkleFrontier;
 use alloy_sol_types::sol;
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
+use k256::ecdsa::signature::hazmat::PrehashVerifier;
 use k256::ecdsa::{RecoveryId, VerifyingKey};
+use sha2::{Digest, Sha512};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use tiny_keccak::{Hasher, Keccak};
 
 sol! {
     #[derive(Debug)]
     struct StateDelta {
         address v;
+        address token; // deltas are per (v, token), an address sending two tokens has two
         bool is_sender; // if is sender on-chain checks nonces
-        uint64 start_nonce; // nonce of first seen tx
-        uint64 end_nonce; // nonce of last seen tx (all prev enforced from start_nonce)
-        int64 delta;
+        uint64 start_nonce; // lowest nonce in the batch
+        uint64 end_nonce; // highest nonce, the ones in between are spaced as nonce_policy allows
+        uint32 channel; // 0 unless is_sender, (v, token, channel) is unique in n
+        uint8 nonce_policy; // `NoncePolicy::tag` of the batch, strict: every nonce exactly once
+        int128 delta;
+        bool solvent; // a prior balance of (v, token) was given and covers the net of its channels
+    }
+    #[derive(Debug)]
+    struct Deposit {
+        uint64 id; // deposit id of the contract, the contract checks to/token/atoms against it
+        address to;
+        address token;
+        int128 atoms;
+    }
+    #[derive(Debug)]
+    struct Withdrawal {
+        address from; // signer, whose delta was debited
+        address to; // onchain recipient of the payout
+        address token;
+        int128 atoms; // to pay out, the fee stays with the contract
+        uint64 nonce; // of the signer, makes the authorization unique
+        uint32 channel; // the nonce belongs to
+    }
+    /// Work done per phase of the program. The guest can't read its own cycle counter, these are
+    /// the deterministic units the cycles scale with, the host prices them from its
+    /// `cycle-tracker` report. A batch far off the usual ratios is worth a look
+    #[derive(Debug, Default, PartialEq)]
+    struct Metering {
+        uint32 recoveries; // secp256k1 keys recovered, one per signed tx or run without a sender key
+        uint32 verifications; // signatures checked against a known key, sender keys and ed25519
+        uint32 digests; // eip-712 digests, one per tx and one per signed run
+        uint64 hashed_bytes; // input bytes hashed into input_hash, inflated
+        uint64 inflated_bytes; // zstd output, 0 for a raw input
+        uint32 delta_updates; // state delta writes, 3 per transfer, 2 per withdrawal or deposit
+    }
+    /// Identifies the guest behind a proof, so a consumer can tell outputs of different
+    /// programs and revisions apart
+    #[derive(Debug, PartialEq)]
+    struct Program {
+        bytes32 domain_tag; // DOMAIN_TAG, what the proof is about whatever the program
+        uint32 version; // PROGRAM_VERSION, the guest revision, a contract rejects stale ones
     }
     /// The public values encoded as a struct that can be easily deserialized inside Solidity.
     struct PublicValuesStruct {
-        StateDelta[] n;
+        uint8 status; // 0 if the batch is valid, otherwise ProcessError::code and n is empty
+        Program program; // which program committed these values
+        bytes32 domain_separator; // eip-712 domain the txs were signed under, checked onchain
+        uint64 chain_id; // of the domain, from the input header
+        address verifying_contract; // of the domain, the settlement contract the batch is for
+        uint64 batch_nonce; // the contract applies batches in order, each nonce once
+        bytes32 memo_hash; // Processed::memo_hash if the host opts in, otherwise zero
+        bytes32 tx_root; // merkle root of the tx digests, a sender proves inclusion against it
+        bytes32 receipt_root; // Processed::receipt_root if the host opts in, otherwise zero
+        bytes32 balances_hash; // Processed::balances_hash, the balances solvent was checked against
+        uint64 total_fee; // sum of tx fees across tokens, per token it's the fee sink deltas
+        uint32 fee_txs; // signed txs total_fee was charged over, deposits pay no fee
+        uint64 timestamp; // batch timestamp every tx valid_until was checked against
+        uint8 transfer_policy; // TransferPolicy::flags, which colliding recipients were accepted
+        uint8 sig_scheme; // SigScheme the txs were signed with, ed25519 signers are keccak(pubkey)
+        uint64 min_atoms; // every signed tx moved at least this many atoms
+        bytes32 input_hash; // keccak of the serialized Input, or of the part read before a rejection
+        Metering metering; // Processed::metering if the host opts in, otherwise zero
+        uint8 delta_order; // DELTA_ORDER, the rule n is sorted by
+        bytes skipped; // Processed::skipped, empty unless the host opted in to skipping
+        StateDelta[] n; // empty if packed_n is used
+        bytes packed_n; // n in the packed::pack layout, when the host asks for it
+        Deposit[] deposits; // credited in n, each has to match a deposit of the contract
+        Withdrawal[] withdrawals; // debited in n, for the contract to pay out
+    }
+    /// What the aggregation program commits, one proof settling many batches onchain
+    struct AggregateValuesStruct {
+        Program program; // AGGREGATE_DOMAIN_TAG and PROGRAM_VERSION of the aggregation program
+        bytes32 batch_vkey; // vkey hash_u32 of the batch program, its words big endian
+        bytes[] batches; // PublicValuesStruct of every batch proof verified, in order
     }
 }
 
+/// a valid batch
+#[derive(Debug)]
+pub struct Processed {
+    /// sorted by `DELTA_ORDER`
+    pub n: Vec<StateDelta>,
+    pub total_fee: u64,
+    /// transfers and withdrawals, each paid its fee into `total_fee`
+    pub fee_txs: u32,
+    pub timestamp: u64,
+    pub transfer_policy: TransferPolicy,
+    pub min_atoms: u64,
+    pub sig_scheme: SigScheme,
+    /// from the header, every signature was checked under it
+    pub domain: Eip712Domain,
+    pub batch_nonce: u64,
+    /// keccak256(`InputToSer::ser`), hashed chunk by chunk.
+    /// compressed, the txs are hashed inflated, so it is the same as for the raw input
+    /// but for the header flag
+    pub input_hash: [u8; 32],
+    /// keccak256 over (tx u32 ‖ memo_len u8 ‖ memo) of every tx with a memo, in input order,
+    /// zero if none has one. lets a payee check its invoice ids without the whole input
+    pub memo_hash: [u8; 32],
+    /// `merkle` root over the eip-712 digest of every tx in input order, deposits included
+    pub tx_root: [u8; 32],
+    /// `merkle` root over `Receipt::leaf` of every tx in input order,
+    /// zero unless `BatchProcessor::receipts` was called
+    pub receipt_root: [u8; 32],
+    /// keccak256(`PriorBalances::ser`), zero without prior balances
+    pub balances_hash: [u8; 32],
+    /// in skip mode bit (i % 8) of byte (i / 8) is set if tx i was skipped, see
+    /// `BatchProcessor::skip_invalid`. empty otherwise
+    pub skipped: Vec<u8>,
+    pub metering: Metering,
+    pub deposits: Vec<Deposit>,
+    pub withdrawals: Vec<Withdrawal>,
+}
+
 pub struct StateDiff {
-    pub a: Option<[u8; 20]>,
-    pub nonces: Option<(u64, u64)>,
-    pub v: i64,
+    /// (address, token)
+    pub a: Option<([u8; 20], [u8; 20])>,
+    /// set by the first tx sent from this delta, credits land regardless of the channel
+    pub channel: Option<u32>,
+    /// (nonce, tx) of every tx sent from this delta, in input order
+    pub nonces: Vec<(u64, u32)>,
+    /// every add is checked, a batch can't wrap it
+    pub v: i128,
+    /// tx fees credited to this delta as a fee sink, the header `FeeSplit`s are paid out of it
+    pub fees: u64,
+    /// (address, key) from `SenderKeys`, signatures sent from here are verified against the key
+    pub signer: Option<([u8; 20], VerifyingKey)>,
+    /// from `PriorBalances`, bounds the net debit of the (address, token)
+    pub balance: Option<u128>,
 }
 
+/// (address, token, channel) of a delta
+type DeltaKey = ([u8; 20], [u8; 20], u32);
+
 fn pubk_to_adr(pubk: &[u8]) -> [u8; 20] {
     debug_assert_eq!(pubk[0], 0x04);
     let mut s = tiny_keccak::Keccak::v256();
@@ -34,17 +168,366 @@ fn pubk_to_adr(pubk: &[u8]) -> [u8; 20] {
     out[12..].try_into().expect("must be 20 bytes")
 }
 
-fn recover<'a>(tx: &Tx<'a>, digest: &[u8; 32]) -> [u8; 20] {
-    let s = k256::ecdsa::Signature::from_scalars(tx.sig_r(), tx.sig_s()).unwrap();
-    let rec =
-        VerifyingKey::recover_from_prehash(digest, &s, RecoveryId::from_byte(tx.v()).unwrap())
-            .unwrap();
-    let pubk = rec.to_encoded_point(false);
-    pubk_to_adr(pubk.as_bytes())
+/// the address of an ed25519 signer, keccak of the compressed point as for a secp256k1 key
+pub fn ed25519_to_adr(pubkey: &[u8; 32]) -> [u8; 20] {
+    let mut s = tiny_keccak::Keccak::v256();
+    s.update(pubkey);
+    let mut out = [0; 32];
+    s.finalize(&mut out);
+    out[12..].try_into().expect("must be 20 bytes")
+}
+
+/// why a signature is rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigError {
+    /// v has to be 0 or 1, 2/3 (x reduced mod n) never come out of a wallet.
+    /// ed25519 has nothing to recover, v has to be 0
+    RecoveryId(u8),
+    /// s above n/2, the (r, n - s) twin of a valid signature, see BIP-62.
+    /// for ed25519 s not reduced mod l, the (R, s + l) twin
+    HighS,
+    /// r/s out of range or no key recovers, or the ed25519 equation does not hold
+    Invalid,
 }
 
-fn apply_delta(deltas: &mut [StateDiff], idx: u32, addr: [u8; 20], atoms_delta: i64) {
-    let delta = &mut deltas[idx as usize];
+pub fn key_to_adr(key: &VerifyingKey) -> [u8; 20] {
+    pubk_to_adr(key.to_encoded_point(false).as_bytes())
+}
+
+/// v in range and s low, shared by recovery and verification against a known key
+fn parse_sig(
+    sig_r: [u8; 32],
+    sig_s: [u8; 32],
+    v: u8,
+) -> Result<(k256::ecdsa::Signature, RecoveryId), SigError> {
+    if v > 1 {
+        return Err(SigError::RecoveryId(v));
+    }
+    let rid = RecoveryId::from_byte(v).ok_or(SigError::RecoveryId(v))?;
+    let s = k256::ecdsa::Signature::from_scalars(sig_r, sig_s).map_err(|_| SigError::Invalid)?;
+    // Some if s was high
+    if s.normalize_s().is_some() {
+        return Err(SigError::HighS);
+    }
+    Ok((s, rid))
+}
+
+/// recover the signer key of `digest`, every tx has exactly one accepted encoding
+pub fn recover_key(
+    sig_r: [u8; 32],
+    sig_s: [u8; 32],
+    v: u8,
+    digest: &[u8; 32],
+) -> Result<VerifyingKey, SigError> {
+    let (s, rid) = parse_sig(sig_r, sig_s, v)?;
+    VerifyingKey::recover_from_prehash(digest, &s, rid).map_err(|_| SigError::Invalid)
+}
+
+/// recover the signer address of `digest`, every tx has exactly one accepted encoding
+pub fn recover_checked(
+    sig_r: [u8; 32],
+    sig_s: [u8; 32],
+    v: u8,
+    digest: &[u8; 32],
+) -> Result<[u8; 20], SigError> {
+    recover_key(sig_r, sig_s, v, digest).map(|x| key_to_adr(&x))
+}
+
+/// checks a signature of `digest` against a known signer, cheaper than recovering it.
+/// v is only range checked, verifying does not tell which of 0/1 recovers the key
+pub fn verify_checked(
+    key: &VerifyingKey,
+    sig_r: [u8; 32],
+    sig_s: [u8; 32],
+    v: u8,
+    digest: &[u8; 32],
+) -> Result<(), SigError> {
+    let (s, _) = parse_sig(sig_r, sig_s, v)?;
+    key.verify_prehash(digest, &s)
+        .map_err(|_| SigError::Invalid)
+}
+
+/// Checks an ed25519 signature (R = `sig_r`, S = `sig_s`) over the eip-712 `digest`,
+/// cofactorless `[S]B == R + [H(R ‖ A ‖ digest)]A` as in RFC 8032 with a canonical S,
+/// so the signature has exactly one accepted encoding. returns the signer address
+pub fn verify_ed25519(
+    pubkey: &[u8; 32],
+    sig_r: [u8; 32],
+    sig_s: [u8; 32],
+    v: u8,
+    digest: &[u8; 32],
+) -> Result<[u8; 20], SigError> {
+    if v != 0 {
+        return Err(SigError::RecoveryId(v));
+    }
+    let a = CompressedEdwardsY(*pubkey)
+        .decompress()
+        .ok_or(SigError::Invalid)?;
+    // a small order key verifies signatures over any message
+    if a.is_small_order() {
+        return Err(SigError::Invalid);
+    }
+    let s = Option::<Scalar>::from(Scalar::from_canonical_bytes(sig_s)).ok_or(SigError::HighS)?;
+    let mut h = Sha512::new();
+    h.update(sig_r);
+    h.update(pubkey);
+    h.update(digest);
+    let k = Scalar::from_bytes_mod_order_wide(&h.finalize().into());
+    // [S]B - [k]A, compared compressed so R never has to be decompressed
+    let r = EdwardsPoint::vartime_double_scalar_mul_basepoint(&k, &-a, &s);
+    if r.compress().0 != sig_r {
+        return Err(SigError::Invalid);
+    }
+    Ok(ed25519_to_adr(pubkey))
+}
+
+/// the signer address of `digest` under the batch `scheme`, ed25519 needs the tx pubkey
+pub fn signer_checked(
+    scheme: SigScheme,
+    sig_r: [u8; 32],
+    sig_s: [u8; 32],
+    v: u8,
+    pubkey: Option<[u8; 32]>,
+    digest: &[u8; 32],
+) -> Result<[u8; 20], SigError> {
+    match (scheme, pubkey) {
+        (SigScheme::Secp256k1, None) => recover_checked(sig_r, sig_s, v, digest),
+        (SigScheme::Ed25519, Some(pubkey)) => verify_ed25519(&pubkey, sig_r, sig_s, v, digest),
+        _ => Err(SigError::Invalid),
+    }
+}
+
+/// recover the signer address of `digest`, `None` if the signature is malformed
+pub fn try_recover(sig_r: [u8; 32], sig_s: [u8; 32], v: u8, digest: &[u8; 32]) -> Option<[u8; 20]> {
+    recover_checked(sig_r, sig_s, v, digest).ok()
+}
+
+/// Why a batch can't be settled, the program commits `code()` instead of panicking
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessError {
+    /// the signer could not be recovered
+    InvalidSignature { tx: u32 },
+    /// the program requires `atoms > fee`
+    AtomsNotAboveFee { tx: u32, atoms: i128 },
+    /// from_idx/to_idx points past `state_deltas`
+    DeltaIdxOutOfRange { tx: u32, idx: u32 },
+    /// the delta at idx is already used by a different (address, token),
+    /// or sent from on a different channel
+    DeltaAddrMismatch { tx: u32, idx: u32 },
+    /// sender nonces, once sorted, have to be spaced as the header `NoncePolicy` allows
+    NonceGap { tx: u32, expected: u64, got: u64 },
+    /// a declared state delta was never touched by any tx
+    UnusedDelta { idx: u32 },
+    /// the input buffer is truncated or has trailing bytes
+    MalformedInput(InputError),
+    /// the batch timestamp is past the tx `valid_until`
+    Expired { tx: u32, valid_until: u64 },
+    /// kind is not a `TxKind`
+    UnknownKind { tx: u32, kind: u8 },
+    /// malleable signature, s above n/2
+    HighS { tx: u32 },
+    /// v is not 0 or 1
+    BadRecoveryId { tx: u32, v: u8 },
+    /// transfer to the signer, not allowed by the header `TransferPolicy` or not to `from_idx`
+    SelfTransfer { tx: u32 },
+    /// transfer to the fee recipient, not allowed by the header `TransferPolicy` or not to `fee_idx`
+    PaysFeeRecipient { tx: u32 },
+    /// signed tx below the header `min_atoms`
+    BelowMinAtoms { tx: u32, atoms: i128 },
+    /// two deltas ended up with the same (address, token, channel), ex. to replay nonces
+    /// by splitting them across slots. `idx` is the later of the two
+    DuplicateDelta { idx: u32, other: u32 },
+    /// memo longer than `TxToSer::MAX_MEMO` or not zero padded
+    BadMemo { tx: u32, len: u8 },
+    /// the net delta at idx does not fit an int128, ex. a whale receiving many near max
+    /// transfers. idx may be past `state_deltas` for a delta the fee splits appended
+    DeltaOverflow { idx: u32 },
+    /// a run of txs that joins the signature of the next signed tx, see `RunHasher`,
+    /// is cut by a deposit or the end of the batch. `tx` is the first of the run
+    OpenRun { tx: u32 },
+    /// the net delta of the (address, token) of delta idx, all channels, takes more than its
+    /// `PriorBalances` balance
+    Insolvent {
+        idx: u32,
+        balance: u128,
+        delta: i128,
+    },
+}
+
+impl ProcessError {
+    /// down to a single tx and not the host that put the batch together,
+    /// see `BatchProcessor::skip_invalid`
+    pub fn skippable(&self) -> bool {
+        matches!(
+            self,
+            Self::InvalidSignature { .. }
+                | Self::HighS { .. }
+                | Self::BadRecoveryId { .. }
+                | Self::Expired { .. }
+                | Self::AtomsNotAboveFee { .. }
+                | Self::BelowMinAtoms { .. }
+                | Self::SelfTransfer { .. }
+                | Self::PaysFeeRecipient { .. }
+                | Self::NonceGap { .. }
+        )
+    }
+
+    /// committed as `PublicValuesStruct::status`, 0 is reserved for success
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::InvalidSignature { .. } => 1,
+            Self::AtomsNotAboveFee { .. } => 2,
+            Self::DeltaIdxOutOfRange { .. } => 3,
+            Self::DeltaAddrMismatch { .. } => 4,
+            Self::NonceGap { .. } => 5,
+            Self::UnusedDelta { .. } => 6,
+            Self::MalformedInput(_) => 7,
+            Self::Expired { .. } => 8,
+            Self::UnknownKind { .. } => 9,
+            Self::HighS { .. } => 10,
+            Self::BadRecoveryId { .. } => 11,
+            Self::SelfTransfer { .. } => 12,
+            Self::PaysFeeRecipient { .. } => 13,
+            Self::DuplicateDelta { .. } => 14,
+            Self::BelowMinAtoms { .. } => 15,
+            Self::BadMemo { .. } => 16,
+            Self::DeltaOverflow { .. } => 17,
+            Self::OpenRun { .. } => 18,
+            Self::Insolvent { .. } => 19,
+        }
+    }
+}
+
+impl std::fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSignature { tx } => write!(f, "tx {tx} has invalid signature"),
+            Self::AtomsNotAboveFee { tx, atoms } => {
+                write!(f, "tx {tx} atoms={atoms} not above its fee")
+            }
+            Self::DeltaIdxOutOfRange { tx, idx } => {
+                write!(f, "tx {tx} delta idx={idx} out of range")
+            }
+            Self::DeltaAddrMismatch { tx, idx } => {
+                write!(
+                    f,
+                    "tx {tx} delta idx={idx} belongs to another address/token/channel"
+                )
+            }
+            Self::NonceGap { tx, expected, got } => {
+                write!(f, "tx {tx} nonce={got} expected={expected}")
+            }
+            Self::UnusedDelta { idx } => write!(f, "state delta idx={idx} never used"),
+            Self::MalformedInput(e) => write!(f, "malformed input, {e}"),
+            Self::Expired { tx, valid_until } => {
+                write!(f, "tx {tx} expired at valid_until={valid_until}")
+            }
+            Self::UnknownKind { tx, kind } => write!(f, "tx {tx} has unknown kind={kind}"),
+            Self::HighS { tx } => write!(f, "tx {tx} signature has high s"),
+            Self::BadRecoveryId { tx, v } => write!(f, "tx {tx} has bad recovery id v={v}"),
+            Self::SelfTransfer { tx } => write!(f, "tx {tx} self transfer rejected"),
+            Self::PaysFeeRecipient { tx } => {
+                write!(f, "tx {tx} transfer to the fee recipient rejected")
+            }
+            Self::BelowMinAtoms { tx, atoms } => {
+                write!(f, "tx {tx} atoms={atoms} below the batch minimum")
+            }
+            Self::DuplicateDelta { idx, other } => {
+                write!(f, "state delta idx={idx} duplicates idx={other}")
+            }
+            Self::BadMemo { tx, len } => write!(f, "tx {tx} has malformed memo len={len}"),
+            Self::DeltaOverflow { idx } => write!(f, "state delta idx={idx} overflows int128"),
+            Self::OpenRun { tx } => write!(f, "tx {tx} joins a run that is never signed"),
+            Self::Insolvent {
+                idx,
+                balance,
+                delta,
+            } => {
+                write!(
+                    f,
+                    "state delta idx={idx} net {delta} exceeds the prior balance {balance}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProcessError {}
+
+impl From<InputError> for ProcessError {
+    fn from(e: InputError) -> Self {
+        Self::MalformedInput(e)
+    }
+}
+
+/// A batch the program can't settle, committed against the input it read up to the failure so
+/// the proof is about that input and no other
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejected {
+    pub error: ProcessError,
+    /// `BatchProcessor::input_hash` at the failure, the header keccak if there was no processor
+    pub input_hash: [u8; 32],
+}
+
+impl Rejected {
+    /// failed before a `BatchProcessor` could start on `header`
+    pub fn header(header: &[u8], error: impl Into<ProcessError>) -> Self {
+        let mut input_hash = [0; 32];
+        let mut h = Keccak::v256();
+        h.update(header);
+        h.finalize(&mut input_hash);
+        Self {
+            error: error.into(),
+            input_hash,
+        }
+    }
+}
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (input 0x", self.error)?;
+        for b in self.input_hash {
+            write!(f, "{b:02x}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl SigError {
+    /// the rejection of the signature of tx `offset`
+    pub fn at_tx(self, offset: u32) -> ProcessError {
+        match self {
+            Self::RecoveryId(v) => ProcessError::BadRecoveryId { tx: offset, v },
+            Self::HighS => ProcessError::HighS { tx: offset },
+            Self::Invalid => ProcessError::InvalidSignature { tx: offset },
+        }
+    }
+}
+
+fn recover<'a>(
+    scheme: SigScheme,
+    tx: &Tx<'a>,
+    offset: u32,
+    digest: &[u8; 32],
+) -> Result<[u8; 20], ProcessError> {
+    signer_checked(scheme, tx.sig_r(), tx.sig_s(), tx.v(), tx.pubkey(), digest)
+        .map_err(|e| e.at_tx(offset))
+}
+
+fn delta_at(deltas: &mut [StateDiff], tx: u32, idx: u32) -> Result<&mut StateDiff, ProcessError> {
+    deltas
+        .get_mut(idx as usize)
+        .ok_or(ProcessError::DeltaIdxOutOfRange { tx, idx })
+}
+
+fn apply_delta(
+    deltas: &mut [StateDiff],
+    tx: u32,
+    idx: u32,
+    addr: ([u8; 20], [u8; 20]),
+    atoms_delta: i128,
+) -> Result<(), ProcessError> {
+    let delta = delta_at(deltas, tx, idx)?;
     match delta.a {
         None => {
             // first time touching this delta
@@ -52,112 +535,2508 @@ fn apply_delta(deltas: &mut [StateDiff], idx: u32, addr: [u8; 20], atoms_delta:
             delta.v = atoms_delta;
         }
         Some(d) => {
-            assert!(d == addr); // need to be modifying same
-            delta.v += atoms_delta;
+            // need to be modifying same
+            if d != addr {
+                return Err(ProcessError::DeltaAddrMismatch { tx, idx });
+            }
+            delta.v = delta
+                .v
+                .checked_add(atoms_delta)
+                .ok_or(ProcessError::DeltaOverflow { idx })?;
         }
     }
+    Ok(())
 }
 
 fn apply_sender_delta(
     deltas: &mut [StateDiff],
+    tx: u32,
     idx: u32,
-    addr: [u8; 20],
-    nonce: u64,
-    atoms_delta: i64,
-) {
-    let delta = &mut deltas[idx as usize];
+    addr: ([u8; 20], [u8; 20]),
+    (nonce, channel): (u64, u32),
+    atoms_delta: i128,
+) -> Result<(), ProcessError> {
+    let delta = delta_at(deltas, tx, idx)?;
+    // one nonce sequence per delta, a second channel needs a delta of its own
+    if *delta.channel.get_or_insert(channel) != channel {
+        return Err(ProcessError::DeltaAddrMismatch { tx, idx });
+    }
     match delta.a {
         None => {
             // first time touching this delta
             delta.a = Some(addr);
-            delta.nonces = Some((nonce, nonce));
+            delta.nonces.push((nonce, tx));
             delta.v = atoms_delta;
         }
         Some(d) => {
             // need to be modifying same
-            assert!(d == addr);
-            // ex. alice received some payment so addr is set,
-            // but then alice sent something so nonces are not set yet
-            match &mut delta.nonces {
-                Some((_, end)) => {
-                    // enforce strict +1 increments
-                    assert!((*end + 1) == nonce);
-                    *end = nonce;
-                }
-                None => {
-                    delta.nonces = Some((nonce, nonce));
-                }
+            if d != addr {
+                return Err(ProcessError::DeltaAddrMismatch { tx, idx });
             }
-            delta.v += atoms_delta;
+            // ordering is only checked in `nonce_range` once the whole batch is in,
+            // so the host does not have to pre-sort the txs by nonce
+            delta.nonces.push((nonce, tx));
+            delta.v = delta
+                .v
+                .checked_add(atoms_delta)
+                .ok_or(ProcessError::DeltaOverflow { idx })?;
+        }
+    }
+    Ok(())
+}
+
+/// Sorts the nonces sent from one delta and checks their spacing against `policy`,
+/// a duplicate (replayed) nonce is rejected by every policy.
+/// On a gap the offending tx is the one with the higher nonce, ties broken by input position.
+pub(crate) fn nonce_range(
+    nonces: &mut [(u64, u32)],
+    policy: NoncePolicy,
+) -> Result<Option<(u64, u64)>, ProcessError> {
+    nonces.sort_unstable();
+    for w in nonces.windows(2) {
+        let ((prev, _), (got, tx)) = (w[0], w[1]);
+        if !policy.accepts(prev, got) {
+            return Err(ProcessError::NonceGap {
+                tx,
+                expected: prev.saturating_add(1),
+                got,
+            });
         }
     }
+    Ok(nonces.first().zip(nonces.last()).map(|(s, e)| (s.0, e.0)))
 }
 
-pub fn process_txs(v: &[u8]) -> Vec<StateDelta> {
-    let inp = Input { v };
-    let sdl = inp.state_deltas() as usize;
-    let mut deltas = Vec::with_capacity(sdl);
-    let fee_recipient: [u8; 20] = inp.fee_recipient().try_into().unwrap();
-    // first state diff is fee sink
-    deltas.push(StateDiff {
-        a: Some(fee_recipient),
-        nonces: None,
-        v: 0,
+/// The idx hints are host supplied, so the same (address, token, channel) can be spread over
+/// several slots, each passing its own checks. Rebuilds the key order and rejects any repeat.
+fn check_unique(n: &[StateDelta]) -> Result<(), ProcessError> {
+    let mut order: Vec<u32> = (0..n.len() as u32).collect();
+    order.sort_unstable_by_key(|&i| {
+        let d = &n[i as usize];
+        (d.v, d.token, d.channel, i)
     });
-    for _ in 1..sdl {
-        deltas.push(StateDiff {
-            a: None,
-            nonces: None,
-            v: 0,
-        });
+    for w in order.windows(2) {
+        let (a, b) = (&n[w[0] as usize], &n[w[1] as usize]);
+        if (a.v, a.token, a.channel) == (b.v, b.token, b.channel) {
+            return Err(ProcessError::DuplicateDelta {
+                idx: w[1],
+                other: w[0],
+            });
+        }
     }
+    Ok(())
+}
 
-    let mut digest = [0; 32]; // reuse buff
-    let fee_atoms = inp.fee_atoms() as i64;
-    assert!(fee_atoms >= 0);
-    let total_tx = inp.total_tx();
-    for offset in 0..total_tx {
-        println!("cycle-tracker-start: apply_tx");
-        let tx = inp.tx_at(offset);
-        // 1. hash the tx
+/// Every channel of an (address, token) draws on its one onchain balance, so the bound is on
+/// their net. `balances` are (delta idx, balance) from `PriorBalances`
+fn check_solvent(n: &mut [StateDelta], balances: &[(usize, u128)]) -> Result<(), ProcessError> {
+    if balances.is_empty() {
+        return Ok(());
+    }
+    let mut net = BTreeMap::new();
+    for (idx, d) in n.iter().enumerate() {
+        let x: &mut i128 = net.entry((d.v, d.token)).or_default();
+        *x = x
+            .checked_add(d.delta)
+            .ok_or(ProcessError::DeltaOverflow { idx: idx as u32 })?;
+    }
+    let mut covered = BTreeMap::new();
+    for &(idx, balance) in balances {
+        let key = (n[idx].v, n[idx].token);
+        if *covered.entry(key).or_insert(balance) != balance {
+            return Err(InputError::BadPriorBalance { idx: idx as u32 }.into());
+        }
+        let delta = net[&key];
+        if delta < 0 && delta.unsigned_abs() > balance {
+            return Err(ProcessError::Insolvent {
+                idx: idx as u32,
+                balance,
+                delta,
+            });
+        }
+    }
+    for d in n.iter_mut() {
+        d.solvent = covered.contains_key(&(d.v, d.token));
+    }
+    Ok(())
+}
+
+/// `Program::version`, bumped with every guest change a consumer of the public
+/// values has to tell apart, so a contract can reject proofs of a stale program
+pub const PROGRAM_VERSION: u32 = 1;
+
+/// `Program::domain_tag`, `bytes32("ddm.coproc.batch")` in Solidity. Separates these
+/// public values from the output of any other program verified under the same verifier
+pub const DOMAIN_TAG: [u8; 32] = tag(b"ddm.coproc.batch");
+
+impl Program {
+    /// this program
+    pub fn current() -> Self {
+        Self {
+            domain_tag: DOMAIN_TAG.into(),
+            version: PROGRAM_VERSION,
+        }
+    }
+
+    /// the aggregation program
+    pub fn aggregate() -> Self {
+        Self {
+            domain_tag: AGGREGATE_DOMAIN_TAG.into(),
+            version: PROGRAM_VERSION,
+        }
+    }
+}
+
+/// `Program::domain_tag` of the aggregation program, `bytes32("ddm.coproc.aggregate")`
+pub const AGGREGATE_DOMAIN_TAG: [u8; 32] = tag(b"ddm.coproc.aggregate");
+
+/// what the aggregation program commits once it verified a proof of the batch program with
+/// `batch_vkey` for each of `batches`
+pub fn aggregate_values(batch_vkey: &[u32; 8], batches: Vec<Vec<u8>>) -> AggregateValuesStruct {
+    let mut vkey = [0; 32];
+    for (i, w) in batch_vkey.iter().enumerate() {
+        vkey[4 * i..4 * i + 4].copy_from_slice(&w.to_be_bytes());
+    }
+    AggregateValuesStruct {
+        program: Program::aggregate(),
+        batch_vkey: vkey.into(),
+        batches: batches.into_iter().map(Into::into).collect(),
+    }
+}
+
+/// `s` left aligned and zero padded, the way Solidity casts a string literal to bytes32
+const fn tag(s: &[u8]) -> [u8; 32] {
+    let mut out = [0; 32];
+    let mut i = 0;
+    while i < s.len() {
+        out[i] = s[i];
+        i += 1;
+    }
+    out
+}
+
+/// `PublicValuesStruct::delta_order`: the fee recipient deltas first, then every delta by
+/// ascending (v, token, channel), unique per `check_unique`. Independent of the host indices,
+/// so the contract can binary search n or diff it across proofs
+pub const DELTA_ORDER: u8 = 1;
+
+fn sort_deltas(n: &mut [StateDelta], fee_recipient: &[u8; 20]) {
+    n.sort_unstable_by_key(|d| (d.v.as_slice() != fee_recipient, d.v, d.token, d.channel));
+}
+
+/// Moves each split's share of the fees collected at a fee sink delta to its recipient.
+/// The share is credited to a delta of the same (recipient, token) if the batch has one,
+/// otherwise the recipient gets a new delta appended after the host indexed ones.
+fn split_fees(
+    n: &mut Vec<StateDelta>,
+    fees: &[(usize, u64)],
+    splits: &[FeeSplit],
+) -> Result<(), ProcessError> {
+    let overflow = |idx: usize| ProcessError::DeltaOverflow { idx: idx as u32 };
+    for &(idx, collected) in fees {
+        let (token, nonce_policy) = (n[idx].token, n[idx].nonce_policy);
+        for split in splits {
+            // at most the collected fees, the u32 fees of u32 txs
+            let share = split.share(collected) as i128;
+            if share == 0 {
+                continue;
+            }
+            n[idx].delta = n[idx].delta.checked_sub(share).ok_or(overflow(idx))?;
+            let recipient = split.recipient.into();
+            match n.iter().position(|d| d.v == recipient && d.token == token) {
+                Some(i) => n[i].delta = n[i].delta.checked_add(share).ok_or(overflow(i))?,
+                None => n.push(StateDelta {
+                    v: recipient,
+                    token,
+                    is_sender: false,
+                    start_nonce: 0,
+                    end_nonce: 0,
+                    channel: 0,
+                    nonce_policy,
+                    delta: share,
+                    solvent: false,
+                }),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// panics on an invalid batch, see `process_txs_checked`
+pub fn process_txs(v: &[u8]) -> Processed {
+    process_txs_checked(v).unwrap()
+}
+
+/// the eip-712 domain the txs are checked against is the one in the header
+pub fn process_txs_checked(v: &[u8]) -> Result<Processed, ProcessError> {
+    process_txs_keyed(v, &[])
+}
+
+/// `keys` is a serialized `SenderKeys`, see `BatchProcessor::sender_keys`
+pub fn process_txs_keyed(v: &[u8], keys: &[u8]) -> Result<Processed, ProcessError> {
+    process_txs_committed(v, keys).map_err(|r| r.error)
+}
+
+/// `process_txs_keyed`, a failure comes with the input hash the program commits it against
+pub fn process_txs_committed(v: &[u8], keys: &[u8]) -> Result<Processed, Rejected> {
+    let header = &v[..v.len().min(Input::HEADER_SIZE)];
+    let reject = |e: InputError| Rejected::header(header, e);
+    if header.len() < Input::HEADER_SIZE {
+        return Err(reject(InputError::TooShort { len: v.len() }));
+    }
+    if !Input::new(header).try_compressed().map_err(reject)? {
+        // the exact length is only known up front for raw txs
+        Input::try_new(v).map_err(reject)?;
+    }
+    let mut p = BatchProcessor::new(header).map_err(|e| Rejected::header(header, e))?;
+    let txs = &v[Input::HEADER_SIZE..];
+    let fed = p.sender_keys(keys).and_then(|()| match txs.is_empty() {
+        true => Ok(()),
+        false => p.process_chunk(txs),
+    });
+    p.finish_or_reject(fed)
+}
+
+/// what the program commits for the outcome `r` of the batch with `header`, `packed`, `memos`
+/// and `metering` are the host flags of the program. An invalid batch still proves, but commits
+/// the failure code so the host learns why, and the hash of the input it failed on
+pub fn public_values(
+    r: Result<Processed, Rejected>,
+    header: &[u8],
+    packed: bool,
+    memos: bool,
+    metering: bool,
+) -> PublicValuesStruct {
+    match r {
+        Ok(Processed {
+            n,
+            total_fee,
+            fee_txs,
+            timestamp,
+            transfer_policy,
+            min_atoms,
+            sig_scheme,
+            domain,
+            batch_nonce,
+            input_hash,
+            memo_hash,
+            tx_root,
+            receipt_root,
+            balances_hash,
+            metering: m,
+            skipped,
+            deposits,
+            withdrawals,
+        }) => PublicValuesStruct {
+            status: 0,
+            program: Program::current(),
+            domain_separator: domain.separator().into(),
+            chain_id: domain.chain_id,
+            verifying_contract: domain.verifying_contract.into(),
+            batch_nonce,
+            total_fee,
+            fee_txs,
+            timestamp,
+            transfer_policy: transfer_policy.flags(),
+            sig_scheme: sig_scheme as u8,
+            min_atoms,
+            input_hash: input_hash.into(),
+            memo_hash: if memos { memo_hash } else { [0; 32] }.into(),
+            tx_root: tx_root.into(),
+            receipt_root: receipt_root.into(),
+            balances_hash: balances_hash.into(),
+            metering: if metering { m } else { Metering::default() },
+            delta_order: DELTA_ORDER,
+            skipped: skipped.into(),
+            packed_n: if packed {
+                packed::pack(&n).into()
+            } else {
+                vec![].into()
+            },
+            n: if packed { vec![] } else { n },
+            deposits,
+            withdrawals,
+        },
+        Err(Rejected { error, input_hash }) => {
+            // the domain and batch nonce are still committed when the header is long enough to carry them
+            let (domain, batch_nonce) = if header.len() >= Input::HEADER_SIZE {
+                let inp = Input::new(header);
+                (inp.domain(), inp.batch_nonce())
+            } else {
+                let domain = Eip712Domain {
+                    chain_id: 0,
+                    verifying_contract: [0; 20],
+                };
+                (domain, 0)
+            };
+            PublicValuesStruct {
+                status: error.code(),
+                program: Program::current(),
+                domain_separator: domain.separator().into(),
+                chain_id: domain.chain_id,
+                verifying_contract: domain.verifying_contract.into(),
+                batch_nonce,
+                total_fee: 0,
+                fee_txs: 0,
+                timestamp: 0,
+                transfer_policy: 0,
+                sig_scheme: 0,
+                min_atoms: 0,
+                input_hash: input_hash.into(),
+                memo_hash: [0; 32].into(),
+                tx_root: [0; 32].into(),
+                receipt_root: [0; 32].into(),
+                balances_hash: [0; 32].into(),
+                metering: Metering::default(),
+                skipped: vec![].into(),
+                delta_order: DELTA_ORDER,
+                n: vec![],
+                packed_n: vec![].into(),
+                deposits: vec![],
+                withdrawals: vec![],
+            }
+        }
+    }
+}
+
+/// Applies a batch chunk by chunk, so the program only ever holds one chunk of txs
+/// (plus the state deltas) in memory. Chunks share the delta arr, the idx helpers stay global.
+pub struct BatchProcessor {
+    domain: Eip712Domain,
+    /// `domain` separator, computed once per batch
+    domain_separator: [u8; 32],
+    fee_recipient: [u8; 20],
+    timestamp: u64,
+    total_tx: u32,
+    nonce_policy: NoncePolicy,
+    compressed: bool,
+    fee_splits: Vec<FeeSplit>,
+    transfer_policy: TransferPolicy,
+    min_atoms: u64,
+    batch_nonce: u64,
+    sig_scheme: SigScheme,
+    /// `sig_scheme` tx size, chunks are split by it
+    tx_size: usize,
+    /// global idx of the next tx
+    next_tx: u32,
+    deltas: Vec<StateDiff>,
+    total_fee: u64,
+    fee_txs: u32,
+    digest: [u8; 32], // reuse buff
+    /// over the header and every (inflated) chunk so far, the chunks concat back into the input
+    input_hash: Keccak,
+    /// `None` until the first memo, see `Processed::memo_hash`
+    memo_hash: Option<Keccak>,
+    tx_tree: MerkleFrontier,
+    /// `None` unless the host asked for receipts
+    receipt_tree: Option<MerkleFrontier>,
+    /// `Processed::skipped`, `None` unless in skip mode
+    skipped: Option<Vec<u8>>,
+    /// (address, token, channel) -> delta idx, `None` unless the program assigns the slots
+    /// itself, see `discover_deltas`
+    slots: Option<BTreeMap<DeltaKey, u32>>,
+    /// see `Processed::balances_hash`
+    balances_hash: [u8; 32],
+    metering: Metering,
+    /// digests of the txs waiting on the signature that closes their run
+    run_hasher: RunHasher,
+    /// those txs with their digests, copied out of their chunk as the next one may be read
+    /// before the run closes
+    run: Vec<([u8; 32], Vec<u8>)>,
+    deposits: Vec<Deposit>,
+    withdrawals: Vec<Withdrawal>,
+}
+
+impl BatchProcessor {
+    /// `header` is exactly the `Input::HEADER_SIZE` leading bytes of the input
+    pub fn new(header: &[u8]) -> Result<Self, ProcessError> {
+        if header.len() != Input::HEADER_SIZE {
+            return Err(InputError::LengthMismatch {
+                expected: Input::HEADER_SIZE,
+                got: header.len(),
+            }
+            .into());
+        }
+        let inp = Input::new(header);
+        let nonce_policy = inp.try_nonce_policy()?;
+        let compressed = inp.try_compressed()?;
+        let fee_splits = inp.try_fee_splits()?;
+        let transfer_policy = inp.try_transfer_policy()?;
+        let sig_scheme = inp.try_sig_scheme()?;
+        let domain = inp.domain();
+        let mut input_hash = Keccak::v256();
+        input_hash.update(header);
+        let sdl = inp.state_deltas() as usize;
+        let mut deltas = Vec::with_capacity(sdl);
+        // the fee sink of each token is a regular delta pointed at by fee_idx
+        for _ in 0..sdl {
+            deltas.push(StateDiff {
+                a: None,
+                channel: None,
+                nonces: vec![],
+                v: 0,
+                fees: 0,
+                signer: None,
+                balance: None,
+            });
+        }
+        Ok(Self {
+            domain,
+            domain_separator: domain.separator(),
+            fee_recipient: inp.fee_recipient().try_into().unwrap(),
+            timestamp: inp.timestamp(),
+            total_tx: inp.total_tx(),
+            nonce_policy,
+            compressed,
+            fee_splits,
+            transfer_policy,
+            min_atoms: inp.min_atoms(),
+            batch_nonce: inp.batch_nonce(),
+            sig_scheme,
+            tx_size: sig_scheme.tx_size(),
+            next_tx: 0,
+            deltas,
+            total_fee: 0,
+            fee_txs: 0,
+            digest: [0; 32],
+            input_hash,
+            memo_hash: None,
+            tx_tree: MerkleFrontier::default(),
+            receipt_tree: None,
+            skipped: None,
+            slots: None,
+            balances_hash: [0; 32],
+            metering: Metering {
+                hashed_bytes: header.len() as u64,
+                ..Default::default()
+            },
+            run_hasher: RunHasher::default(),
+            run: vec![],
+            deposits: vec![],
+            withdrawals: vec![],
+        })
+    }
+
+    /// the serialized `SenderKeys` of the batch, before the first chunk.
+    /// senders without a key are still recovered tx by tx
+    pub fn sender_keys(&mut self, keys: &[u8]) -> Result<(), ProcessError> {
+        let keys = SenderKeys::deser(keys)?;
+        if self.sig_scheme != SigScheme::Secp256k1 && !keys.keys.is_empty() {
+            return Err(InputError::BadSenderKeys {
+                len: keys.keys.len() * SenderKeys::ENTRY_SIZE,
+            }
+            .into());
+        }
+        for (idx, key) in keys.keys {
+            let bad = InputError::BadSenderKey { idx };
+            let delta = match self.deltas.get_mut(idx as usize) {
+                Some(d) if d.signer.is_none() => d,
+                _ => return Err(bad.into()),
+            };
+            let key = VerifyingKey::from_sec1_bytes(&key).map_err(|_| bad)?;
+            delta.signer = Some((key_to_adr(&key), key));
+        }
+        Ok(())
+    }
+
+    /// the serialized `PriorBalances` of the batch, before the first chunk.
+    /// deltas without a balance are not bounded, the contract checks them
+    pub fn prior_balances(&mut self, balances: &[u8]) -> Result<(), ProcessError> {
+        for (idx, balance) in PriorBalances::deser(balances)?.balances {
+            match self.deltas.get_mut(idx as usize) {
+                Some(d) if d.balance.is_none() => d.balance = Some(balance),
+                _ => return Err(InputError::BadPriorBalance { idx }.into()),
+            }
+        }
+        if !balances.is_empty() {
+            let mut h = Keccak::v256();
+            h.update(balances);
+            h.finalize(&mut self.balances_hash);
+        }
+        Ok(())
+    }
+
+    /// ignore the host idx hints of every tx and assign the delta slots in the program,
+    /// before the first chunk. The header `state_deltas` still has to be the number of slots,
+    /// `SenderKeys` and `PriorBalances` stay keyed by them. The `from_idx` hint only picks the
+    /// sender key a signature is checked against, the wrong key fails it
+    pub fn discover_deltas(&mut self) {
+        self.slots = Some(BTreeMap::new());
+    }
+
+    /// skip the txs that fail for a reason of their own (`ProcessError::skippable`) instead of
+    /// rejecting the batch, before the first chunk. A skipped tx touches no delta and has no
+    /// receipt, a slot only skipped txs point at is dropped. The nonces of a sender have to come
+    /// in order, one its policy does not accept after the previous is skipped
+    pub fn skip_invalid(&mut self) {
+        self.skipped = Some(vec![0; (self.total_tx as usize).div_ceil(8)]);
+    }
+
+    /// build `Processed::receipt_root` too, before the first chunk
+    pub fn receipts(&mut self) {
+        self.receipt_tree = Some(MerkleFrontier::default());
+    }
+
+    fn push_receipt(&mut self, digest: [u8; 32], from: [u8; 20], tx: &Tx<'_>, status: TxKind) {
+        if let Some(tree) = &mut self.receipt_tree {
+            let receipt = Receipt {
+                digest,
+                from,
+                to: tx.to().try_into().unwrap(),
+                atoms: tx.atoms(),
+                status,
+            };
+            tree.push(receipt.leaf());
+        }
+    }
+
+    /// verified against the key of the sending delta if the host gave one, recovered otherwise
+    fn signer(
+        &mut self,
+        tx: &Tx<'_>,
+        offset: u32,
+        digest: &[u8; 32],
+    ) -> Result<[u8; 20], ProcessError> {
+        let keyed = self
+            .deltas
+            .get(tx.from_idx() as usize)
+            .and_then(|x| x.signer.as_ref());
+        let Some((adr, key)) = keyed else {
+            match self.sig_scheme {
+                SigScheme::Secp256k1 => self.metering.recoveries += 1,
+                SigScheme::Ed25519 => self.metering.verifications += 1,
+            }
+            return recover(self.sig_scheme, tx, offset, digest);
+        };
+        self.metering.verifications += 1;
+        verify_checked(key, tx.sig_r(), tx.sig_s(), tx.v(), digest).map_err(|e| e.at_tx(offset))?;
+        Ok(*adr)
+    }
+
+    /// txs the header declares that were not processed yet
+    pub fn remaining(&self) -> u32 {
+        self.total_tx - self.next_tx
+    }
+
+    /// `chunk` is one or more whole serialized txs, in batch order,
+    /// a single zstd frame of them if the header says compressed
+    pub fn process_chunk(&mut self, chunk: &[u8]) -> Result<(), ProcessError> {
+        if self.compressed {
+            println!("cycle-tracker-start: decompress");
+            let max_len = (self.remaining() as usize)
+                .checked_mul(self.tx_size)
+                .ok_or(InputError::BadFrame { len: chunk.len() })?;
+            let raw = decompress(chunk, max_len)?;
+            self.metering.inflated_bytes += raw.len() as u64;
+            println!("cycle-tracker-end: decompress");
+            return self.process_raw_chunk(&raw);
+        }
+        self.process_raw_chunk(chunk)
+    }
+
+    fn process_raw_chunk(&mut self, chunk: &[u8]) -> Result<(), ProcessError> {
+        let size = self.tx_size;
+        let txs = chunk.len() / size;
+        if txs == 0 || txs * size != chunk.len() {
+            return Err(InputError::BadChunk { len: chunk.len() }.into());
+        }
+        if txs > self.remaining() as usize {
+            return Err(InputError::LengthMismatch {
+                expected: self.remaining() as usize * size,
+                got: chunk.len(),
+            }
+            .into());
+        }
+        self.input_hash.update(chunk);
+        self.metering.hashed_bytes += chunk.len() as u64;
+        for i in 0..txs {
+            let v = &chunk[i * size..(i + 1) * size];
+            self.apply_tx(&Tx { v })?;
+            self.next_tx += 1;
+        }
+        Ok(())
+    }
+
+    fn apply_tx(&mut self, tx: &Tx<'_>) -> Result<(), ProcessError> {
+        let offset = self.next_tx;
+        let kind = TxKind::from_u8(tx.kind()).ok_or(ProcessError::UnknownKind {
+            tx: offset,
+            kind: tx.kind(),
+        })?;
+        self.hash_memo(tx)?;
+        // 1. hash the tx, every digest is a leaf of the tx tree
         // 2. recover sig addr
         println!("cycle-tracker-start: keccak");
-        tx.keccak(&mut digest);
+        tx.keccak(&self.domain_separator, &mut self.digest);
+        self.metering.digests += 1;
         println!("cycle-tracker-end: keccak");
+        self.tx_tree.push(self.digest);
+        if kind == TxKind::Deposit {
+            if !self.run.is_empty() {
+                return Err(self.open_run());
+            }
+            let v = self.indexed(tx, None);
+            self.apply_deposit(&Tx { v: &v })?;
+            self.push_receipt(self.digest, [0; 20], tx, kind);
+            return Ok(());
+        }
+        if tx.joins_run() {
+            self.run_hasher.update(&self.digest);
+            self.run.push((self.digest, tx.v.to_vec()));
+            return Ok(());
+        }
         println!("cycle-tracker-start: recover");
-        let from = recover(&tx, &digest);
+        let from = if self.run.is_empty() {
+            let digest = self.digest;
+            self.signer(tx, offset, &digest)
+        } else {
+            // one signature over the digests of the whole run
+            self.run_hasher.update(&self.digest);
+            let digest = self.run_hasher.finalize(&self.domain_separator);
+            self.metering.digests += 1;
+            self.signer(tx, offset, &digest)
+        };
         println!("cycle-tracker-end: recover");
+        let run = std::mem::take(&mut self.run);
+        let start = offset - run.len() as u32;
+        let from = match from {
+            Ok(from) => from,
+            // nobody authorized the run
+            Err(e) => {
+                for tx in start..=offset {
+                    self.skip(tx, e.clone())?;
+                }
+                return Ok(());
+            }
+        };
+        for (i, (digest, v)) in run.iter().enumerate() {
+            let v = self.indexed(&Tx { v }, Some(from));
+            self.settle(&Tx { v: &v }, start + i as u32, from, *digest)?;
+        }
+        let v = self.indexed(tx, Some(from));
+        self.settle(&Tx { v: &v }, offset, from, self.digest)
+    }
+
+    /// In discovery mode `tx` with its idx hints replaced by the slots the program assigns,
+    /// in order of first appearance (from, to, fee sink), the order `BatchBuilder` assigns them
+    /// in, so an honest input comes out unchanged. `from` is the signer, `None` for a deposit
+    fn indexed<'b>(&mut self, tx: &Tx<'b>, from: Option<[u8; 20]>) -> Cow<'b, [u8]> {
+        let fee_recipient = self.fee_recipient;
+        let Some(slots) = &mut self.slots else {
+            return Cow::Borrowed(tx.v);
+        };
+        println!("cycle-tracker-start: discover");
+        let mut slot = |key| {
+            let next = slots.len() as u32;
+            *slots.entry(key).or_insert(next)
+        };
+        let token: [u8; 20] = tx.token().try_into().unwrap();
+        let to: [u8; 20] = tx.to().try_into().unwrap();
+        let mut v = tx.v.to_vec();
+        let from_idx = from.map(|from| slot((from, token, tx.channel())));
+        let to_idx = match from_idx {
+            // the payout leaves the batch, the recipient has no delta
+            _ if tx.kind() == TxKind::Withdrawal as u8 => 0,
+            Some(idx) if from == Some(to) => idx,
+            _ => slot((to, token, 0)),
+        };
+        let fee_idx = slot((fee_recipient, token, 0));
+        let at = TxToSer::SIGNED_SIZE + 65;
+        if let Some(idx) = from_idx {
+            v[at..at + 4].copy_from_slice(&idx.to_be_bytes());
+        }
+        v[at + 4..at + 8].copy_from_slice(&to_idx.to_be_bytes());
+        v[at + 8..at + 12].copy_from_slice(&fee_idx.to_be_bytes());
+        println!("cycle-tracker-end: discover");
+        Cow::Owned(v)
+    }
+
+    fn open_run(&self) -> ProcessError {
+        ProcessError::OpenRun {
+            tx: self.next_tx - self.run.len() as u32,
+        }
+    }
+
+    /// `tx` at `offset`, signed by `from`, is applied unless it fails a check that is down to
+    /// the tx itself, in skip mode it is then skipped instead
+    fn settle(
+        &mut self,
+        tx: &Tx<'_>,
+        offset: u32,
+        from: [u8; 20],
+        digest: [u8; 32],
+    ) -> Result<(), ProcessError> {
+        if let Err(e) = self.check_signed(tx, offset, &from) {
+            return self.skip(offset, e);
+        }
+        self.apply_signed(tx, offset, from)?;
+        // a known kind, `apply_tx` checked it
+        let status = TxKind::from_u8(tx.kind()).unwrap();
+        self.push_receipt(digest, from, tx, status);
+        Ok(())
+    }
+
+    /// the checks of a signed tx before it touches any delta
+    fn check_signed(&self, tx: &Tx<'_>, offset: u32, from: &[u8; 20]) -> Result<(), ProcessError> {
+        if tx.kind() == TxKind::Transfer as u8 {
+            self.check_recipient(tx, offset, from)?;
+        }
+        let valid_until = tx.valid_until();
+        if self.timestamp > valid_until {
+            return Err(ProcessError::Expired {
+                tx: offset,
+                valid_until,
+            });
+        }
         let atoms = tx.atoms();
-        assert!(atoms > fee_atoms);
-        let to_recipient = atoms - fee_atoms;
-        let to_fee_sink = fee_atoms;
-        let to = tx.to().try_into().unwrap();
-        apply_sender_delta(&mut deltas, tx.from_idx(), from, tx.nonce(), -atoms);
-        apply_delta(&mut deltas, tx.to_idx(), to, to_recipient);
-        apply_delta(&mut deltas, 0, fee_recipient, to_fee_sink);
+        if atoms <= tx.fee() as i128 {
+            return Err(ProcessError::AtomsNotAboveFee { tx: offset, atoms });
+        }
+        if atoms < self.min_atoms as i128 {
+            return Err(ProcessError::BelowMinAtoms { tx: offset, atoms });
+        }
+        if self.skipped.is_some() {
+            // skipping has to be decided before applying, so the nonces come in order
+            let token = tx.token();
+            let last = self
+                .deltas
+                .get(tx.from_idx() as usize)
+                .filter(|d| d.a.is_some_and(|(a, t)| a == *from && t == token))
+                .and_then(|d| d.nonces.last());
+            if let Some(&(last, _)) = last {
+                let got = tx.nonce();
+                if !self.nonce_policy.accepts(last, got) {
+                    return Err(ProcessError::NonceGap {
+                        tx: offset,
+                        expected: last.saturating_add(1),
+                        got,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// marks tx `offset` skipped for `e` in skip mode if `e` is `ProcessError::skippable`
+    fn skip(&mut self, offset: u32, e: ProcessError) -> Result<(), ProcessError> {
+        match &mut self.skipped {
+            Some(bitmap) if e.skippable() => {
+                bitmap[offset as usize / 8] |= 1 << (offset % 8);
+                Ok(())
+            }
+            _ => Err(e),
+        }
+    }
+
+    /// `tx` at `offset` is a transfer or withdrawal signed by `from` that passed `check_signed`
+    fn apply_signed(
+        &mut self,
+        tx: &Tx<'_>,
+        offset: u32,
+        from: [u8; 20],
+    ) -> Result<(), ProcessError> {
+        println!("cycle-tracker-start: apply_tx");
+        let kind = TxKind::from_u8(tx.kind());
+        let atoms = tx.atoms();
+        let fee = tx.fee() as i128;
+        let to_recipient = atoms - fee;
+        let to_fee_sink = fee;
+        self.total_fee += fee as u64;
+        self.fee_txs += 1;
+        let to: [u8; 20] = tx.to().try_into().unwrap();
+        let token: [u8; 20] = tx.token().try_into().unwrap();
+        let (from_idx, nonce, channel) = (tx.from_idx(), tx.nonce(), tx.channel());
+        let deltas = &mut self.deltas;
+        let seq = (nonce, channel);
+        apply_sender_delta(deltas, offset, from_idx, (from, token), seq, -atoms)?;
+        let fee_sink = (self.fee_recipient, token);
+        if kind == Some(TxKind::Withdrawal) {
+            // the fee sink is the contract itself, it takes all atoms and pays out the rest
+            apply_delta(deltas, offset, tx.fee_idx(), fee_sink, atoms)?;
+            self.metering.delta_updates += 2;
+            self.withdrawals.push(Withdrawal {
+                from: from.into(),
+                to: to.into(),
+                token: token.into(),
+                atoms: to_recipient,
+                nonce,
+                channel,
+            });
+        } else {
+            apply_delta(deltas, offset, tx.to_idx(), (to, token), to_recipient)?;
+            apply_delta(deltas, offset, tx.fee_idx(), fee_sink, to_fee_sink)?;
+            self.metering.delta_updates += 3;
+        }
+        // in range, apply_delta checked it
+        deltas[tx.fee_idx() as usize].fees += fee as u64;
         println!("cycle-tracker-end: apply_tx");
+        Ok(())
     }
 
-    deltas
-        .into_iter()
-        .map(|x| {
-            if let Some((start, end)) = x.nonces {
-                StateDelta {
-                    v: x.a.unwrap().into(),
-                    is_sender: true,
-                    start_nonce: start,
-                    end_nonce: end,
-                    delta: x.v,
-                }
-            } else {
-                StateDelta {
-                    v: x.a.unwrap().into(),
-                    is_sender: false,
-                    start_nonce: 0,
-                    end_nonce: 0,
-                    delta: x.v,
-                }
+    fn hash_memo(&mut self, tx: &Tx<'_>) -> Result<(), ProcessError> {
+        let offset = self.next_tx;
+        let memo = tx.memo().ok_or(ProcessError::BadMemo {
+            tx: offset,
+            len: tx.memo_len(),
+        })?;
+        if !memo.is_empty() {
+            let h = self.memo_hash.get_or_insert_with(Keccak::v256);
+            h.update(&offset.to_be_bytes());
+            h.update(&[memo.len() as u8]);
+            h.update(memo);
+        }
+        Ok(())
+    }
+
+    /// a colliding recipient has to be allowed and land on the one delta, see `TransferPolicy`
+    fn check_recipient(
+        &self,
+        tx: &Tx<'_>,
+        offset: u32,
+        from: &[u8; 20],
+    ) -> Result<(), ProcessError> {
+        let policy = self.transfer_policy;
+        if tx.to() == from {
+            if !policy.allow_self || tx.to_idx() != tx.from_idx() {
+                return Err(ProcessError::SelfTransfer { tx: offset });
+            }
+        } else if tx.to() == self.fee_recipient
+            && (!policy.allow_fee_recipient || tx.to_idx() != tx.fee_idx())
+        {
+            return Err(ProcessError::PaysFeeRecipient { tx: offset });
+        }
+        Ok(())
+    }
+
+    /// unsigned, credits `to` against the contract (the fee sink) and commits the deposit
+    fn apply_deposit(&mut self, tx: &Tx<'_>) -> Result<(), ProcessError> {
+        let offset = self.next_tx;
+        let atoms = tx.atoms();
+        if atoms <= 0 {
+            return Err(ProcessError::AtomsNotAboveFee { tx: offset, atoms });
+        }
+        let to: [u8; 20] = tx.to().try_into().unwrap();
+        let token: [u8; 20] = tx.token().try_into().unwrap();
+        let contract = (self.fee_recipient, token);
+        apply_delta(&mut self.deltas, offset, tx.to_idx(), (to, token), atoms)?;
+        apply_delta(&mut self.deltas, offset, tx.fee_idx(), contract, -atoms)?;
+        self.metering.delta_updates += 2;
+        self.deposits.push(Deposit {
+            id: tx.nonce(),
+            to: to.into(),
+            token: token.into(),
+            atoms,
+        });
+        Ok(())
+    }
+
+    /// keccak of the header and the (inflated) chunks hashed so far
+    pub fn input_hash(&self) -> [u8; 32] {
+        let mut out = [0; 32];
+        self.input_hash.clone().finalize(&mut out);
+        out
+    }
+
+    /// `finish` unless feeding the batch already failed with `fed`, either way what the program
+    /// commits. A failure is committed against the input hashed until then
+    pub fn finish_or_reject(self, fed: Result<(), ProcessError>) -> Result<Processed, Rejected> {
+        let input_hash = self.input_hash();
+        fed.and_then(|()| self.finish())
+            .map_err(|error| Rejected { error, input_hash })
+    }
+
+    /// errors if the header declared more txs than were processed
+    pub fn finish(mut self) -> Result<Processed, ProcessError> {
+        if self.remaining() != 0 {
+            return Err(InputError::LengthMismatch {
+                expected: (self.total_tx as usize).saturating_mul(self.tx_size),
+                got: self.next_tx as usize * self.tx_size,
             }
+            .into());
+        }
+        if !self.run.is_empty() {
+            return Err(self.open_run());
+        }
+        let policy = self.nonce_policy;
+        if self.skipped.is_some() {
+            self.deltas.retain(|x| x.a.is_some());
+        }
+        let fees: Vec<(usize, u64)> = self
+            .deltas
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.fees > 0)
+            .map(|(idx, x)| (idx, x.fees))
+            .collect();
+        let balances: Vec<(usize, u128)> = self
+            .deltas
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, x)| Some((idx, x.balance?)))
+            .collect();
+        let mut n = self
+            .deltas
+            .into_iter()
+            .enumerate()
+            .map(|(idx, mut x)| {
+                let (a, token) = x.a.ok_or(ProcessError::UnusedDelta { idx: idx as u32 })?;
+                let delta = x.v;
+                Ok(
+                    if let Some((start, end)) = nonce_range(&mut x.nonces, policy)? {
+                        StateDelta {
+                            v: a.into(),
+                            token: token.into(),
+                            is_sender: true,
+                            start_nonce: start,
+                            end_nonce: end,
+                            channel: x.channel.unwrap_or_default(),
+                            nonce_policy: policy.tag(),
+                            delta,
+                            solvent: false,
+                        }
+                    } else {
+                        StateDelta {
+                            v: a.into(),
+                            token: token.into(),
+                            is_sender: false,
+                            start_nonce: 0,
+                            end_nonce: 0,
+                            channel: 0,
+                            nonce_policy: policy.tag(),
+                            delta,
+                            solvent: false,
+                        }
+                    },
+                )
+            })
+            .collect::<Result<Vec<_>, ProcessError>>()?;
+        check_unique(&n)?;
+        split_fees(&mut n, &fees, &self.fee_splits)?;
+        check_solvent(&mut n, &balances)?;
+        sort_deltas(&mut n, &self.fee_recipient);
+        let mut input_hash = [0; 32];
+        self.input_hash.finalize(&mut input_hash);
+        let mut memo_hash = [0; 32];
+        if let Some(h) = self.memo_hash {
+            h.finalize(&mut memo_hash);
+        }
+        Ok(Processed {
+            n,
+            total_fee: self.total_fee,
+            fee_txs: self.fee_txs,
+            timestamp: self.timestamp,
+            transfer_policy: self.transfer_policy,
+            min_atoms: self.min_atoms,
+            sig_scheme: self.sig_scheme,
+            domain: self.domain,
+            batch_nonce: self.batch_nonce,
+            input_hash,
+            memo_hash,
+            tx_root: self.tx_tree.root(),
+            receipt_root: self.receipt_tree.map(|x| x.root()).unwrap_or_default(),
+            balances_hash: self.balances_hash,
+            skipped: self.skipped.unwrap_or_default(),
+            metering: self.metering,
+            deposits: self.deposits,
+            withdrawals: self.withdrawals,
         })
-        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+    use k256::elliptic_curve::PrimeField;
+
+    const DOMAIN: Eip712Domain = Eip712Domain {
+        chain_id: 1,
+        verifying_contract: [0xDD; 20],
+    };
+    const TOKEN: [u8; 20] = [0xC0; 20];
+    const FEE: u32 = 10;
+    const NOW: u64 = 1_700_000_000;
+
+    fn signed_tx(
+        sk: &SigningKey,
+        to: [u8; 20],
+        atoms: i128,
+        nonce: u64,
+        idx: (u32, u32),
+    ) -> TxToSer {
+        token_tx(sk, to, TOKEN, atoms, FEE, nonce, (idx.0, idx.1, 0))
+    }
+
+    /// idx is (from, to, fee)
+    fn token_tx(
+        sk: &SigningKey,
+        to: [u8; 20],
+        token: [u8; 20],
+        atoms: i128,
+        fee: u32,
+        nonce: u64,
+        idx: (u32, u32, u32),
+    ) -> TxToSer {
+        let tx = TxToSer {
+            to,
+            token,
+            atoms,
+            nonce,
+            channel: 0,
+            fee,
+            valid_until: NOW,
+            kind: TxKind::Transfer,
+            memo: vec![],
+            sig_r: [0; 32],
+            sig_s: [0; 32],
+            v: 0,
+            from_idx: idx.0,
+            to_idx: idx.1,
+            fee_idx: idx.2,
+            pubkey: None,
+        };
+        sign(sk, tx)
+    }
+
+    fn sign(sk: &SigningKey, mut tx: TxToSer) -> TxToSer {
+        let (sig, rec) = sk
+            .sign_prehash_recoverable(&tx.keccak(&DOMAIN.separator()))
+            .unwrap();
+        tx.sig_r = sig.r().to_bytes().into();
+        tx.sig_s = sig.s().to_bytes().into();
+        tx.v = rec.to_byte();
+        tx
+    }
+
+    fn adr(sk: &SigningKey) -> [u8; 20] {
+        key_to_adr(sk.verifying_key())
+    }
+
+    /// n is sorted by `DELTA_ORDER`, not by the host idx
+    fn find(n: &[StateDelta], v: [u8; 20], token: [u8; 20], channel: u32) -> &StateDelta {
+        n.iter()
+            .find(|d| (d.v, d.token, d.channel) == (v.into(), token.into(), channel))
+            .unwrap()
+    }
+
+    /// clears every signature and signs the last tx over the run digest
+    fn sign_run(sk: &SigningKey, mut run: Vec<TxToSer>) -> Vec<TxToSer> {
+        for tx in run.iter_mut() {
+            (tx.sig_r, tx.sig_s, tx.v) = ([0; 32], [0; 32], 0);
+        }
+        let digest = TxToSer::run_digest(&run, &DOMAIN.separator());
+        let (sig, rec) = sk.sign_prehash_recoverable(&digest).unwrap();
+        let last = run.last_mut().unwrap();
+        last.sig_r = sig.r().to_bytes().into();
+        last.sig_s = sig.s().to_bytes().into();
+        last.v = rec.to_byte();
+        run
+    }
+
+    /// RFC 8032 with the expanded secret key taken as the scalar `a` and the nonce derived
+    /// from it directly, good enough for tests
+    fn ed25519_sign(a: &Scalar, mut tx: TxToSer) -> TxToSer {
+        use curve25519_dalek::constants::ED25519_BASEPOINT_TABLE;
+        let pubkey = (ED25519_BASEPOINT_TABLE * a).compress().0;
+        tx.pubkey = Some(pubkey);
+        tx.v = 0;
+        let digest = tx.keccak(&DOMAIN.separator());
+        let wide = |parts: &[&[u8]]| {
+            let mut h = Sha512::new();
+            for x in parts {
+                h.update(x);
+            }
+            Scalar::from_bytes_mod_order_wide(&h.finalize().into())
+        };
+        let r = wide(&[a.as_bytes(), &digest]);
+        tx.sig_r = (ED25519_BASEPOINT_TABLE * &r).compress().0;
+        let k = wide(&[&tx.sig_r, &pubkey, &digest]);
+        tx.sig_s = (r + k * a).to_bytes();
+        tx
+    }
+
+    fn input(state_deltas: u32, tx: Vec<TxToSer>) -> Vec<u8> {
+        input_with(NoncePolicy::Strict, state_deltas, tx)
+    }
+
+    fn input_with(nonce_policy: NoncePolicy, state_deltas: u32, tx: Vec<TxToSer>) -> Vec<u8> {
+        InputToSer {
+            state_deltas,
+            fee_recipient: [0xFE; 20],
+            timestamp: NOW,
+            nonce_policy,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
+            tx,
+        }
+        .ser()
+    }
+
+    #[test]
+    fn test_process_txs_checked() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = [0xB0; 20];
+        let inp = input(
+            3,
+            vec![
+                signed_tx(&alice, bob, 100, 5, (1, 2)),
+                signed_tx(&alice, bob, 50, 6, (1, 2)),
+            ],
+        );
+        let Processed {
+            n: d,
+            total_fee,
+            timestamp,
+            input_hash,
+            ..
+        } = process_txs_checked(&inp).unwrap();
+        assert_eq!((total_fee, timestamp), (20, NOW));
+        let mut expected = [0; 32];
+        let mut h = Keccak::v256();
+        h.update(&inp);
+        h.finalize(&mut expected);
+        assert_eq!(input_hash, expected, "keccak of the raw input");
+        assert_eq!(d.len(), 3);
+        assert_eq!(d[0].delta, 20, "fee sink");
+        assert_eq!((d[1].delta, d[1].start_nonce, d[1].end_nonce), (-150, 5, 6));
+        assert!(d[1].is_sender);
+        assert_eq!(d[2].delta, 130);
+    }
+
+    #[test]
+    fn test_process_txs_checked_domain() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let inp = input(3, vec![signed_tx(&alice, [0xB0; 20], 100, 5, (1, 2))]);
+        let Processed { domain, .. } = process_txs_checked(&inp).unwrap();
+        assert_eq!(domain, DOMAIN, "domain read from the header");
+
+        // same txs against a header for another settlement contract recover someone else
+        let mut other = inp.clone();
+        other[147] ^= 1;
+        let Processed { n, domain, .. } = process_txs_checked(&other).unwrap();
+        assert_ne!(domain, DOMAIN);
+        assert_ne!(n[1].v, process_txs_checked(&inp).unwrap().n[1].v);
+    }
+
+    #[test]
+    fn test_process_txs_checked_ed25519() {
+        let alice = Scalar::from_bytes_mod_order([0x22; 32]);
+        let bob = [0xB0; 20];
+        // the secp256k1 helpers fill in everything but the signature
+        let unsigned = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let tx = |nonce| ed25519_sign(&alice, signed_tx(&unsigned, bob, 100, nonce, (1, 2)));
+        let batch = |tx: Vec<TxToSer>| {
+            InputToSer {
+                state_deltas: 3,
+                fee_recipient: [0xFE; 20],
+                timestamp: NOW,
+                nonce_policy: NoncePolicy::Strict,
+                compressed: false,
+                fee_splits: vec![],
+                transfer_policy: TransferPolicy::default(),
+                min_atoms: 0,
+                domain: DOMAIN,
+                batch_nonce: 0,
+                sig_scheme: SigScheme::Ed25519,
+                tx,
+            }
+            .ser()
+        };
+        let Processed { n, sig_scheme, .. } =
+            process_txs_checked(&batch(vec![tx(5), tx(6)])).unwrap();
+        assert_eq!(sig_scheme, SigScheme::Ed25519);
+        let pubkey = tx(5).pubkey.unwrap();
+        assert_eq!(n[1].v, ed25519_to_adr(&pubkey));
+        assert_eq!((n[1].delta, n[1].start_nonce, n[1].end_nonce), (-200, 5, 6));
+        assert_eq!(n[2].delta, 180);
+
+        let mut wrong_key = tx(6);
+        wrong_key.pubkey = Some(ed25519_sign(&Scalar::ONE, tx(6)).pubkey.unwrap());
+        let mut tampered = tx(6);
+        tampered.atoms += 1;
+        let mut high_s = tx(6);
+        high_s.sig_s = [0xFF; 32];
+        let mut v = tx(6);
+        v.v = 1;
+        let mut identity = tx(6);
+        identity.pubkey = Some(EdwardsPoint::default().compress().0);
+        for (bad, err) in [
+            (wrong_key, ProcessError::InvalidSignature { tx: 1 }),
+            (tampered, ProcessError::InvalidSignature { tx: 1 }),
+            (high_s, ProcessError::HighS { tx: 1 }),
+            (v, ProcessError::BadRecoveryId { tx: 1, v: 1 }),
+            (identity, ProcessError::InvalidSignature { tx: 1 }),
+        ] {
+            assert_eq!(
+                process_txs_checked(&batch(vec![tx(5), bad])).err(),
+                Some(err)
+            );
+        }
+
+        // secp256k1 signed txs under an ed25519 header don't even split into txs
+        let mut secp = input(3, vec![signed_tx(&unsigned, bob, 100, 5, (1, 2))]);
+        secp[Input::HEADER_SIZE - 1] = SigScheme::Ed25519 as u8;
+        assert!(matches!(
+            process_txs_checked(&secp),
+            Err(ProcessError::MalformedInput(
+                InputError::LengthMismatch { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_process_txs_checked_run() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = SigningKey::from_bytes(&[0x22; 32].into()).unwrap();
+        let carol = [0xCA; 20];
+        let run = |sk, nonces: std::ops::Range<u64>| {
+            let txs = nonces
+                .map(|x| signed_tx(sk, carol, 100, x, (1, 2)))
+                .collect();
+            sign_run(sk, txs)
+        };
+        let alice_adr = adr(&alice);
+
+        // a run of alice, a plain tx of bob, another run of alice
+        let mut txs = run(&alice, 5..8);
+        txs.push(signed_tx(&bob, carol, 100, 0, (3, 2)));
+        txs.extend(run(&alice, 8..10));
+        assert!(txs[..2].iter().all(TxToSer::joins_run));
+        let inp = input(4, txs.clone());
+        let p = process_txs_checked(&inp).unwrap();
+        let a = find(&p.n, alice_adr, TOKEN, 0);
+        assert_eq!((a.delta, a.start_nonce, a.end_nonce), (-500, 5, 9));
+        assert_eq!(find(&p.n, carol, TOKEN, 0).delta, 6 * 90);
+        assert_eq!(find(&p.n, adr(&bob), TOKEN, 0).delta, -100);
+        // the tx tree is still over the tx digests, a run member proves inclusion the same way
+        let digests: Vec<_> = txs.iter().map(|x| x.keccak(&DOMAIN.separator())).collect();
+        assert_eq!(p.tx_root, merkle::root(&digests));
+
+        // a run signature does not verify for the last tx alone and vice versa,
+        // either way someone other than alice is debited
+        let mut last_only = run(&alice, 5..8);
+        last_only[2] = signed_tx(&alice, carol, 100, 7, (1, 2));
+        let p = process_txs_checked(&input(3, last_only)).unwrap();
+        assert!(p.n.iter().all(|d| d.v != alice_adr));
+        let mut tampered = run(&alice, 5..8);
+        tampered[0].atoms += 1;
+        let p = process_txs_checked(&input(3, tampered)).unwrap();
+        assert!(p.n.iter().all(|d| d.v != alice_adr));
+
+        // the run errors are reported at the tx they concern
+        let mut bad_tail = run(&alice, 5..8);
+        bad_tail[1].atoms = 5;
+        assert_eq!(
+            process_txs_checked(&input(3, sign_run(&alice, bad_tail))).err(),
+            Some(ProcessError::AtomsNotAboveFee { tx: 1, atoms: 5 })
+        );
+        let mut open = vec![signed_tx(&alice, carol, 100, 5, (1, 2))];
+        open.extend(run(&alice, 6..8));
+        open.pop();
+        assert_eq!(
+            process_txs_checked(&input(3, open)).err(),
+            Some(ProcessError::OpenRun { tx: 1 })
+        );
+        let mut cut = run(&alice, 5..8);
+        let mut deposit = signed_tx(&alice, carol, 100, 0, (0, 2));
+        deposit.kind = TxKind::Deposit;
+        cut.insert(1, deposit);
+        assert_eq!(
+            process_txs_checked(&input(3, cut)).err(),
+            Some(ProcessError::OpenRun { tx: 0 })
+        );
+
+        // streamed, a run can span chunks
+        let ser = InputToSer {
+            compressed: true,
+            ..InputToSer::deser(&inp).unwrap()
+        };
+        let mut p = BatchProcessor::new(&ser.ser_header()).unwrap();
+        for chunk in ser.ser_chunks(2) {
+            p.process_chunk(&chunk).unwrap();
+        }
+        assert_eq!(
+            find(&p.finish().unwrap().n, alice_adr, TOKEN, 0).delta,
+            -500
+        );
+    }
+
+    #[test]
+    fn test_process_txs_keyed() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = SigningKey::from_bytes(&[0x22; 32].into()).unwrap();
+        let carol = [0xCA; 20];
+        let key = |sk: &SigningKey| -> [u8; 33] {
+            let p = sk.verifying_key().to_encoded_point(true);
+            p.as_bytes().try_into().unwrap()
+        };
+        let keys = |keys: Vec<(u32, [u8; 33])>| SenderKeys { keys }.ser();
+        let mut txs = sign_run(
+            &alice,
+            (5..8)
+                .map(|x| signed_tx(&alice, carol, 100, x, (1, 2)))
+                .collect(),
+        );
+        txs.push(signed_tx(&bob, carol, 100, 0, (3, 2)));
+        txs.push(signed_tx(&alice, carol, 100, 8, (1, 2)));
+        let inp = input(4, txs);
+
+        // alice is verified against her key, bob is still recovered
+        let recovered = process_txs_checked(&inp).unwrap();
+        let keyed = process_txs_keyed(&inp, &keys(vec![(1, key(&alice))])).unwrap();
+        let deltas = |p: &Processed| p.n.iter().map(|x| (x.v, x.delta)).collect::<Vec<_>>();
+        assert_eq!(deltas(&keyed), deltas(&recovered));
+        assert_eq!(
+            keyed.input_hash, recovered.input_hash,
+            "keys are not part of the input"
+        );
+        let both = keys(vec![(3, key(&bob)), (1, key(&alice))]);
+        assert_eq!(
+            deltas(&process_txs_keyed(&inp, &both).unwrap()),
+            deltas(&recovered)
+        );
+
+        // a key that did not sign fails the signatures instead of crediting someone else
+        assert_eq!(
+            process_txs_keyed(&inp, &keys(vec![(1, key(&bob))])).err(),
+            Some(ProcessError::InvalidSignature { tx: 2 })
+        );
+        // the twin (n - s) verifies against the key too, it is still rejected
+        let at = Input::HEADER_SIZE + 3 * TxToSer::SIZE + 146;
+        let s = k256::Scalar::from_repr(<[u8; 32]>::try_from(&inp[at..at + 32]).unwrap().into());
+        let mut high_s = inp.clone();
+        high_s[at..at + 32].copy_from_slice(&(-s.unwrap()).to_bytes());
+        assert_eq!(
+            process_txs_keyed(&high_s, &keys(vec![(3, key(&bob))])).err(),
+            Some(ProcessError::HighS { tx: 3 })
+        );
+
+        let bad_key = |k: Vec<u8>| match process_txs_keyed(&inp, &k).err() {
+            Some(ProcessError::MalformedInput(e)) => e,
+            e => panic!("{e:?}"),
+        };
+        assert_eq!(
+            bad_key(keys(vec![(4, key(&alice))])),
+            InputError::BadSenderKey { idx: 4 }
+        );
+        assert_eq!(
+            bad_key(keys(vec![(1, key(&alice)), (1, key(&alice))])),
+            InputError::BadSenderKey { idx: 1 }
+        );
+        assert_eq!(
+            bad_key(keys(vec![(1, [0xFF; 33])])),
+            InputError::BadSenderKey { idx: 1 }
+        );
+        assert_eq!(bad_key(vec![0; 3]), InputError::BadSenderKeys { len: 3 });
+        let mut ed25519 = inp.clone();
+        ed25519[Input::HEADER_SIZE - 1] = SigScheme::Ed25519 as u8;
+        let mut p = BatchProcessor::new(&ed25519[..Input::HEADER_SIZE]).unwrap();
+        assert!(p.sender_keys(&[]).is_ok());
+        assert!(p.sender_keys(&keys(vec![(1, key(&alice))])).is_err());
+    }
+
+    #[test]
+    fn test_process_txs_checked_metering() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = SigningKey::from_bytes(&[0x22; 32].into()).unwrap();
+        let carol = [0xCA; 20];
+        let run = (5..8).map(|x| signed_tx(&alice, carol, 100, x, (1, 2)));
+        let mut txs = sign_run(&alice, run.collect());
+        txs.push(signed_tx(&bob, carol, 100, 0, (3, 2)));
+        let mut deposit = signed_tx(&bob, carol, 100, 0, (0, 2));
+        deposit.kind = TxKind::Deposit;
+        txs.push(deposit);
+        let inp = input(4, txs);
+        let p = process_txs_checked(&inp).unwrap();
+        let expected = Metering {
+            recoveries: 2,
+            verifications: 0,
+            digests: 5 + 1,
+            hashed_bytes: inp.len() as u64,
+            inflated_bytes: 0,
+            delta_updates: 4 * 3 + 2,
+        };
+        assert_eq!(p.metering, expected);
+
+        let key = alice.verifying_key().to_encoded_point(true);
+        let keys = SenderKeys {
+            keys: vec![(1, key.as_bytes().try_into().unwrap())],
+        };
+        let p = process_txs_keyed(&inp, &keys.ser()).unwrap();
+        assert_eq!((p.metering.recoveries, p.metering.verifications), (1, 1));
+
+        let compressed = InputToSer {
+            compressed: true,
+            ..InputToSer::deser(&inp).unwrap()
+        };
+        let p = process_txs_checked(&compressed.ser()).unwrap();
+        assert_eq!(p.metering.inflated_bytes, 5 * TxToSer::SIZE as u64);
+        assert_eq!(p.metering.hashed_bytes, inp.len() as u64, "hashed inflated");
+    }
+
+    #[test]
+    fn test_process_txs_checked_delta_order() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let carol = SigningKey::from_bytes(&[0x33; 32].into()).unwrap();
+        let (bob, usdt) = ([0x01; 20], [0x02; 20]);
+        // same txs, two host index layouts, idx is (from, to, fee)
+        let txs = |i: [u32; 6]| {
+            vec![
+                token_tx(&alice, bob, TOKEN, 100, FEE, 0, (i[0], i[1], i[2])),
+                token_tx(&carol, bob, usdt, 100, FEE, 0, (i[3], i[4], i[5])),
+            ]
+        };
+        let a = process_txs_checked(&input(6, txs([0, 1, 2, 3, 4, 5]))).unwrap();
+        let b = process_txs_checked(&input(6, txs([5, 3, 1, 2, 4, 0]))).unwrap();
+        let key = |p: &Processed| {
+            p.n.iter()
+                .map(|d| (d.v, d.token, d.channel, d.delta))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(key(&a), key(&b), "independent of the host idx");
+        // the fee sink leads even though bob sorts below it
+        let n = a.n;
+        assert_eq!((n[0].v.0 .0, n[0].token.0 .0), ([0xFE; 20], usdt));
+        assert_eq!((n[1].v.0 .0, n[1].token.0 .0), ([0xFE; 20], TOKEN));
+        assert_eq!((n[2].v.0 .0, n[2].token.0 .0), (bob, usdt));
+        assert_eq!((n[3].v.0 .0, n[3].token.0 .0), (bob, TOKEN));
+        assert!(n[3..].windows(2).all(|w| w[0].v < w[1].v));
+        assert_eq!(DELTA_ORDER, 1);
+    }
+
+    #[test]
+    fn test_process_txs_checked_batch_nonce() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let inp = input(3, vec![signed_tx(&alice, [0xB0; 20], 100, 5, (1, 2))]);
+        let first = process_txs_checked(&inp).unwrap();
+        assert_eq!(first.batch_nonce, 0);
+
+        // the same txs resubmitted as the next batch, only the contract can tell it is a replay
+        let mut next = inp;
+        next[167..175].copy_from_slice(&1u64.to_be_bytes());
+        let next = process_txs_checked(&next).unwrap();
+        assert_eq!(next.batch_nonce, 1, "committed");
+        let deltas = |p: &Processed| p.n.iter().map(|d| (d.v, d.delta)).collect::<Vec<_>>();
+        assert_eq!(deltas(&next), deltas(&first));
+        assert_ne!(next.input_hash, first.input_hash, "header is hashed");
+    }
+
+    #[test]
+    fn test_process_txs_checked_tx_root() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = [0xB0; 20];
+        let txs: Vec<TxToSer> = (5..8)
+            .map(|nonce| signed_tx(&alice, bob, 100, nonce, (1, 2)))
+            .collect();
+        let digests: Vec<[u8; 32]> = txs.iter().map(|t| t.keccak(&DOMAIN.separator())).collect();
+        let Processed { tx_root, .. } = process_txs_checked(&input(3, txs)).unwrap();
+        assert_eq!(tx_root, merkle::root(&digests));
+        // what a sender keeps to later show its payment settled
+        let proof = merkle::proof(&digests, 2).unwrap();
+        assert!(merkle::verify(&proof, &tx_root, &digests[2]));
+
+        let empty = process_txs_checked(&input(0, vec![])).unwrap();
+        assert_eq!(empty.tx_root, [0; 32]);
+    }
+
+    #[test]
+    fn test_process_txs_checked_memo() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = [0xB0; 20];
+        let memo = |nonce, memo: &[u8]| {
+            sign(
+                &alice,
+                TxToSer {
+                    memo: memo.to_vec(),
+                    ..signed_tx(&alice, bob, 100, nonce, (1, 2))
+                },
+            )
+        };
+        let plain = input(3, vec![signed_tx(&alice, bob, 100, 5, (1, 2))]);
+        assert_eq!(process_txs_checked(&plain).unwrap().memo_hash, [0; 32]);
+
+        let inp = input(3, vec![memo(5, b""), memo(6, b"inv-7")]);
+        let Processed { n, memo_hash, .. } = process_txs_checked(&inp).unwrap();
+        let mut expected = [0; 32];
+        let mut h = Keccak::v256();
+        h.update(&1u32.to_be_bytes());
+        h.update(&[5]);
+        h.update(b"inv-7");
+        h.finalize(&mut expected);
+        assert_eq!(memo_hash, expected, "only txs with a memo");
+
+        // the memo is signed, a batcher can't swap the invoice id
+        let mut swapped = inp.clone();
+        let at = Input::HEADER_SIZE + TxToSer::SIZE + 82;
+        swapped[at..at + 5].copy_from_slice(b"inv-8");
+        let d = process_txs_checked(&swapped).map(|p| p.n[1].v);
+        assert_ne!(d, Ok(n[1].v));
+
+        let err = |bad: Vec<u8>| process_txs_checked(&bad).unwrap_err();
+        let mut long = inp.clone();
+        long[Input::HEADER_SIZE + 81] = 33;
+        assert_eq!(err(long), ProcessError::BadMemo { tx: 0, len: 33 });
+        let mut padded = inp;
+        padded[Input::HEADER_SIZE + TxToSer::SIGNED_SIZE - 1] = 1;
+        let e = err(padded);
+        assert_eq!(e, ProcessError::BadMemo { tx: 0, len: 0 });
+        assert_eq!(e.code(), 16);
+    }
+
+    #[test]
+    fn test_process_txs_checked_per_tx_fee() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = [0xB0; 20];
+        let inp = input(
+            3,
+            vec![
+                token_tx(&alice, bob, TOKEN, 100, 0, 0, (1, 2, 0)),
+                token_tx(&alice, bob, TOKEN, 100, 99, 1, (1, 2, 0)),
+                token_tx(&alice, bob, TOKEN, 100, 7, 2, (1, 2, 0)),
+            ],
+        );
+        let r = process_txs_checked(&inp).unwrap();
+        assert_eq!((r.total_fee, r.fee_txs), (106, 3));
+        assert_eq!(r.n[0].delta, 106, "fee sink");
+        assert_eq!((r.n[1].delta, r.n[2].delta), (-300, 194));
+
+        let inp = input(
+            3,
+            vec![token_tx(&alice, bob, TOKEN, 100, 100, 0, (1, 2, 0))],
+        );
+        assert_eq!(
+            process_txs_checked(&inp).unwrap_err(),
+            ProcessError::AtomsNotAboveFee { tx: 0, atoms: 100 }
+        );
+    }
+
+    #[test]
+    fn test_process_txs_checked_deposit_withdrawal() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let (bob, carol) = ([0xB0; 20], [0xCA; 20]);
+        let mut deposit = signed_tx(&alice, bob, 500, 77, (0, 2));
+        deposit.kind = TxKind::Deposit;
+        deposit.sig_r = [0; 32]; // deposits are not signed
+        let mut withdrawal = signed_tx(&alice, carol, 100, 2, (1, 2));
+        withdrawal.kind = TxKind::Withdrawal;
+        // 0 contract (fee sink), 1 alice, 2 bob
+        let inp = input(
+            3,
+            vec![
+                deposit,
+                signed_tx(&alice, bob, 200, 1, (1, 2)),
+                sign(&alice, withdrawal),
+            ],
+        );
+        let r = process_txs_checked(&inp).unwrap();
+        assert_eq!(
+            r.n[0].delta,
+            -500 + 10 + 100,
+            "contract: deposit out, fee + withdrawal in"
+        );
+        assert_eq!(r.n[1].delta, -300);
+        assert_eq!(r.n[2].delta, 500 + 190);
+        assert_eq!(r.n.iter().map(|d| d.delta).sum::<i128>(), 0);
+        assert_eq!(r.deposits.len(), 1);
+        assert_eq!((r.deposits[0].id, r.deposits[0].atoms), (77, 500));
+        assert_eq!(r.withdrawals.len(), 1);
+        let w = &r.withdrawals[0];
+        assert_eq!((w.to.0 .0, w.atoms, w.nonce), (carol, 90, 2));
+        assert_eq!((r.total_fee, r.fee_txs), (20, 2), "the deposit pays no fee");
+
+        let mut unknown = inp.clone();
+        unknown[Input::HEADER_SIZE + 80] = 7;
+        assert_eq!(
+            process_txs_checked(&unknown).unwrap_err(),
+            ProcessError::UnknownKind { tx: 0, kind: 7 }
+        );
+    }
+
+    #[test]
+    fn test_process_txs_checked_multi_token() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let (bob, usdt) = ([0xB0; 20], [0xD0; 20]);
+        // 0 usdc fee sink, 1 alice usdc, 2 bob usdc, 3 usdt fee sink, 4 alice usdt, 5 bob usdt
+        let inp = input(
+            6,
+            vec![
+                token_tx(&alice, bob, TOKEN, 100, FEE, 0, (1, 2, 0)),
+                token_tx(&alice, bob, usdt, 40, FEE, 0, (4, 5, 3)),
+                token_tx(&alice, bob, TOKEN, 100, FEE, 1, (1, 2, 0)),
+            ],
+        );
+        let Processed {
+            n: d, total_fee, ..
+        } = process_txs_checked(&inp).unwrap();
+        assert_eq!(total_fee, 30, "summed across tokens");
+        // the fee sinks lead, by token
+        let token = |i: usize| d[i].token.0 .0;
+        assert_eq!((d[0].v.0 .0, token(0), d[0].delta), ([0xFE; 20], TOKEN, 20));
+        assert_eq!((d[1].v.0 .0, token(1), d[1].delta), ([0xFE; 20], usdt, 10));
+        let a = adr(&alice);
+        let (a_usdc, a_usdt) = (find(&d, a, TOKEN, 0), find(&d, a, usdt, 0));
+        assert_eq!(
+            (a_usdc.delta, a_usdc.end_nonce),
+            (-200, 1),
+            "one delta per token"
+        );
+        assert_eq!((a_usdt.delta, a_usdt.end_nonce), (-40, 0));
+        let b = (find(&d, bob, TOKEN, 0).delta, find(&d, bob, usdt, 0).delta);
+        assert_eq!(b, (180, 30));
+
+        // usdt can't be credited into the usdc delta of bob
+        let inp = input(
+            5,
+            vec![
+                token_tx(&alice, bob, TOKEN, 100, FEE, 0, (1, 2, 0)),
+                token_tx(&alice, bob, usdt, 40, FEE, 0, (4, 2, 3)),
+            ],
+        );
+        assert_eq!(
+            process_txs_checked(&inp).unwrap_err(),
+            ProcessError::DeltaAddrMismatch { tx: 1, idx: 2 }
+        );
+    }
+
+    #[test]
+    fn test_process_txs_checked_channels() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let (bob, carol) = ([0xB0; 20], [0xCA; 20]);
+        let on = |channel: u32, to: [u8; 20], nonce: u64, idx: (u32, u32)| {
+            sign(
+                &alice,
+                TxToSer {
+                    channel,
+                    ..signed_tx(&alice, to, 100, nonce, idx)
+                },
+            )
+        };
+        // 0 fee sink, 1 alice channel 0, 2 bob, 3 alice channel 1, 4 carol
+        // both streams start at nonce 0 and are signed independently
+        let inp = input(
+            5,
+            vec![
+                on(0, bob, 0, (1, 2)),
+                on(1, carol, 0, (3, 4)),
+                on(1, carol, 1, (3, 4)),
+                on(0, bob, 1, (1, 2)),
+            ],
+        );
+        let d = process_txs_checked(&inp).unwrap().n;
+        let (c0, c1) = (
+            find(&d, adr(&alice), TOKEN, 0),
+            find(&d, adr(&alice), TOKEN, 1),
+        );
+        assert_eq!((c0.start_nonce, c0.end_nonce), (0, 1));
+        assert_eq!((c1.start_nonce, c1.end_nonce), (0, 1));
+        assert_eq!((c0.delta, c1.delta), (-200, -200));
+        // receivers have none
+        assert_eq!(find(&d, bob, TOKEN, 0).delta, 180);
+        assert_eq!(find(&d, carol, TOKEN, 0).delta, 180);
+
+        // a delta holds one nonce sequence
+        let inp = input(3, vec![on(0, bob, 0, (1, 2)), on(1, bob, 0, (1, 2))]);
+        assert_eq!(
+            process_txs_checked(&inp).unwrap_err(),
+            ProcessError::DeltaAddrMismatch { tx: 1, idx: 1 }
+        );
+
+        // the channel is signed, moving a tx to another channel changes the signer
+        let mut inp = input(3, vec![on(0, bob, 0, (1, 2))]);
+        let signer = process_txs_checked(&inp).unwrap().n[1].v;
+        inp[Input::HEADER_SIZE + 67] = 1;
+        assert!(process_txs_checked(&inp).map_or(true, |p| p.n[1].v != signer));
+    }
+
+    #[test]
+    fn test_batch_processor_discover_deltas() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let (bob, carol) = ([0xB0; 20], [0xCA; 20]);
+        let mut deposit = signed_tx(&alice, bob, 500, 77, (0, 2));
+        deposit.kind = TxKind::Deposit;
+        deposit.sig_r = [0; 32];
+        let mut withdrawal = signed_tx(&alice, carol, 100, 2, (1, 2));
+        withdrawal.kind = TxKind::Withdrawal;
+        let txs = vec![
+            deposit,
+            signed_tx(&alice, bob, 200, 1, (1, 2)),
+            sign(&alice, withdrawal),
+        ];
+        let hinted = process_txs_checked(&input(3, txs.clone())).unwrap();
+        let discover = |state_deltas, txs| {
+            let inp = input(state_deltas, txs);
+            let mut p = BatchProcessor::new(&inp[..Input::HEADER_SIZE]).unwrap();
+            p.discover_deltas();
+            p.process_chunk(&inp[Input::HEADER_SIZE..])?;
+            p.finish()
+        };
+        // the hints are not signed, any will do
+        let scrambled: Vec<_> = txs
+            .iter()
+            .map(|tx| TxToSer {
+                from_idx: 9,
+                to_idx: 9,
+                fee_idx: 9,
+                ..tx.clone()
+            })
+            .collect();
+        assert!(process_txs_checked(&input(3, scrambled.clone())).is_err());
+        let found = discover(3, scrambled).unwrap();
+        let key = |p: &Processed| {
+            p.n.iter()
+                .map(|d| (d.v, d.token, d.channel, d.start_nonce, d.delta))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(key(&found), key(&hinted));
+
+        // the slots still have to match the header, bob 0, contract 1, alice 2
+        assert_eq!(
+            discover(4, txs.clone()).unwrap_err(),
+            ProcessError::UnusedDelta { idx: 3 }
+        );
+        assert_eq!(
+            discover(2, txs).unwrap_err(),
+            ProcessError::DeltaIdxOutOfRange { tx: 1, idx: 2 }
+        );
+    }
+
+    #[test]
+    fn test_public_values() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let inp = input(3, vec![signed_tx(&alice, [0xB0; 20], 100, 0, (1, 2))]);
+        let header = &inp[..Input::HEADER_SIZE];
+        let pv = public_values(
+            process_txs_committed(&inp, &[]),
+            header,
+            false,
+            false,
+            false,
+        );
+        assert_eq!((pv.status, pv.n.len(), pv.packed_n.len()), (0, 3, 0));
+        assert_eq!(pv.domain_separator, DOMAIN.separator());
+        let packed = public_values(process_txs_committed(&inp, &[]), header, true, false, false);
+        assert_eq!(packed.packed_n, packed::pack(&pv.n));
+        assert!(packed.n.is_empty());
+
+        // no such recovery id, still committed to the domain of the header and to the input
+        let keccak = |v: &[u8]| {
+            let mut out = [0; 32];
+            let mut h = Keccak::v256();
+            h.update(v);
+            h.finalize(&mut out);
+            out
+        };
+        let bad_v = |mut v: Vec<u8>| {
+            v[Input::HEADER_SIZE + TxToSer::SIGNED_SIZE + 64] = 7;
+            v
+        };
+        let bad = bad_v(inp.clone());
+        let pv = public_values(
+            process_txs_committed(&bad, &[]),
+            header,
+            false,
+            false,
+            false,
+        );
+        assert_eq!(
+            pv.status,
+            ProcessError::BadRecoveryId { tx: 0, v: 7 }.code()
+        );
+        assert_eq!(
+            (pv.chain_id, pv.input_hash),
+            (DOMAIN.chain_id, keccak(&bad).into())
+        );
+        assert_eq!(pv.program, Program::current());
+        assert_eq!(pv.program.domain_tag, DOMAIN_TAG);
+        assert_eq!(&DOMAIN_TAG[..17], b"ddm.coproc.batch\0");
+        // same header and nonce, another rejected input is another commitment
+        let other = bad_v(input(
+            3,
+            vec![signed_tx(&alice, [0xB0; 20], 101, 0, (1, 2))],
+        ));
+        let other = public_values(
+            process_txs_committed(&other, &[]),
+            header,
+            false,
+            false,
+            false,
+        );
+        assert_eq!(
+            (other.status, other.batch_nonce),
+            (pv.status, pv.batch_nonce)
+        );
+        assert_ne!(other.input_hash, pv.input_hash);
+
+        // rejected before a processor, committed to what there was of the header
+        let pv = public_values(process_txs_committed(&[], &[]), &[], false, false, false);
+        assert_eq!((pv.chain_id, pv.input_hash), (0, keccak(&[]).into()));
+        // rejected by `finish`, committed to the chunks hashed until then
+        let two = input(
+            3,
+            vec![
+                signed_tx(&alice, [0xB0; 20], 100, 0, (1, 2)),
+                signed_tx(&alice, [0xB0; 20], 100, 1, (1, 2)),
+            ],
+        );
+        let read = &two[..Input::HEADER_SIZE + (two.len() - Input::HEADER_SIZE) / 2];
+        let mut p = BatchProcessor::new(&two[..Input::HEADER_SIZE]).unwrap();
+        let fed = p.process_chunk(&read[Input::HEADER_SIZE..]);
+        assert_eq!(p.input_hash(), keccak(read));
+        let r = p.finish_or_reject(fed).unwrap_err();
+        assert!(matches!(r.error, ProcessError::MalformedInput(_)));
+        assert_eq!(r.input_hash, keccak(read));
+    }
+
+    #[test]
+    fn test_aggregate_values() {
+        use alloy_sol_types::SolType;
+        let batch = |nonce| {
+            let mut pv = public_values(process_txs_committed(&[], &[]), &[], false, false, false);
+            pv.batch_nonce = nonce;
+            PublicValuesStruct::abi_encode(&pv)
+        };
+        let vkey = [1, 2, 3, 4, 5, 6, 7, 0x0a0b0c0d];
+        let agg = aggregate_values(&vkey, vec![batch(1), batch(2)]);
+        let agg =
+            AggregateValuesStruct::abi_decode(&AggregateValuesStruct::abi_encode(&agg)).unwrap();
+        assert_eq!(agg.program, Program::aggregate());
+        assert_eq!(&agg.program.domain_tag[..21], b"ddm.coproc.aggregate\0");
+        assert_eq!(agg.batch_vkey[..8], [0, 0, 0, 1, 0, 0, 0, 2]);
+        assert_eq!(agg.batch_vkey[28..], [0x0a, 0x0b, 0x0c, 0x0d]);
+        let nonces: Vec<_> = agg
+            .batches
+            .iter()
+            .map(|x| PublicValuesStruct::abi_decode(x).unwrap().batch_nonce)
+            .collect();
+        assert_eq!(nonces, [1, 2]);
+    }
+
+    #[test]
+    fn test_batch_processor_skip_invalid() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = [0xB0; 20];
+        let expired = sign(
+            &alice,
+            TxToSer {
+                valid_until: NOW - 1,
+                ..signed_tx(&alice, bob, 100, 1, (1, 2))
+            },
+        );
+        let mut forged = signed_tx(&alice, bob, 100, 2, (3, 2));
+        forged.sig_r = [0; 32];
+        let txs = vec![
+            signed_tx(&alice, bob, 100, 0, (1, 2)),
+            expired,
+            signed_tx(&alice, bob, 100, 1, (1, 2)),
+            forged,
+            signed_tx(&alice, bob, 100, 5, (1, 2)),
+        ];
+        // 0 fee sink, 1 alice, 2 bob, 3 only the forged tx points at
+        let inp = input(4, txs);
+        assert_eq!(
+            process_txs_checked(&inp).unwrap_err(),
+            ProcessError::Expired {
+                tx: 1,
+                valid_until: NOW - 1
+            }
+        );
+        let mut p = BatchProcessor::new(&inp[..Input::HEADER_SIZE]).unwrap();
+        p.skip_invalid();
+        p.process_chunk(&inp[Input::HEADER_SIZE..]).unwrap();
+        let r = p.finish().unwrap();
+        assert_eq!(r.skipped, vec![0b11010]);
+        assert_eq!((r.total_fee, r.fee_txs), (20, 2));
+        assert_eq!(r.n.len(), 3);
+        let a = find(&r.n, adr(&alice), TOKEN, 0);
+        assert_eq!((a.start_nonce, a.end_nonce, a.delta), (0, 1, -200));
+        assert_eq!(find(&r.n, bob, TOKEN, 0).delta, 180);
+
+        // a fault of the host still fails the batch
+        let inp = input(2, vec![signed_tx(&alice, bob, 100, 0, (1, 2))]);
+        let mut p = BatchProcessor::new(&inp[..Input::HEADER_SIZE]).unwrap();
+        p.skip_invalid();
+        assert_eq!(
+            p.process_chunk(&inp[Input::HEADER_SIZE..]).unwrap_err(),
+            ProcessError::DeltaIdxOutOfRange { tx: 0, idx: 2 }
+        );
+        let plain = process_txs_checked(&input(3, vec![signed_tx(&alice, bob, 100, 0, (1, 2))]));
+        assert!(plain.unwrap().skipped.is_empty());
+    }
+
+    #[test]
+    fn test_process_txs_prior_balances() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let (bob, carol) = ([0xB0; 20], [0xCA; 20]);
+        let on = |channel: u32, to: [u8; 20], nonce: u64, idx: (u32, u32)| {
+            sign(
+                &alice,
+                TxToSer {
+                    channel,
+                    ..signed_tx(&alice, to, 100, nonce, idx)
+                },
+            )
+        };
+        // 0 fee sink, 1 alice channel 0, 2 bob, 3 alice channel 1, 4 carol
+        let inp = input(5, vec![on(0, bob, 0, (1, 2)), on(1, carol, 0, (3, 4))]);
+        let with = |balances: Vec<(u32, u128)>| {
+            let balances = PriorBalances { balances }.ser();
+            let mut p = BatchProcessor::new(&inp[..Input::HEADER_SIZE]).unwrap();
+            p.prior_balances(&balances)?;
+            p.process_chunk(&inp[Input::HEADER_SIZE..])?;
+            p.finish()
+        };
+        let plain = with(vec![]).unwrap();
+        assert_eq!(plain.balances_hash, [0; 32]);
+        assert!(plain.n.iter().all(|d| !d.solvent));
+
+        // both channels draw on the one balance, -200 in total
+        let r = with(vec![(1, 200), (3, 200)]).unwrap();
+        let balances = PriorBalances {
+            balances: vec![(1, 200), (3, 200)],
+        };
+        let mut h = Keccak::v256();
+        h.update(&balances.ser());
+        let mut expected = [0; 32];
+        h.finalize(&mut expected);
+        assert_eq!(r.balances_hash, expected);
+        assert!(find(&r.n, adr(&alice), TOKEN, 0).solvent);
+        assert!(
+            find(&r.n, adr(&alice), TOKEN, 1).solvent,
+            "covered by idx 1"
+        );
+        assert!(!find(&r.n, bob, TOKEN, 0).solvent, "no balance given");
+        assert!(with(vec![(1, 200)]).unwrap().n.iter().any(|d| d.solvent));
+        assert_eq!(
+            with(vec![(3, 199)]).unwrap_err(),
+            ProcessError::Insolvent {
+                idx: 3,
+                balance: 199,
+                delta: -200
+            }
+        );
+
+        let bad = |idx| Err(InputError::BadPriorBalance { idx }.into());
+        assert_eq!(with(vec![(1, 200), (3, 300)]).map(|_| ()), bad(3));
+        assert_eq!(with(vec![(1, 200), (1, 200)]).map(|_| ()), bad(1));
+        assert_eq!(with(vec![(5, 200)]).map(|_| ()), bad(5));
+    }
+
+    #[test]
+    fn test_batch_processor_chunks() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = [0xB0; 20];
+        let ser = InputToSer {
+            state_deltas: 3,
+            fee_recipient: [0xFE; 20],
+            timestamp: NOW,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
+            tx: (0..5)
+                .map(|n| signed_tx(&alice, bob, 100, n, (1, 2)))
+                .collect(),
+        };
+        let whole = process_txs_checked(&ser.ser()).unwrap();
+
+        let mut p = BatchProcessor::new(&ser.ser_header()).unwrap();
+        for chunk in ser.ser_chunks(2) {
+            p.process_chunk(&chunk).unwrap();
+        }
+        assert_eq!(p.remaining(), 0);
+        let chunked = p.finish().unwrap();
+        assert_eq!(chunked.total_fee, whole.total_fee);
+        assert_eq!(chunked.input_hash, whole.input_hash);
+        assert_eq!(chunked.n.len(), whole.n.len());
+        for (a, b) in chunked.n.iter().zip(&whole.n) {
+            assert_eq!((a.v, a.delta, a.end_nonce), (b.v, b.delta, b.end_nonce));
+        }
+
+        let chunks = ser.ser_chunks(2);
+        let mut p = BatchProcessor::new(&ser.ser_header()).unwrap();
+        assert_eq!(
+            p.process_chunk(&chunks[0][1..]).unwrap_err(),
+            ProcessError::MalformedInput(InputError::BadChunk {
+                len: 2 * TxToSer::SIZE - 1
+            })
+        );
+        p.process_chunk(&chunks[0]).unwrap();
+        assert_eq!(p.remaining(), 3);
+        assert!(matches!(
+            p.finish(),
+            Err(ProcessError::MalformedInput(
+                InputError::LengthMismatch { .. }
+            ))
+        ));
+
+        // nonces are checked across chunks, in any order
+        let mut shuffled = ser.tx.clone();
+        shuffled.reverse();
+        let rev = InputToSer {
+            tx: shuffled,
+            state_deltas: 3,
+            fee_recipient: [0xFE; 20],
+            timestamp: NOW,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
+        };
+        let mut p = BatchProcessor::new(&rev.ser_header()).unwrap();
+        for chunk in rev.ser_chunks(2) {
+            p.process_chunk(&chunk).unwrap();
+        }
+        let d = p.finish().unwrap().n;
+        assert_eq!((d[1].start_nonce, d[1].end_nonce), (0, 4));
+        assert_eq!(d[1].delta, whole.n[1].delta);
+
+        let gap = InputToSer {
+            tx: ser.tx.iter().filter(|t| t.nonce != 3).cloned().collect(),
+            state_deltas: 3,
+            fee_recipient: [0xFE; 20],
+            timestamp: NOW,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
+        };
+        let mut p = BatchProcessor::new(&gap.ser_header()).unwrap();
+        for chunk in gap.ser_chunks(2) {
+            p.process_chunk(&chunk).unwrap();
+        }
+        assert_eq!(
+            p.finish().unwrap_err(),
+            ProcessError::NonceGap {
+                tx: 3,
+                expected: 3,
+                got: 4
+            }
+        );
+    }
+
+    #[test]
+    fn test_process_txs_checked_nonce_policy() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = [0xB0; 20];
+        // a subset of alice's signed txs, 2 and 4 are held back
+        let tx = || {
+            [1, 3, 6]
+                .map(|n| signed_tx(&alice, bob, 100, n, (1, 2)))
+                .to_vec()
+        };
+        let run = |p: NoncePolicy| process_txs_checked(&input_with(p, 3, tx()));
+
+        assert_eq!(
+            run(NoncePolicy::Strict).unwrap_err(),
+            ProcessError::NonceGap {
+                tx: 1,
+                expected: 2,
+                got: 3
+            }
+        );
+        let d = run(NoncePolicy::Monotonic).unwrap().n;
+        assert_eq!((d[1].start_nonce, d[1].end_nonce), (1, 6));
+        assert!(d.iter().all(|x| x.nonce_policy == 1));
+        assert_eq!(d[1].delta, -300);
+        assert_eq!(
+            run(NoncePolicy::Windowed(2)).unwrap_err(),
+            ProcessError::NonceGap {
+                tx: 2,
+                expected: 4,
+                got: 6
+            }
+        );
+        let d = run(NoncePolicy::Windowed(3)).unwrap().n;
+        assert_eq!((d[1].end_nonce, d[1].nonce_policy), (6, 2));
+
+        // duplicates are never accepted
+        let dup = vec![
+            signed_tx(&alice, bob, 100, 1, (1, 2)),
+            signed_tx(&alice, bob, 100, 1, (1, 2)),
+        ];
+        assert!(matches!(
+            process_txs_checked(&input_with(NoncePolicy::Monotonic, 3, dup)),
+            Err(ProcessError::NonceGap { tx: 1, .. })
+        ));
+        let dup = vec![
+            signed_tx(&alice, bob, 100, u64::MAX, (1, 2)),
+            signed_tx(&alice, bob, 100, u64::MAX, (1, 2)),
+        ];
+        assert_eq!(
+            process_txs_checked(&input_with(NoncePolicy::Monotonic, 3, dup)).unwrap_err(),
+            ProcessError::NonceGap {
+                tx: 1,
+                expected: u64::MAX,
+                got: u64::MAX
+            }
+        );
+
+        let mut bad = input(3, tx());
+        bad[36] = 7;
+        assert_eq!(
+            process_txs_checked(&bad).unwrap_err(),
+            ProcessError::MalformedInput(InputError::BadNoncePolicy { tag: 7 })
+        );
+        assert!(BatchProcessor::new(&bad[..Input::HEADER_SIZE]).is_err());
+    }
+
+    #[test]
+    fn test_process_txs_checked_fee_splits() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let (bob, carol) = ([0xB0; 20], [0xCA; 20]);
+        let mut ser = InputToSer {
+            state_deltas: 3,
+            fee_recipient: [0xFE; 20],
+            timestamp: NOW,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![
+                FeeSplit {
+                    recipient: bob,
+                    bps: 3000,
+                },
+                FeeSplit {
+                    recipient: carol,
+                    bps: 2500,
+                },
+            ],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
+            tx: (0..3)
+                .map(|n| signed_tx(&alice, bob, 100, n, (1, 2)))
+                .collect(),
+        };
+        let r = process_txs_checked(&ser.ser()).unwrap();
+        assert_eq!(r.total_fee, 30, "splits don't change what was charged");
+        let d = r.n;
+        assert_eq!(d.len(), 4, "carol had no delta, one is appended");
+        // 30% of 30 to bob, 25% rounded down to carol, the rest stays
+        assert_eq!(d[0].delta, 30 - 9 - 7);
+        assert_eq!(d[2].delta, 270 + 9, "merged into bob's delta");
+        assert_eq!((d[3].v.0 .0, d[3].token.0 .0), (carol, TOKEN));
+        assert_eq!((d[3].delta, d[3].is_sender), (7, false));
+        assert_eq!(d.iter().map(|x| x.delta).sum::<i128>(), 0);
+
+        ser.fee_splits[1].bps = 7001;
+        assert_eq!(
+            process_txs_checked(&ser.ser()).unwrap_err(),
+            ProcessError::MalformedInput(InputError::BadFeeSplits { bps: 10_001 })
+        );
+    }
+
+    #[test]
+    fn test_process_txs_checked_compressed() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = [0xB0; 20];
+        let ser = InputToSer {
+            state_deltas: 3,
+            fee_recipient: [0xFE; 20],
+            timestamp: NOW,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: true,
+            fee_splits: vec![],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
+            tx: (0..6)
+                .map(|n| signed_tx(&alice, bob, 100, n, (1, 2)))
+                .collect(),
+        };
+        let whole = process_txs_checked(&ser.ser()).unwrap();
+        assert_eq!(whole.total_fee, 6 * FEE as u64);
+        assert_eq!((whole.n[1].end_nonce, whole.n[2].delta), (5, 6 * 90));
+
+        let mut p = BatchProcessor::new(&ser.ser_header()).unwrap();
+        for frame in ser.ser_chunks(4) {
+            p.process_chunk(&frame).unwrap();
+        }
+        let chunked = p.finish().unwrap();
+        let raw: Vec<u8> = ser.tx.iter().flat_map(|t| t.ser()).collect();
+        let mut h = Keccak::v256();
+        h.update(&ser.ser_header());
+        h.update(&raw);
+        let mut expected = [0; 32];
+        h.finalize(&mut expected);
+        assert_eq!(whole.input_hash, expected, "hashed inflated");
+        assert_eq!(chunked.input_hash, expected);
+
+        // a frame may not carry more txs than the header has left
+        let mut p = BatchProcessor::new(&ser.ser_header()).unwrap();
+        let frames = ser.ser_chunks(4);
+        p.process_chunk(&frames[0]).unwrap();
+        assert!(matches!(
+            p.process_chunk(&frames[0]),
+            Err(ProcessError::MalformedInput(InputError::BadFrame { .. }))
+        ));
+        // raw txs under a compressed header are not a frame
+        let mut p = BatchProcessor::new(&ser.ser_header()).unwrap();
+        assert!(matches!(
+            p.process_chunk(&raw),
+            Err(ProcessError::MalformedInput(InputError::BadFrame { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_process_txs_checked_collisions() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let me = pubk_to_adr(alice.verifying_key().to_encoded_point(false).as_bytes());
+        let fee_recipient = [0xFE; 20];
+        let run = |allow_self, allow_fee_recipient, sd, tx: TxToSer| {
+            let ser = InputToSer {
+                state_deltas: sd,
+                fee_recipient,
+                timestamp: NOW,
+                nonce_policy: NoncePolicy::Strict,
+                compressed: false,
+                fee_splits: vec![],
+                transfer_policy: TransferPolicy {
+                    allow_self,
+                    allow_fee_recipient,
+                },
+                min_atoms: 0,
+                domain: DOMAIN,
+                batch_nonce: 0,
+                sig_scheme: SigScheme::Secp256k1,
+                tx: vec![tx],
+            };
+            process_txs_checked(&ser.ser())
+        };
+
+        let own = || signed_tx(&alice, me, 100, 0, (1, 1));
+        let e = run(false, true, 2, own()).unwrap_err();
+        assert_eq!(
+            (e.clone(), e.code()),
+            (ProcessError::SelfTransfer { tx: 0 }, 12)
+        );
+        let r = run(true, false, 2, own()).unwrap();
+        assert_eq!(r.transfer_policy.flags(), 1);
+        assert_eq!((r.n[1].delta, r.n[1].end_nonce), (-(FEE as i128), 0));
+        assert_eq!(r.n[0].delta, FEE as i128, "only the fee moves");
+        assert_eq!(
+            run(true, false, 3, signed_tx(&alice, me, 100, 0, (1, 2))).unwrap_err(),
+            ProcessError::SelfTransfer { tx: 0 },
+            "has to credit the sender delta itself"
+        );
+
+        let to_sink = |to_idx| signed_tx(&alice, fee_recipient, 100, 0, (1, to_idx));
+        let e = run(true, false, 2, to_sink(0)).unwrap_err();
+        assert_eq!(
+            (e.clone(), e.code()),
+            (ProcessError::PaysFeeRecipient { tx: 0 }, 13)
+        );
+        let r = run(false, true, 2, to_sink(0)).unwrap();
+        assert_eq!((r.n[0].delta, r.n[1].delta), (100, -100));
+        assert_eq!(
+            run(false, true, 3, to_sink(2)).unwrap_err(),
+            ProcessError::PaysFeeRecipient { tx: 0 },
+            "has to credit the fee sink delta itself"
+        );
+    }
+
+    #[test]
+    fn test_process_txs_checked_delta_overflow() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let carol = SigningKey::from_bytes(&[0x33; 32].into()).unwrap();
+        let bob = [0xB0; 20];
+        let whale = |sk, idx| token_tx(sk, bob, TOKEN, i128::MAX, FEE, 0, idx);
+        // two near max credits to bob, each fits an i128 but not their sum
+        let inp = input(4, vec![whale(&alice, (1, 2, 0)), whale(&carol, (3, 2, 0))]);
+        let e = process_txs_checked(&inp).unwrap_err();
+        assert_eq!(e, ProcessError::DeltaOverflow { idx: 2 });
+        assert_eq!(e.code(), 17);
+
+        // bob ends at exactly i128::MAX - FEE, his fee share pushes him over
+        let mut ser = InputToSer {
+            state_deltas: 5,
+            fee_recipient: [0xFE; 20],
+            timestamp: NOW,
+            nonce_policy: NoncePolicy::Strict,
+            compressed: false,
+            fee_splits: vec![FeeSplit {
+                recipient: bob,
+                bps: 10_000,
+            }],
+            transfer_policy: TransferPolicy::default(),
+            min_atoms: 0,
+            domain: DOMAIN,
+            batch_nonce: 0,
+            sig_scheme: SigScheme::Secp256k1,
+            tx: vec![
+                whale(&alice, (1, 2, 0)),
+                token_tx(&carol, [0xD0; 20], TOKEN, 100, FEE, 0, (3, 4, 0)),
+            ],
+        };
+        assert_eq!(
+            process_txs_checked(&ser.ser()).unwrap_err(),
+            ProcessError::DeltaOverflow { idx: 2 }
+        );
+        ser.fee_splits[0].bps = 5_000;
+        let d = process_txs_checked(&ser.ser()).unwrap().n;
+        assert_eq!(find(&d, bob, TOKEN, 0).delta, i128::MAX);
+    }
+
+    #[test]
+    fn test_process_txs_checked_min_atoms() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = [0xB0; 20];
+        let run = |atoms| {
+            let ser = InputToSer {
+                state_deltas: 3,
+                fee_recipient: [0xFE; 20],
+                timestamp: NOW,
+                nonce_policy: NoncePolicy::Strict,
+                compressed: false,
+                fee_splits: vec![],
+                transfer_policy: TransferPolicy::default(),
+                min_atoms: 100,
+                domain: DOMAIN,
+                batch_nonce: 0,
+                sig_scheme: SigScheme::Secp256k1,
+                tx: vec![signed_tx(&alice, bob, atoms, 0, (1, 2))],
+            };
+            process_txs_checked(&ser.ser())
+        };
+        assert_eq!(run(100).unwrap().min_atoms, 100, "committed");
+        let e = run(99).unwrap_err();
+        assert_eq!(
+            (e.clone(), e.code()),
+            (ProcessError::BelowMinAtoms { tx: 0, atoms: 99 }, 15)
+        );
+    }
+
+    #[test]
+    fn test_process_txs_checked_errors() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = [0xB0; 20];
+        let err = |tx: Vec<TxToSer>, sd: u32| process_txs_checked(&input(sd, tx)).unwrap_err();
+
+        let e = err(
+            vec![
+                signed_tx(&alice, bob, 100, 0, (1, 2)),
+                signed_tx(&alice, bob, 100, 0, (3, 2)),
+            ],
+            4,
+        );
+        assert_eq!(
+            (e.clone(), e.code()),
+            (ProcessError::DuplicateDelta { idx: 3, other: 1 }, 14),
+            "the same nonce sent twice from two slots"
+        );
+        let e = err(
+            vec![
+                signed_tx(&alice, bob, 100, 0, (1, 2)),
+                signed_tx(&alice, bob, 100, 1, (1, 3)),
+            ],
+            4,
+        );
+        assert_eq!(e, ProcessError::DuplicateDelta { idx: 3, other: 2 });
+
+        let e = err(vec![signed_tx(&alice, bob, 10, 0, (1, 2))], 3);
+        assert_eq!(e, ProcessError::AtomsNotAboveFee { tx: 0, atoms: 10 });
+        let e = err(
+            vec![
+                signed_tx(&alice, bob, 100, 0, (1, 2)),
+                signed_tx(&alice, bob, 100, 2, (1, 2)),
+            ],
+            3,
+        );
+        assert_eq!(
+            e,
+            ProcessError::NonceGap {
+                tx: 1,
+                expected: 1,
+                got: 2
+            }
+        );
+        // a replayed nonce is a gap, reported at the later copy
+        let e = err(
+            vec![
+                signed_tx(&alice, bob, 100, 1, (1, 2)),
+                signed_tx(&alice, bob, 100, 0, (1, 2)),
+                signed_tx(&alice, bob, 100, 1, (1, 2)),
+            ],
+            3,
+        );
+        assert_eq!(
+            e,
+            ProcessError::NonceGap {
+                tx: 2,
+                expected: 2,
+                got: 1
+            }
+        );
+        let e = err(vec![signed_tx(&alice, bob, 100, 0, (1, 3))], 3);
+        assert_eq!(e, ProcessError::DeltaIdxOutOfRange { tx: 0, idx: 3 });
+        let e = err(vec![signed_tx(&alice, bob, 100, 0, (1, 1))], 3);
+        assert_eq!(e, ProcessError::DeltaAddrMismatch { tx: 0, idx: 1 });
+        let e = err(vec![signed_tx(&alice, bob, 100, 0, (1, 2))], 4);
+        assert_eq!(e, ProcessError::UnusedDelta { idx: 3 });
+        assert_eq!(e.code(), 6);
+
+        let mut bad = signed_tx(&alice, bob, 100, 0, (1, 2));
+        bad.sig_r = [0; 32];
+        assert_eq!(err(vec![bad], 3), ProcessError::InvalidSignature { tx: 0 });
+        let mut bad = signed_tx(&alice, bob, 100, 0, (1, 2));
+        bad.v += 2;
+        assert_eq!(
+            err(vec![bad.clone()], 3),
+            ProcessError::BadRecoveryId { tx: 0, v: bad.v }
+        );
+
+        // same signature with s flipped to n - s still verifies, but is malleable
+        let mut high = signed_tx(&alice, bob, 100, 0, (1, 2));
+        let s = k256::Scalar::from_repr(high.sig_s.into()).unwrap();
+        high.sig_s = (-s).to_bytes().into();
+        high.v ^= 1;
+        let e = err(vec![high], 3);
+        assert_eq!(e, ProcessError::HighS { tx: 0 });
+        assert_eq!(e.code(), 10);
+
+        // signed for another domain recovers to someone else, who has no nonce/delta here
+        let inp = input(3, vec![signed_tx(&alice, bob, 100, 0, (1, 2))]);
+        let mut other = inp.clone();
+        other[139..147].copy_from_slice(&2u64.to_be_bytes());
+        let d = process_txs_checked(&other).unwrap().n;
+        assert_ne!(d[1].v, process_txs_checked(&inp).unwrap().n[1].v);
+
+        let mut stale = signed_tx(&alice, bob, 100, 0, (1, 2));
+        stale.valid_until = NOW - 1;
+        let e = err(vec![sign(&alice, stale)], 3);
+        assert_eq!(
+            e,
+            ProcessError::Expired {
+                tx: 0,
+                valid_until: NOW - 1
+            }
+        );
+        assert_eq!(e.code(), 8);
+
+        let mut truncated = input(3, vec![signed_tx(&alice, bob, 100, 0, (1, 2))]);
+        truncated.pop();
+        assert!(matches!(
+            process_txs_checked(&truncated),
+            Err(ProcessError::MalformedInput(_))
+        ));
+    }
 }
@@ -0,0 +1,168 @@
+//! Merkle tree over the eip-712 digests of the txs of a batch, in input order.
+//! Leaves are hashed once more, `keccak256(leaf)`, as OpenZeppelin `StandardMerkleTree` does:
+//! a leaf preimage is 32 bytes and a node preimage 64, so an inner node can't be passed off
+//! as a leaf. Pairs are hashed sorted, `keccak256(min(a, b) ‖ max(a, b))`, the same as
+//! `MerkleProof.verify`, so a proof is just the sibling hashes bottom up, checked against
+//! `hash_leaf(leaf)`.
+//! A node without a sibling is carried up a level unchanged, an empty tree has a zero root.
+//!
+//! The program only keeps the frontier (one pending subtree per level), the host side
+//! `proof` rebuilds the whole tree from the leaves.
+use tiny_keccak::{Hasher, Keccak};
+
+pub fn hash_leaf(leaf: &[u8; 32]) -> [u8; 32] {
+    let mut h = Keccak::v256();
+    h.update(leaf);
+    let mut out = [0; 32];
+    h.finalize(&mut out);
+    out
+}
+
+pub fn hash_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+    let mut h = Keccak::v256();
+    h.update(lo);
+    h.update(hi);
+    let mut out = [0; 32];
+    h.finalize(&mut out);
+    out
+}
+
+/// streaming root, pushing n leaves keeps at most log2(n) + 1 hashes around
+#[derive(Default)]
+pub struct MerkleFrontier {
+    /// (level, root of a complete subtree), levels strictly decreasing
+    stack: Vec<(u32, [u8; 32])>,
+}
+
+impl MerkleFrontier {
+    pub fn push(&mut self, leaf: [u8; 32]) {
+        let mut node = (0, hash_leaf(&leaf));
+        while let Some(&(level, left)) = self.stack.last() {
+            if level != node.0 {
+                break;
+            }
+            self.stack.pop();
+            node = (level + 1, hash_pair(&left, &node.1));
+        }
+        self.stack.push(node);
+    }
+
+    /// the incomplete right edge folds in from the right, as carrying up odd nodes would
+    pub fn root(&self) -> [u8; 32] {
+        let mut nodes = self.stack.iter().rev().map(|x| x.1);
+        let Some(last) = nodes.next() else {
+            return [0; 32];
+        };
+        nodes.fold(last, |acc, left| hash_pair(&left, &acc))
+    }
+}
+
+pub fn root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    let mut f = MerkleFrontier::default();
+    for leaf in leaves {
+        f.push(*leaf);
+    }
+    f.root()
+}
+
+/// sibling hashes from leaf `idx` up to the root, levels where it has no sibling are skipped.
+/// `None` if idx is out of range
+pub fn proof(leaves: &[[u8; 32]], mut idx: usize) -> Option<Vec<[u8; 32]>> {
+    if idx >= leaves.len() {
+        return None;
+    }
+    let mut level: Vec<_> = leaves.iter().map(hash_leaf).collect();
+    let mut out = vec![];
+    while level.len() > 1 {
+        if let Some(sibling) = level.get(idx ^ 1) {
+            out.push(*sibling);
+        }
+        level = level
+            .chunks(2)
+            .map(|x| match x {
+                [a, b] => hash_pair(a, b),
+                [a] => *a,
+                _ => unreachable!(),
+            })
+            .collect();
+        idx /= 2;
+    }
+    Some(out)
+}
+
+pub fn verify(proof: &[[u8; 32]], root: &[u8; 32], leaf: &[u8; 32]) -> bool {
+    proof
+        .iter()
+        .fold(hash_leaf(leaf), |acc, x| hash_pair(&acc, x))
+        == *root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: u8) -> Vec<[u8; 32]> {
+        (0..n).map(|i| [i + 1; 32]).collect()
+    }
+
+    /// level by level, carrying odd nodes up
+    fn naive_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+        if leaves.is_empty() {
+            return [0; 32];
+        }
+        let mut level: Vec<_> = leaves.iter().map(hash_leaf).collect();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|x| {
+                    if x.len() == 2 {
+                        hash_pair(&x[0], &x[1])
+                    } else {
+                        x[0]
+                    }
+                })
+                .collect();
+        }
+        level[0]
+    }
+
+    #[test]
+    fn test_frontier_matches_naive() {
+        for n in 0..34 {
+            let l = leaves(n);
+            assert_eq!(root(&l), naive_root(&l), "n={n}");
+        }
+        assert_eq!(
+            root(&leaves(1)),
+            hash_leaf(&[1; 32]),
+            "a single leaf hashed is the root"
+        );
+        assert_eq!(hash_pair(&[1; 32], &[2; 32]), hash_pair(&[2; 32], &[1; 32]));
+    }
+
+    #[test]
+    fn test_proof() {
+        for n in 1..20 {
+            let l = leaves(n);
+            let r = root(&l);
+            for (i, leaf) in l.iter().enumerate() {
+                let p = proof(&l, i).unwrap();
+                assert!(verify(&p, &r, leaf), "n={n} i={i}");
+                assert!(!verify(&p, &r, &[0xEE; 32]));
+            }
+            assert_eq!(proof(&l, n as usize), None);
+        }
+    }
+
+    #[test]
+    fn test_inner_node_is_no_leaf() {
+        let l = leaves(4);
+        let r = root(&l);
+        // the left inner node, proven with its sibling as a leaf one level up
+        let inner = hash_pair(&hash_leaf(&l[0]), &hash_leaf(&l[1]));
+        let sibling = hash_pair(&hash_leaf(&l[2]), &hash_leaf(&l[3]));
+        assert_eq!(hash_pair(&inner, &sibling), r);
+        assert!(!verify(&[sibling], &r, &inner));
+    }
+}
@@ -0,0 +1,258 @@
+//! Packed encoding of the state deltas, an alternative to ABI encoding `StateDelta[]`.
+//! ABI pads every field to a 32 byte word, so a delta costs 8 words of calldata.
+//! Packed, a receiver costs 41-59 bytes (41-50 for amounts that fit 64 bits) and a sender a few
+//! more for the nonces.
+//!
+//! Layout, integers are big-endian unless varint:
+//! ```text
+//! count           u32
+//! nonce_policy    u8, shared by every entry, the whole batch runs under one policy
+//! sender bitmask  ceil(count / 8) bytes, entry i is a sender if bit (i % 8) of byte (i / 8) is set
+//! solvent bitmask ceil(count / 8) bytes, same bit order, entry i is `StateDelta::solvent`
+//! entries         count times, strictly ascending by (v, token, channel):
+//!     v           20 bytes
+//!     token       20 bytes
+//!     delta       zigzag LEB128 varint of the int128
+//!     start_nonce LEB128 varint, senders only
+//!     nonce_span  LEB128 varint of end_nonce - start_nonce, senders only
+//!     channel     LEB128 varint, senders only
+//! ```
+//! LEB128 is 7 bits per byte, low group first, the high bit is set on every byte but the last,
+//! always in its shortest form: no trailing zero groups, nothing past the 128th bit.
+//! zigzag maps 0, -1, 1, -2, .. to 0, 1, 2, 3, .. so small debits stay small.
+//!
+//! Solidity side, walk the calldata with a cursor:
+//! `count` = first 4 bytes, the policy byte and the two bitmasks follow, then per entry read 20 + 20 bytes of
+//! addresses, check `(v, token, channel)` is above the previous entry (uniqueness without a mapping),
+//! decode the varints and `delta = (z >> 1) ^ -(z & 1)`.
+//! An entry is a sender iff its bitmask bit is set, only then do the nonce and channel varints follow.
+use crate::StateDelta;
+
+fn write_varint(out: &mut Vec<u8>, mut x: u128) {
+    while x >= 0x80 {
+        out.push((x as u8) | 0x80);
+        x >>= 7;
+    }
+    out.push(x as u8);
+}
+
+/// only the shortest encoding is accepted, so a value has exactly one encoding:
+/// a last byte of 0 after the first (zero padding) and bits shifted past the 128th are rejected
+fn read_varint(v: &[u8], at: &mut usize) -> Option<u128> {
+    let mut x: u128 = 0;
+    for shift in (0..128).step_by(7) {
+        let b = *v.get(*at)?;
+        *at += 1;
+        if shift == 126 && b & 0x7f > 0x03 {
+            return None;
+        }
+        x |= ((b & 0x7f) as u128) << shift;
+        if b & 0x80 == 0 {
+            return (b != 0 || shift == 0).then_some(x);
+        }
+    }
+    None
+}
+
+/// nonces and channels are narrower than the varint
+fn read_varint_u64(v: &[u8], at: &mut usize) -> Option<u64> {
+    read_varint(v, at)?.try_into().ok()
+}
+
+fn zigzag(x: i128) -> u128 {
+    ((x << 1) ^ (x >> 127)) as u128
+}
+
+fn unzigzag(z: u128) -> i128 {
+    ((z >> 1) as i128) ^ -((z & 1) as i128)
+}
+
+/// encode `n` in the packed layout, entries are sorted by (v, token, channel).
+/// the nonce policy is taken from the first entry, `process_txs` sets the same on all of them
+pub fn pack(n: &[StateDelta]) -> Vec<u8> {
+    let mut sorted: Vec<&StateDelta> = n.iter().collect();
+    sorted.sort_by_key(|d| (d.v, d.token, d.channel));
+
+    let mut out = Vec::with_capacity(5 + 2 * n.len().div_ceil(8) + n.len() * 48);
+    out.extend_from_slice(&(n.len() as u32).to_be_bytes());
+    out.push(n.first().map_or(0, |d| d.nonce_policy));
+    let mut mask = vec![0u8; n.len().div_ceil(8)];
+    let mut solvent = vec![0u8; n.len().div_ceil(8)];
+    for (i, d) in sorted.iter().enumerate() {
+        if d.is_sender {
+            mask[i / 8] |= 1 << (i % 8);
+        }
+        if d.solvent {
+            solvent[i / 8] |= 1 << (i % 8);
+        }
+    }
+    out.extend_from_slice(&mask);
+    out.extend_from_slice(&solvent);
+    for d in sorted {
+        out.extend_from_slice(d.v.as_slice());
+        out.extend_from_slice(d.token.as_slice());
+        write_varint(&mut out, zigzag(d.delta));
+        if d.is_sender {
+            write_varint(&mut out, d.start_nonce as u128);
+            write_varint(&mut out, (d.end_nonce - d.start_nonce) as u128);
+            write_varint(&mut out, d.channel as u128);
+        }
+    }
+    out
+}
+
+/// `None` if `v` is truncated, has trailing bytes or the entries are not strictly ascending
+pub fn unpack(v: &[u8]) -> Option<Vec<StateDelta>> {
+    let count = u32::from_be_bytes(v.get(..4)?.try_into().unwrap()) as usize;
+    let nonce_policy = *v.get(4)?;
+    let len = count.div_ceil(8);
+    let mask = v.get(5..5 + len)?;
+    let solvent = v.get(5 + len..5 + 2 * len)?;
+    let mut at = 5 + 2 * len;
+    let mut n: Vec<StateDelta> = Vec::with_capacity(count);
+    for i in 0..count {
+        let bit = |mask: &[u8]| mask[i / 8] & (1 << (i % 8)) != 0;
+        let is_sender = bit(mask);
+        let a: [u8; 20] = v.get(at..at + 20)?.try_into().unwrap();
+        let token: [u8; 20] = v.get(at + 20..at + 40)?.try_into().unwrap();
+        at += 40;
+        let delta = unzigzag(read_varint(v, &mut at)?);
+        let (start_nonce, end_nonce, channel) = if is_sender {
+            let start = read_varint_u64(v, &mut at)?;
+            let end = start.checked_add(read_varint_u64(v, &mut at)?)?;
+            (start, end, read_varint_u64(v, &mut at)?.try_into().ok()?)
+        } else {
+            (0, 0, 0)
+        };
+        let d = StateDelta {
+            v: a.into(),
+            token: token.into(),
+            is_sender,
+            start_nonce,
+            end_nonce,
+            channel,
+            nonce_policy,
+            delta,
+            solvent: bit(solvent),
+        };
+        if let Some(prev) = n.last() {
+            if (prev.v, prev.token, prev.channel) >= (d.v, d.token, d.channel) {
+                return None;
+            }
+        }
+        n.push(d);
+    }
+    (at == v.len()).then_some(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_sol_types::SolValue;
+
+    /// nonces are (start, end, channel)
+    fn delta(v: u8, token: u8, delta: i128, nonces: Option<(u64, u64, u32)>) -> StateDelta {
+        StateDelta {
+            v: [v; 20].into(),
+            token: [token; 20].into(),
+            is_sender: nonces.is_some(),
+            start_nonce: nonces.map(|x| x.0).unwrap_or_default(),
+            end_nonce: nonces.map(|x| x.1).unwrap_or_default(),
+            channel: nonces.map(|x| x.2).unwrap_or_default(),
+            nonce_policy: 1,
+            delta,
+            solvent: delta < 0,
+        }
+    }
+
+    #[test]
+    fn test_varint_zigzag() {
+        for x in [
+            0,
+            1,
+            -1,
+            63,
+            -64,
+            64,
+            i64::MAX as i128,
+            i128::MAX,
+            i128::MIN,
+        ] {
+            let mut out = vec![];
+            write_varint(&mut out, zigzag(x));
+            let mut at = 0;
+            assert_eq!(unzigzag(read_varint(&out, &mut at).unwrap()), x);
+            assert_eq!(at, out.len());
+        }
+        let mut out = vec![];
+        write_varint(&mut out, zigzag(-64));
+        assert_eq!(out.len(), 1, "small debits fit one byte");
+
+        for bad in [
+            &[0x80, 0x00][..],
+            &[0x81, 0x80, 0x00],
+            &[0xff; 19],
+            &[[0xff; 18].as_slice(), &[0x04]].concat(),
+        ] {
+            assert!(read_varint(bad, &mut 0).is_none(), "{bad:02x?}");
+        }
+        let mut max = vec![0xff; 18];
+        max.push(0x03);
+        assert_eq!(read_varint(&max, &mut 0), Some(u128::MAX));
+    }
+
+    #[test]
+    fn test_pack_round_trip() {
+        let n = vec![
+            delta(0xFE, 1, 30, None),
+            delta(0xA0, 1, -300, Some((5, 9, 0))),
+            delta(0xB0, 1, 270, None),
+            delta(0xA0, 2, -40, Some((u64::MAX - 1, u64::MAX, 0))),
+            delta(0xA0, 1, -5, Some((0, 0, u32::MAX))),
+        ];
+        let packed = pack(&n);
+        let out = unpack(&packed).unwrap();
+        let key = |d: &StateDelta| {
+            (
+                d.v,
+                d.token,
+                d.channel,
+                d.is_sender,
+                d.start_nonce,
+                d.end_nonce,
+                d.nonce_policy,
+                d.delta,
+                d.solvent,
+            )
+        };
+        let mut expected: Vec<_> = n.iter().map(key).collect();
+        expected.sort();
+        assert_eq!(out.iter().map(key).collect::<Vec<_>>(), expected);
+        // sorted (a0,1,0) sender, (a0,1,max) sender, (a0,2,0) sender, (b0,1), (fe,1)
+        assert_eq!(packed[4], 1, "nonce policy");
+        assert_eq!(packed[5], 0b00111);
+        assert_eq!(packed[6], 0b00111, "solvent");
+
+        assert!(
+            packed.len() * 3 < n.abi_encoded_size(),
+            "packed={} abi={}",
+            packed.len(),
+            n.abi_encoded_size()
+        );
+    }
+
+    #[test]
+    fn test_unpack_rejects() {
+        let n = vec![delta(0xA0, 1, -3, Some((0, 1, 0))), delta(0xB0, 1, 3, None)];
+        let packed = pack(&n);
+        assert!(unpack(&packed[..packed.len() - 1]).is_none(), "truncated");
+        assert!(
+            unpack(&[packed.clone(), vec![0]].concat()).is_none(),
+            "trailing"
+        );
+
+        let dup = pack(&[delta(0xB0, 1, 3, None), delta(0xB0, 1, 3, None)]);
+        assert!(unpack(&dup).is_none(), "not strictly ascending");
+        assert_eq!(unpack(&pack(&[])).unwrap().len(), 0);
+    }
+}
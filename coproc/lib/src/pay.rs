@@ -0,0 +1,310 @@
+//! Ingestion of `ddm` payments into the coproc wire format,
+//! so the SP1 path and the payment model share one pipeline.
+use crate::builder::BatchBuilder;
+use crate::ds::{Eip712Domain, InputToSer, NoncePolicy, TxKind, TxToSer};
+use crate::ProcessError;
+use ddm::pay::GPayment;
+
+/// secp256k1 recoverable signature (r, s, v) over `TxToSer::keccak`
+pub type Sig = ([u8; 32], [u8; 32], u8);
+
+/// A payment as the coproc understands it:
+/// the vendor is the recipient address, amount is in atoms,
+/// chain_id is the one of the eip-712 domain the payment is signed under.
+/// product_id is not part of the tx wire format and is dropped.
+pub type CoprocPayment<P> = GPayment<[u8; 20], u64, u64, P, u64, Sig>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConvertErr {
+    /// the program asserts `atoms > fee`
+    AtomsBelowFee { idx: usize, atoms: u64 },
+    /// signer could not be recovered from the signature
+    InvalidSignature { idx: usize },
+    /// payment is signed for another chain than the batch domain
+    ChainIdMismatch { idx: usize, chain_id: u64 },
+    /// the program rejects txs whose `valid_until` is before the batch timestamp
+    Expired { idx: usize },
+    /// the program rejects txs below the batch `min_atoms`
+    BelowMinAtoms { idx: usize, atoms: u64 },
+    /// the payer pays itself, the program rejects it under the default `TransferPolicy`
+    SelfPayment { idx: usize },
+    /// the vendor is the batch fee recipient, rejected under the default `TransferPolicy`
+    PaysFeeRecipient { idx: usize },
+    /// the payer's nonces are spaced further apart than the batch `nonce_policy` allows
+    NonceGap { idx: usize, expected: u64, got: u64 },
+    /// any other reason `BatchBuilder` turned the payments down
+    Rejected(ProcessError),
+}
+
+impl ConvertErr {
+    /// `tx` of a builder error is the payment index, every payment is a single tx
+    fn from_builder(err: ProcessError) -> Self {
+        match err {
+            ProcessError::InvalidSignature { tx }
+            | ProcessError::HighS { tx }
+            | ProcessError::BadRecoveryId { tx, .. } => Self::InvalidSignature { idx: tx as usize },
+            ProcessError::SelfTransfer { tx } => Self::SelfPayment { idx: tx as usize },
+            ProcessError::PaysFeeRecipient { tx } => Self::PaysFeeRecipient { idx: tx as usize },
+            ProcessError::NonceGap { tx, expected, got } => Self::NonceGap {
+                idx: tx as usize,
+                expected,
+                got,
+            },
+            err => Self::Rejected(err),
+        }
+    }
+}
+
+impl std::fmt::Display for ConvertErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AtomsBelowFee { idx, atoms } => {
+                write!(f, "payment {idx} atoms={atoms} not above batch fee")
+            }
+            Self::InvalidSignature { idx } => write!(f, "payment {idx} has invalid signature"),
+            Self::ChainIdMismatch { idx, chain_id } => {
+                write!(
+                    f,
+                    "payment {idx} chain_id={chain_id} differs from batch domain"
+                )
+            }
+            Self::Expired { idx } => write!(f, "payment {idx} expired before batch timestamp"),
+            Self::BelowMinAtoms { idx, atoms } => {
+                write!(f, "payment {idx} atoms={atoms} below batch min_atoms")
+            }
+            Self::SelfPayment { idx } => write!(f, "payment {idx} pays its own payer"),
+            Self::PaysFeeRecipient { idx } => write!(f, "payment {idx} pays the fee recipient"),
+            Self::NonceGap { idx, expected, got } => {
+                write!(f, "payment {idx} nonce={got} expected={expected}")
+            }
+            Self::Rejected(err) => write!(f, "payments rejected: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ConvertErr {}
+
+/// What the payment model has no notion of, fixed for the whole batch.
+/// The signed fields (token, fee, valid_until) have to match what the payers signed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchParams {
+    pub token: [u8; 20],
+    pub fee: u32,
+    pub valid_until: u64,
+    pub fee_recipient: [u8; 20],
+    pub timestamp: u64,
+    pub nonce_policy: NoncePolicy,
+    pub min_atoms: u64,
+    pub batch_nonce: u64,
+}
+
+/// indices are left at 0, `BatchBuilder` assigns them per batch in `input_from_payments`.
+/// the payment model keeps one nonce counter per payer, so everything is on channel 0
+pub fn to_tx<P>(p: &CoprocPayment<P>, params: &BatchParams) -> TxToSer {
+    TxToSer {
+        to: p.vendor,
+        token: params.token,
+        atoms: p.amount as i128,
+        nonce: p.nonce,
+        channel: 0,
+        fee: params.fee,
+        valid_until: params.valid_until,
+        kind: TxKind::Transfer,
+        memo: vec![],
+        sig_r: p.signature.0,
+        sig_s: p.signature.1,
+        v: p.signature.2,
+        from_idx: 0,
+        to_idx: 0,
+        fee_idx: 0,
+        pubkey: None,
+    }
+}
+
+/// Convert a batch of signed payments into the program input, built with `BatchBuilder` under
+/// the default `TransferPolicy`, so an input that converts is one the program accepts.
+/// Paying oneself or the fee recipient is an error, as is a gap in a payer's nonces.
+pub fn input_from_payments<P>(
+    payments: &[CoprocPayment<P>],
+    domain: &Eip712Domain,
+    params: &BatchParams,
+) -> Result<InputToSer, ConvertErr> {
+    let mut builder = BatchBuilder::new(params.fee_recipient, params.timestamp, *domain)
+        .nonce_policy(params.nonce_policy)
+        .min_atoms(params.min_atoms)
+        .batch_nonce(params.batch_nonce);
+    for (i, p) in payments.iter().enumerate() {
+        if p.chain_id != domain.chain_id {
+            return Err(ConvertErr::ChainIdMismatch {
+                idx: i,
+                chain_id: p.chain_id,
+            });
+        }
+        if p.amount <= params.fee as u64 {
+            return Err(ConvertErr::AtomsBelowFee {
+                idx: i,
+                atoms: p.amount,
+            });
+        }
+        if p.amount < params.min_atoms {
+            return Err(ConvertErr::BelowMinAtoms {
+                idx: i,
+                atoms: p.amount,
+            });
+        }
+        if params.timestamp > params.valid_until {
+            return Err(ConvertErr::Expired { idx: i });
+        }
+        builder
+            .add(to_tx(p, params))
+            .map_err(ConvertErr::from_builder)?;
+    }
+    builder.build().map_err(ConvertErr::from_builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_txs_checked;
+    use k256::ecdsa::SigningKey;
+
+    const DOMAIN: Eip712Domain = Eip712Domain {
+        chain_id: 1,
+        verifying_contract: [0xC0; 20],
+    };
+    const PARAMS: BatchParams = BatchParams {
+        token: [0xD0; 20],
+        fee: 20,
+        valid_until: 2_000_000_000,
+        fee_recipient: [0xFE; 20],
+        timestamp: 1_700_000_000,
+        nonce_policy: NoncePolicy::Strict,
+        min_atoms: 0,
+        batch_nonce: 0,
+    };
+
+    fn signed(sk: &SigningKey, vendor: [u8; 20], nonce: u64, amount: u64) -> CoprocPayment<u64> {
+        let mut p = GPayment {
+            vendor,
+            nonce,
+            chain_id: 1,
+            product_id: 7,
+            amount,
+            signature: ([0; 32], [0; 32], 0),
+        };
+        let digest = to_tx(&p, &PARAMS).keccak(&DOMAIN.separator());
+        let (sig, rec) = sk.sign_prehash_recoverable(&digest).unwrap();
+        p.signature = (
+            sig.r().to_bytes().into(),
+            sig.s().to_bytes().into(),
+            rec.to_byte(),
+        );
+        p
+    }
+
+    #[test]
+    fn test_input_from_payments_assigns_idx() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let bob = SigningKey::from_bytes(&[0x22; 32].into()).unwrap();
+        let vendor = [0xAA; 20];
+        let payments = vec![
+            signed(&alice, vendor, 1, 1000),
+            signed(&alice, vendor, 2, 500),
+            signed(&bob, vendor, 9, 300),
+        ];
+
+        let inp = input_from_payments(&payments, &DOMAIN, &PARAMS).unwrap();
+        // alice, vendor, fee sink, bob
+        assert_eq!(inp.state_deltas, 4);
+        assert_eq!((inp.tx[0].from_idx, inp.tx[0].to_idx), (0, 1));
+        assert_eq!((inp.tx[1].from_idx, inp.tx[1].to_idx), (0, 1));
+        assert_eq!((inp.tx[2].from_idx, inp.tx[2].to_idx), (3, 1));
+        assert_eq!(inp.tx[1].atoms, 500);
+        assert_eq!(inp.tx[2].nonce, 9);
+        assert_eq!(inp.timestamp, PARAMS.timestamp);
+        assert!(inp
+            .tx
+            .iter()
+            .all(|t| t.token == PARAMS.token && t.fee == 20 && t.fee_idx == 2));
+
+        let r = process_txs_checked(&inp.ser()).unwrap();
+        assert_eq!(r.total_fee, 60);
+    }
+
+    #[test]
+    fn test_input_from_payments_rejects() {
+        let alice = SigningKey::from_bytes(&[0x11; 32].into()).unwrap();
+        let payments = vec![
+            signed(&alice, [0xAA; 20], 1, 1000),
+            signed(&alice, [0xAA; 20], 2, 20),
+        ];
+        assert_eq!(
+            input_from_payments(&payments, &DOMAIN, &PARAMS).err(),
+            Some(ConvertErr::AtomsBelowFee { idx: 1, atoms: 20 })
+        );
+
+        let alice_adr = crate::key_to_adr(alice.verifying_key());
+        assert_eq!(
+            input_from_payments(&[signed(&alice, alice_adr, 1, 1000)], &DOMAIN, &PARAMS).err(),
+            Some(ConvertErr::SelfPayment { idx: 0 })
+        );
+        let to_fee_sink = vec![
+            signed(&alice, [0xAA; 20], 1, 1000),
+            signed(&alice, PARAMS.fee_recipient, 2, 1000),
+        ];
+        assert_eq!(
+            input_from_payments(&to_fee_sink, &DOMAIN, &PARAMS).err(),
+            Some(ConvertErr::PaysFeeRecipient { idx: 1 })
+        );
+        let gap = vec![
+            signed(&alice, [0xAA; 20], 1, 1000),
+            signed(&alice, [0xAA; 20], 3, 1000),
+        ];
+        assert_eq!(
+            input_from_payments(&gap, &DOMAIN, &PARAMS).err(),
+            Some(ConvertErr::NonceGap {
+                idx: 1,
+                expected: 2,
+                got: 3
+            })
+        );
+
+        let mut bad = signed(&alice, [0xAA; 20], 1, 1000);
+        bad.signature.2 = 7;
+        assert_eq!(
+            input_from_payments(&[bad], &DOMAIN, &PARAMS).err(),
+            Some(ConvertErr::InvalidSignature { idx: 0 })
+        );
+
+        let mut other_chain = signed(&alice, [0xAA; 20], 1, 1000);
+        other_chain.chain_id = 10;
+        assert_eq!(
+            input_from_payments(&[other_chain], &DOMAIN, &PARAMS).err(),
+            Some(ConvertErr::ChainIdMismatch {
+                idx: 0,
+                chain_id: 10
+            })
+        );
+
+        let late = BatchParams {
+            timestamp: PARAMS.valid_until + 1,
+            ..PARAMS
+        };
+        assert_eq!(
+            input_from_payments(&payments[..1], &DOMAIN, &late).err(),
+            Some(ConvertErr::Expired { idx: 0 })
+        );
+
+        let strict = BatchParams {
+            min_atoms: 1001,
+            ..PARAMS
+        };
+        assert_eq!(
+            input_from_payments(&payments[..1], &DOMAIN, &strict).err(),
+            Some(ConvertErr::BelowMinAtoms {
+                idx: 0,
+                atoms: 1000
+            })
+        );
+    }
+}
@@ -9,7 +9,53 @@
 sp1_zkvm::entrypoint!(main);
 
 use alloy_sol_types::SolType;
-use fibonacci_lib::{ds::*, process_txs, PublicValuesStruct};
+use fibonacci_lib::{
+    frame::unframe, public_values, BatchProcessor, ProcessError, Processed, PublicValuesStruct,
+    Rejected,
+};
+
+/// the txs come in as separate segments after the header,
+/// each chunk is dropped once applied so memory stays bounded by the chunk size.
+/// every segment is a `frame`. A failure is committed against the input read until then
+fn process(
+    header: &[u8],
+    keys: &[u8],
+    balances: &[u8],
+    receipts: bool,
+    discover: bool,
+    skip: bool,
+) -> Result<Processed, Rejected> {
+    let header = unframe(header).map_err(|e| Rejected::header(header, e))?;
+    let mut p = BatchProcessor::new(header).map_err(|e| Rejected::header(header, e))?;
+    let fed = feed(&mut p, keys, balances, receipts, discover, skip);
+    p.finish_or_reject(fed)
+}
+
+fn feed(
+    p: &mut BatchProcessor,
+    keys: &[u8],
+    balances: &[u8],
+    receipts: bool,
+    discover: bool,
+    skip: bool,
+) -> Result<(), ProcessError> {
+    if discover {
+        p.discover_deltas();
+    }
+    if skip {
+        p.skip_invalid();
+    }
+    p.sender_keys(unframe(keys)?)?;
+    p.prior_balances(unframe(balances)?)?;
+    if receipts {
+        p.receipts();
+    }
+    while p.remaining() > 0 {
+        let chunk = sp1_zkvm::io::read_vec();
+        p.process_chunk(unframe(&chunk)?)?;
+    }
+    Ok(())
+}
 
 pub fn main() {
     // Read an input to the program.
@@ -17,18 +63,35 @@ pub fn main() {
     // Behind the scenes, this compiles down to a custom system call which handles reading inputs
     // from the prover.
     println!("cycle-tracker-start: read_input");
-    let inp = sp1_zkvm::io::read_vec();
+    let header = sp1_zkvm::io::read_vec();
+    // packed: commit the deltas as `packed_n` instead of the abi encoded `n`
+    // memos: commit `memo_hash`, zero otherwise
+    // metering: commit `metering`, zero otherwise
+    // receipts: commit `receipt_root`, zero otherwise
+    // discover: assign the delta slots here, ignoring the tx idx hints
+    // skip: skip the invalid txs and commit them in `skipped`, instead of failing the batch
+    let (packed, memos, metering, receipts, discover, skip) =
+        sp1_zkvm::io::read::<(bool, bool, bool, bool, bool, bool)>();
+    // `SenderKeys`, empty if the host has none, the keyed senders are verified not recovered
+    let keys = sp1_zkvm::io::read_vec();
+    // `PriorBalances`, empty if the host has none, the covered deltas are committed solvent
+    let balances = sp1_zkvm::io::read_vec();
     println!("cycle-tracker-end: read_input");
     // let inp = deserialize::<Input, Error>(&input).unwrap();
 
-    // program gets some weird 8 bytes lead on the input
     println!("cycle-tracker-start: process_tx");
-    let r = process_txs(&inp[8..]);
+    let r = process(&header, &keys, &balances, receipts, discover, skip);
     println!("cycle-tracker-end: process_tx");
 
     // Encode the public values of the program.
+    // An invalid batch still proves, but commits the failure code so the host learns why.
     println!("cycle-tracker-start: ser_output");
-    let bytes = PublicValuesStruct::abi_encode(&PublicValuesStruct { n: r });
+    if let Err(e) = &r {
+        println!("invalid batch: {}", e);
+    }
+    let header = unframe(&header).unwrap_or_default();
+    let pv = public_values(r, header, packed, memos, metering);
+    let bytes = PublicValuesStruct::abi_encode(&pv);
     println!("cycle-tracker-end: ser_output");
     // let bytes = vec![];
 
@@ -10,5 +10,6 @@ fn main() {
         ..Default::default()
     };
 
-    build_program_with_args("../program", args);
+    build_program_with_args("../program", args.clone());
+    build_program_with_args("../aggregation", args);
 }
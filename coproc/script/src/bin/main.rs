@@ -1,11 +1,25 @@
+use alloy_primitives::{Address, U256};
+use alloy_rpc_types_eth::{Filter, Log};
 use alloy_sol_types::SolType;
-use clap::Parser;
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, State},
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Json, Router,
+};
+use clap::{Parser, Subcommand, ValueEnum};
 use fibonacci_lib::{
-    ds::{InputToSer, TxToSer},
-    PublicValuesStruct,
+    builder::BatchBuilder,
+    calldata::{Function, VERIFY_PROOF},
+    ds::{Eip712Domain, Input, InputToSer, PriorBalances, SenderKeys, TxKind, TxToSer},
+    frame::frame,
+    inspect::inspect,
+    merkle, process_txs_committed, public_values, AggregateValuesStruct, BatchProcessor,
+    ProcessError, PublicValuesStruct, Rejected,
 };
 use k256::{
-    ecdsa::{RecoveryId, SigningKey, VerifyingKey},
+    ecdsa::SigningKey,
     elliptic_curve::{
         rand_core::{self, CryptoRng, RngCore},
         sec1::ToEncodedPoint,
@@ -14,31 +28,1254 @@ use k256::{
     Secp256k1,
 };
 use rand::{rngs::StdRng, SeedableRng};
-use serde::Deserialize;
-use sp1_sdk::{include_elf, ProverClient, SP1Stdin};
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use sp1_sdk::{
+    include_elf, EnvProver, ExecutionReport, HashableKey, Prover, ProverClient, SP1Proof,
+    SP1ProofWithPublicValues, SP1ProvingKey, SP1Stdin, SP1VerifyingKey,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::time::{SystemTime, UNIX_EPOCH};
 use tiny_keccak::{Hasher, Keccak};
 
 /// The ELF (executable and linkable format) file for the Succinct RISC-V zkVM.
 pub const FIBONACCI_ELF: &[u8] = include_elf!("fibonacci-program");
 
+/// The aggregation program, verifies batch proofs of `FIBONACCI_ELF`
+pub const AGGREGATION_ELF: &[u8] = include_elf!("aggregation-program");
+
+/// mainnet, the settlement contract is not deployed yet
+const DOMAIN: Eip712Domain = Eip712Domain {
+    chain_id: 1,
+    verifying_contract: [0; 20],
+};
+
+/// mainnet USDC, the token of the benchmark transfers
+const USDC: [u8; 20] = [
+    0xa0, 0xb8, 0x69, 0x91, 0xc6, 0x21, 0x8b, 0x36, 0xc1, 0xd1, 0x9d, 0x4a, 0x2e, 0x9e, 0xb0, 0xce,
+    0x36, 0x06, 0xeb, 0x48,
+];
+
+/// fee every mock tx pays to the fee sink
+const FEE: u32 = 20;
+
+/// how long a mock tx stays settleable after signing
+const VALID_FOR_SECS: u64 = 24 * 60 * 60;
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
 /// The arguments for the command.
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_override_self = true)]
 struct Args {
+    #[command(subcommand)]
+    cmd: Option<Cmd>,
+
+    /// TOML file of defaults for the flags above the subcommand, a key per long flag, ex.
+    /// `limit = 1000`, `evm-proof = "groth16"` or `packed = true`. The command line overrides it
+    #[arg(long)]
+    config: Option<PathBuf>,
+
     #[arg(long)]
     execute: bool,
 
     #[arg(long)]
     prove: bool,
 
+    /// Execute, prove, and compute the public values natively, failing unless all three match
+    /// byte for byte
+    #[arg(long)]
+    check: bool,
+
     #[arg(long, default_value = "20")]
     n: u32,
 
     /// Path to USDC transfers JSON file for benchmarking with real data
-    #[arg(long)]
+    #[arg(long, conflicts_with_all = ["rpc_url", "usdc_csv", "usdc_parquet"])]
     usdc_json: Option<String>,
+
+    /// Path to a CSV of USDC transfers with a header row, see --csv-from/--csv-to/--csv-value
+    #[arg(long, conflicts_with_all = ["rpc_url", "usdc_parquet"])]
+    usdc_csv: Option<String>,
+
+    /// Path to a Parquet file of USDC transfers, the columns are mapped as for --usdc-csv
+    #[arg(long, conflicts_with = "rpc_url")]
+    usdc_parquet: Option<String>,
+
+    /// Column of the CSV or Parquet file holding the sender address
+    #[arg(long, default_value = "from")]
+    csv_from: String,
+
+    /// Column of the CSV or Parquet file holding the recipient address
+    #[arg(long, default_value = "to")]
+    csv_to: String,
+
+    /// Column of the CSV or Parquet file holding the transferred atoms
+    #[arg(long, default_value = "value")]
+    csv_value: String,
+
+    /// Fetch the USDC transfers of --block-range from this node instead, for benchmarking with
+    /// real data
+    #[arg(long, requires = "block_range")]
+    rpc_url: Option<String>,
+
+    /// Blocks to fetch the transfers of, as `from..to` both inclusive
+    #[arg(long, value_parser = parse_block_range)]
+    block_range: Option<(u64, u64)>,
+
+    /// Max txs in the batch
+    #[arg(long, default_value = "100")]
+    limit: usize,
+
+    /// Mock senders sending each other random amounts, instead of the fixed alice/bob/charlie
+    /// scenario. Ignored with --usdc-json
+    #[arg(long)]
+    senders: Option<usize>,
+
+    /// Txs every mock sender signs, with --senders
+    #[arg(long, default_value = "10")]
+    txs_per_sender: usize,
+
+    /// Seed of the mock accounts and amounts, the same seed gives the same batch shape
+    #[arg(long, default_value = "42")]
+    seed: u64,
+
+    /// Txs per input segment, the program only holds one segment in memory at a time
+    #[arg(long, default_value = "1000")]
+    chunk_txs: usize,
+
+    /// Commit the state deltas in the packed layout instead of abi encoding them
+    #[arg(long)]
+    packed: bool,
+
+    /// zstd compress every input segment, the program inflates them before parsing
+    #[arg(long)]
+    compressed: bool,
+
+    /// Commit the hash of the tx memos
+    #[arg(long)]
+    memos: bool,
+
+    /// Sequence number of the batch, the contract only applies the next one
+    #[arg(long, default_value = "0")]
+    batch_nonce: u64,
+
+    /// Commit the per-phase work counts of the program
+    #[arg(long)]
+    metering: bool,
+
+    /// Hand the program the sender keys, it verifies their signatures instead of recovering
+    #[arg(long)]
+    sender_keys: bool,
+
+    /// Commit the merkle root of the per-tx receipts
+    #[arg(long)]
+    receipts: bool,
+
+    /// Prior onchain balance of every sender, the program bounds their net debit by it
+    #[arg(long)]
+    prior_balance: Option<u128>,
+
+    /// Print the header and tx table of the input before handing it to the program
+    #[arg(long)]
+    inspect: bool,
+
+    /// The program assigns the delta slots itself instead of trusting the tx idx hints
+    #[arg(long)]
+    discover_deltas: bool,
+
+    /// Skip the invalid txs instead of rejecting the whole batch
+    #[arg(long)]
+    skip_invalid: bool,
+
+    /// With --prove, wrap the proof for the onchain verifier and write its fixture
+    #[arg(long, value_enum)]
+    evm_proof: Option<ProofSystem>,
+
+    /// With --evm-proof, also write the calldata posting the proof to this settlement contract
+    /// function, ex. `settle(bytes publicValues, bytes proof)`. Defaults to `verifyProof` of
+    /// the SP1 verifier
+    #[arg(long, num_args = 0..=1, default_missing_value = VERIFY_PROOF)]
+    calldata: Option<Function>,
+
+    /// With --execute, write the cycle counts and batch shape as JSON here, to track perf
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Times a failed proof is retried, the prover network fails transiently
+    #[arg(long, default_value = "2")]
+    retries: u32,
+
+    /// Wait before the first retry, doubled for every later one
+    #[arg(long, default_value = "10")]
+    retry_backoff_secs: u64,
+
+    /// Prove on the local CPU once the retries are used up
+    #[arg(long)]
+    cpu_fallback: bool,
+
+    /// With --prove, write the proof and its manifest here as `<request id>.proof`, keccak of the
+    /// batch with its sender keys and prior balances
+    #[arg(long)]
+    out_dir: Option<PathBuf>,
+}
+
+/// `Args::report`, the fields are flat so runs diff line by line
+#[derive(Debug, Serialize)]
+struct ExecReport {
+    /// committed by the program, 0 if the batch was accepted
+    status: u8,
+    txs: usize,
+    state_deltas: u32,
+    input_size: usize,
+    chunks: usize,
+    public_values_size: usize,
+    total_instructions: u64,
+    total_syscalls: u64,
+    touched_memory_addresses: u64,
+    gas: Option<u64>,
+    /// cycles of every `cycle-tracker-start`/`end` section of the program
+    sections: BTreeMap<String, u64>,
+    instructions_per_tx: f64,
+    instructions_per_input_byte: f64,
+}
+
+/// the `ExecReport` of executing `ser` to `public_values`
+fn exec_report(
+    args: &Args,
+    ser: &InputToSer,
+    public_values: &[u8],
+    report: &ExecutionReport,
+) -> ExecReport {
+    let chunks = ser.ser_chunks(args.chunk_txs);
+    let size = chunks.iter().map(|c| c.len()).sum::<usize>() + Input::HEADER_SIZE;
+    let total = report.total_instruction_count();
+    ExecReport {
+        status: PublicValuesStruct::abi_decode(public_values).map_or(u8::MAX, |x| x.status),
+        txs: ser.tx.len(),
+        state_deltas: ser.state_deltas,
+        input_size: size,
+        chunks: chunks.len(),
+        public_values_size: public_values.len(),
+        total_instructions: total,
+        total_syscalls: report.total_syscall_count(),
+        touched_memory_addresses: report.touched_memory_addresses,
+        gas: report.gas,
+        sections: report.cycle_tracker.clone().into_iter().collect(),
+        instructions_per_tx: total as f64 / ser.tx.len().max(1) as f64,
+        instructions_per_input_byte: total as f64 / size as f64,
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Cmd {
+    /// Print the verification key hash of the program and write it to a JSON artifact, the
+    /// settlement contract is deployed pinned to it
+    Vkey {
+        /// Defaults to `contracts/src/fixtures/vkey.json`
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Prove every `<name>.bin` batch (`InputToSer::ser`) dropped in `dir`, forever.
+    /// Writes `<name>.proof` to `out`, and `<name>-fixture.json` with --evm-proof, or
+    /// `<name>.err` if the batch can't be proven. A batch with either is not picked up again,
+    /// so the daemon can restart. Enqueue by renaming into `dir`, a half written batch fails.
+    /// The sender keys and prior balances are read from `<name>.keys` and `<name>.balances`,
+    /// write them before the batch
+    Daemon(DaemonArgs),
+    /// Write deterministic batches and the public values the program commits for them, computed
+    /// natively, to cross-check the Solidity decoder and guest changes against
+    GenVectors {
+        #[arg(long, default_value = "vectors.json")]
+        out: PathBuf,
+    },
+    /// Execute a mock batch of every size and print the cycles and input bytes per tx
+    Bench {
+        #[arg(long, value_delimiter = ',', default_value = "100,1000,10000")]
+        sizes: Vec<usize>,
+        /// Mock senders the txs of every batch are spread over
+        #[arg(long, default_value = "10")]
+        senders: usize,
+        /// Price of a million cycles, adds the projected proving cost of every size
+        #[arg(long)]
+        cost_per_mcycle: Option<f64>,
+        /// Also write the rows as JSON here
+        #[arg(long)]
+        json: Option<PathBuf>,
+    },
+    /// Re-verify a proof written with --out-dir or by the daemon against its manifest and the
+    /// vkey of this program
+    VerifyArtifact {
+        /// The `.proof`, its manifest is the `.manifest.json` next to it
+        proof: PathBuf,
+    },
+    /// Prove every batch (`InputToSer::ser`), then one proof of the aggregation program
+    /// verifying all of them, so the contract settles them with a single verification. With
+    /// --evm-proof writes `<system>-aggregate-fixture.json`. The sender keys and prior balances
+    /// of `<name>.bin` are read from `<name>.keys` and `<name>.balances` next to it. Fails
+    /// without proving anything if the program rejects any of the batches
+    Aggregate {
+        #[arg(required = true)]
+        batches: Vec<PathBuf>,
+    },
+    /// Serve `POST /execute` and `POST /prove` of a batch (`InputToSer::ser`) in the body, for
+    /// the gateway to request its settlement proofs over the network. The batch flags of the
+    /// command line apply to every request, the sender keys and prior balances come in the
+    /// `x-sender-keys` and `x-prior-balances` headers
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:3000")]
+        addr: SocketAddr,
+        /// Every proof and its manifest is kept here too, as with --out-dir
+        #[arg(long, default_value = "proofs")]
+        out: PathBuf,
+        /// Largest batch accepted
+        #[arg(long, default_value = "64")]
+        max_body_mb: usize,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct DaemonArgs {
+    dir: PathBuf,
+    /// Defaults to `dir`
+    #[arg(long)]
+    out: Option<PathBuf>,
+    /// How long to sleep once the queue is drained
+    #[arg(long, default_value = "5")]
+    poll_secs: u64,
+    /// Serve the prover metrics for Prometheus on `http://<addr>/metrics`
+    #[arg(long)]
+    metrics_addr: Option<SocketAddr>,
+    /// Batches proven at once, every job has its own prover client
+    #[arg(long, default_value = "1")]
+    jobs: usize,
+    /// A job only picks up the next batch while the machine has this much memory available,
+    /// so concurrent jobs don't run it out of memory. Ignored where /proc/meminfo is missing
+    #[arg(long, default_value = "0")]
+    min_free_mem_gb: u64,
+}
+
+/// The vkey artifact, `Cmd::Vkey`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VkeyArtifact {
+    vkey: String,
+}
+
+/// the dir the contract tests load their fixtures from
+fn fixture_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../contracts/src/fixtures")
+}
+
+/// The SNARK the proof is wrapped in for the EVM
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum ProofSystem {
+    Plonk,
+    Groth16,
+}
+
+/// What the Solidity verifier is fed, written to `contracts/src/fixtures`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchProofFixture {
+    vkey: String,
+    public_values: String,
+    proof: String,
+}
+
+/// `<name>.manifest.json` next to every `<name>.proof` written, for the audit trail of the
+/// settled batches
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProofManifest {
+    input_hash: String,
+    batch_nonce: u64,
+    status: u8,
+    /// `Program::version` the guest committed
+    program_version: u32,
+    vkey: String,
+    /// core, groth16 or plonk
+    proof_system: String,
+    public_values: String,
+    cycles: u64,
+    started_at: u64,
+    proved_at: u64,
+}
+
+/// the stem the artifacts of a request are written under, keccak of everything handed to the
+/// program with it. not the committed input hash, rejected batches would then share names
+fn request_id(ser: &InputToSer, keys: &SenderKeys, balances: &PriorBalances) -> String {
+    let mut h = Keccak::v256();
+    for part in [ser.ser(), keys.ser(), balances.ser()] {
+        h.update(&(part.len() as u64).to_be_bytes());
+        h.update(&part);
+    }
+    let mut out = [0; 32];
+    h.finalize(&mut out);
+    hex::encode(out)
+}
+
+/// writes `<stem>.manifest.json` then `<stem>.proof` to `dir`, returns the proof path
+fn write_artifact(
+    dir: &Path,
+    stem: &str,
+    proof: &SP1ProofWithPublicValues,
+    vkey: String,
+    cycles: u64,
+    started_at: u64,
+    system: Option<ProofSystem>,
+) -> PathBuf {
+    let public_values = proof.public_values.as_slice();
+    let decoded = PublicValuesStruct::abi_decode(public_values).unwrap();
+    let manifest = ProofManifest {
+        input_hash: decoded.input_hash.to_string(),
+        batch_nonce: decoded.batch_nonce,
+        status: decoded.status,
+        program_version: decoded.program.version,
+        vkey,
+        proof_system: system.map_or("core".into(), |x| format!("{:?}", x).to_lowercase()),
+        public_values: format!("0x{}", hex::encode(public_values)),
+        cycles,
+        started_at,
+        proved_at: unix_now(),
+    };
+    fs::write(
+        dir.join(format!("{stem}.manifest.json")),
+        serde_json::to_string_pretty(&manifest).unwrap(),
+    )
+    .expect("failed to write manifest");
+    let path = dir.join(format!("{stem}.proof"));
+    proof.save(&path).expect("failed to write proof");
+    path
+}
+
+/// `Cmd::VerifyArtifact`
+fn verify_artifact(path: &Path) -> Result<ProofManifest, String> {
+    let manifest = path.with_extension("manifest.json");
+    let manifest: ProofManifest = fs::read_to_string(&manifest)
+        .map_err(|e| format!("failed to read {}: {e}", manifest.display()))
+        .and_then(|x| serde_json::from_str(&x).map_err(|e| format!("bad manifest: {e}")))?;
+    let proof = SP1ProofWithPublicValues::load(path).map_err(|e| format!("bad proof: {e}"))?;
+    let public_values = format!("0x{}", hex::encode(proof.public_values.as_slice()));
+    if public_values != manifest.public_values {
+        return Err("the proof commits other public values than the manifest".into());
+    }
+    let prover = ProverClient::builder().cpu().build();
+    let (_, vk) = prover.setup(FIBONACCI_ELF);
+    if vk.bytes32() != manifest.vkey {
+        return Err(format!(
+            "proven by vkey {}, this program is {}",
+            manifest.vkey,
+            vk.bytes32()
+        ));
+    }
+    prover
+        .verify(&proof, &vk)
+        .map_err(|e| format!("failed to verify proof: {e}"))?;
+    Ok(manifest)
+}
+
+/// one size of `Cmd::Bench`
+#[derive(Debug, Serialize)]
+struct BenchRow {
+    txs: usize,
+    state_deltas: u32,
+    input_bytes: usize,
+    cycles: u64,
+    cycles_per_tx: f64,
+    input_bytes_per_tx: f64,
+    /// `cycles` priced at `--cost-per-mcycle`
+    projected_cost: Option<f64>,
+}
+
+/// `Cmd::Bench`, every size from the same seed
+fn bench(
+    args: &Args,
+    sizes: &[usize],
+    senders: usize,
+    cost_per_mcycle: Option<f64>,
+) -> Vec<BenchRow> {
+    let client = ProverClient::from_env();
+    sizes
+        .iter()
+        .map(|&size| {
+            let mut rng = StdRng::seed_from_u64(args.seed);
+            let per_sender = size.div_ceil(senders);
+            let batch = build_batch_from_mock_senders(senders, per_sender, size, &mut rng);
+            let (keys, balances) = builder_extras(args, &batch);
+            let ser = batch.compressed(args.compressed).build().unwrap();
+            let input_bytes = ser.ser().len();
+            let stdin = batch_stdin(args, &ser, &keys, &balances);
+            let (_, report) = client.execute(FIBONACCI_ELF, &stdin).run().unwrap();
+            let cycles = report.total_instruction_count();
+            let txs = ser.tx.len();
+            BenchRow {
+                txs,
+                state_deltas: ser.state_deltas,
+                input_bytes,
+                cycles,
+                cycles_per_tx: cycles as f64 / txs.max(1) as f64,
+                input_bytes_per_tx: input_bytes as f64 / txs.max(1) as f64,
+                projected_cost: cost_per_mcycle.map(|x| x * cycles as f64 / 1e6),
+            }
+        })
+        .collect()
+}
+
+/// one case of `Cmd::GenVectors`, the flags are the program flags of the same name
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TestVector {
+    name: String,
+    /// `InputToSer::ser`
+    input: String,
+    packed: bool,
+    memos: bool,
+    metering: bool,
+    status: u8,
+    /// abi encoded `PublicValuesStruct`
+    public_values: String,
+}
+
+/// `Cmd::GenVectors`, the same seed and clock every run so the vectors only change with the code
+fn gen_vectors() -> Vec<TestVector> {
+    const NOW: u64 = 1_700_000_000;
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut alice = MockAcc::new(&mut rng);
+    let mut bob = MockAcc::new(&mut rng);
+    let charlie = MockAcc::new(&mut rng);
+    let fee_sink = MockAcc::new(&mut rng);
+    let mut batch = BatchBuilder::new(fee_sink.addr, NOW, DOMAIN);
+    for tx in [
+        alice.signed_tx_at(bob.addr, USDC, 1000, NOW),
+        alice.signed_tx_at(charlie.addr, USDC, 500, NOW),
+        bob.signed_tx_at(charlie.addr, USDC, 300, NOW),
+    ] {
+        batch.add(tx).unwrap();
+    }
+    let mut ser = batch.build().unwrap();
+    let raw = ser.ser();
+    ser.compressed = true;
+    let compressed = ser.ser();
+    // no such recovery id on the first tx, the program rejects the batch
+    let mut bad_v = raw.clone();
+    bad_v[Input::HEADER_SIZE + TxToSer::SIGNED_SIZE + 64] = 7;
+    let cases = [
+        ("transfers", raw.clone(), false, false, false),
+        ("transfers_packed", raw.clone(), true, false, false),
+        ("transfers_all_flags", raw, true, true, true),
+        ("compressed", compressed, false, false, false),
+        ("bad_recovery_id", bad_v, false, false, false),
+    ];
+    cases
+        .into_iter()
+        .map(|(name, input, packed, memos, metering)| {
+            let header = &input[..Input::HEADER_SIZE];
+            let r = process_txs_committed(&input, &[]);
+            let pv = public_values(r, header, packed, memos, metering);
+            TestVector {
+                name: name.into(),
+                input: format!("0x{}", hex::encode(&input)),
+                packed,
+                memos,
+                metering,
+                status: pv.status,
+                public_values: format!("0x{}", hex::encode(PublicValuesStruct::abi_encode(&pv))),
+            }
+        })
+        .collect()
+}
+
+/// writes the fixture of an EVM proof to `path`
+fn write_fixture(proof: &SP1ProofWithPublicValues, vkey: String, path: &Path) {
+    let fixture = BatchProofFixture {
+        vkey,
+        public_values: format!("0x{}", hex::encode(proof.public_values.as_slice())),
+        proof: format!("0x{}", hex::encode(proof.bytes())),
+    };
+    fs::write(path, serde_json::to_string_pretty(&fixture).unwrap())
+        .expect("failed to write fixture");
+}
+
+/// `f` called with the EVM `proof`
+fn calldata(f: &Function, proof: &SP1ProofWithPublicValues, vk: &SP1VerifyingKey) -> Vec<u8> {
+    let vkey = hex::decode(vk.bytes32().trim_start_matches("0x")).unwrap();
+    f.encode(
+        &vkey.try_into().unwrap(),
+        proof.public_values.as_slice(),
+        &proof.bytes(),
+    )
+}
+
+/// the sender keys and prior balances of a batch being built, as --sender-keys and
+/// --prior-balance ask for
+fn builder_extras(args: &Args, batch: &BatchBuilder) -> (SenderKeys, PriorBalances) {
+    let keys = if args.sender_keys {
+        batch.sender_keys()
+    } else {
+        SenderKeys::default()
+    };
+    let balances = match args.prior_balance {
+        Some(balance) => batch.prior_balances(|_, _| Some(balance)),
+        None => PriorBalances::default(),
+    };
+    (keys, balances)
+}
+
+/// the sender keys and prior balances of the persisted batch at `path`, `<name>.keys`
+/// (`SenderKeys::ser`) and `<name>.balances` (`PriorBalances::ser`) next to it. Either may be
+/// missing, the program then recovers every signer or leaves its balance unbounded
+fn batch_extras(path: &Path) -> Result<(SenderKeys, PriorBalances), String> {
+    let read = |ext: &str| {
+        let path = path.with_extension(ext);
+        match fs::read(&path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+            r => r.map_err(|e| format!("{}: {e}", path.display())),
+        }
+    };
+    let keys = SenderKeys::deser(&read("keys")?).map_err(|e| format!("bad sender keys: {e}"))?;
+    let balances =
+        PriorBalances::deser(&read("balances")?).map_err(|e| format!("bad prior balances: {e}"))?;
+    Ok((keys, balances))
+}
+
+/// the program stdin for `ser`, in the order the program reads it
+fn batch_stdin(
+    args: &Args,
+    ser: &InputToSer,
+    keys: &SenderKeys,
+    balances: &PriorBalances,
+) -> SP1Stdin {
+    let mut stdin = SP1Stdin::new();
+    stdin.write_vec(frame(&ser.ser_header()));
+    stdin.write(&(
+        args.packed,
+        args.memos,
+        args.metering,
+        args.receipts,
+        args.discover_deltas,
+        args.skip_invalid,
+    ));
+    stdin.write_vec(frame(&keys.ser()));
+    stdin.write_vec(frame(&balances.ser()));
+    for chunk in ser.ser_chunks(args.chunk_txs) {
+        stdin.write_vec(frame(&chunk));
+    }
+    stdin
+}
+
+/// the public values the program commits for `ser`, computed on the host the way the program
+/// does with the same flags
+fn native_public_values(
+    args: &Args,
+    ser: &InputToSer,
+    keys: &SenderKeys,
+    balances: &PriorBalances,
+) -> Vec<u8> {
+    let header = ser.ser_header();
+    let r = BatchProcessor::new(&header)
+        .map_err(|e| Rejected::header(&header, e))
+        .and_then(|mut p| {
+            let fed = (|| -> Result<(), ProcessError> {
+                if args.discover_deltas {
+                    p.discover_deltas();
+                }
+                if args.skip_invalid {
+                    p.skip_invalid();
+                }
+                p.sender_keys(&keys.ser())?;
+                p.prior_balances(&balances.ser())?;
+                if args.receipts {
+                    p.receipts();
+                }
+                for chunk in ser.ser_chunks(args.chunk_txs) {
+                    p.process_chunk(&chunk)?;
+                }
+                Ok(())
+            })();
+            p.finish_or_reject(fed)
+        });
+    let pv = public_values(r, &header, args.packed, args.memos, args.metering);
+    PublicValuesStruct::abi_encode(&pv)
+}
+
+/// `Args::check`, the first byte `a` and `b` differ at if they do
+fn first_diff(a: &[u8], b: &[u8]) -> Option<usize> {
+    if a == b {
+        return None;
+    }
+    Some(a.iter().zip(b).take_while(|(x, y)| x == y).count())
+}
+
+/// proves and verifies, wrapped for the EVM if `system` is set
+fn prove_batch(
+    client: &EnvProver,
+    pk: &SP1ProvingKey,
+    stdin: &SP1Stdin,
+    system: Option<ProofSystem>,
+) -> Result<SP1ProofWithPublicValues, String> {
+    let prove = client.prove(pk, stdin);
+    let proof = match system {
+        Some(ProofSystem::Plonk) => prove.plonk().run(),
+        Some(ProofSystem::Groth16) => prove.groth16().run(),
+        None => prove.run(),
+    }
+    .map_err(|e| format!("failed to generate proof: {e}"))?;
+    client
+        .verify(&proof, &pk.vk)
+        .map_err(|e| format!("failed to verify proof: {e}"))?;
+    Ok(proof)
+}
+
+/// `prove_batch` retried with a doubling backoff until `Args::retries` attempts after the first
+/// failed, `attempts` counts the ones that already failed before, ex. in a crashed daemon.
+/// `failed` sees the attempt count after every failure. Then the local CPU prover with
+/// `Args::cpu_fallback`
+fn prove_retrying(
+    args: &Args,
+    client: &EnvProver,
+    pk: &SP1ProvingKey,
+    stdin: &SP1Stdin,
+    mut attempts: u32,
+    mut failed: impl FnMut(u32, &str),
+) -> Result<SP1ProofWithPublicValues, String> {
+    let mut last = String::new();
+    while attempts <= args.retries {
+        if attempts > 0 {
+            let backoff = args.retry_backoff_secs << (attempts - 1).min(16);
+            println!("retrying in {}s, attempt {}", backoff, attempts + 1);
+            std::thread::sleep(Duration::from_secs(backoff));
+        }
+        match prove_batch(client, pk, stdin, args.evm_proof) {
+            Ok(proof) => return Ok(proof),
+            Err(e) => {
+                attempts += 1;
+                eprintln!("attempt {} failed: {}", attempts, e);
+                failed(attempts, &e);
+                last = e;
+            }
+        }
+    }
+    if !args.cpu_fallback {
+        return Err(last);
+    }
+    println!("falling back to the local CPU prover");
+    let cpu = ProverClient::builder().cpu().build();
+    let prove = cpu.prove(pk, stdin);
+    let proof = match args.evm_proof {
+        Some(ProofSystem::Plonk) => prove.plonk().run(),
+        Some(ProofSystem::Groth16) => prove.groth16().run(),
+        None => prove.run(),
+    }
+    .map_err(|e| format!("cpu fallback failed to generate proof: {e}"))?;
+    client
+        .verify(&proof, &pk.vk)
+        .map_err(|e| format!("cpu fallback failed to verify proof: {e}"))?;
+    Ok(proof)
+}
+
+/// `Cmd::Aggregate`, the aggregate proof and the vkey of the aggregation program
+fn aggregate(
+    args: &Args,
+    batches: &[PathBuf],
+) -> Result<(SP1ProofWithPublicValues, SP1VerifyingKey), String> {
+    let client = ProverClient::from_env();
+    let (pk, vk) = client.setup(FIBONACCI_ELF);
+    // executed first, a rejected batch would settle as a failure in the aggregate
+    let mut stdins = vec![];
+    let mut rejected = vec![];
+    for path in batches {
+        let v = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let ser = InputToSer::deser(&v).map_err(|e| format!("{}: {e}", path.display()))?;
+        let (keys, balances) =
+            batch_extras(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let stdin = batch_stdin(args, &ser, &keys, &balances);
+        let (output, _) = client
+            .execute(FIBONACCI_ELF, &stdin)
+            .run()
+            .map_err(|e| format!("failed to execute {}: {e}", path.display()))?;
+        let status = PublicValuesStruct::abi_decode(output.as_slice())
+            .map_err(|e| e.to_string())?
+            .status;
+        if status != 0 {
+            rejected.push(format!("{} (status={status})", path.display()));
+        }
+        stdins.push(stdin);
+    }
+    if !rejected.is_empty() {
+        return Err(format!(
+            "not aggregating, rejected by the program: {}",
+            rejected.join(", ")
+        ));
+    }
+    let mut public_values = vec![];
+    let mut proofs = vec![];
+    for (path, stdin) in batches.iter().zip(stdins) {
+        println!("proving {}", path.display());
+        // compressed, the aggregation program can only verify those
+        let proof = client
+            .prove(&pk, &stdin)
+            .compressed()
+            .run()
+            .map_err(|e| format!("failed to prove {}: {e}", path.display()))?;
+        public_values.push(proof.public_values.as_slice().to_vec());
+        let SP1Proof::Compressed(proof) = proof.proof else {
+            unreachable!("proven compressed")
+        };
+        proofs.push(*proof);
+    }
+    let mut stdin = SP1Stdin::new();
+    stdin.write(&vk.hash_u32());
+    stdin.write(&public_values);
+    for proof in proofs {
+        stdin.write_proof(proof, vk.vk.clone());
+    }
+    let (pk, vk) = client.setup(AGGREGATION_ELF);
+    println!("aggregating {} batches", batches.len());
+    let proof = prove_retrying(args, &client, &pk, &stdin, 0, |_, _| {})?;
+    Ok((proof, vk))
+}
+
+/// Counters of `Cmd::Daemon`, in the Prometheus text format on `/metrics`
+#[derive(Default)]
+struct Metrics {
+    queue_depth: AtomicU64,
+    proved: AtomicU64,
+    failed: AtomicU64,
+    prove_millis: AtomicU64,
+    last_prove_millis: AtomicU64,
+    cycles: AtomicU64,
+    last_cycles: AtomicU64,
+    proof_bytes: AtomicU64,
+    last_proof_bytes: AtomicU64,
+}
+
+impl Metrics {
+    fn render(&self) -> String {
+        let get = |x: &AtomicU64| x.load(Ordering::Relaxed).to_string();
+        let secs = |x: &AtomicU64| (x.load(Ordering::Relaxed) as f64 / 1e3).to_string();
+        // (name, type, help, value)
+        #[rustfmt::skip]
+        let rows = [
+            ("queue_depth", "gauge", "batches waiting to be proven", get(&self.queue_depth)),
+            ("proved_total", "counter", "batches proven", get(&self.proved)),
+            ("failed_total", "counter", "batches not proven", get(&self.failed)),
+            ("prove_seconds_total", "counter", "time spent proving", secs(&self.prove_millis)),
+            ("last_prove_seconds", "gauge", "time of the last batch", secs(&self.last_prove_millis)),
+            ("cycles_total", "counter", "program cycles proven", get(&self.cycles)),
+            ("last_cycles", "gauge", "program cycles of the last batch", get(&self.last_cycles)),
+            ("proof_bytes_total", "counter", "size of the written proofs", get(&self.proof_bytes)),
+            ("last_proof_bytes", "gauge", "size of the last proof", get(&self.last_proof_bytes)),
+        ];
+        let mut out = String::new();
+        for (name, kind, help, value) in rows {
+            let name = format!("ddm_prover_{name}");
+            out += &format!("# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n");
+        }
+        out
+    }
+
+    fn record(&self, took: Duration, cycles: u64, proof_bytes: u64) {
+        let millis = took.as_millis() as u64;
+        self.proved.fetch_add(1, Ordering::Relaxed);
+        self.prove_millis.fetch_add(millis, Ordering::Relaxed);
+        self.last_prove_millis.store(millis, Ordering::Relaxed);
+        self.cycles.fetch_add(cycles, Ordering::Relaxed);
+        self.last_cycles.store(cycles, Ordering::Relaxed);
+        self.proof_bytes.fetch_add(proof_bytes, Ordering::Relaxed);
+        self.last_proof_bytes.store(proof_bytes, Ordering::Relaxed);
+    }
+}
+
+/// how long a metrics connection may take to send its request or read the answer. the
+/// connections are served one at a time, a stalled one holds up the next for at most this long
+const METRICS_IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// answers every request on `addr`, `/metrics` with `metrics` and anything else with a 404
+fn serve_metrics(addr: SocketAddr, metrics: Arc<Metrics>) {
+    let listener = TcpListener::bind(addr).expect("failed to bind the metrics addr");
+    println!("serving metrics on http://{}/metrics", addr);
+    std::thread::spawn(move || {
+        for mut conn in listener.incoming().flatten() {
+            let timeout = Some(METRICS_IO_TIMEOUT);
+            if conn.set_read_timeout(timeout).is_err() || conn.set_write_timeout(timeout).is_err() {
+                continue;
+            }
+            let mut req = [0; 1024];
+            let n = conn.read(&mut req).unwrap_or(0);
+            let (status, body) = if req[..n].starts_with(b"GET /metrics ") {
+                ("200 OK", metrics.render())
+            } else {
+                ("404 Not Found", String::new())
+            };
+            let _ = write!(
+                conn,
+                "HTTP/1.1 {status}\r\ncontent-type: text/plain; version=0.0.4\r\n\
+                 content-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+        }
+    });
+}
+
+/// the batches in `dir` without a proof or error in `out` yet, oldest name first
+fn queued(dir: &Path, out: &Path) -> Vec<PathBuf> {
+    let mut queue: Vec<PathBuf> = fs::read_dir(dir)
+        .expect("failed to read the batch dir")
+        .filter_map(|x| Some(x.ok()?.path()))
+        .filter(|x| x.extension().is_some_and(|x| x == "bin"))
+        .filter(|x| {
+            let stem = x.file_stem().unwrap().to_string_lossy();
+            !out.join(format!("{stem}.proof")).exists() && !out.join(format!("{stem}.err")).exists()
+        })
+        .collect();
+    // the enqueuer decides the order
+    queue.sort();
+    queue
+}
+
+/// `<name>.job` next to the proofs of `Cmd::Daemon` while a batch is retried,
+/// a restarted daemon picks up the attempt count where the crashed one left it
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct JobState {
+    attempts: u32,
+    last_error: String,
+}
+
+/// executes the batch at `path` for its cycle count, then proves it, keeping the `JobState` at
+/// `job` up to date. (cycles, proof)
+fn prove_file(
+    args: &Args,
+    client: &EnvProver,
+    pk: &SP1ProvingKey,
+    path: &Path,
+    job: &Path,
+) -> Result<(u64, SP1ProofWithPublicValues), String> {
+    let v = fs::read(path).map_err(|e| e.to_string())?;
+    let ser = InputToSer::deser(&v).map_err(|e| e.to_string())?;
+    let (keys, balances) = batch_extras(path)?;
+    let stdin = batch_stdin(args, &ser, &keys, &balances);
+    let (_, report) = client
+        .execute(FIBONACCI_ELF, &stdin)
+        .run()
+        .map_err(|e| format!("failed to execute: {e}"))?;
+    let state: JobState = fs::read_to_string(job)
+        .ok()
+        .and_then(|x| serde_json::from_str(&x).ok())
+        .unwrap_or_default();
+    if state.attempts > 0 {
+        println!(
+            "resuming {} after {} failed attempts",
+            path.display(),
+            state.attempts
+        );
+    }
+    let proof = prove_retrying(args, client, pk, &stdin, state.attempts, |attempts, e| {
+        let state = JobState {
+            attempts,
+            last_error: e.to_string(),
+        };
+        let _ = fs::write(job, serde_json::to_string_pretty(&state).unwrap());
+    })?;
+    Ok((report.total_instruction_count(), proof))
+}
+
+/// `MemAvailable` of /proc/meminfo in bytes, `None` off linux
+fn mem_available() -> Option<u64> {
+    let info = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = info.lines().find(|x| x.starts_with("MemAvailable:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// `Cmd::Daemon`
+fn daemon(args: &Args, daemon: &DaemonArgs) -> ! {
+    let out = daemon.out.as_ref().unwrap_or(&daemon.dir);
+    fs::create_dir_all(out).expect("failed to create out dir");
+    let metrics = Arc::new(Metrics::default());
+    if let Some(addr) = daemon.metrics_addr {
+        serve_metrics(addr, metrics.clone());
+    }
+    println!(
+        "watching {} for batches with {} jobs",
+        daemon.dir.display(),
+        daemon.jobs
+    );
+    // the batches a job is proving, the others leave them alone
+    let claimed = Mutex::new(HashSet::new());
+    std::thread::scope(|s| {
+        for job in 0..daemon.jobs.max(1) {
+            let (claimed, metrics) = (&claimed, &metrics);
+            s.spawn(move || prove_queued(args, daemon, out, job, claimed, metrics));
+        }
+    });
+    unreachable!("the jobs never return")
+}
+
+/// one job of `Cmd::Daemon`
+fn prove_queued(
+    args: &Args,
+    daemon: &DaemonArgs,
+    out: &Path,
+    job: usize,
+    claimed: &Mutex<HashSet<PathBuf>>,
+    metrics: &Metrics,
+) -> ! {
+    let poll = Duration::from_secs(daemon.poll_secs);
+    let client = ProverClient::from_env();
+    let (pk, vk) = client.setup(FIBONACCI_ELF);
+    println!("job {} ready, vkey {}", job, vk.bytes32());
+    loop {
+        let min_free = daemon.min_free_mem_gb << 30;
+        if mem_available().is_some_and(|x| x < min_free) {
+            std::thread::sleep(poll);
+            continue;
+        }
+        let path = {
+            let mut claimed = claimed.lock().unwrap();
+            let queue: Vec<_> = queued(&daemon.dir, out)
+                .into_iter()
+                .filter(|x| !claimed.contains(x))
+                .collect();
+            metrics
+                .queue_depth
+                .store(queue.len().saturating_sub(1) as u64, Ordering::Relaxed);
+            let path = queue.into_iter().next();
+            if let Some(path) = &path {
+                claimed.insert(path.clone());
+            }
+            path
+        };
+        let Some(path) = path else {
+            std::thread::sleep(poll);
+            continue;
+        };
+        let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+        println!("job {} proving {}", job, path.display());
+        let (started, started_at) = (Instant::now(), unix_now());
+        let job_path = out.join(format!("{stem}.job"));
+        match prove_file(args, &client, &pk, &path, &job_path) {
+            Ok((cycles, proof)) => {
+                if args.evm_proof.is_some() {
+                    let fixture = out.join(format!("{stem}-fixture.json"));
+                    write_fixture(&proof, vk.bytes32(), &fixture);
+                }
+                // last, the proof existing marks the batch done
+                let proof_path = write_artifact(
+                    out,
+                    &stem,
+                    &proof,
+                    vk.bytes32(),
+                    cycles,
+                    started_at,
+                    args.evm_proof,
+                );
+                let bytes = fs::metadata(&proof_path).map_or(0, |x| x.len());
+                metrics.record(started.elapsed(), cycles, bytes);
+                println!("job {} proved {}", job, stem);
+            }
+            Err(e) => {
+                eprintln!("job {} failed {}: {}", job, stem, e);
+                fs::write(out.join(format!("{stem}.err")), e).expect("failed to write error");
+                metrics.failed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        // settled either way, the proof or error is what the next run goes by
+        let _ = fs::remove_file(&job_path);
+        claimed.lock().unwrap().remove(&path);
+    }
+}
+
+/// `Cmd::Serve`, shared by every request
+struct Service {
+    args: Args,
+    out: PathBuf,
+    client: EnvProver,
+    pk: SP1ProvingKey,
+    /// one proof at a time, the others wait for their turn
+    proving: Mutex<()>,
+}
+
+/// `POST /execute`
+#[derive(Debug, Serialize)]
+struct ExecuteResponse {
+    #[serde(flatten)]
+    report: ExecReport,
+    public_values: String,
+}
+
+/// `POST /prove`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProveResponse {
+    /// `request_id`, the artifacts in the out dir are named by it
+    id: String,
+    manifest: ProofManifest,
+    /// the `.proof` written, `SP1ProofWithPublicValues::load` reads it back
+    proof: String,
+    /// what the settlement contract is fed, with --evm-proof
+    evm_proof: Option<String>,
+    /// with --calldata
+    calldata: Option<String>,
+}
+
+type ServiceError = (StatusCode, String);
+
+/// the hex `name` header of a request, empty if it is missing
+fn hex_header(headers: &HeaderMap, name: &str) -> Result<Vec<u8>, ServiceError> {
+    let bad = |e: String| (StatusCode::BAD_REQUEST, format!("bad {name}: {e}"));
+    let Some(v) = headers.get(name) else {
+        return Ok(vec![]);
+    };
+    let v = v.to_str().map_err(|e| bad(e.to_string()))?;
+    hex::decode(v.trim_start_matches("0x")).map_err(|e| bad(e.to_string()))
+}
+
+impl Service {
+    /// the sender keys and prior balances come with the batch, hex in the `x-sender-keys`
+    /// (`SenderKeys::ser`) and `x-prior-balances` (`PriorBalances::ser`) headers
+    fn stdin(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<(InputToSer, SP1Stdin, String), ServiceError> {
+        let bad = |e: String| (StatusCode::BAD_REQUEST, e);
+        let ser = InputToSer::deser(body).map_err(|e| bad(format!("bad batch: {e}")))?;
+        let keys = SenderKeys::deser(&hex_header(headers, "x-sender-keys")?)
+            .map_err(|e| bad(format!("bad sender keys: {e}")))?;
+        let balances = PriorBalances::deser(&hex_header(headers, "x-prior-balances")?)
+            .map_err(|e| bad(format!("bad prior balances: {e}")))?;
+        let stdin = batch_stdin(&self.args, &ser, &keys, &balances);
+        let id = request_id(&ser, &keys, &balances);
+        Ok((ser, stdin, id))
+    }
+
+    fn execute(&self, headers: &HeaderMap, body: &[u8]) -> Result<ExecuteResponse, ServiceError> {
+        let (ser, stdin, _) = self.stdin(headers, body)?;
+        let (output, report) = self
+            .client
+            .execute(FIBONACCI_ELF, &stdin)
+            .run()
+            .map_err(|e| {
+                (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    format!("failed to execute: {e}"),
+                )
+            })?;
+        Ok(ExecuteResponse {
+            report: exec_report(&self.args, &ser, output.as_slice(), &report),
+            public_values: format!("0x{}", hex::encode(output.as_slice())),
+        })
+    }
+
+    fn prove(&self, headers: &HeaderMap, body: &[u8]) -> Result<ProveResponse, ServiceError> {
+        let (_, stdin, id) = self.stdin(headers, body)?;
+        let internal = |e: String| (StatusCode::INTERNAL_SERVER_ERROR, e);
+        let (_, report) = self
+            .client
+            .execute(FIBONACCI_ELF, &stdin)
+            .run()
+            .map_err(|e| {
+                (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    format!("failed to execute: {e}"),
+                )
+            })?;
+        let _turn = self.proving.lock().unwrap();
+        let started_at = unix_now();
+        let proof = prove_retrying(&self.args, &self.client, &self.pk, &stdin, 0, |_, _| {})
+            .map_err(internal)?;
+        let path = write_artifact(
+            &self.out,
+            &id,
+            &proof,
+            self.pk.vk.bytes32(),
+            report.total_instruction_count(),
+            started_at,
+            self.args.evm_proof,
+        );
+        let manifest = fs::read_to_string(path.with_extension("manifest.json"))
+            .map_err(|e| internal(e.to_string()))?;
+        let saved = fs::read(&path).map_err(|e| internal(e.to_string()))?;
+        Ok(ProveResponse {
+            id,
+            manifest: serde_json::from_str(&manifest).unwrap(),
+            proof: format!("0x{}", hex::encode(saved)),
+            evm_proof: self
+                .args
+                .evm_proof
+                .map(|_| format!("0x{}", hex::encode(proof.bytes()))),
+            calldata: self
+                .args
+                .calldata
+                .as_ref()
+                .map(|f| format!("0x{}", hex::encode(calldata(f, &proof, &self.pk.vk)))),
+        })
+    }
+}
+
+/// runs `f` of the service on the blocking pool, executing and proving take their time
+async fn blocking<T: Send + 'static>(
+    service: Arc<Service>,
+    f: impl FnOnce(&Service) -> Result<T, ServiceError> + Send + 'static,
+) -> Result<Json<T>, ServiceError> {
+    tokio::task::spawn_blocking(move || f(&service))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(Json)
+}
+
+/// `Cmd::Serve`
+fn serve(args: Args, addr: SocketAddr, out: PathBuf, max_body: usize) -> ! {
+    fs::create_dir_all(&out).expect("failed to create out dir");
+    let client = ProverClient::from_env();
+    let (pk, vk) = client.setup(FIBONACCI_ELF);
+    println!("serving vkey {} on {}", vk.bytes32(), addr);
+    let service = Arc::new(Service {
+        args,
+        out,
+        client,
+        pk,
+        proving: Mutex::new(()),
+    });
+    let app = Router::new()
+        .route(
+            "/execute",
+            post(
+                |State(s): State<Arc<Service>>, headers: HeaderMap, body: Bytes| {
+                    blocking(s, move |s| s.execute(&headers, &body))
+                },
+            ),
+        )
+        .route(
+            "/prove",
+            post(
+                |State(s): State<Arc<Service>>, headers: HeaderMap, body: Bytes| {
+                    blocking(s, move |s| s.prove(&headers, &body))
+                },
+            ),
+        )
+        .layer(DefaultBodyLimit::max(max_body))
+        .with_state(service);
+    let rt = tokio::runtime::Runtime::new().expect("failed to start runtime");
+    rt.block_on(async {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .expect("failed to bind");
+        axum::serve(listener, app).await.expect("server failed");
+    });
+    unreachable!("the server never returns")
 }
 
 #[derive(Debug, Deserialize)]
@@ -48,6 +1285,19 @@ struct Transfer {
     atoms: i64,
 }
 
+/// `from..to`
+fn parse_block_range(s: &str) -> Result<(u64, u64), String> {
+    let (from, to) = s
+        .split_once("..")
+        .ok_or_else(|| format!("expected from..to, got {s}"))?;
+    let from: u64 = from.parse().map_err(|e| format!("bad from block: {e}"))?;
+    let to: u64 = to.parse().map_err(|e| format!("bad to block: {e}"))?;
+    if from > to {
+        return Err(format!("from block {from} is past to block {to}"));
+    }
+    Ok((from, to))
+}
+
 #[derive(Debug, Deserialize)]
 struct TransfersData {
     transfers: Vec<Transfer>,
@@ -87,15 +1337,6 @@ fn sign(sk: &SigningKey, hash: [u8; 32]) -> Sig {
     (r_bytes.into(), s_bytes.into(), recovery_id.into())
 }
 
-fn recover(sig: &Sig, hash: &[u8; 32]) -> [u8; 20] {
-    let rec = sig.2;
-    let s = k256::ecdsa::Signature::from_scalars(sig.0, sig.1).unwrap();
-    let rec =
-        VerifyingKey::recover_from_prehash(hash, &s, RecoveryId::from_byte(rec).unwrap()).unwrap();
-    let pubk = rec.to_encoded_point(false);
-    pubk_to_adr(pubk.as_bytes())
-}
-
 /// Wrapper to make any RngCore implement CryptoRng for deterministic key generation.
 /// This is a hack for testing purposes - do not use in production!
 struct CryptoRngWrapper<R: RngCore>(R);
@@ -135,76 +1376,46 @@ impl MockAcc {
         Self { sk, addr, nonce }
     }
 
-    pub fn signed_tx(&mut self, to: [u8; 20], atoms: i64) -> TxToSer {
+    pub fn signed_tx(&mut self, to: [u8; 20], token: [u8; 20], atoms: i128) -> TxToSer {
+        self.signed_tx_at(to, token, atoms, unix_now())
+    }
+
+    /// `signed_tx` as if signed at `now`
+    pub fn signed_tx_at(
+        &mut self,
+        to: [u8; 20],
+        token: [u8; 20],
+        atoms: i128,
+        now: u64,
+    ) -> TxToSer {
         self.nonce += 1;
         let mut tx = TxToSer {
             to,
+            token,
             atoms,
             nonce: self.nonce,
+            channel: 0,
+            fee: FEE,
+            valid_until: now + VALID_FOR_SECS,
+            kind: TxKind::Transfer,
+            memo: vec![],
             sig_r: [0; 32],
             sig_s: [0; 32],
             v: 0,
             from_idx: 0,
             to_idx: 0,
+            fee_idx: 0,
+            pubkey: None,
         };
-        let digest = tx.keccak();
+        let digest = tx.keccak(&DOMAIN.separator());
         let sig = sign(&self.sk, digest);
         tx.sig_r = sig.0;
         tx.sig_s = sig.1;
         tx.v = sig.2;
         tx
     }
-    pub fn tx(&mut self, to: &Self, atoms: i64) -> TxToSer {
-        self.signed_tx(to.addr, atoms)
-    }
-}
-
-fn rec(tx: &TxToSer) -> [u8; 20] {
-    recover(&(tx.sig_r, tx.sig_s, tx.v), &tx.keccak())
-}
-
-struct InputBuilder {
-    fee_atoms: u16,
-    state_deltas: HashSet<[u8; 20]>,
-    fee_recipient: [u8; 20],
-    txs: Vec<TxToSer>,
-}
-impl InputBuilder {
-    pub fn new(fee_atoms: u16, fee_recipient: [u8; 20]) -> Self {
-        Self {
-            fee_atoms,
-            fee_recipient,
-            txs: vec![],
-            state_deltas: HashSet::new(),
-        }
-    }
-    pub fn add(mut self, tx: TxToSer) -> Self {
-        let from = rec(&tx);
-        self.state_deltas.insert(from);
-        self.state_deltas.insert(tx.to);
-        self.txs.push(tx);
-        self
-    }
-    pub fn ser(&self) -> InputToSer {
-        let mut txs = vec![];
-        let idx: HashMap<_, _> = self
-            .state_deltas
-            .iter()
-            .enumerate()
-            .map(|(x, y)| (*y, (x + 1) as u32))
-            .collect();
-        for mut tx in self.txs.clone() {
-            let from = rec(&tx);
-            tx.from_idx = idx[&from];
-            tx.to_idx = idx[&tx.to];
-            txs.push(tx);
-        }
-        InputToSer {
-            fee_atoms: self.fee_atoms,
-            fee_recipient: self.fee_recipient,
-            state_deltas: self.state_deltas.len() as u32 + 1,
-            tx: txs,
-        }
+    pub fn tx(&mut self, to: &Self, atoms: i128) -> TxToSer {
+        self.signed_tx(to.addr, USDC, atoms)
     }
 }
 
@@ -224,11 +1435,158 @@ fn load_usdc_transfers(path: &str) -> Result<Vec<Transfer>, Box<dyn std::error::
     Ok(data.transfers)
 }
 
+/// `cols` are the (from, to, value) column names of the header row. Every row is loaded or the
+/// whole file is rejected, a row missing a field or with a bad value is not skipped
+fn load_usdc_transfers_csv(
+    path: &str,
+    cols: [&str; 3],
+) -> Result<Vec<Transfer>, Box<dyn std::error::Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+    let header = reader.headers()?.clone();
+    let mut idx = [0; 3];
+    for (i, col) in cols.iter().enumerate() {
+        idx[i] = header
+            .iter()
+            .position(|x| x == *col)
+            .ok_or_else(|| format!("no column {col} in the csv header"))?;
+    }
+    let mut transfers = vec![];
+    for record in reader.records() {
+        // a row with fewer fields than the header is an error of the reader already
+        let record = record?;
+        let line = record.position().map_or(0, |x| x.line());
+        let field = |i: usize| &record[idx[i]];
+        transfers.push(Transfer {
+            from: field(0).to_string(),
+            to: field(1).to_string(),
+            atoms: field(2)
+                .parse()
+                .map_err(|e| format!("line {line}: bad {}: {e}", cols[2]))?,
+        });
+    }
+    Ok(transfers)
+}
+
+/// `cols` are the (from, to, value) columns. Addresses are strings or 20 raw bytes, the value
+/// any integer column. As with the CSV, one bad row rejects the whole file
+fn load_usdc_transfers_parquet(
+    path: &str,
+    cols: [&str; 3],
+) -> Result<Vec<Transfer>, Box<dyn std::error::Error>> {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+    let reader = SerializedFileReader::try_from(path)?;
+    let schema = reader.metadata().file_metadata().schema_descr_ptr();
+    for col in cols {
+        if !schema.columns().iter().any(|x| x.name() == col) {
+            return Err(format!("no column {col} in the parquet schema").into());
+        }
+    }
+    let mut transfers = vec![];
+    for (n, row) in reader.get_row_iter(None)?.enumerate() {
+        let row = row?;
+        let mut fields: [Option<&Field>; 3] = [None; 3];
+        for (name, field) in row.get_column_iter() {
+            if let Some(i) = cols.iter().position(|x| x == name) {
+                fields[i] = Some(field);
+            }
+        }
+        let bad = |i: usize| format!("row {n}: bad {}: {:?}", cols[i], fields[i]);
+        let address = |i: usize| match fields[i] {
+            Some(Field::Str(x)) => Ok(x.clone()),
+            Some(Field::Bytes(x)) if x.len() == 20 => Ok(format!("0x{}", hex::encode(x.data()))),
+            _ => Err(bad(i)),
+        };
+        let atoms = match fields[2] {
+            Some(Field::Long(x)) => Some(*x),
+            Some(Field::Int(x)) => Some(*x as i64),
+            Some(Field::ULong(x)) => i64::try_from(*x).ok(),
+            Some(Field::UInt(x)) => Some(*x as i64),
+            Some(Field::Str(x)) => x.trim().parse().ok(),
+            _ => None,
+        };
+        transfers.push(Transfer {
+            from: address(0)?,
+            to: address(1)?,
+            atoms: atoms.ok_or_else(|| bad(2))?,
+        });
+    }
+    Ok(transfers)
+}
+
+/// blocks per `eth_getLogs`, most providers cap the range of one call
+const LOG_BLOCKS: u64 = 1000;
+
+/// keccak256("Transfer(address,address,uint256)"), topic 0 of an ERC-20 transfer log
+fn transfer_topic() -> [u8; 32] {
+    keccak256(b"Transfer(address,address,uint256)")
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<serde_json::Value>,
+}
+
+/// the USDC transfer logs of `blocks`, oldest first, stops once `limit` are fetched.
+/// a value past `i64::MAX` can't be a mock transfer and is dropped
+fn fetch_usdc_transfers(
+    rpc_url: &str,
+    blocks: (u64, u64),
+    limit: usize,
+) -> Result<Vec<Transfer>, Box<dyn std::error::Error>> {
+    let http = reqwest::blocking::Client::new();
+    let mut transfers = vec![];
+    let mut from = blocks.0;
+    while from <= blocks.1 && transfers.len() < limit {
+        let to = blocks.1.min(from.saturating_add(LOG_BLOCKS - 1));
+        let filter = Filter::new()
+            .address(Address::from(USDC))
+            .event_signature(transfer_topic())
+            .from_block(from)
+            .to_block(to);
+        let req = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_getLogs",
+            "params": [filter],
+        });
+        let res: RpcResponse<Vec<Log>> = http.post(rpc_url).json(&req).send()?.json()?;
+        let logs = match (res.result, res.error) {
+            (Some(logs), _) => logs,
+            (None, e) => return Err(format!("eth_getLogs {from}..{to}: {e:?}").into()),
+        };
+        println!("  blocks {}..{}: {} transfer logs", from, to, logs.len());
+        for log in logs {
+            let topics = log.topics();
+            if topics.len() != 3 {
+                continue;
+            }
+            let Ok(atoms) = i64::try_from(U256::from_be_slice(&log.data().data)) else {
+                continue;
+            };
+            transfers.push(Transfer {
+                from: Address::from_word(topics[1]).to_string(),
+                to: Address::from_word(topics[2]).to_string(),
+                atoms,
+            });
+        }
+        if to == u64::MAX {
+            break;
+        }
+        from = to + 1;
+    }
+    transfers.truncate(limit);
+    Ok(transfers)
+}
+
 fn build_batch_from_usdc_transfers(
     transfers: Vec<Transfer>,
     limit: usize,
     rng: &mut StdRng,
-) -> InputBuilder {
+) -> BatchBuilder {
     println!("Building batch from {} USDC transfers", transfers.len());
 
     // Create a HashMap to map real addresses to MockAcc
@@ -236,14 +1594,14 @@ fn build_batch_from_usdc_transfers(
 
     // Create fee sink
     let fee_sink = MockAcc::new(rng);
-    let mut batch = InputBuilder::new(20, fee_sink.addr);
+    let mut batch = BatchBuilder::new(fee_sink.addr, unix_now(), DOMAIN);
 
     // Process each transfer
     for (idx, transfer) in transfers.iter().enumerate() {
-        if transfer.atoms < batch.fee_atoms as i64 {
+        if transfer.atoms <= FEE as i64 {
             continue;
         }
-        if batch.txs.len() >= limit {
+        if batch.len() >= limit {
             break;
         }
         // Parse addresses
@@ -274,9 +1632,13 @@ fn build_batch_from_usdc_transfers(
         let to_addr = addr_to_mock.get(&to_addr).unwrap().addr;
         let from_mock = addr_to_mock.get_mut(&from_addr).unwrap();
 
-        let tx = from_mock.signed_tx(to_addr, transfer.atoms);
+        let tx = from_mock.signed_tx(to_addr, USDC, transfer.atoms as i128);
 
-        batch = batch.add(tx);
+        if let Err(e) = batch.add(tx) {
+            // ex. a self transfer, give the nonce back so the sender has no gap
+            eprintln!("Skipping transfer {}: {}", idx, e);
+            from_mock.nonce -= 1;
+        }
 
         if (idx + 1) % 1000 == 0 {
             println!("  Processed {}/{} transfers", idx + 1, transfers.len());
@@ -287,23 +1649,227 @@ fn build_batch_from_usdc_transfers(
     batch
 }
 
+/// every sender signs `txs_per_sender` txs to the others, round robin so the runs interleave
+fn build_batch_from_mock_senders(
+    senders: usize,
+    txs_per_sender: usize,
+    limit: usize,
+    rng: &mut StdRng,
+) -> BatchBuilder {
+    println!(
+        "Building batch from {} mock senders, {} txs each",
+        senders, txs_per_sender
+    );
+    let mut accs: Vec<_> = (0..senders).map(|_| MockAcc::new(rng)).collect();
+    let fee_sink = MockAcc::new(rng);
+    let mut batch = BatchBuilder::new(fee_sink.addr, unix_now(), DOMAIN);
+    'rounds: for _ in 0..txs_per_sender {
+        for i in 0..senders {
+            if batch.len() >= limit {
+                break 'rounds;
+            }
+            // anyone but the sender itself
+            let to = (i + 1 + rng.next_u64() as usize % (senders - 1)) % senders;
+            let atoms = FEE as i128 + 1 + (rng.next_u64() % 10_000) as i128;
+            let to = accs[to].addr;
+            batch.add(accs[i].signed_tx(to, USDC, atoms)).unwrap();
+        }
+    }
+    batch
+}
+
+/// the flags of the `--config` file in `argv`, if any, spliced in front of the others so the
+/// command line overrides them
+fn with_config(argv: Vec<String>) -> Result<Vec<String>, String> {
+    let path = argv
+        .iter()
+        .enumerate()
+        .find_map(|(i, x)| match x.strip_prefix("--config") {
+            Some("") => argv.get(i + 1).cloned(),
+            Some(x) => x.strip_prefix('=').map(String::from),
+            None => None,
+        });
+    let Some(path) = path else {
+        return Ok(argv);
+    };
+    let content = fs::read_to_string(&path).map_err(|e| format!("failed to read {path}: {e}"))?;
+    let table: toml::Table = content
+        .parse()
+        .map_err(|e| format!("bad config {path}: {e}"))?;
+    let mut out = vec![argv[0].clone()];
+    for (key, value) in table {
+        let flag = format!("--{}", key.replace('_', "-"));
+        match value {
+            toml::Value::Boolean(true) => out.push(flag),
+            toml::Value::Boolean(false) => {}
+            toml::Value::String(x) => out.extend([flag, x]),
+            toml::Value::Integer(x) => out.extend([flag, x.to_string()]),
+            x => return Err(format!("config {key}: unsupported value {x}")),
+        }
+    }
+    out.extend(argv.into_iter().skip(1));
+    Ok(out)
+}
+
 fn main() {
     // Setup the logger.
     sp1_sdk::utils::setup_logger();
     dotenv::dotenv().ok();
 
     // Parse the command line arguments.
-    let args = Args::parse();
+    let argv = with_config(std::env::args().collect()).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    let args = Args::parse_from(argv);
+    if args.calldata.is_some() && args.evm_proof.is_none() {
+        eprintln!("Error: --calldata needs --evm-proof");
+        std::process::exit(1);
+    }
 
-    if args.execute == args.prove {
-        eprintln!("Error: You must specify either --execute or --prove");
+    if let Some(Cmd::Daemon(d)) = &args.cmd {
+        daemon(&args, d);
+    }
+    if let Some(Cmd::Serve {
+        addr,
+        out,
+        max_body_mb,
+    }) = &args.cmd
+    {
+        let (addr, out, max_body) = (*addr, out.clone(), max_body_mb << 20);
+        serve(args, addr, out, max_body);
+    }
+    if let Some(Cmd::Bench {
+        sizes,
+        senders,
+        cost_per_mcycle,
+        json,
+    }) = &args.cmd
+    {
+        if *senders < 2 {
+            eprintln!("Error: --senders needs at least 2 senders to transfer between");
+            std::process::exit(1);
+        }
+        let rows = bench(&args, sizes, *senders, *cost_per_mcycle);
+        println!(
+            "{:>8} {:>8} {:>12} {:>14} {:>12} {:>12} {:>12}",
+            "txs", "deltas", "input bytes", "cycles", "cycles/tx", "bytes/tx", "cost"
+        );
+        for r in &rows {
+            let cost = r.projected_cost.map_or("-".into(), |x| format!("{x:.4}"));
+            println!(
+                "{:>8} {:>8} {:>12} {:>14} {:>12.0} {:>12.1} {:>12}",
+                r.txs,
+                r.state_deltas,
+                r.input_bytes,
+                r.cycles,
+                r.cycles_per_tx,
+                r.input_bytes_per_tx,
+                cost
+            );
+        }
+        if let Some(path) = json {
+            fs::write(path, serde_json::to_string_pretty(&rows).unwrap())
+                .expect("failed to write bench rows");
+        }
+        return;
+    }
+    if let Some(Cmd::Aggregate { batches }) = &args.cmd {
+        let (proof, vk) = aggregate(&args, batches).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
+        let decoded = AggregateValuesStruct::abi_decode(proof.public_values.as_slice()).unwrap();
+        println!(
+            "aggregated {} batches, vkey {}",
+            decoded.batches.len(),
+            vk.bytes32()
+        );
+        if let Some(system) = args.evm_proof {
+            let dir = fixture_dir();
+            fs::create_dir_all(&dir).expect("failed to create fixture dir");
+            let path = dir.join(format!("{:?}-aggregate-fixture.json", system).to_lowercase());
+            write_fixture(&proof, vk.bytes32(), &path);
+            println!("fixture written to {}", path.display());
+            if let Some(f) = &args.calldata {
+                let path = dir.join(format!("{:?}-aggregate-calldata.txt", system).to_lowercase());
+                let calldata = format!("0x{}", hex::encode(calldata(f, &proof, &vk)));
+                fs::write(&path, &calldata).expect("failed to write calldata");
+                println!("calldata written to {}", path.display());
+            }
+        }
+        return;
+    }
+    if let Some(Cmd::GenVectors { out }) = &args.cmd {
+        let vectors = gen_vectors();
+        fs::write(out, serde_json::to_string_pretty(&vectors).unwrap())
+            .expect("failed to write vectors");
+        for v in &vectors {
+            println!("{}: status {}", v.name, v.status);
+        }
+        println!("{} vectors written to {}", vectors.len(), out.display());
+        return;
+    }
+    if let Some(Cmd::VerifyArtifact { proof }) = &args.cmd {
+        match verify_artifact(proof) {
+            Ok(m) => println!(
+                "verified batch {} with input hash {}, status {}",
+                m.batch_nonce, m.input_hash, m.status
+            ),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(Cmd::Vkey { out }) = &args.cmd {
+        // the key only depends on the ELF, no need for the network prover
+        let (_, vk) = ProverClient::builder().cpu().build().setup(FIBONACCI_ELF);
+        let artifact = VkeyArtifact { vkey: vk.bytes32() };
+        let path = out
+            .clone()
+            .unwrap_or_else(|| fixture_dir().join("vkey.json"));
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).expect("failed to create vkey dir");
+        }
+        fs::write(&path, serde_json::to_string_pretty(&artifact).unwrap())
+            .expect("failed to write vkey");
+        println!("{}", artifact.vkey);
+        println!("written to {}", path.display());
+        return;
+    }
+
+    if [args.execute, args.prove, args.check]
+        .iter()
+        .filter(|x| **x)
+        .count()
+        != 1
+    {
+        eprintln!("Error: You must specify one of --execute, --prove or --check");
         std::process::exit(1);
     }
 
-    let limit = 100;
+    let limit = args.limit;
+    if args.report.is_some() && !args.execute {
+        eprintln!("Error: --report needs --execute");
+        std::process::exit(1);
+    }
+    if args.out_dir.is_some() && !args.prove {
+        eprintln!("Error: --out-dir needs --prove");
+        std::process::exit(1);
+    }
+    if args.evm_proof.is_some() && !args.prove {
+        eprintln!("Error: --evm-proof needs --prove");
+        std::process::exit(1);
+    }
+    if args.senders.is_some_and(|x| x < 2) {
+        eprintln!("Error: --senders needs at least 2 senders to transfer between");
+        std::process::exit(1);
+    }
 
     // Create deterministic RNG with fixed seed for consistent cycle counts
-    let mut rng = StdRng::seed_from_u64(42);
+    let mut rng = StdRng::seed_from_u64(args.seed);
 
     // Build the batch based on whether we're using USDC transfers or the default scenario
     let batch = if let Some(json_path) = &args.usdc_json {
@@ -315,6 +1881,48 @@ fn main() {
                 std::process::exit(1);
             }
         }
+    } else if let Some(csv_path) = &args.usdc_csv {
+        println!("Loading USDC transfers from: {}", csv_path);
+        let cols = [
+            args.csv_from.as_str(),
+            args.csv_to.as_str(),
+            args.csv_value.as_str(),
+        ];
+        match load_usdc_transfers_csv(csv_path, cols) {
+            Ok(transfers) => build_batch_from_usdc_transfers(transfers, limit, &mut rng),
+            Err(e) => {
+                eprintln!("Error loading USDC transfers: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(parquet_path) = &args.usdc_parquet {
+        println!("Loading USDC transfers from: {}", parquet_path);
+        let cols = [
+            args.csv_from.as_str(),
+            args.csv_to.as_str(),
+            args.csv_value.as_str(),
+        ];
+        match load_usdc_transfers_parquet(parquet_path, cols) {
+            Ok(transfers) => build_batch_from_usdc_transfers(transfers, limit, &mut rng),
+            Err(e) => {
+                eprintln!("Error loading USDC transfers: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if let (Some(rpc_url), Some(blocks)) = (&args.rpc_url, args.block_range) {
+        println!(
+            "Fetching USDC transfers of blocks {}..{}",
+            blocks.0, blocks.1
+        );
+        match fetch_usdc_transfers(rpc_url, blocks, limit) {
+            Ok(transfers) => build_batch_from_usdc_transfers(transfers, limit, &mut rng),
+            Err(e) => {
+                eprintln!("Error fetching USDC transfers: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(senders) = args.senders {
+        build_batch_from_mock_senders(senders, args.txs_per_sender, limit, &mut rng)
     } else {
         // Default scenario with alice, bob, charlie
         let mut alice = MockAcc::new(&mut rng);
@@ -323,23 +1931,81 @@ fn main() {
 
         let fee_sink = MockAcc::new(&mut rng);
 
-        let batch = InputBuilder::new(20, fee_sink.addr);
+        let mut batch = BatchBuilder::new(fee_sink.addr, unix_now(), DOMAIN);
+        for tx in [
+            alice.tx(&bob, 1000),
+            alice.tx(&bob, 100),
+            alice.tx(&bob, 2000),
+            alice.tx(&charlie, 1000),
+            bob.tx(&alice, 1000),
+            charlie.tx(&bob, 1000),
+        ] {
+            batch.add(tx).unwrap();
+        }
         batch
-            .add(alice.tx(&bob, 1000))
-            .add(alice.tx(&bob, 100))
-            .add(alice.tx(&bob, 2000))
-            .add(alice.tx(&charlie, 1000))
-            .add(bob.tx(&alice, 1000))
-            .add(charlie.tx(&bob, 1000))
     };
 
     let client = ProverClient::from_env();
-    let mut stdin = SP1Stdin::new();
-    let ser = batch.ser();
+    let (keys, balances) = builder_extras(&args, &batch);
+    let receipts: Vec<_> = batch.receipts().iter().map(|x| x.leaf()).collect();
+    let ser = match batch
+        .compressed(args.compressed)
+        .batch_nonce(args.batch_nonce)
+        .build()
+    {
+        Ok(ser) => ser,
+        Err(e) => {
+            eprintln!("Error building batch: {}", e);
+            std::process::exit(1);
+        }
+    };
     println!("state_deltas={} txs={}", ser.state_deltas, ser.tx.len());
-    let ser = ser.ser();
-    println!("input size: {}", ser.len());
-    stdin.write(&ser);
+    if args.inspect {
+        print!("{}", inspect(&ser.ser()));
+    }
+    let chunks = ser.ser_chunks(args.chunk_txs);
+    let size = chunks.iter().map(|c| c.len()).sum::<usize>() + Input::HEADER_SIZE;
+    println!("input size: {} in {} chunks", size, chunks.len());
+    let stdin = batch_stdin(&args, &ser, &keys, &balances);
+
+    if args.check {
+        let native = native_public_values(&args, &ser, &keys, &balances);
+        let (output, _) = client.execute(FIBONACCI_ELF, &stdin).run().unwrap();
+        println!("executed, {} public value bytes", output.as_slice().len());
+        let (pk, _) = client.setup(FIBONACCI_ELF);
+        let proof = prove_retrying(&args, &client, &pk, &stdin, 0, |_, _| {}).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
+        println!(
+            "proved, {} public value bytes",
+            proof.public_values.as_slice().len()
+        );
+        let (executed, proven) = (output.as_slice(), proof.public_values.as_slice());
+        let mut ok = true;
+        for (a, b, x, y) in [
+            ("execute", "native", executed, native.as_slice()),
+            ("prove", "native", proven, native.as_slice()),
+            ("prove", "execute", proven, executed),
+        ] {
+            if let Some(at) = first_diff(x, y) {
+                eprintln!(
+                    "MISMATCH: {} ({} bytes) and {} ({} bytes) public values differ at byte {}",
+                    a,
+                    x.len(),
+                    b,
+                    y.len(),
+                    at
+                );
+                ok = false;
+            }
+        }
+        if !ok {
+            std::process::exit(1);
+        }
+        println!("execute, prove and native public values match");
+        return;
+    }
 
     if args.execute {
         // Execute the program
@@ -347,26 +2013,96 @@ fn main() {
         println!("Program executed successfully.");
 
         // Read the output.
+        println!("public values size: {}", output.as_slice().len());
         let decoded = PublicValuesStruct::abi_decode(output.as_slice()).unwrap();
-        let PublicValuesStruct { n } = decoded;
+        let PublicValuesStruct {
+            status,
+            total_fee,
+            fee_txs,
+            metering,
+            receipt_root,
+            skipped,
+            n,
+            ..
+        } = decoded;
+        if status != 0 {
+            eprintln!("Batch rejected by program, status={}", status);
+        }
+        println!("total fee: {} over {} txs", total_fee, fee_txs);
+        if args.prior_balance.is_some() {
+            let solvent = n.iter().filter(|x| x.solvent).count();
+            println!("solvent deltas: {}/{}", solvent, n.len());
+        }
+        if args.skip_invalid {
+            let skipped: u32 = skipped.iter().map(|x| x.count_ones()).sum();
+            println!("skipped txs: {}", skipped);
+        }
+        if args.metering {
+            println!("{:?}", metering);
+        }
+        if args.receipts {
+            let expected = merkle::root(&receipts);
+            println!(
+                "receipt root: {} of {} receipts, matches the batch: {}",
+                receipt_root,
+                receipts.len(),
+                receipt_root.0 == expected
+            );
+        }
         // println!("{:#?}", n);
 
         // Record the number of cycles executed.
         println!("Number of cycles: {:.3}M", report.total_instruction_count() as f64 / 1e6);
+
+        if let Some(path) = &args.report {
+            let out = exec_report(&args, &ser, output.as_slice(), &report);
+            fs::write(path, serde_json::to_string_pretty(&out).unwrap())
+                .expect("failed to write report");
+            println!("report written to {}", path.display());
+        }
     } else {
         // Setup the program for proving.
         let (pk, vk) = client.setup(FIBONACCI_ELF);
+        let started_at = unix_now();
 
-        // Generate the proof
-        let proof = client
-            .prove(&pk, &stdin)
-            .run()
-            .expect("failed to generate proof");
+        // Generate and verify the proof, wrapped for the EVM if asked
+        let proof = prove_retrying(&args, &client, &pk, &stdin, 0, |_, _| {}).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
+        println!("Successfully generated and verified proof!");
 
-        println!("Successfully generated proof!");
+        if let Some(system) = args.evm_proof {
+            let dir = fixture_dir();
+            fs::create_dir_all(&dir).expect("failed to create fixture dir");
+            let path = dir.join(format!("{:?}-fixture.json", system).to_lowercase());
+            write_fixture(&proof, vk.bytes32(), &path);
+            println!("vkey: {}", vk.bytes32());
+            println!("fixture written to {}", path.display());
+            if let Some(f) = &args.calldata {
+                let path = dir.join(format!("{:?}-calldata.txt", system).to_lowercase());
+                let calldata = format!("0x{}", hex::encode(calldata(f, &proof, &vk)));
+                fs::write(&path, &calldata).expect("failed to write calldata");
+                println!("calldata: {}", calldata);
+                println!("calldata written to {}", path.display());
+            }
+        }
 
-        // Verify the proof.
-        client.verify(&proof, &vk).expect("failed to verify proof");
-        println!("Successfully verified proof!");
+        if let Some(dir) = &args.out_dir {
+            // the manifest records the cycles, the proof alone does not tell
+            let (_, report) = client.execute(FIBONACCI_ELF, &stdin).run().unwrap();
+            fs::create_dir_all(dir).expect("failed to create out dir");
+            let stem = request_id(&ser, &keys, &balances);
+            let path = write_artifact(
+                dir,
+                &stem,
+                &proof,
+                vk.bytes32(),
+                report.total_instruction_count(),
+                started_at,
+                args.evm_proof,
+            );
+            println!("proof written to {}", path.display());
+        }
     }
 }
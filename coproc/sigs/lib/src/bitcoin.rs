@@ -0,0 +1,179 @@
+//! Just enough of the Bitcoin transaction serialization for `BitcoinTxData`: a transaction with
+//! or without witnesses (BIP-144) split into the fields the sighashes take, each kept encoded.
+//! Only what consensus accepts parses: minimal compact sizes, at least one input, nothing after
+//! the lock time.
+
+pub struct Tx<'a> {
+    pub version: &'a [u8],
+    pub inputs: Vec<TxIn<'a>>,
+    /// Serialized outputs: amount || script
+    pub outputs: Vec<&'a [u8]>,
+    pub lock_time: &'a [u8],
+}
+
+pub struct TxIn<'a> {
+    /// Serialized input without its witness: outpoint || script_sig || sequence
+    pub raw: &'a [u8],
+    /// txid || vout
+    pub outpoint: &'a [u8],
+    pub sequence: &'a [u8],
+    pub witness: Vec<&'a [u8]>,
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (x, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(x)
+    }
+
+    /// A CompactSize, in the fewest bytes
+    fn compact_size(&mut self) -> Option<usize> {
+        let (n, min) = match self.take(1)?[0] {
+            x @ 0..=0xfc => return Some(x as usize),
+            0xfd => (
+                u16::from_le_bytes(self.take(2)?.try_into().unwrap()) as u64,
+                0xfd,
+            ),
+            0xfe => (
+                u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as u64,
+                0x1_0000,
+            ),
+            0xff => (
+                u64::from_le_bytes(self.take(8)?.try_into().unwrap()),
+                0x1_0000_0000,
+            ),
+        };
+        if n < min {
+            return None;
+        }
+        usize::try_from(n).ok()
+    }
+
+    fn var_bytes(&mut self) -> Option<&'a [u8]> {
+        let n = self.compact_size()?;
+        self.take(n)
+    }
+
+    /// What was read since `start`
+    fn since(&self, start: &'a [u8]) -> &'a [u8] {
+        &start[..start.len() - self.0.len()]
+    }
+}
+
+/// The transaction `data` serializes, `None` unless it is exactly one
+pub fn parse(data: &[u8]) -> Option<Tx<'_>> {
+    let mut r = Reader(data);
+    let version = r.take(4)?;
+    // BIP-144: a zero marker where the input count would be, then flag 1
+    let segwit = r.0.starts_with(&[0x00, 0x01]);
+    if segwit {
+        r.take(2)?;
+    }
+    let mut inputs = vec![];
+    for _ in 0..r.compact_size()? {
+        let start = r.0;
+        let outpoint = r.take(36)?;
+        r.var_bytes()?;
+        let sequence = r.take(4)?;
+        inputs.push(TxIn {
+            raw: r.since(start),
+            outpoint,
+            sequence,
+            witness: vec![],
+        });
+    }
+    let mut outputs = vec![];
+    for _ in 0..r.compact_size()? {
+        let start = r.0;
+        r.take(8)?;
+        r.var_bytes()?;
+        outputs.push(r.since(start));
+    }
+    if segwit {
+        for input in &mut inputs {
+            for _ in 0..r.compact_size()? {
+                input.witness.push(r.var_bytes()?);
+            }
+        }
+    }
+    let lock_time = r.take(4)?;
+    (r.0.is_empty() && !inputs.is_empty()).then_some(Tx {
+        version,
+        inputs,
+        outputs,
+        lock_time,
+    })
+}
+
+pub fn compact_size(n: usize) -> Vec<u8> {
+    match n {
+        0..=0xfc => vec![n as u8],
+        0xfd..=0xffff => [&[0xfd][..], &(n as u16).to_le_bytes()].concat(),
+        _ => [&[0xfe][..], &(n as u32).to_le_bytes()].concat(),
+    }
+}
+
+/// `data` prefixed with its length
+pub fn var_bytes(data: &[u8]) -> Vec<u8> {
+    [compact_size(data.len()), data.to_vec()].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        // A legacy transaction, one input and two outputs
+        let outpoint = [[0x11; 32].as_slice(), &[1, 0, 0, 0]].concat();
+        let input = [outpoint.clone(), var_bytes(&[0x51]), vec![0xff; 4]].concat();
+        let outputs = [
+            [[7; 8].as_slice(), &var_bytes(&[0x6a])].concat(),
+            [0; 9].to_vec(),
+        ];
+        let legacy = [
+            &[2, 0, 0, 0][..],
+            &compact_size(1),
+            &input,
+            &compact_size(2),
+            &outputs.concat(),
+            &[0; 4],
+        ]
+        .concat();
+        let tx = parse(&legacy).unwrap();
+        assert_eq!(tx.version, [2, 0, 0, 0]);
+        assert_eq!(
+            (tx.inputs[0].raw, tx.inputs[0].outpoint),
+            (&input[..], &outpoint[..])
+        );
+        assert_eq!(tx.outputs, [&outputs[0][..], &outputs[1][..]]);
+        assert!(tx.inputs[0].witness.is_empty());
+
+        // With the witness, the same fields
+        let witness = [compact_size(2), var_bytes(&[1; 72]), var_bytes(&[2; 33])].concat();
+        let segwit = [
+            &legacy[..4],
+            &[0, 1],
+            &legacy[4..legacy.len() - 4],
+            &witness,
+            &[0; 4],
+        ]
+        .concat();
+        let tx = parse(&segwit).unwrap();
+        assert_eq!(tx.inputs[0].raw, input);
+        assert_eq!(tx.inputs[0].witness, [&[1; 72][..], &[2; 33]]);
+
+        // Truncated, trailing bytes, no inputs, or a compact size longer than it takes
+        assert!(parse(&segwit[..segwit.len() - 1]).is_none());
+        assert!(parse(&[&segwit[..], &[0]].concat()).is_none());
+        assert!(parse(&[&legacy[..4], &[0, 0], &[0; 4]].concat()).is_none());
+        assert!(parse(&[&legacy[..4], &[0xfd, 1, 0], &legacy[5..]].concat()).is_none());
+        assert_eq!(compact_size(0xfd), [0xfd, 0xfd, 0]);
+    }
+}
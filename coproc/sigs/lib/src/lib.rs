@@ -1,31 +1,63 @@
 use alloy_sol_types::sol;
+use base64ct::{Base64UrlUnpadded, Encoding};
+use rand_chacha::rand_core::CryptoRngCore;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use zeroize::Zeroizing;
+
+mod bitcoin;
+pub mod pure;
+mod rlp;
 
 sol! {
     /// The public values encoded as a struct that can be easily deserialized inside Solidity.
+    /// The verdict of every signature item, by its index in the input
     struct PublicValuesStruct {
-        uint32 n;
-        uint32 a;
-        uint32 b;
+        /// Number of items
+        uint32 count;
+        /// Bit i is set if item i verified: byte i / 8, least significant bit first
+        bytes bitmap;
+        /// The `SigError` code of every failed item, in input order
+        bytes failures;
+        /// Merkle root of the leaves of the verified items, see `merkle_root`, zero unless the
+        /// program is asked to commit it
+        bytes32 authorizations;
+        /// keccak256(abi.encodePacked(leaves)) of every item in input order, verified or not,
+        /// see `items_hash`: what the verdicts are of
+        bytes32 items;
     }
-}
 
-/// Compute the n'th fibonacci number (wrapping around on overflows), using normal Rust code.
-pub fn fibonacci(n: u32) -> (u32, u32) {
-    let mut a = 0u32;
-    let mut b = 1u32;
-    for _ in 0..n {
-        let c = a.wrapping_add(b);
-        a = b;
-        b = c;
+    /// The public values of the signer recovery mode, instead of the verdicts: who signed the
+    /// items, see `recover_signers`
+    struct SignersStruct {
+        /// Number of items
+        uint32 count;
+        /// The recovered addresses in ascending order without duplicates, empty if only their
+        /// hash is committed
+        address[] signers;
+        /// keccak256(abi.encodePacked(signers)) of the full list
+        bytes32 hash;
+        /// `items_hash` of the items, what the signers were recovered from
+        bytes32 items;
     }
-    (a, b)
 }
 
 // ============================================================================
 // Signature Data Structures
 // ============================================================================
 
+/// What the program does with the items and commits
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ProgramMode {
+    /// Verify every item, commit a `PublicValuesStruct`
+    #[default]
+    Verify,
+    /// Recover the signers of the ECDSA items without verifying, commit a `SignersStruct`
+    RecoverSigners,
+    /// As `RecoverSigners`, committing the hash of the signers only, not the list
+    RecoverSignersHash,
+}
+
 /// ECDSA signature data over secp256k1
 /// Contains a message hash, signature, and public key as byte arrays
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -38,6 +70,39 @@ pub struct EcdsaSecp256k1Data {
     pub public_key: Vec<u8>,
     /// Recovery ID for public key recovery (0-3)
     pub recovery_id: u8,
+    /// How the signed digest is derived from `message_hash`
+    pub hashing: EcdsaHashing,
+    /// The message itself, if carried: the guest hashes it and checks it is `message_hash`, so
+    /// the proof binds the message, not only its digest
+    pub message: Option<RawMessage>,
+}
+
+/// A message an item carries in full, hashed to its `message_hash` in the guest, on the
+/// SHA-256 or Keccak precompile
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RawMessage {
+    pub bytes: Vec<u8>,
+    pub digest: MessageDigest,
+}
+
+/// The hash a `RawMessage` is the preimage of
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MessageDigest {
+    /// As `sign_ecdsa` and `sign_schnorr` hash the message
+    #[default]
+    Sha256,
+    /// As Ethereum hashes it, ex. the EIP-191 payload of `personal_sign_payload`
+    Keccak256,
+}
+
+/// The digest an `EcdsaSecp256k1Data` signature is over
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum EcdsaHashing {
+    /// SHA-256 of `message_hash`, as the k256 `Signer` hashes it again
+    #[default]
+    Sha256,
+    /// `message_hash` itself, as wallets sign a keccak digest (EIP-191 personal_sign, EIP-712)
+    Prehashed,
 }
 
 /// Schnorr signature data over secp256k1
@@ -48,8 +113,82 @@ pub struct SchnorrSecp256k1Data {
     pub message_hash: [u8; 32],
     /// Schnorr signature (64 bytes: r || s)
     pub signature: Vec<u8>,
-    /// X-only public key (32 bytes)
+    /// X-only public key (32 bytes), the Taproot output key if tweaked
     pub public_key: [u8; 32],
+    /// How the signed message is derived from `message_hash`
+    pub hashing: SchnorrHashing,
+    /// The Taproot internal key the public key is tweaked from, checked if present
+    pub taproot: Option<TaprootTweak>,
+    /// The message itself, if carried, see `EcdsaSecp256k1Data::message`
+    pub message: Option<RawMessage>,
+}
+
+/// The message a `SchnorrSecp256k1Data` signature is over
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SchnorrHashing {
+    /// SHA-256 of `message_hash`, as the k256 `Signer` hashes it again
+    #[default]
+    Sha256,
+    /// `message_hash` itself is the BIP-340 message, as Taproot sighashes are signed
+    Bip340,
+}
+
+/// Taproot (BIP-341) output key tweak: Q = P + H_TapTweak(P || merkle_root) * G
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TaprootTweak {
+    /// X-only internal key P (32 bytes)
+    pub internal_key: [u8; 32],
+    /// Root of the script tree, `None` for a key path only output
+    pub merkle_root: Option<[u8; 32]>,
+}
+
+/// ECDSA signature data over P-256 (secp256r1), as produced by HSMs and WebAuthn authenticators
+/// Unlike `EcdsaSecp256k1Data` the message hash is the signed digest, it is not hashed again
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct P256EcdsaData {
+    /// SHA-256 hash of the message (32 bytes)
+    pub message_hash: [u8; 32],
+    /// ECDSA signature in compact format (64 bytes: r || s)
+    pub signature: Vec<u8>,
+    /// Compressed public key (33 bytes: 0x02/0x03 || x)
+    pub public_key: Vec<u8>,
+    /// Recovery ID for public key recovery (0-3)
+    pub recovery_id: u8,
+}
+
+/// WebAuthn (passkey) assertion, a P-256 signature over authenticatorData || SHA-256(clientDataJSON)
+/// The client data is passed as its hash, as the authenticator signed it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WebAuthnAssertionData {
+    /// rpIdHash (32 bytes) || flags (1 byte) || signCount (4 bytes) || extensions
+    pub authenticator_data: Vec<u8>,
+    /// SHA-256 hash of the clientDataJSON (32 bytes)
+    pub client_data_hash: [u8; 32],
+    /// ASN.1 DER encoded ECDSA signature, as returned by the authenticator
+    pub signature: Vec<u8>,
+    /// Compressed P-256 public key of the credential (33 bytes)
+    pub public_key: Vec<u8>,
+    /// Also require the user verified (UV) flag, not only user present (UP)
+    pub require_user_verification: bool,
+}
+
+/// RSA signature data, SHA-256 with PKCS#1 v1.5 or PSS padding, 2048 to 4096-bit keys
+/// Contains the message, signature, and public key as byte arrays
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RsaSignatureData {
+    /// The message, hashed with SHA-256 by the padding scheme
+    pub message: Vec<u8>,
+    /// RSA signature (as long as the modulus)
+    pub signature: Vec<u8>,
+    /// PKCS#1 DER encoded public key (RSAPublicKey: modulus and exponent)
+    pub public_key: Vec<u8>,
+    pub padding: RsaPadding,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RsaPadding {
+    Pkcs1v15,
+    Pss,
 }
 
 /// EdDSA (Ed25519) signature data
@@ -62,531 +201,4934 @@ pub struct Ed25519Data {
     pub signature: Vec<u8>,
     /// Ed25519 public key (32 bytes)
     pub public_key: [u8; 32],
+    /// Which signatures are accepted, the chain the guest serves decides
+    pub mode: Ed25519Mode,
+    /// The RFC 8032 variant the signature is of, what the signer supports decides
+    pub algorithm: Ed25519Algorithm,
 }
 
-/// Composite structure containing all signature types for testing
-/// This will be passed into the RISC-V VM program to test verification
+/// FROST threshold Schnorr signature over secp256k1: t of n signers aggregate a single BIP-340
+/// signature under the group key, which tells nothing of the threshold or who signed
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-pub struct SignatureTestData {
-    pub ecdsa: EcdsaSecp256k1Data,
-    pub schnorr: SchnorrSecp256k1Data,
-    pub ed25519: Ed25519Data,
+pub struct ThresholdSchnorrData {
+    /// The signed message (32 bytes), BIP-340 signs it as is
+    pub message_hash: [u8; 32],
+    /// Aggregated signature (64 bytes: R.x || z)
+    pub signature: Vec<u8>,
+    /// X-only group public key (32 bytes)
+    pub group_public_key: [u8; 32],
 }
 
-// ============================================================================
-// Helper Functions for ECDSA
-// ============================================================================
+/// MuSig2 (BIP-327) multi-signature over secp256k1: every signer co-signs a single BIP-340
+/// signature under the aggregate of their keys, ex. a voucher by client and vendor
+/// The keys are aggregated in the guest, so the signature binds exactly these signers
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MuSig2Data {
+    /// The signed message (32 bytes), BIP-340 signs it as is
+    pub message_hash: [u8; 32],
+    /// Aggregated signature (64 bytes: R.x || s)
+    pub signature: Vec<u8>,
+    /// Compressed public keys of the signers (33 bytes each, concatenated), in key aggregation
+    /// order
+    pub public_keys: Vec<u8>,
+}
 
-impl EcdsaSecp256k1Data {
-    /// Verify the ECDSA signature using compressed public key
-    pub fn verify(&self) -> bool {
-        use k256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
-        use k256::PublicKey;
+/// BIP-340 signature over secp256k1 under the plain sum of the signers' keys, ex. a voucher
+/// co-signed by client and vendor: dual control checked as one verification
+/// Every key carries a proof of possession, checked in the guest, so no signer can pick a key
+/// that cancels the others'
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CosignedSchnorrData {
+    /// The signed message (32 bytes), BIP-340 signs it as is
+    pub message_hash: [u8; 32],
+    /// Aggregated signature (64 bytes: R.x || s)
+    pub signature: Vec<u8>,
+    /// Compressed public keys of the signers (33 bytes each, concatenated), distinct
+    pub public_keys: Vec<u8>,
+    /// The proof of possession of every key, see `prove_possession` (64 bytes each,
+    /// concatenated), in key order
+    pub possession_proofs: Vec<u8>,
+}
 
-        // Direct construction from fixed-size arrays
-        let signature = Signature::from_slice(&self.signature).unwrap();
+/// A signed Ethereum transaction as broadcast, legacy (EIP-155 or not) or EIP-1559, and the
+/// account it is from
+/// The program parses it and recovers the sender of its signing hash, as a node does
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EthTxData {
+    /// The RLP encoded transaction, an EIP-1559 one prefixed with its type 0x02
+    pub raw: Vec<u8>,
+    /// Address of the sender, the signature has to recover to it
+    pub sender: [u8; 20],
+}
 
-        // Use compressed key format (33 bytes) - much cheaper than uncompressed
-        let public_key = PublicKey::from_sec1_bytes(&self.public_key).unwrap();
-        let verifying_key = VerifyingKey::from(public_key);
+/// The envelope of a transaction `sign_eth_tx` signs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EthTxKind {
+    /// Gas price, chain id in `v` (EIP-155)
+    Legacy,
+    /// Max fee and priority fee, empty access list
+    Eip1559,
+}
 
-        verifying_key.verify(&self.message_hash, &signature).is_ok()
-    }
+/// The ECDSA signature of an `EthTxData` and the hash it signs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EthTxSignature {
+    /// Keccak-256 of the unsigned transaction, with the chain id for EIP-155
+    pub sighash: [u8; 32],
+    /// r || s (64 bytes)
+    pub signature: [u8; 64],
+    /// y parity of R, `v` - 27 or the EIP-155 equivalent for a legacy transaction
+    pub recovery_id: u8,
+    /// `None` for a legacy transaction from before EIP-155
+    pub chain_id: Option<u64>,
+}
 
-    /// Recover the public key from the signature (optimal pattern)
-    pub fn recover(&self) -> [u8; 33] {
-        use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+/// A signed Bitcoin transaction and the outputs it spends, the signature of one of its inputs
+/// verified: a P2WPKH (BIP-143) or Taproot key path (BIP-341) spend
+/// The program parses it and checks the witness signs the sighash under the key the spent
+/// output commits to, as a node does
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BitcoinTxData {
+    /// The transaction with its witnesses (BIP-144 serialization)
+    pub tx: Vec<u8>,
+    /// Index of the input verified
+    pub input: u32,
+    /// The outputs the inputs spend, in input order, BIP-341 signs all of them
+    pub prevouts: Vec<BitcoinPrevout>,
+}
 
-        // Direct construction from fixed-size array
-        let signature = Signature::from_slice(&self.signature).unwrap();
-        let recovery_id = RecoveryId::from_byte(self.recovery_id).unwrap();
-        let recovered_key = VerifyingKey::recover_from_prehash(&self.message_hash, &signature, recovery_id).unwrap();
+/// An output a Bitcoin transaction spends
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BitcoinPrevout {
+    /// Amount in satoshis
+    pub amount: u64,
+    pub script_pubkey: Vec<u8>,
+}
 
-        // Return compressed public key (33 bytes)
-        recovered_key.to_encoded_point(true).as_bytes()[..33].try_into().unwrap()
-    }
+/// The output `sign_bitcoin_tx` spends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitcoinOutputKind {
+    /// Pay to witness public key hash, an ECDSA signature and the compressed key
+    P2wpkh,
+    /// Pay to Taproot, a BIP-340 signature of the output key, without a script tree
+    P2tr,
 }
 
-// ============================================================================
-// Helper Functions for Schnorr
-// ============================================================================
+/// The signature of a Bitcoin input, the hash it signs and the key it is under
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BitcoinSignature {
+    /// P2WPKH: a DER signature under the compressed key of the witness, its hash the program
+    Ecdsa {
+        sighash: [u8; 32],
+        signature: Vec<u8>,
+        public_key: Vec<u8>,
+    },
+    /// Taproot key path: a 64-byte BIP-340 signature under the output key
+    Schnorr {
+        sighash: [u8; 32],
+        signature: Vec<u8>,
+        output_key: [u8; 32],
+    },
+}
 
-impl SchnorrSecp256k1Data {
-    /// Verify the Schnorr signature using direct byte arrays
-    pub fn verify(&self) -> bool {
-        use k256::schnorr::{signature::Verifier, Signature, VerifyingKey};
+/// Verification policy of an Ed25519 signature, implementations disagree on the edge cases:
+/// non-canonical encodings and small order components
+/// Every mode requires a canonical s (s < L)
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Ed25519Mode {
+    /// RFC 8032 as ed25519-dalek `verify_strict`: canonical R and A, neither of small order,
+    /// cofactorless equation R + H * A == s * B
+    #[default]
+    Strict,
+    /// ZIP-215 (Zcash, Cosmos): any R and A encoding that decompresses,
+    /// cofactored equation [8](R + H * A) == [8](s * B)
+    Zip215,
+    /// Canonical R and A, cofactored equation
+    Cofactored,
+}
 
-        // Direct construction from fixed-size arrays
-        let signature = Signature::try_from(&self.signature[..]).unwrap();
-        let verifying_key = VerifyingKey::from_bytes(&self.public_key).unwrap();
+/// The Ed25519 variant of RFC 8032 a signature is of: the message signed as is, under a context
+/// string, or its SHA-512 signed, as HSMs that only sign digests do
+/// Each is domain separated, a signature of one fails as any other
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Ed25519Algorithm {
+    /// Ed25519, PureEdDSA without a context
+    #[default]
+    Pure,
+    /// Ed25519ctx: under a context string of 1 to 255 bytes
+    Ctx(Vec<u8>),
+    /// Ed25519ph: SHA-512 of the message signed, under a context string of up to 255 bytes
+    Ph(Vec<u8>),
+}
 
-        verifying_key.verify(&self.message_hash, &signature).is_ok()
+/// A gateway session handshake: client and gateway exchange ephemeral X25519 keys, and the
+/// gateway signs both keys and the transcript under its long-term Ed25519 identity key, as a
+/// TLS 1.3 server signs its handshake
+/// The program checks the signature, so a proof attests the gateway took the session and no
+/// secret is an input of it, the session key MAC is the gateway's to check, see `verify_mac`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SessionAuthData {
+    /// Ephemeral X25519 public key of the client (32 bytes, Montgomery u)
+    pub client_public_key: [u8; 32],
+    /// Ephemeral X25519 public key of the gateway (32 bytes, Montgomery u)
+    pub gateway_public_key: [u8; 32],
+    /// Long-term Ed25519 identity key of the gateway (32 bytes)
+    pub identity_key: [u8; 32],
+    /// The handshake messages as the gateway saw them
+    pub transcript: Vec<u8>,
+    /// Ed25519 signature of the handshake hash under the identity key (64 bytes: R || s)
+    pub signature: Vec<u8>,
+}
+
+/// The signature schemes a `SignatureItem` can be verified under
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Scheme {
+    EcdsaSecp256k1,
+    SchnorrSecp256k1,
+    Ed25519,
+    P256Ecdsa,
+    WebAuthn,
+    Rsa,
+    ThresholdSchnorr,
+    MuSig2,
+    EthTx,
+    BitcoinTx,
+    SessionAuth,
+    CosignedSchnorr,
+}
+
+/// One signature to verify, tagged with its scheme
+/// The program reads them in frames of a `Vec<SignatureItem>`, so any mix of schemes is verified
+/// in one run
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SignatureItem {
+    EcdsaSecp256k1(EcdsaSecp256k1Data),
+    SchnorrSecp256k1(SchnorrSecp256k1Data),
+    Ed25519(Ed25519Data),
+    P256Ecdsa(P256EcdsaData),
+    WebAuthn(WebAuthnAssertionData),
+    Rsa(RsaSignatureData),
+    ThresholdSchnorr(ThresholdSchnorrData),
+    MuSig2(MuSig2Data),
+    EthTx(EthTxData),
+    BitcoinTx(BitcoinTxData),
+    SessionAuth(SessionAuthData),
+    CosignedSchnorr(CosignedSchnorrData),
+}
+
+/// Why a signature failed verification, a malformed input fails instead of aborting the proof
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SigError {
+    /// The signature does not parse: its length, encoding or a scalar out of range
+    MalformedSignature,
+    /// The public key does not parse or is not on the curve
+    MalformedKey,
+    /// An RSA modulus out of `RsaSignatureData::MIN_BITS..=MAX_BITS`
+    UnsupportedKeySize,
+    /// The WebAuthn authenticator data is shorter than its fixed fields
+    MalformedAuthenticatorData,
+    /// The WebAuthn user present (or verified, if required) flag is not set
+    MissingUserFlags,
+    /// A small order point the Ed25519 mode rejects
+    RejectedByMode,
+    /// The Taproot output key is not the tweaked internal key
+    TweakMismatch,
+    /// Well formed, but the signature does not verify
+    InvalidSignature,
+    /// The Ethereum transaction is not canonical RLP of a legacy or EIP-1559 transaction, or the
+    /// Bitcoin one does not parse or has no such input
+    MalformedTransaction,
+    /// The Bitcoin output spent is neither P2WPKH nor Taproot, or spent by a Taproot script
+    UnsupportedScript,
+    /// The Ed25519 context string is over 255 bytes, or empty for Ed25519ctx
+    InvalidContext,
+    /// An ECDSA signature with s above n / 2, the malleable twin of the low-s one
+    HighS,
+    /// A BIP-340 signature that holds for a nonce or key of odd y: the signer did not negate its
+    /// nonce or secret to the even y of the x-only point
+    OddY,
+    /// An Ed25519 s not below L, or an R or A the mode rejects that is not the encoding its
+    /// point compresses to
+    NonCanonical,
+    /// A co-signer's proof of possession does not verify, or is missing
+    InvalidPossession,
+    /// The message an item carries does not hash to its message hash
+    MessageMismatch,
+    /// The item was in a frame that is not bincode of signature items, one never read, or
+    /// past the item count of the stream header
+    MalformedFrame,
+}
+
+impl std::fmt::Display for SigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SigError::MalformedSignature => write!(f, "malformed signature"),
+            SigError::MalformedKey => write!(f, "malformed public key"),
+            SigError::UnsupportedKeySize => write!(f, "unsupported key size"),
+            SigError::MalformedAuthenticatorData => write!(f, "malformed authenticator data"),
+            SigError::MissingUserFlags => write!(f, "missing user present/verified flag"),
+            SigError::RejectedByMode => write!(f, "rejected by the verification mode"),
+            SigError::TweakMismatch => write!(f, "output key is not the tweaked internal key"),
+            SigError::InvalidSignature => write!(f, "invalid signature"),
+            SigError::MalformedTransaction => write!(f, "malformed transaction"),
+            SigError::UnsupportedScript => write!(f, "unsupported output script"),
+            SigError::InvalidContext => write!(f, "invalid context string"),
+            SigError::HighS => write!(f, "high s"),
+            SigError::OddY => write!(f, "signed for an odd y"),
+            SigError::NonCanonical => write!(f, "non-canonical encoding"),
+            SigError::InvalidPossession => write!(f, "invalid proof of possession"),
+            SigError::MessageMismatch => write!(f, "message does not match its hash"),
+            SigError::MalformedFrame => write!(f, "malformed frame"),
+        }
     }
+}
 
-    /// Batch verify multiple Schnorr signatures
-    /// NOTE: True mathematical batch verification (single multiscalar multiplication)
-    /// is not yet possible due to SP1-patched k256 API limitations.
-    /// This performs optimized individual verification for now.
-    pub fn batch_verify(signatures: &[SchnorrSecp256k1Data]) -> usize {
-        use k256::schnorr::{signature::Verifier, Signature, VerifyingKey};
+impl std::error::Error for SigError {}
 
-        signatures
-            .iter()
-            .filter(|sig_data| {
-                let signature = Signature::try_from(&sig_data.signature[..]).unwrap();
-                let verifying_key = VerifyingKey::from_bytes(&sig_data.public_key).unwrap();
-                verifying_key.verify(&sig_data.message_hash, &signature).is_ok()
-            })
-            .count()
+impl SigError {
+    /// Code of the error in the public values, 0 is not an error
+    pub fn code(&self) -> u8 {
+        match self {
+            SigError::MalformedSignature => 1,
+            SigError::MalformedKey => 2,
+            SigError::UnsupportedKeySize => 3,
+            SigError::MalformedAuthenticatorData => 4,
+            SigError::MissingUserFlags => 5,
+            SigError::RejectedByMode => 6,
+            SigError::InvalidSignature => 7,
+            SigError::TweakMismatch => 8,
+            SigError::MalformedTransaction => 9,
+            SigError::UnsupportedScript => 10,
+            SigError::InvalidContext => 11,
+            SigError::HighS => 12,
+            SigError::OddY => 13,
+            SigError::NonCanonical => 14,
+            SigError::InvalidPossession => 15,
+            SigError::MessageMismatch => 16,
+            SigError::MalformedFrame => 17,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        Some(match code {
+            1 => SigError::MalformedSignature,
+            2 => SigError::MalformedKey,
+            3 => SigError::UnsupportedKeySize,
+            4 => SigError::MalformedAuthenticatorData,
+            5 => SigError::MissingUserFlags,
+            6 => SigError::RejectedByMode,
+            7 => SigError::InvalidSignature,
+            8 => SigError::TweakMismatch,
+            9 => SigError::MalformedTransaction,
+            10 => SigError::UnsupportedScript,
+            11 => SigError::InvalidContext,
+            12 => SigError::HighS,
+            13 => SigError::OddY,
+            14 => SigError::NonCanonical,
+            15 => SigError::InvalidPossession,
+            16 => SigError::MessageMismatch,
+            17 => SigError::MalformedFrame,
+            _ => return None,
+        })
     }
 }
 
 // ============================================================================
-// Helper Functions for Ed25519
+// Signature Verifier
 // ============================================================================
 
-impl Ed25519Data {
-    /// Verify Ed25519 signature using curve25519_dalek primitives
-    /// Verification equation: R + H(R||A||M) * A == s * B
-    pub fn verify(&self) -> bool {
-        use curve25519_dalek::{
-            constants::ED25519_BASEPOINT_TABLE,
-            edwards::CompressedEdwardsY,
-            scalar::Scalar,
-        };
-        use sha2::{Digest, Sha512};
-
-        // Signature is (R, s) - split into two 32-byte arrays directly
-        let r_bytes: [u8; 32] = self.signature[0..32].try_into().unwrap();
-        let s_bytes: [u8; 32] = self.signature[32..64].try_into().unwrap();
-
-        // Parse R
-        let r_point = match CompressedEdwardsY(r_bytes).decompress() {
-            Some(p) => p,
-            None => return false,
-        };
+/// A signature of some scheme, all the program needs to verify it and track its cost
+/// Every data type implements it, `SignatureItem` by its variant, so the program's loop is the
+/// same whatever the schemes
+pub trait SignatureVerifier {
+    fn scheme(&self) -> Scheme;
 
-        // Parse s
-        let s = Scalar::from_bytes_mod_order(s_bytes);
+    /// Verify the signature, the reason it fails otherwise
+    fn try_verify(&self) -> Result<(), SigError>;
 
-        // Parse public key A
-        let a_point = match CompressedEdwardsY(self.public_key).decompress() {
-            Some(p) => p,
-            None => return false,
-        };
+    fn verify(&self) -> bool {
+        self.try_verify().is_ok()
+    }
 
-        // Compute H(R||A||M) using SHA-512
-        let mut hasher = Sha512::new();
-        hasher.update(&r_bytes);
-        hasher.update(&self.public_key);
-        hasher.update(&self.message);
-        let hash = hasher.finalize();
+    /// Cycle-tracker section of the signature, its scheme name unless the cost varies within it
+    fn cycle_label(&self) -> String {
+        self.scheme().name().to_string()
+    }
 
-        // Convert hash to scalar
-        let h = Scalar::from_bytes_mod_order_wide(&hash.into());
+    /// Whether the scheme recovers the public key from the signature
+    fn recoverable(&self) -> bool {
+        false
+    }
 
-        // Verify: R + H * A == s * B
-        let lhs = r_point + (h * a_point);
-        let rhs = ED25519_BASEPOINT_TABLE * &s;
+    /// The compressed public key recovered from the signature, `None` if it recovers no key or
+    /// the scheme is not `recoverable`
+    fn recover_public_key(&self) -> Option<[u8; 33]> {
+        None
+    }
 
-        lhs == rhs
+    /// Verify on the upstream crates instead of the SP1-patched ones, see `pure`, `None` if the
+    /// scheme has no such verifier
+    fn try_verify_pure(&self) -> Option<Result<(), SigError>> {
+        None
     }
 }
 
 // ============================================================================
-// Helper Functions for SignatureTestData
+// Public Values
 // ============================================================================
 
-impl SignatureTestData {
-    /// Verify all signatures in the test data
-    pub fn verify_all(&self) -> bool {
-        self.ecdsa.verify() && self.schnorr.verify() && self.ed25519.verify()
+impl PublicValuesStruct {
+    /// The public values of the verdicts of the items, in input order
+    pub fn from_verdicts(verdicts: &[Result<(), SigError>]) -> Self {
+        let mut bitmap = vec![0u8; verdicts.len().div_ceil(8)];
+        let mut failures = vec![];
+        for (i, verdict) in verdicts.iter().enumerate() {
+            match verdict {
+                Ok(()) => bitmap[i / 8] |= 1 << (i % 8),
+                Err(e) => failures.push(e.code()),
+            }
+        }
+        PublicValuesStruct {
+            count: verdicts.len() as u32,
+            bitmap: bitmap.into(),
+            failures: failures.into(),
+            authorizations: Default::default(),
+            items: Default::default(),
+        }
+    }
+
+    /// Whether item `i` verified, false if out of range
+    pub fn verified(&self, i: usize) -> bool {
+        i < self.count as usize
+            && self
+                .bitmap
+                .get(i / 8)
+                .is_some_and(|x| x >> (i % 8) & 1 == 1)
     }
 
-    /// Serialize to bincode
-    pub fn to_bincode(&self) -> Result<Vec<u8>, bincode::Error> {
-        bincode::serialize(self)
+    /// The verdicts of the items, in input order, `None` if the failures don't match the bitmap
+    pub fn verdicts(&self) -> Option<Vec<Result<(), SigError>>> {
+        let mut failures = self.failures.iter();
+        let verdicts = (0..self.count as usize)
+            .map(|i| {
+                if self.verified(i) {
+                    Some(Ok(()))
+                } else {
+                    Some(Err(SigError::from_code(*failures.next()?)?))
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+        failures.next().is_none().then_some(verdicts)
     }
+}
 
-    /// Deserialize from bincode
-    pub fn from_bincode(bytes: &[u8]) -> Result<Self, bincode::Error> {
-        bincode::deserialize(bytes)
+impl SignersStruct {
+    /// The public values of the `signers` of `count` items, as `recover_signers` lists them,
+    /// the list itself left out if `hash_only`. `items` is the `items_hash` of the items
+    pub fn new(count: usize, signers: &[[u8; 20]], hash_only: bool, items: [u8; 32]) -> Self {
+        let addresses = match hash_only {
+            true => vec![],
+            false => signers.iter().map(|x| x.into()).collect(),
+        };
+        SignersStruct {
+            count: count as u32,
+            signers: addresses,
+            hash: signers_hash(signers).into(),
+            items: items.into(),
+        }
     }
 }
 
+/// keccak256(abi.encodePacked(signers)): every address padded to 32 bytes, as Solidity packs
+/// the elements of an array
+pub fn signers_hash(signers: &[[u8; 20]]) -> [u8; 32] {
+    let packed: Vec<u8> = signers
+        .iter()
+        .flat_map(|x| [&[0; 12][..], x].concat())
+        .collect();
+    keccak256(&packed)
+}
+
 // ============================================================================
-// Test Helper Functions
+// Helper Functions for Raw Messages
 // ============================================================================
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    /// Helper function to create SHA-256 hash
-    fn sha256(data: &[u8]) -> [u8; 32] {
-        use sha2::{Digest, Sha256};
-        let mut hasher = Sha256::new();
-        hasher.update(data);
-        hasher.finalize().into()
-    }
-
-    /// Create sample ECDSA test data with a valid signature
-    fn create_sample_ecdsa() -> EcdsaSecp256k1Data {
-        use k256::ecdsa::{signature::Signer, SigningKey, RecoveryId, VerifyingKey};
-
-        // Create a deterministic signing key for testing
-        let secret_bytes = [0x42u8; 32];
-        let signing_key = SigningKey::from_bytes(&secret_bytes.into()).unwrap();
-        let verifying_key = signing_key.verifying_key();
-
-        // Create a message and hash it
-        let message = b"Hello, ECDSA over secp256k1!";
-        let message_hash = sha256(message);
-
-        // Sign the hash
-        let signature: k256::ecdsa::Signature = signing_key.sign(&message_hash);
-
-        // Get the public key in COMPRESSED format (33 bytes) - much cheaper to parse than uncompressed!
-        let public_key_point = verifying_key.to_encoded_point(true);
-        let public_key = public_key_point.as_bytes()[..33].to_vec();
-
-        // Get the signature bytes (64 bytes: r || s)
-        let signature: Vec<u8> = signature.to_bytes().to_vec();
-
-        // Find the correct recovery_id by trying all possibilities
-        let mut recovery_id = 0u8;
-        for i in 0u8..4u8 {
-            if let Some(rec_id) = RecoveryId::from_byte(i) {
-                if let Ok(recovered) = VerifyingKey::recover_from_prehash(&message_hash, &k256::ecdsa::Signature::from_slice(&signature).unwrap(), rec_id) {
-                    if recovered == *verifying_key {
-                        recovery_id = i;
-                        break;
-                    }
-                }
-            }
+impl MessageDigest {
+    pub fn hash(&self, message: &[u8]) -> [u8; 32] {
+        match self {
+            MessageDigest::Sha256 => sha256(message),
+            MessageDigest::Keccak256 => keccak256(message),
         }
+    }
+}
 
-        EcdsaSecp256k1Data {
-            message_hash,
-            signature,
-            public_key,
-            recovery_id,
+impl RawMessage {
+    pub fn new(bytes: &[u8], digest: MessageDigest) -> Self {
+        RawMessage {
+            bytes: bytes.to_vec(),
+            digest,
         }
     }
 
-    /// Create sample Schnorr test data with a valid signature
-    fn create_sample_schnorr() -> SchnorrSecp256k1Data {
-        use k256::schnorr::{signature::Signer, SigningKey};
-
-        // Create a deterministic signing key for testing
-        let secret_bytes = [0x43u8; 32];
-        let signing_key = SigningKey::from_bytes(&secret_bytes).unwrap();
-        let verifying_key = signing_key.verifying_key();
+    /// Check the message hashes to `message_hash`, the hash the signature is over
+    pub fn check(&self, message_hash: &[u8; 32]) -> Result<(), SigError> {
+        (self.digest.hash(&self.bytes) == *message_hash)
+            .then_some(())
+            .ok_or(SigError::MessageMismatch)
+    }
+}
 
-        // Create a message and hash it
-        let message = b"Hello, Schnorr over secp256k1!";
-        let message_hash = sha256(message);
+/// Check the message an item carries, if any, see `RawMessage::check`
+fn check_message(message: &Option<RawMessage>, message_hash: &[u8; 32]) -> Result<(), SigError> {
+    message.as_ref().map_or(Ok(()), |x| x.check(message_hash))
+}
 
-        // Sign the hash
-        let signature: k256::schnorr::Signature = signing_key.sign(&message_hash);
+// ============================================================================
+// Helper Functions for ECDSA
+// ============================================================================
 
-        // Get the x-only public key (32 bytes)
-        let public_key: [u8; 32] = verifying_key.to_bytes().into();
+impl EcdsaSecp256k1Data {
+    /// Data of a wallet `personal_sign` (EIP-191) signature over `message`
+    /// The signature is r || s || v (65 bytes), v either 27/28 or 0/1
+    /// The public key is recovered from it, `None` if it recovers no key
+    pub fn from_personal_sign(message: &[u8], signature: &[u8]) -> Option<Self> {
+        Self::from_wallet_signature(personal_sign_hash(message), signature)
+    }
 
-        // Get the signature bytes (64 bytes)
-        let signature: Vec<u8> = signature.to_bytes().to_vec();
+    /// Data of a wallet `eth_signTypedData` (EIP-712) signature over the struct hashed to
+    /// `struct_hash` under `domain`, the signature as in `from_personal_sign`
+    pub fn from_eip712(
+        domain: &Eip712Domain,
+        struct_hash: [u8; 32],
+        signature: &[u8],
+    ) -> Option<Self> {
+        Self::from_wallet_signature(eip712_hash(domain, &struct_hash), signature)
+    }
 
-        SchnorrSecp256k1Data {
-            message_hash,
-            signature,
-            public_key,
+    fn from_wallet_signature(message_hash: [u8; 32], signature: &[u8]) -> Option<Self> {
+        if signature.len() != 65 {
+            return None;
         }
+        let (rs, v) = (&signature[..64], signature[64]);
+        let recovery_id = match v {
+            27 | 28 => v - 27,
+            _ => v,
+        };
+        let mut data = EcdsaSecp256k1Data {
+            message_hash,
+            signature: rs.to_vec(),
+            public_key: vec![],
+            recovery_id,
+            hashing: EcdsaHashing::Prehashed,
+            message: None,
+        };
+        data.public_key = data
+            .recovered_key()?
+            .to_encoded_point(true)
+            .as_bytes()
+            .to_vec();
+        Some(data)
     }
 
-    /// Create sample Ed25519 test data with a valid signature
-    fn create_sample_ed25519() -> Ed25519Data {
-        use curve25519_dalek::{constants::ED25519_BASEPOINT_TABLE, scalar::Scalar};
-        use sha2::{Digest, Sha512};
-
-        // Create a deterministic secret scalar
-        let secret_bytes = [0x44u8; 32];
-        let secret_scalar = Scalar::from_bytes_mod_order(secret_bytes);
-
-        // Compute public key A = secret * B
-        let public_key_point = ED25519_BASEPOINT_TABLE * &secret_scalar;
-        let public_key = public_key_point.compress().to_bytes();
-
-        // Create a message
-        let message = b"Hello, Ed25519!";
-
-        // Generate nonce r = H(secret || message) mod L
-        let mut nonce_hasher = Sha512::new();
-        nonce_hasher.update(&secret_bytes);
-        nonce_hasher.update(message);
-        let nonce_hash = nonce_hasher.finalize();
-        let r = Scalar::from_bytes_mod_order_wide(&nonce_hash.into());
-
-        // Compute R = r * B
-        let r_point = ED25519_BASEPOINT_TABLE * &r;
-        let r_bytes = r_point.compress().to_bytes();
-
-        // Compute challenge H(R || A || M)
-        let mut challenge_hasher = Sha512::new();
-        challenge_hasher.update(&r_bytes);
-        challenge_hasher.update(&public_key);
-        challenge_hasher.update(message);
-        let challenge_hash = challenge_hasher.finalize();
-        let h = Scalar::from_bytes_mod_order_wide(&challenge_hash.into());
-
-        // Compute s = r + h * secret (mod L)
-        let s = r + (h * secret_scalar);
-        let s_bytes = s.to_bytes();
-
-        // Signature is (R, s)
-        let mut signature = Vec::with_capacity(64);
-        signature.extend_from_slice(&r_bytes);
-        signature.extend_from_slice(&s_bytes);
-
-        Ed25519Data {
-            message: message.to_vec(),
-            signature,
-            public_key,
+    /// Carry `message`, the preimage of `message_hash` under `digest`, so the guest checks it
+    pub fn with_message(self, message: &[u8], digest: MessageDigest) -> Self {
+        EcdsaSecp256k1Data {
+            message: Some(RawMessage::new(message, digest)),
+            ..self
         }
     }
 
-    /// Create complete sample signature test data
-    pub fn create_sample_signature_test_data() -> SignatureTestData {
-        SignatureTestData {
-            ecdsa: create_sample_ecdsa(),
-            schnorr: create_sample_schnorr(),
-            ed25519: create_sample_ed25519(),
+    /// The digest the signature is over
+    pub fn digest(&self) -> [u8; 32] {
+        match self.hashing {
+            EcdsaHashing::Sha256 => sha256(&self.message_hash),
+            EcdsaHashing::Prehashed => self.message_hash,
         }
     }
 
-    #[test]
-    fn test_ecdsa_data() {
-        let ecdsa = create_sample_ecdsa();
-        assert_eq!(ecdsa.message_hash.len(), 32);
-        assert_eq!(ecdsa.signature.len(), 64);
-        assert_eq!(ecdsa.public_key.len(), 65);
-        assert_eq!(ecdsa.public_key[0], 0x04); // Uncompressed format marker
+    /// Recover the public key from the signature (optimal pattern), `None` if it recovers no key
+    pub fn recover(&self) -> Option<[u8; 33]> {
+        let recovered_key = self.recovered_key()?;
 
-        // Test verification
-        assert!(ecdsa.verify());
+        // Return compressed public key (33 bytes)
+        recovered_key
+            .to_encoded_point(true)
+            .as_bytes()
+            .try_into()
+            .ok()
     }
 
-    #[test]
-    fn test_schnorr_data() {
-        let schnorr = create_sample_schnorr();
-        assert_eq!(schnorr.message_hash.len(), 32);
-        assert_eq!(schnorr.signature.len(), 64);
-        assert_eq!(schnorr.public_key.len(), 32);
-
-        // Test verification
-        assert!(schnorr.verify());
+    /// The Ethereum address of the public key
+    pub fn address(&self) -> Option<[u8; 20]> {
+        let public_key = k256::ecdsa::VerifyingKey::from_sec1_bytes(&self.public_key).ok()?;
+        Some(eth_address(&public_key))
     }
 
-    #[test]
-    fn test_ed25519_data() {
-        let ed25519 = create_sample_ed25519();
-        assert!(ed25519.message.len() > 0);
-        assert_eq!(ed25519.signature.len(), 64);
-        assert_eq!(ed25519.public_key.len(), 32);
-
-        // Test verification
-        assert!(ed25519.verify());
+    /// Recover the Ethereum address of the signer, as `ecrecover` does
+    /// `None` if the signature recovers no key
+    pub fn recover_address(&self) -> Option<[u8; 20]> {
+        Some(eth_address(&self.recovered_key()?))
     }
 
-    #[test]
-    fn test_signature_test_data() {
-        let test_data = create_sample_signature_test_data();
+    fn recovered_key(&self) -> Option<k256::ecdsa::VerifyingKey> {
+        use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
 
-        // Test individual verifications
-        assert!(test_data.ecdsa.verify());
-        assert!(test_data.schnorr.verify());
-        assert!(test_data.ed25519.verify());
+        let signature = Signature::from_slice(&self.signature).ok()?;
+        let recovery_id = RecoveryId::from_byte(self.recovery_id)?;
+        VerifyingKey::recover_from_prehash(&self.digest(), &signature, recovery_id).ok()
+    }
+}
 
-        // Test verify_all
-        assert!(test_data.verify_all());
+impl SignatureVerifier for EcdsaSecp256k1Data {
+    fn scheme(&self) -> Scheme {
+        Scheme::EcdsaSecp256k1
     }
 
-    #[test]
-    fn test_bincode_serialization() {
-        let test_data = create_sample_signature_test_data();
+    /// Verify the ECDSA signature using compressed public key, a high s is rejected as
+    /// Ethereum (EIP-2) and Bitcoin (BIP-146) do
+    /// A carried message is hashed and checked first
+    fn try_verify(&self) -> Result<(), SigError> {
+        use k256::ecdsa::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey};
+        use k256::PublicKey;
 
-        // Serialize
-        let serialized = test_data.to_bincode().expect("Failed to serialize");
-        assert!(serialized.len() > 0);
+        check_message(&self.message, &self.message_hash)?;
 
-        // Deserialize
-        let deserialized =
-            SignatureTestData::from_bincode(&serialized).expect("Failed to deserialize");
+        // Direct construction from fixed-size arrays
+        let signature =
+            Signature::from_slice(&self.signature).map_err(|_| SigError::MalformedSignature)?;
+        if signature.normalize_s().is_some() {
+            return Err(SigError::HighS);
+        }
 
-        // Verify they are equal
-        assert_eq!(test_data, deserialized);
+        // Use compressed key format (33 bytes) - much cheaper than uncompressed
+        let public_key =
+            PublicKey::from_sec1_bytes(&self.public_key).map_err(|_| SigError::MalformedKey)?;
+        let verifying_key = VerifyingKey::from(public_key);
 
-        // Verify the deserialized data still works
-        assert!(deserialized.verify_all());
+        verifying_key
+            .verify_prehash(&self.digest(), &signature)
+            .map_err(|_| SigError::InvalidSignature)
     }
 
-    #[test]
-    fn test_all_signature_types() {
-        let test_data = create_sample_signature_test_data();
-
-        println!(
-            "ECDSA message hash: {:?}",
-            hex::encode(&test_data.ecdsa.message_hash)
-        );
-        println!(
-            "ECDSA signature: {:?}",
-            hex::encode(&test_data.ecdsa.signature)
-        );
-        println!(
-            "ECDSA public key: {:?}",
-            hex::encode(&test_data.ecdsa.public_key)
-        );
-
-        println!(
-            "\nSchnorr message hash: {:?}",
-            hex::encode(&test_data.schnorr.message_hash)
-        );
-        println!(
-            "Schnorr signature: {:?}",
-            hex::encode(&test_data.schnorr.signature)
-        );
-        println!(
-            "Schnorr public key: {:?}",
-            hex::encode(&test_data.schnorr.public_key)
-        );
+    fn try_verify_pure(&self) -> Option<Result<(), SigError>> {
+        Some(pure::ecdsa_secp256k1(self))
+    }
 
-        println!(
-            "\nEd25519 message: {:?}",
-            String::from_utf8_lossy(&test_data.ed25519.message)
-        );
-        println!(
-            "Ed25519 signature: {:?}",
-            hex::encode(&test_data.ed25519.signature)
-        );
-        println!(
-            "Ed25519 public key: {:?}",
-            hex::encode(&test_data.ed25519.public_key)
-        );
+    fn recoverable(&self) -> bool {
+        true
+    }
 
-        // Verify all signatures
-        assert!(test_data.verify_all(), "All signatures should verify");
+    fn recover_public_key(&self) -> Option<[u8; 33]> {
+        self.recover()
     }
 }
 
-// Make the sample data creation function public for use in other crates
-#[cfg(not(test))]
-pub fn create_sample_signature_test_data() -> SignatureTestData {
-    use k256::ecdsa::{signature::Signer as EcdsaSigner, SigningKey as EcdsaSigningKey};
-    use k256::elliptic_curve::sec1::ToEncodedPoint;
-    use k256::schnorr::{signature::Signer as SchnorrSigner, SigningKey as SchnorrSigningKey};
-    use sha2::{Digest, Sha256, Sha512};
-
-    fn sha256(data: &[u8]) -> [u8; 32] {
-        let mut hasher = Sha256::new();
-        hasher.update(data);
-        hasher.finalize().into()
-    }
-
-    // ECDSA
-    let ecdsa_secret = [0x42u8; 32];
-    let ecdsa_signing_key = EcdsaSigningKey::from_bytes(&ecdsa_secret.into()).unwrap();
-    let ecdsa_verifying_key = ecdsa_signing_key.verifying_key();
-    let ecdsa_message = b"Hello, ECDSA over secp256k1!";
-    let ecdsa_message_hash = sha256(ecdsa_message);
-    let ecdsa_signature: k256::ecdsa::Signature = ecdsa_signing_key.sign(&ecdsa_message_hash);
-
-    // Use COMPRESSED public key (33 bytes) - much cheaper to parse than uncompressed!
-    let ecdsa_public_key_point = ecdsa_verifying_key.to_encoded_point(true);
-    let ecdsa_public_key = ecdsa_public_key_point.as_bytes()[..33].to_vec();
-
-    // Signature bytes (64 bytes)
-    let ecdsa_signature_bytes: Vec<u8> = ecdsa_signature.to_bytes().to_vec();
-
-    // Find the correct recovery_id
-    use k256::ecdsa::{RecoveryId, VerifyingKey as EcdsaVerifyingKey};
-    let mut ecdsa_recovery_id = 0u8;
-    for i in 0u8..4u8 {
-        if let Some(rec_id) = RecoveryId::from_byte(i) {
-            if let Ok(recovered) = EcdsaVerifyingKey::recover_from_prehash(&ecdsa_message_hash, &k256::ecdsa::Signature::from_slice(&ecdsa_signature_bytes).unwrap(), rec_id) {
-                if recovered == *ecdsa_verifying_key {
-                    ecdsa_recovery_id = i;
-                    break;
-                }
-            }
-        }
-    }
+/// Keccak-256 of the uncompressed key without its 0x04 tag, the last 20 bytes
+fn eth_address(public_key: &k256::ecdsa::VerifyingKey) -> [u8; 20] {
+    let point = public_key.to_encoded_point(false);
+    keccak256(&point.as_bytes()[1..])[12..].try_into().unwrap()
+}
 
-    // Schnorr
-    let schnorr_secret = [0x43u8; 32];
-    let schnorr_signing_key = SchnorrSigningKey::from_bytes(&schnorr_secret).unwrap();
-    let schnorr_verifying_key = schnorr_signing_key.verifying_key();
-    let schnorr_message = b"Hello, Schnorr over secp256k1!";
-    let schnorr_message_hash = sha256(schnorr_message);
-    let schnorr_signature: k256::schnorr::Signature =
-        schnorr_signing_key.sign(&schnorr_message_hash);
-    let schnorr_public_key: [u8; 32] = schnorr_verifying_key.to_bytes().into();
-    let schnorr_signature_bytes: Vec<u8> = schnorr_signature.to_bytes().to_vec();
+// ============================================================================
+// Helper Functions for EIP-191
+// ============================================================================
 
-    // Ed25519 using curve25519-dalek
-    use curve25519_dalek::{constants::ED25519_BASEPOINT_TABLE, scalar::Scalar};
+/// The payload a wallet signs for `personal_sign`:
+/// "\x19Ethereum Signed Message:\n" || decimal length of the message || message
+pub fn personal_sign_payload(message: &[u8]) -> Vec<u8> {
+    let mut payload = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    payload.extend_from_slice(message);
+    payload
+}
 
-    let ed25519_secret = [0x44u8; 32];
-    let secret_scalar = Scalar::from_bytes_mod_order(ed25519_secret);
+/// The digest a wallet signs for `personal_sign`, Keccak-256 of the payload
+pub fn personal_sign_hash(message: &[u8]) -> [u8; 32] {
+    keccak256(&personal_sign_payload(message))
+}
 
-    // Compute public key A = secret * B
-    let public_key_point = ED25519_BASEPOINT_TABLE * &secret_scalar;
-    let ed25519_public_key = public_key_point.compress().to_bytes();
+// ============================================================================
+// Helper Functions for EIP-712
+// ============================================================================
 
-    let ed25519_message = b"Hello, Ed25519!";
+/// EIP-712 domain, the fields left `None` are left out of `EIP712Domain(..)` as wallets do
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Eip712Domain {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub chain_id: Option<u64>,
+    pub verifying_contract: Option<[u8; 20]>,
+    pub salt: Option<[u8; 32]>,
+}
 
-    // Generate nonce r = H(secret || message) mod L
-    let mut nonce_hasher = Sha512::new();
-    nonce_hasher.update(&ed25519_secret);
-    nonce_hasher.update(ed25519_message);
-    let nonce_hash = nonce_hasher.finalize();
-    let r = Scalar::from_bytes_mod_order_wide(&nonce_hash.into());
+/// A member of an EIP-712 struct, encoded as its 32-byte word
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eip712Value<'a> {
+    /// Any uintN that fits 128 bits
+    Uint(u128),
+    Bool(bool),
+    Address([u8; 20]),
+    /// bytes32, a uint256, or the hash of a nested struct or array
+    Word([u8; 32]),
+    /// Dynamic values are encoded as their Keccak-256 hash
+    String(&'a str),
+    Bytes(&'a [u8]),
+}
 
-    // Compute R = r * B
-    let r_point = ED25519_BASEPOINT_TABLE * &r;
-    let r_bytes = r_point.compress().to_bytes();
+impl Eip712Value<'_> {
+    /// The word of the value in `encodeData`
+    pub fn encode(&self) -> [u8; 32] {
+        let mut word = [0u8; 32];
+        match self {
+            Eip712Value::Uint(x) => word[16..].copy_from_slice(&x.to_be_bytes()),
+            Eip712Value::Bool(x) => word[31] = *x as u8,
+            Eip712Value::Address(x) => word[12..].copy_from_slice(x),
+            Eip712Value::Word(x) => word = *x,
+            Eip712Value::String(x) => word = keccak256(x.as_bytes()),
+            Eip712Value::Bytes(x) => word = keccak256(x),
+        }
+        word
+    }
+}
 
-    // Compute challenge H(R || A || M)
-    let mut challenge_hasher = Sha512::new();
-    challenge_hasher.update(&r_bytes);
-    challenge_hasher.update(&ed25519_public_key);
-    challenge_hasher.update(ed25519_message);
-    let challenge_hash = challenge_hasher.finalize();
-    let h = Scalar::from_bytes_mod_order_wide(&challenge_hash.into());
+impl Eip712Domain {
+    /// `encodeType` of the domain, its present fields in the order of the spec
+    pub fn encode_type(&self) -> String {
+        let fields = [
+            (self.name.is_some(), "string name"),
+            (self.version.is_some(), "string version"),
+            (self.chain_id.is_some(), "uint256 chainId"),
+            (
+                self.verifying_contract.is_some(),
+                "address verifyingContract",
+            ),
+            (self.salt.is_some(), "bytes32 salt"),
+        ];
+        let present: Vec<_> = fields.iter().filter(|(x, _)| *x).map(|(_, x)| *x).collect();
+        format!("EIP712Domain({})", present.join(","))
+    }
 
-    // Compute s = r + h * secret (mod L)
-    let s = r + (h * secret_scalar);
-    let s_bytes = s.to_bytes();
+    /// The domain separator, `hashStruct` of the domain
+    pub fn separator(&self) -> [u8; 32] {
+        let mut values = vec![];
+        values.extend(self.name.as_deref().map(Eip712Value::String));
+        values.extend(self.version.as_deref().map(Eip712Value::String));
+        values.extend(self.chain_id.map(|x| Eip712Value::Uint(x.into())));
+        values.extend(self.verifying_contract.map(Eip712Value::Address));
+        values.extend(self.salt.map(Eip712Value::Word));
+        eip712_struct_hash(&self.encode_type(), &values)
+    }
+}
 
-    // Signature is (R, s)
-    let mut ed25519_signature_bytes = Vec::with_capacity(64);
-    ed25519_signature_bytes.extend_from_slice(&r_bytes);
-    ed25519_signature_bytes.extend_from_slice(&s_bytes);
-
-    SignatureTestData {
-        ecdsa: EcdsaSecp256k1Data {
-            message_hash: ecdsa_message_hash,
-            signature: ecdsa_signature_bytes,
-            public_key: ecdsa_public_key,
-            recovery_id: ecdsa_recovery_id,
-        },
-        schnorr: SchnorrSecp256k1Data {
-            message_hash: schnorr_message_hash,
-            signature: schnorr_signature_bytes,
-            public_key: schnorr_public_key,
-        },
-        ed25519: Ed25519Data {
-            message: ed25519_message.to_vec(),
-            signature: ed25519_signature_bytes,
-            public_key: ed25519_public_key,
-        },
+/// `hashStruct`: Keccak-256 of the type hash and the encoded members, in the order of the type
+/// `encode_type` is the type with the types it references appended, ex.
+/// "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+pub fn eip712_struct_hash(encode_type: &str, values: &[Eip712Value]) -> [u8; 32] {
+    let mut encoded = Vec::with_capacity(32 * (values.len() + 1));
+    encoded.extend_from_slice(&keccak256(encode_type.as_bytes()));
+    for value in values {
+        encoded.extend_from_slice(&value.encode());
+    }
+    keccak256(&encoded)
+}
+
+/// The digest a wallet signs for `eth_signTypedData`:
+/// Keccak-256 of "\x19\x01" || domain separator || struct hash
+pub fn eip712_hash(domain: &Eip712Domain, struct_hash: &[u8; 32]) -> [u8; 32] {
+    let mut payload = Vec::with_capacity(66);
+    payload.extend_from_slice(b"\x19\x01");
+    payload.extend_from_slice(&domain.separator());
+    payload.extend_from_slice(struct_hash);
+    keccak256(&payload)
+}
+
+/// Hash a message using Keccak-256
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    use tiny_keccak::{Hasher, Keccak};
+    let mut hasher = Keccak::v256();
+    let mut output = [0u8; 32];
+    hasher.update(data);
+    hasher.finalize(&mut output);
+    output
+}
+
+// ============================================================================
+// Helper Functions for Schnorr
+// ============================================================================
+
+impl SchnorrSecp256k1Data {
+    /// Carry `message`, see `EcdsaSecp256k1Data::with_message`
+    pub fn with_message(self, message: &[u8], digest: MessageDigest) -> Self {
+        SchnorrSecp256k1Data {
+            message: Some(RawMessage::new(message, digest)),
+            ..self
+        }
+    }
+
+    /// The message BIP-340 signs, `message_hash` hashed again unless signed as is
+    fn bip340_message(&self) -> [u8; 32] {
+        match self.hashing {
+            SchnorrHashing::Sha256 => sha256(&self.message_hash),
+            SchnorrHashing::Bip340 => self.message_hash,
+        }
+    }
+
+    /// Why a well formed signature under `key` fails: `OddY` if R = s * G - e * P is the
+    /// nonce point but of odd y, or the signature holds for -P, the odd y point of the key,
+    /// `InvalidSignature` otherwise
+    /// Only run on a failure, it costs another two scalar multiplications
+    fn failure(&self, key: &k256::schnorr::VerifyingKey) -> SigError {
+        use k256::elliptic_curve::{ops::Reduce, point::AffineCoordinates};
+        use k256::{ProjectivePoint, Scalar, U256};
+
+        let (r, s) = self.signature.split_at(32);
+        let message = [r, &self.public_key, &self.bip340_message()].concat();
+        let challenge = tagged_hash("BIP0340/challenge", &message);
+        let e = <Scalar as Reduce<U256>>::reduce_bytes(&challenge.into());
+        let s = <Scalar as Reduce<U256>>::reduce_bytes(&<[u8; 32]>::try_from(s).unwrap().into());
+        let (s_g, e_p) = (
+            ProjectivePoint::GENERATOR * s,
+            ProjectivePoint::from(*key.as_affine()) * e,
+        );
+        match [s_g - e_p, s_g + e_p]
+            .iter()
+            .any(|x| <[u8; 32]>::from(x.to_affine().x()) == r)
+        {
+            true => SigError::OddY,
+            false => SigError::InvalidSignature,
+        }
+    }
+
+    /// Batch verify multiple Schnorr signatures
+    /// NOTE: True mathematical batch verification (single multiscalar multiplication)
+    /// is not yet possible due to SP1-patched k256 API limitations.
+    /// This performs optimized individual verification for now.
+    pub fn batch_verify(signatures: &[SchnorrSecp256k1Data]) -> usize {
+        signatures
+            .iter()
+            .filter(|sig_data| sig_data.verify())
+            .count()
+    }
+}
+
+impl SignatureVerifier for SchnorrSecp256k1Data {
+    fn scheme(&self) -> Scheme {
+        Scheme::SchnorrSecp256k1
+    }
+
+    /// Verify the Schnorr signature using direct byte arrays
+    /// A carried message is hashed and checked first, then a Taproot output key is checked to be
+    /// the tweaked internal key
+    /// The key and R are x-only, BIP-340 takes the point of even y: a signature for the other
+    /// fails with `OddY`
+    fn try_verify(&self) -> Result<(), SigError> {
+        use k256::schnorr::{signature::Verifier, Signature, VerifyingKey};
+
+        check_message(&self.message, &self.message_hash)?;
+        if let Some(tweak) = &self.taproot {
+            if tweak.output_key()? != self.public_key {
+                return Err(SigError::TweakMismatch);
+            }
+        }
+
+        // Direct construction from fixed-size arrays, the conversion panics on other lengths
+        if self.signature.len() != 64 {
+            return Err(SigError::MalformedSignature);
+        }
+        let signature =
+            Signature::try_from(&self.signature[..]).map_err(|_| SigError::MalformedSignature)?;
+        let verifying_key =
+            VerifyingKey::from_bytes(&self.public_key).map_err(|_| SigError::MalformedKey)?;
+
+        match self.hashing {
+            SchnorrHashing::Sha256 => verifying_key.verify(&self.message_hash, &signature),
+            SchnorrHashing::Bip340 => verifying_key.verify_raw(&self.message_hash, &signature),
+        }
+        .map_err(|_| self.failure(&verifying_key))
+    }
+
+    fn try_verify_pure(&self) -> Option<Result<(), SigError>> {
+        Some(pure::schnorr_secp256k1(self))
+    }
+}
+
+// ============================================================================
+// Helper Functions for FROST
+// ============================================================================
+
+impl SignatureVerifier for ThresholdSchnorrData {
+    fn scheme(&self) -> Scheme {
+        Scheme::ThresholdSchnorr
+    }
+
+    /// Verify the aggregated signature, a plain BIP-340 signature under the group key
+    fn try_verify(&self) -> Result<(), SigError> {
+        SchnorrSecp256k1Data {
+            message_hash: self.message_hash,
+            signature: self.signature.clone(),
+            public_key: self.group_public_key,
+            hashing: SchnorrHashing::Bip340,
+            taproot: None,
+            message: None,
+        }
+        .try_verify()
+    }
+
+    fn try_verify_pure(&self) -> Option<Result<(), SigError>> {
+        Some(pure::threshold_schnorr(self))
+    }
+}
+
+// ============================================================================
+// Helper Functions for MuSig2
+// ============================================================================
+
+/// BIP-327 key aggregation coefficients of the keys, the second distinct key's is 1
+fn musig2_coefficients(public_keys: &[[u8; 33]]) -> Vec<k256::Scalar> {
+    use k256::{elliptic_curve::ops::Reduce, Scalar, U256};
+
+    let list_hash = tagged_hash("KeyAgg list", public_keys.as_flattened());
+    let second = public_keys.iter().find(|x| **x != public_keys[0]);
+    public_keys
+        .iter()
+        .map(|x| {
+            if Some(x) == second {
+                return Scalar::ONE;
+            }
+            let coefficient = tagged_hash("KeyAgg coefficient", &[&list_hash[..], x].concat());
+            <Scalar as Reduce<U256>>::reduce_bytes(&coefficient.into())
+        })
+        .collect()
+}
+
+/// BIP-327 aggregate Q = sum(a_i * P_i) of the compressed keys, an error if a key is malformed,
+/// there are none, or Q is the point at infinity
+fn musig2_aggregate(public_keys: &[[u8; 33]]) -> Result<k256::AffinePoint, SigError> {
+    use k256::{ProjectivePoint, PublicKey};
+
+    if public_keys.is_empty() {
+        return Err(SigError::MalformedKey);
+    }
+    let mut aggregate = ProjectivePoint::IDENTITY;
+    for (x, a) in public_keys.iter().zip(musig2_coefficients(public_keys)) {
+        let point = PublicKey::from_sec1_bytes(x).map_err(|_| SigError::MalformedKey)?;
+        aggregate += point.to_projective() * a;
+    }
+    let aggregate = aggregate.to_affine();
+    if aggregate == k256::AffinePoint::IDENTITY {
+        return Err(SigError::MalformedKey);
+    }
+    Ok(aggregate)
+}
+
+/// The x-only aggregate key of the compressed keys (BIP-327 KeyAgg), the key the MuSig2
+/// signature verifies under, as a BIP-340 one
+pub fn musig2_key_agg(public_keys: &[[u8; 33]]) -> Result<[u8; 32], SigError> {
+    use k256::elliptic_curve::point::AffineCoordinates;
+
+    Ok(musig2_aggregate(public_keys)?.x().into())
+}
+
+impl MuSig2Data {
+    /// The compressed public keys of the signers, an error unless whole keys
+    pub fn keys(&self) -> Result<Vec<[u8; 33]>, SigError> {
+        let (keys, rest) = self.public_keys.as_chunks::<33>();
+        if !rest.is_empty() {
+            return Err(SigError::MalformedKey);
+        }
+        Ok(keys.to_vec())
+    }
+}
+
+impl SignatureVerifier for MuSig2Data {
+    fn scheme(&self) -> Scheme {
+        Scheme::MuSig2
+    }
+
+    /// Verify the aggregated signature, a plain BIP-340 signature under the aggregate key
+    fn try_verify(&self) -> Result<(), SigError> {
+        SchnorrSecp256k1Data {
+            message_hash: self.message_hash,
+            signature: self.signature.clone(),
+            public_key: musig2_key_agg(&self.keys()?)?,
+            hashing: SchnorrHashing::Bip340,
+            taproot: None,
+            message: None,
+        }
+        .try_verify()
+    }
+
+    fn try_verify_pure(&self) -> Option<Result<(), SigError>> {
+        Some(pure::musig2(self))
+    }
+}
+
+// ============================================================================
+// Helper Functions for Key Aggregation
+// ============================================================================
+
+/// The message of the proof of possession of a compressed key, the key itself tagged
+pub fn possession_message(public_key: &[u8; 33]) -> [u8; 32] {
+    tagged_hash("DDM/possession", public_key)
+}
+
+/// The compressed public key of the secret key `secret_bytes` and its proof of possession: a
+/// BIP-340 signature of `possession_message` under the key, which only its owner can make
+pub fn prove_possession(secret_bytes: &SecretBytes) -> ([u8; 33], [u8; 64]) {
+    use k256::elliptic_curve::sec1::ToEncodedPoint;
+
+    let secret = k256::SecretKey::from_slice(&secret_bytes[..]).unwrap();
+    let public_key: [u8; 33] = secret
+        .public_key()
+        .to_encoded_point(true)
+        .as_bytes()
+        .try_into()
+        .unwrap();
+    // The x-only key is the even one, the signing key negates the secret as needed
+    let signing_key = k256::schnorr::SigningKey::from(secret);
+    let proof = signing_key
+        .sign_raw(&possession_message(&public_key), &[0u8; 32])
+        .unwrap();
+    (public_key, proof.to_bytes())
+}
+
+/// The BIP-340 data a proof of possession verifies as: the key x-only, of either y, knowing the
+/// secret of P or of -P is the same
+fn possession_data(public_key: &[u8; 33], proof: &[u8]) -> SchnorrSecp256k1Data {
+    SchnorrSecp256k1Data {
+        message_hash: possession_message(public_key),
+        signature: proof.to_vec(),
+        public_key: public_key[1..].try_into().unwrap(),
+        hashing: SchnorrHashing::Bip340,
+        taproot: None,
+        message: None,
+    }
+}
+
+/// Check the proof of possession of a compressed key, see `prove_possession`
+pub fn verify_possession(public_key: &[u8; 33], proof: &[u8]) -> Result<(), SigError> {
+    k256::PublicKey::from_sec1_bytes(public_key).map_err(|_| SigError::MalformedKey)?;
+    possession_data(public_key, proof)
+        .try_verify()
+        .map_err(|_| SigError::InvalidPossession)
+}
+
+/// The compressed sum of the keys, once every proof of possession checks: an error if a key is
+/// malformed or repeated, so each is a distinct signer, or the sum is the point at infinity
+/// The keys are not weighted as in MuSig2, the proofs rule out a rogue key instead
+pub fn aggregate_public_keys(
+    public_keys: &[[u8; 33]],
+    proofs: &[[u8; 64]],
+) -> Result<[u8; 33], SigError> {
+    use k256::elliptic_curve::sec1::ToEncodedPoint;
+    use k256::{ProjectivePoint, PublicKey};
+
+    if public_keys.is_empty()
+        || public_keys
+            .iter()
+            .enumerate()
+            .any(|(i, x)| public_keys[..i].contains(x))
+    {
+        return Err(SigError::MalformedKey);
+    }
+    if proofs.len() != public_keys.len() {
+        return Err(SigError::InvalidPossession);
+    }
+    let mut aggregate = ProjectivePoint::IDENTITY;
+    for (x, proof) in public_keys.iter().zip(proofs) {
+        verify_possession(x, proof)?;
+        aggregate += PublicKey::from_sec1_bytes(x)
+            .map_err(|_| SigError::MalformedKey)?
+            .to_projective();
+    }
+    let aggregate = aggregate.to_affine();
+    if aggregate == k256::AffinePoint::IDENTITY {
+        return Err(SigError::MalformedKey);
+    }
+    Ok(aggregate
+        .to_encoded_point(true)
+        .as_bytes()
+        .try_into()
+        .unwrap())
+}
+
+impl CosignedSchnorrData {
+    /// The compressed public keys of the signers, an error unless whole keys
+    pub fn keys(&self) -> Result<Vec<[u8; 33]>, SigError> {
+        let (keys, rest) = self.public_keys.as_chunks::<33>();
+        if !rest.is_empty() {
+            return Err(SigError::MalformedKey);
+        }
+        Ok(keys.to_vec())
+    }
+
+    /// The proofs of possession of the keys, an error unless whole signatures
+    pub fn proofs(&self) -> Result<Vec<[u8; 64]>, SigError> {
+        let (proofs, rest) = self.possession_proofs.as_chunks::<64>();
+        if !rest.is_empty() {
+            return Err(SigError::InvalidPossession);
+        }
+        Ok(proofs.to_vec())
+    }
+}
+
+impl SignatureVerifier for CosignedSchnorrData {
+    fn scheme(&self) -> Scheme {
+        Scheme::CosignedSchnorr
+    }
+
+    /// Verify the proofs of possession, then the aggregated signature, a plain BIP-340
+    /// signature under the x-only sum of the keys
+    fn try_verify(&self) -> Result<(), SigError> {
+        let aggregate = aggregate_public_keys(&self.keys()?, &self.proofs()?)?;
+        SchnorrSecp256k1Data {
+            message_hash: self.message_hash,
+            signature: self.signature.clone(),
+            public_key: aggregate[1..].try_into().unwrap(),
+            hashing: SchnorrHashing::Bip340,
+            taproot: None,
+            message: None,
+        }
+        .try_verify()
+    }
+
+    fn try_verify_pure(&self) -> Option<Result<(), SigError>> {
+        Some(pure::cosigned_schnorr(self))
+    }
+}
+
+// ============================================================================
+// Helper Functions for Ethereum Transactions
+// ============================================================================
+
+impl EthTxData {
+    /// Type of an EIP-1559 transaction, the byte its encoding starts with
+    pub const EIP1559: u8 = 0x02;
+
+    /// The transaction hash, Keccak-256 of the transaction as broadcast
+    pub fn tx_hash(&self) -> [u8; 32] {
+        keccak256(&self.raw)
+    }
+
+    /// The signature and the hash it signs, an error if the transaction is not canonical RLP
+    /// of a legacy or EIP-1559 one, or `v` is not a recovery id
+    pub fn signature(&self) -> Result<EthTxSignature, SigError> {
+        let malformed = SigError::MalformedTransaction;
+        let (typed, encoded) = match self.raw.split_first() {
+            Some((&Self::EIP1559, rest)) => (true, rest),
+            Some((0xc0.., _)) => (false, &self.raw[..]),
+            _ => return Err(malformed),
+        };
+        let fields = rlp::list(encoded).ok_or(malformed)?;
+        let mut unsigned: Vec<Vec<u8>>;
+        let (recovery_id, chain_id, r, s) = if typed {
+            // [chain_id, nonce, priority fee, max fee, gas, to, value, data, access list, y, r, s]
+            let [chain_id, _, _, _, _, _, _, _, _, y_parity, r, s] = fields[..] else {
+                return Err(malformed);
+            };
+            unsigned = fields[..9].iter().map(|x| x.to_vec()).collect();
+            let y_parity = rlp::uint(y_parity)
+                .filter(|x| *x <= 1)
+                .ok_or(SigError::MalformedSignature)?;
+            (
+                y_parity as u8,
+                Some(rlp::uint(chain_id).ok_or(malformed)?),
+                r,
+                s,
+            )
+        } else {
+            // [nonce, gas price, gas, to, value, data, v, r, s]
+            let [_, _, _, _, _, _, v, r, s] = fields[..] else {
+                return Err(malformed);
+            };
+            unsigned = fields[..6].iter().map(|x| x.to_vec()).collect();
+            match rlp::uint(v).ok_or(malformed)? {
+                v @ (27 | 28) => ((v - 27) as u8, None, r, s),
+                // EIP-155 signs the chain id too, v = chain_id * 2 + 35 + y
+                v @ 35.. => {
+                    let chain_id = (v - 35) / 2;
+                    unsigned.push(rlp::encode_uint(chain_id as u128));
+                    unsigned.extend([rlp::encode_uint(0), rlp::encode_uint(0)]);
+                    (((v - 35) % 2) as u8, Some(chain_id), r, s)
+                }
+                _ => return Err(SigError::MalformedSignature),
+            }
+        };
+
+        let mut signature = [0u8; 64];
+        for (word, field) in signature.chunks_mut(32).zip([r, s]) {
+            let x = rlp::bytes(field)
+                .filter(|x| x.len() <= 32)
+                .ok_or(SigError::MalformedSignature)?;
+            word[32 - x.len()..].copy_from_slice(x);
+        }
+        let mut payload = rlp::encode_list(&unsigned);
+        if typed {
+            payload.insert(0, Self::EIP1559);
+        }
+        Ok(EthTxSignature {
+            sighash: keccak256(&payload),
+            signature,
+            recovery_id,
+            chain_id,
+        })
+    }
+
+    /// Recover the address of the sender from the signature, as a node does, a high s is
+    /// rejected (EIP-2)
+    pub fn recover_sender(&self) -> Result<[u8; 20], SigError> {
+        use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+
+        let tx = self.signature()?;
+        let signature =
+            Signature::from_slice(&tx.signature).map_err(|_| SigError::MalformedSignature)?;
+        if signature.normalize_s().is_some() {
+            return Err(SigError::HighS);
+        }
+        let recovery_id =
+            RecoveryId::from_byte(tx.recovery_id).ok_or(SigError::MalformedSignature)?;
+        let public_key = VerifyingKey::recover_from_prehash(&tx.sighash, &signature, recovery_id)
+            .map_err(|_| SigError::InvalidSignature)?;
+        Ok(eth_address(&public_key))
+    }
+}
+
+impl SignatureVerifier for EthTxData {
+    fn scheme(&self) -> Scheme {
+        Scheme::EthTx
+    }
+
+    /// Verify the transaction is signed by `sender`: the signature of its signing hash
+    /// recovers to it
+    fn try_verify(&self) -> Result<(), SigError> {
+        (self.recover_sender()? == self.sender)
+            .then_some(())
+            .ok_or(SigError::InvalidSignature)
+    }
+
+    fn try_verify_pure(&self) -> Option<Result<(), SigError>> {
+        Some(pure::eth_tx(self))
+    }
+}
+
+// ============================================================================
+// Helper Functions for Bitcoin Transactions
+// ============================================================================
+
+fn sha256d(data: &[u8]) -> [u8; 32] {
+    sha256(&sha256(data))
+}
+
+impl BitcoinTxData {
+    pub const SIGHASH_DEFAULT: u8 = 0x00;
+    pub const SIGHASH_ALL: u8 = 0x01;
+    pub const SIGHASH_NONE: u8 = 0x02;
+    pub const SIGHASH_SINGLE: u8 = 0x03;
+    pub const SIGHASH_ANYONECANPAY: u8 = 0x80;
+
+    /// The txid, double SHA-256 of the transaction without its witnesses, in internal byte order
+    /// (reversed as displayed), `None` if it does not parse
+    pub fn txid(&self) -> Option<[u8; 32]> {
+        let tx = bitcoin::parse(&self.tx)?;
+        let mut stripped = tx.version.to_vec();
+        stripped.extend(bitcoin::compact_size(tx.inputs.len()));
+        stripped.extend(tx.inputs.iter().flat_map(|x| x.raw));
+        stripped.extend(bitcoin::compact_size(tx.outputs.len()));
+        stripped.extend(tx.outputs.concat());
+        stripped.extend_from_slice(tx.lock_time);
+        Some(sha256d(&stripped))
+    }
+
+    /// The output the verified input spends
+    pub fn prevout(&self) -> Option<&BitcoinPrevout> {
+        self.prevouts.get(self.input as usize)
+    }
+
+    /// The signature of the input, the hash it signs and the key it is under, an error if the
+    /// transaction does not parse, the output spent is of another type, or the witness is not
+    /// one of a key spend
+    pub fn signature(&self) -> Result<BitcoinSignature, SigError> {
+        use bitcoin_hashes::{hash160, Hash};
+
+        let tx = bitcoin::parse(&self.tx).ok_or(SigError::MalformedTransaction)?;
+        if self.prevouts.len() != tx.inputs.len() {
+            return Err(SigError::MalformedTransaction);
+        }
+        let i = self.input as usize;
+        let input = tx.inputs.get(i).ok_or(SigError::MalformedTransaction)?;
+        let script_pubkey = &self.prevouts[i].script_pubkey;
+        match script_pubkey[..] {
+            // OP_0 <20-byte key hash>
+            [0x00, 0x14, ref program @ ..] if program.len() == 20 => {
+                let [signature, public_key] = input.witness[..] else {
+                    return Err(SigError::MalformedSignature);
+                };
+                let (&hash_type, signature) =
+                    signature.split_last().ok_or(SigError::MalformedSignature)?;
+                if hash160::Hash::hash(public_key).to_byte_array() != program {
+                    return Err(SigError::InvalidSignature);
+                }
+                // The script code of the key hash, as in P2PKH
+                let script_code = [&[0x76, 0xa9, 0x14], program, &[0x88, 0xac]].concat();
+                let sighash =
+                    segwit_v0_sighash(&tx, i, &script_code, self.prevouts[i].amount, hash_type);
+                Ok(BitcoinSignature::Ecdsa {
+                    sighash,
+                    signature: signature.to_vec(),
+                    public_key: public_key.to_vec(),
+                })
+            }
+            // OP_1 <32-byte output key>
+            [0x51, 0x20, ref output_key @ ..] if output_key.len() == 32 => {
+                // The annex is the last element if there are two or more and it starts with 0x50
+                let (witness, annex) = match input.witness[..] {
+                    [ref witness @ .., annex]
+                        if !witness.is_empty() && annex.first() == Some(&0x50) =>
+                    {
+                        (witness, Some(annex))
+                    }
+                    ref witness => (witness, None),
+                };
+                let signature = match witness {
+                    [signature] => signature,
+                    [] => return Err(SigError::MalformedSignature),
+                    _ => return Err(SigError::UnsupportedScript),
+                };
+                let (signature, hash_type) = match signature.len() {
+                    64 => (*signature, Self::SIGHASH_DEFAULT),
+                    65 if signature[64] != Self::SIGHASH_DEFAULT => {
+                        (&signature[..64], signature[64])
+                    }
+                    _ => return Err(SigError::MalformedSignature),
+                };
+                let sighash = taproot_sighash(&tx, &self.prevouts, i, hash_type, annex)?;
+                Ok(BitcoinSignature::Schnorr {
+                    sighash,
+                    signature: signature.to_vec(),
+                    output_key: output_key.try_into().unwrap(),
+                })
+            }
+            _ => Err(SigError::UnsupportedScript),
+        }
+    }
+}
+
+/// BIP-143 signature hash of input `i` spending `amount` with `script_code`
+fn segwit_v0_sighash(
+    tx: &bitcoin::Tx,
+    i: usize,
+    script_code: &[u8],
+    amount: u64,
+    hash_type: u8,
+) -> [u8; 32] {
+    let anyone_can_pay = hash_type & BitcoinTxData::SIGHASH_ANYONECANPAY != 0;
+    let output_type = hash_type & 0x1f;
+    let single_or_none =
+        [BitcoinTxData::SIGHASH_SINGLE, BitcoinTxData::SIGHASH_NONE].contains(&output_type);
+    let input = &tx.inputs[i];
+
+    let hash_prevouts = match anyone_can_pay {
+        true => [0; 32],
+        false => sha256d(
+            &tx.inputs
+                .iter()
+                .flat_map(|x| x.outpoint)
+                .copied()
+                .collect::<Vec<_>>(),
+        ),
+    };
+    let hash_sequence = match anyone_can_pay || single_or_none {
+        true => [0; 32],
+        false => sha256d(
+            &tx.inputs
+                .iter()
+                .flat_map(|x| x.sequence)
+                .copied()
+                .collect::<Vec<_>>(),
+        ),
+    };
+    let hash_outputs = match tx.outputs.get(i) {
+        _ if !single_or_none => sha256d(&tx.outputs.concat()),
+        Some(output) if output_type == BitcoinTxData::SIGHASH_SINGLE => sha256d(output),
+        _ => [0; 32],
+    };
+
+    let mut preimage = tx.version.to_vec();
+    preimage.extend(hash_prevouts);
+    preimage.extend(hash_sequence);
+    preimage.extend_from_slice(input.outpoint);
+    preimage.extend(bitcoin::var_bytes(script_code));
+    preimage.extend(amount.to_le_bytes());
+    preimage.extend_from_slice(input.sequence);
+    preimage.extend(hash_outputs);
+    preimage.extend_from_slice(tx.lock_time);
+    preimage.extend((hash_type as u32).to_le_bytes());
+    sha256d(&preimage)
+}
+
+/// BIP-341 signature hash of the key path spend of input `i`, an error if the hash type is not
+/// one or SIGHASH_SINGLE has no output of the input
+fn taproot_sighash(
+    tx: &bitcoin::Tx,
+    prevouts: &[BitcoinPrevout],
+    i: usize,
+    hash_type: u8,
+    annex: Option<&[u8]>,
+) -> Result<[u8; 32], SigError> {
+    if !matches!(hash_type, 0x00..=0x03 | 0x81..=0x83) {
+        return Err(SigError::MalformedSignature);
+    }
+    let anyone_can_pay = hash_type & BitcoinTxData::SIGHASH_ANYONECANPAY != 0;
+    let output_type = match hash_type {
+        BitcoinTxData::SIGHASH_DEFAULT => BitcoinTxData::SIGHASH_ALL,
+        _ => hash_type & 0x03,
+    };
+    let input = &tx.inputs[i];
+
+    // Epoch 0, then the fields of SigMsg
+    let mut message = vec![0x00, hash_type];
+    message.extend_from_slice(tx.version);
+    message.extend_from_slice(tx.lock_time);
+    if !anyone_can_pay {
+        message.extend(sha256(
+            &tx.inputs
+                .iter()
+                .flat_map(|x| x.outpoint)
+                .copied()
+                .collect::<Vec<_>>(),
+        ));
+        message.extend(sha256(
+            &prevouts
+                .iter()
+                .flat_map(|x| x.amount.to_le_bytes())
+                .collect::<Vec<_>>(),
+        ));
+        let script_pubkeys = prevouts
+            .iter()
+            .flat_map(|x| bitcoin::var_bytes(&x.script_pubkey));
+        message.extend(sha256(&script_pubkeys.collect::<Vec<_>>()));
+        message.extend(sha256(
+            &tx.inputs
+                .iter()
+                .flat_map(|x| x.sequence)
+                .copied()
+                .collect::<Vec<_>>(),
+        ));
+    }
+    if output_type == BitcoinTxData::SIGHASH_ALL {
+        message.extend(sha256(&tx.outputs.concat()));
+    }
+    // The spend type: key path, with or without an annex
+    message.push(annex.is_some() as u8);
+    if anyone_can_pay {
+        message.extend_from_slice(input.outpoint);
+        message.extend(prevouts[i].amount.to_le_bytes());
+        message.extend(bitcoin::var_bytes(&prevouts[i].script_pubkey));
+        message.extend_from_slice(input.sequence);
+    } else {
+        message.extend((i as u32).to_le_bytes());
+    }
+    if let Some(annex) = annex {
+        message.extend(sha256(&bitcoin::var_bytes(annex)));
+    }
+    if output_type == BitcoinTxData::SIGHASH_SINGLE {
+        let output = tx.outputs.get(i).ok_or(SigError::MalformedSignature)?;
+        message.extend(sha256(output));
+    }
+    Ok(tagged_hash("TapSighash", &message))
+}
+
+impl SignatureVerifier for BitcoinTxData {
+    fn scheme(&self) -> Scheme {
+        Scheme::BitcoinTx
+    }
+
+    /// Verify the signature of the input under the key the spent output commits to, a high s
+    /// fails as relay policy has it (BIP-146)
+    fn try_verify(&self) -> Result<(), SigError> {
+        use k256::ecdsa::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey};
+
+        match self.signature()? {
+            BitcoinSignature::Ecdsa {
+                sighash,
+                signature,
+                public_key,
+            } => {
+                let signature =
+                    Signature::from_der(&signature).map_err(|_| SigError::MalformedSignature)?;
+                if signature.normalize_s().is_some() {
+                    return Err(SigError::HighS);
+                }
+                let verifying_key = VerifyingKey::from_sec1_bytes(&public_key)
+                    .map_err(|_| SigError::MalformedKey)?;
+                verifying_key
+                    .verify_prehash(&sighash, &signature)
+                    .map_err(|_| SigError::InvalidSignature)
+            }
+            BitcoinSignature::Schnorr {
+                sighash,
+                signature,
+                output_key,
+            } => SchnorrSecp256k1Data {
+                message_hash: sighash,
+                signature,
+                public_key: output_key,
+                hashing: SchnorrHashing::Bip340,
+                taproot: None,
+                message: None,
+            }
+            .try_verify(),
+        }
+    }
+
+    fn try_verify_pure(&self) -> Option<Result<(), SigError>> {
+        Some(pure::bitcoin_tx(self))
+    }
+}
+
+// ============================================================================
+// Helper Functions for BIP-340 and Taproot
+// ============================================================================
+
+/// BIP-340 tagged hash: SHA-256(SHA-256(tag) || SHA-256(tag) || data)
+pub fn tagged_hash(tag: &str, data: &[u8]) -> [u8; 32] {
+    let tag_hash = sha256(tag.as_bytes());
+    let mut payload = Vec::with_capacity(64 + data.len());
+    payload.extend_from_slice(&tag_hash);
+    payload.extend_from_slice(&tag_hash);
+    payload.extend_from_slice(data);
+    sha256(&payload)
+}
+
+/// BIP-340 challenge of a signature with nonce x-coordinate `r` by the x-only `public_key` over
+/// `message`, e = H_BIP0340/challenge(r || P || m) mod n is what s * G - e * P has to match
+pub fn bip340_challenge(r: &[u8; 32], public_key: &[u8; 32], message: &[u8]) -> [u8; 32] {
+    let mut payload = Vec::with_capacity(64 + message.len());
+    payload.extend_from_slice(r);
+    payload.extend_from_slice(public_key);
+    payload.extend_from_slice(message);
+    tagged_hash("BIP0340/challenge", &payload)
+}
+
+impl TaprootTweak {
+    /// The tweak t = H_TapTweak(P || merkle_root), the merkle root left out if there is none
+    pub fn tweak(&self) -> [u8; 32] {
+        let mut payload = self.internal_key.to_vec();
+        payload.extend(self.merkle_root.iter().flatten());
+        tagged_hash("TapTweak", &payload)
+    }
+
+    /// The x-only output key Q = P + t * G, an error if P is not a key or the tweak is not a
+    /// scalar (t >= n) or Q is the point at infinity, as BIP-341 fails then
+    pub fn output_key(&self) -> Result<[u8; 32], SigError> {
+        use k256::elliptic_curve::{point::AffineCoordinates, PrimeField};
+        use k256::{schnorr::VerifyingKey, ProjectivePoint, Scalar};
+
+        let internal_key =
+            VerifyingKey::from_bytes(&self.internal_key).map_err(|_| SigError::MalformedKey)?;
+        let t = Option::<Scalar>::from(Scalar::from_repr(self.tweak().into()))
+            .ok_or(SigError::TweakMismatch)?;
+        let internal_point = ProjectivePoint::from(*internal_key.as_affine());
+        let output_key = (internal_point + ProjectivePoint::GENERATOR * t).to_affine();
+        if output_key == k256::AffinePoint::IDENTITY {
+            return Err(SigError::TweakMismatch);
+        }
+        Ok(output_key.x().into())
+    }
+}
+
+// ============================================================================
+// Helper Functions for P-256
+// ============================================================================
+
+impl P256EcdsaData {
+    /// Recover the compressed public key from the signature, `None` if it recovers no key
+    pub fn recover(&self) -> Option<[u8; 33]> {
+        use ecdsa::RecoveryId;
+        use p256::ecdsa::{Signature, VerifyingKey};
+
+        let signature = Signature::from_slice(&self.signature).ok()?;
+        let recovery_id = RecoveryId::from_byte(self.recovery_id)?;
+        let recovered_key =
+            VerifyingKey::recover_from_prehash(&self.message_hash, &signature, recovery_id).ok()?;
+
+        recovered_key
+            .to_encoded_point(true)
+            .as_bytes()
+            .try_into()
+            .ok()
+    }
+}
+
+impl SignatureVerifier for P256EcdsaData {
+    fn scheme(&self) -> Scheme {
+        Scheme::P256Ecdsa
+    }
+
+    /// Verify the ECDSA signature over the prehashed message using compressed public key, a high s
+    /// is rejected as for secp256k1 so a signature has one encoding
+    /// A malformed signature or key fails verification
+    fn try_verify(&self) -> Result<(), SigError> {
+        use p256::ecdsa::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey};
+
+        let signature =
+            Signature::from_slice(&self.signature).map_err(|_| SigError::MalformedSignature)?;
+        if signature.normalize_s().is_some() {
+            return Err(SigError::HighS);
+        }
+        let verifying_key =
+            VerifyingKey::from_sec1_bytes(&self.public_key).map_err(|_| SigError::MalformedKey)?;
+
+        verifying_key
+            .verify_prehash(&self.message_hash, &signature)
+            .map_err(|_| SigError::InvalidSignature)
+    }
+
+    fn try_verify_pure(&self) -> Option<Result<(), SigError>> {
+        Some(pure::p256_ecdsa(self))
+    }
+
+    fn recoverable(&self) -> bool {
+        true
+    }
+
+    fn recover_public_key(&self) -> Option<[u8; 33]> {
+        self.recover()
+    }
+}
+
+// ============================================================================
+// Helper Functions for WebAuthn
+// ============================================================================
+
+impl WebAuthnAssertionData {
+    /// authenticatorData flag: user present
+    pub const FLAG_UP: u8 = 0x01;
+    /// authenticatorData flag: user verified
+    pub const FLAG_UV: u8 = 0x04;
+
+    /// The flags byte of the authenticator data, `None` if it is too short
+    pub fn flags(&self) -> Option<u8> {
+        self.authenticator_data.get(32).copied()
+    }
+
+    /// The signature counter of the authenticator data, `None` if it is too short
+    pub fn sign_count(&self) -> Option<u32> {
+        let bytes = self.authenticator_data.get(33..37)?;
+        Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// The payload the authenticator signed: authenticatorData || clientDataHash
+    pub fn signed_payload(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(self.authenticator_data.len() + 32);
+        payload.extend_from_slice(&self.authenticator_data);
+        payload.extend_from_slice(&self.client_data_hash);
+        payload
+    }
+
+    /// The user was present, and verified if required, per the authenticator data flags
+    fn check_flags(&self) -> Result<(), SigError> {
+        let flags = self
+            .flags()
+            .filter(|_| self.sign_count().is_some())
+            .ok_or(SigError::MalformedAuthenticatorData)?;
+        let required = if self.require_user_verification {
+            Self::FLAG_UP | Self::FLAG_UV
+        } else {
+            Self::FLAG_UP
+        };
+        if flags & required != required {
+            return Err(SigError::MissingUserFlags);
+        }
+        Ok(())
+    }
+}
+
+impl SignatureVerifier for WebAuthnAssertionData {
+    fn scheme(&self) -> Scheme {
+        Scheme::WebAuthn
+    }
+
+    /// Verify the assertion per the WebAuthn spec: the user was present (and verified if
+    /// required), and the signature is over SHA-256 of the signed payload
+    /// Either s is accepted, authenticators don't normalize it and relying parties take both,
+    /// an assertion is bound by its challenge rather than by its signature bytes
+    /// A malformed assertion fails verification
+    fn try_verify(&self) -> Result<(), SigError> {
+        use p256::ecdsa::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey};
+
+        self.check_flags()?;
+        let signature =
+            Signature::from_der(&self.signature).map_err(|_| SigError::MalformedSignature)?;
+        let verifying_key =
+            VerifyingKey::from_sec1_bytes(&self.public_key).map_err(|_| SigError::MalformedKey)?;
+
+        verifying_key
+            .verify_prehash(&sha256(&self.signed_payload()), &signature)
+            .map_err(|_| SigError::InvalidSignature)
+    }
+
+    fn try_verify_pure(&self) -> Option<Result<(), SigError>> {
+        Some(pure::webauthn(self))
+    }
+}
+
+// ============================================================================
+// Helper Functions for RSA
+// ============================================================================
+
+impl RsaSignatureData {
+    /// Smallest and largest modulus accepted, in bits
+    pub const MIN_BITS: usize = 2048;
+    pub const MAX_BITS: usize = 4096;
+
+    /// The public key, an error if malformed or its size is out of range
+    pub fn key(&self) -> Result<rsa::RsaPublicKey, SigError> {
+        use rsa::{pkcs1::DecodeRsaPublicKey, traits::PublicKeyParts};
+
+        let key = rsa::RsaPublicKey::from_pkcs1_der(&self.public_key)
+            .map_err(|_| SigError::MalformedKey)?;
+        let bits = key.n().bits();
+        (Self::MIN_BITS..=Self::MAX_BITS)
+            .contains(&bits)
+            .then_some(key)
+            .ok_or(SigError::UnsupportedKeySize)
+    }
+}
+
+impl SignatureVerifier for RsaSignatureData {
+    fn scheme(&self) -> Scheme {
+        Scheme::Rsa
+    }
+
+    /// Verify the signature over SHA-256 of the message
+    /// A malformed signature or key fails verification
+    fn try_verify(&self) -> Result<(), SigError> {
+        use rsa::{Pkcs1v15Sign, Pss};
+        use sha2::Sha256;
+
+        let key = self.key()?;
+        let hashed = sha256(&self.message);
+        match self.padding {
+            RsaPadding::Pkcs1v15 => {
+                key.verify(Pkcs1v15Sign::new::<Sha256>(), &hashed, &self.signature)
+            }
+            RsaPadding::Pss => key.verify(Pss::new::<Sha256>(), &hashed, &self.signature),
+        }
+        .map_err(|_| SigError::InvalidSignature)
+    }
+
+    fn try_verify_pure(&self) -> Option<Result<(), SigError>> {
+        Some(pure::rsa(self))
+    }
+
+    /// Its key size and padding, the cost depends on both
+    fn cycle_label(&self) -> String {
+        use rsa::traits::PublicKeyParts;
+
+        let bits = self.key().map_or(0, |key| key.n().bits());
+        let padding = match self.padding {
+            RsaPadding::Pkcs1v15 => "pkcs1v15",
+            RsaPadding::Pss => "pss",
+        };
+        format!("rsa{}_{}", bits, padding)
+    }
+}
+
+// ============================================================================
+// Helper Functions for Ed25519
+// ============================================================================
+
+impl Ed25519Data {
+    /// dom2(phflag, context) of RFC 8032, empty for Ed25519 so it stays as it was
+    fn dom2(&self) -> Result<Vec<u8>, SigError> {
+        let (phflag, context) = match &self.algorithm {
+            Ed25519Algorithm::Pure => return Ok(vec![]),
+            Ed25519Algorithm::Ctx(context) if context.is_empty() => {
+                return Err(SigError::InvalidContext)
+            }
+            Ed25519Algorithm::Ctx(context) => (0, context),
+            Ed25519Algorithm::Ph(context) => (1, context),
+        };
+        let len = u8::try_from(context.len()).map_err(|_| SigError::InvalidContext)?;
+        Ok([
+            &b"SigEd25519 no Ed25519 collisions"[..],
+            &[phflag, len],
+            context,
+        ]
+        .concat())
+    }
+
+    /// The message as signed, PH(M): SHA-512 of it for Ed25519ph, as is otherwise
+    fn prehashed(&self) -> Vec<u8> {
+        use sha2::{Digest, Sha512};
+
+        match self.algorithm {
+            Ed25519Algorithm::Ph(_) => Sha512::digest(&self.message).to_vec(),
+            _ => self.message.clone(),
+        }
+    }
+
+    /// The challenge before its reduction: SHA-512(dom2 || R || A || PH(M)), an error if the
+    /// context string is not one of the algorithm
+    /// It is only hashing, the upstream crates' verifier computes it here as well
+    pub fn challenge(&self, r_bytes: [u8; 32]) -> Result<[u8; 64], SigError> {
+        use sha2::{Digest, Sha512};
+
+        let mut hasher = Sha512::new();
+        hasher.update(self.dom2()?);
+        hasher.update(r_bytes);
+        hasher.update(self.public_key);
+        hasher.update(self.prehashed());
+        Ok(hasher.finalize().into())
+    }
+}
+
+impl SignatureVerifier for Ed25519Data {
+    fn scheme(&self) -> Scheme {
+        Scheme::Ed25519
+    }
+
+    /// Verify Ed25519 signature using curve25519_dalek primitives under `mode`
+    /// Verification equation: R + H(dom2||R||A||PH(M)) * A == s * B, times the cofactor unless
+    /// strict
+    fn try_verify(&self) -> Result<(), SigError> {
+        use curve25519_dalek::{
+            constants::ED25519_BASEPOINT_TABLE,
+            edwards::CompressedEdwardsY,
+            scalar::Scalar,
+            traits::IsIdentity,
+        };
+
+        // Signature is (R, s) - split into two 32-byte arrays directly
+        if self.signature.len() != 64 {
+            return Err(SigError::MalformedSignature);
+        }
+        let r_bytes: [u8; 32] = self.signature[0..32].try_into().unwrap();
+        let s_bytes: [u8; 32] = self.signature[32..64].try_into().unwrap();
+
+        // Parse R, decompression accepts a non-canonical y
+        let r_point = match CompressedEdwardsY(r_bytes).decompress() {
+            Some(p) => p,
+            None => return Err(SigError::MalformedSignature),
+        };
+
+        // Parse s, a non-canonical s is malleable, every mode rejects it
+        let s = match Option::<Scalar>::from(Scalar::from_canonical_bytes(s_bytes)) {
+            Some(s) => s,
+            None => return Err(SigError::NonCanonical),
+        };
+
+        // Parse public key A
+        let a_point = match CompressedEdwardsY(self.public_key).decompress() {
+            Some(p) => p,
+            None => return Err(SigError::MalformedKey),
+        };
+
+        // A canonical encoding is the one the point compresses back to
+        if self.mode != Ed25519Mode::Zip215
+            && (r_point.compress().0 != r_bytes || a_point.compress().0 != self.public_key)
+        {
+            return Err(SigError::NonCanonical);
+        }
+        if self.mode == Ed25519Mode::Strict
+            && (r_point.is_small_order() || a_point.is_small_order())
+        {
+            return Err(SigError::RejectedByMode);
+        }
+
+        // Compute H(dom2||R||A||PH(M)) using SHA-512 and convert it to a scalar
+        let h = Scalar::from_bytes_mod_order_wide(&self.challenge(r_bytes)?);
+
+        // Verify: s * B - H * A - R is the identity, or of small order when cofactored
+        let diff = ED25519_BASEPOINT_TABLE * &s - h * a_point - r_point;
+        let ok = match self.mode {
+            Ed25519Mode::Strict => diff.is_identity(),
+            Ed25519Mode::Zip215 | Ed25519Mode::Cofactored => diff.mul_by_cofactor().is_identity(),
+        };
+        ok.then_some(()).ok_or(SigError::InvalidSignature)
+    }
+
+    fn try_verify_pure(&self) -> Option<Result<(), SigError>> {
+        Some(pure::ed25519(self))
+    }
+
+    /// Its algorithm, Ed25519ph hashes the message once more
+    fn cycle_label(&self) -> String {
+        match self.algorithm {
+            Ed25519Algorithm::Pure => "ed25519",
+            Ed25519Algorithm::Ctx(_) => "ed25519ctx",
+            Ed25519Algorithm::Ph(_) => "ed25519ph",
+        }
+        .to_string()
+    }
+}
+
+// ============================================================================
+// Helper Functions for Session Authentication
+// ============================================================================
+
+impl SessionAuthData {
+    /// SHA-256 of both ephemeral keys and the transcript, what the gateway signs
+    pub fn handshake_hash(&self) -> [u8; 32] {
+        sha256(
+            &[
+                &b"ddm session"[..],
+                &self.client_public_key,
+                &self.gateway_public_key,
+                &self.transcript,
+            ]
+            .concat(),
+        )
+    }
+
+    /// The Ed25519 data the handshake signature verifies as, strict so it has one encoding
+    fn signed_data(&self) -> Ed25519Data {
+        Ed25519Data {
+            message: self.handshake_hash().to_vec(),
+            signature: self.signature.clone(),
+            public_key: self.identity_key,
+            mode: Ed25519Mode::Strict,
+            algorithm: Ed25519Algorithm::Pure,
+        }
+    }
+
+    /// The session key: HKDF-SHA256 of the X25519 shared secret, bound to both public keys
+    pub fn session_key(
+        shared_secret: &[u8; 32],
+        client_public_key: &[u8; 32],
+        gateway_public_key: &[u8; 32],
+    ) -> [u8; 32] {
+        let info = [&b"ddm session"[..], client_public_key, gateway_public_key].concat();
+        let mut key = [0u8; 32];
+        hkdf::Hkdf::<sha2::Sha256>::new(None, shared_secret)
+            .expand(&info, &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 length");
+        key
+    }
+
+    /// HMAC-SHA256 of `transcript` under `session_key`
+    pub fn transcript_mac(session_key: &[u8; 32], transcript: &[u8]) -> [u8; 32] {
+        use hmac::{Hmac, Mac};
+
+        let mut mac =
+            Hmac::<sha2::Sha256>::new_from_slice(session_key).expect("HMAC takes any key length");
+        mac.update(transcript);
+        mac.finalize().into_bytes().into()
+    }
+
+    /// Check the client's MAC of the transcript on the gateway, off the guest: `gateway_secret`
+    /// has to be the one of the gateway key, the MAC holds under the session key derived from
+    /// X25519(secret, client key), compared in constant time
+    pub fn verify_mac(&self, gateway_secret: &SecretBytes, mac: &[u8; 32]) -> Result<(), SigError> {
+        use curve25519_dalek::montgomery::MontgomeryPoint;
+        use hmac::{Hmac, Mac};
+
+        if MontgomeryPoint::mul_base_clamped(**gateway_secret).0 != self.gateway_public_key {
+            return Err(SigError::MalformedKey);
+        }
+        // A client key of small order gives the all-zero secret whatever the gateway secret
+        let shared = Zeroizing::new(
+            MontgomeryPoint(self.client_public_key)
+                .mul_clamped(**gateway_secret)
+                .0,
+        );
+        if *shared == [0u8; 32] {
+            return Err(SigError::MalformedKey);
+        }
+        let key = Zeroizing::new(Self::session_key(
+            &shared,
+            &self.client_public_key,
+            &self.gateway_public_key,
+        ));
+        let mut hmac =
+            Hmac::<sha2::Sha256>::new_from_slice(&*key).expect("HMAC takes any key length");
+        hmac.update(&self.transcript);
+        hmac.verify_slice(mac)
+            .map_err(|_| SigError::InvalidSignature)
+    }
+}
+
+impl SignatureVerifier for SessionAuthData {
+    fn scheme(&self) -> Scheme {
+        Scheme::SessionAuth
+    }
+
+    /// Verify the session using curve25519_dalek primitives: neither ephemeral key is of small
+    /// order, which would share the all-zero secret, and the identity key signed the handshake
+    fn try_verify(&self) -> Result<(), SigError> {
+        use curve25519_dalek::montgomery::MontgomeryPoint;
+
+        let small = |u: &[u8; 32]| {
+            MontgomeryPoint(*u)
+                .to_edwards(0)
+                .is_some_and(|x| x.is_small_order())
+        };
+        if small(&self.client_public_key) || small(&self.gateway_public_key) {
+            return Err(SigError::MalformedKey);
+        }
+        self.signed_data().try_verify()
+    }
+
+    fn try_verify_pure(&self) -> Option<Result<(), SigError>> {
+        Some(pure::session_auth(self))
+    }
+}
+
+// ============================================================================
+// Helper Functions for SignatureItem
+// ============================================================================
+
+impl Scheme {
+    pub const ALL: [Scheme; 12] = [
+        Scheme::EcdsaSecp256k1,
+        Scheme::SchnorrSecp256k1,
+        Scheme::Ed25519,
+        Scheme::P256Ecdsa,
+        Scheme::WebAuthn,
+        Scheme::Rsa,
+        Scheme::ThresholdSchnorr,
+        Scheme::MuSig2,
+        Scheme::EthTx,
+        Scheme::BitcoinTx,
+        Scheme::SessionAuth,
+        Scheme::CosignedSchnorr,
+    ];
+
+    /// Tag of the scheme in the leaves of the authorization root
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+
+    /// Short name, used for the cycle-tracker sections of the program
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scheme::EcdsaSecp256k1 => "ecdsa",
+            Scheme::SchnorrSecp256k1 => "schnorr",
+            Scheme::Ed25519 => "ed25519",
+            Scheme::P256Ecdsa => "p256",
+            Scheme::WebAuthn => "webauthn",
+            Scheme::Rsa => "rsa",
+            Scheme::ThresholdSchnorr => "frost",
+            Scheme::MuSig2 => "musig2",
+            Scheme::EthTx => "ethtx",
+            Scheme::BitcoinTx => "btctx",
+            Scheme::SessionAuth => "session",
+            Scheme::CosignedSchnorr => "cosign",
+        }
+    }
+}
+
+impl std::str::FromStr for Scheme {
+    type Err = String;
+
+    /// A scheme by its short name, see `Scheme::name`
+    fn from_str(s: &str) -> Result<Self, String> {
+        Scheme::ALL
+            .into_iter()
+            .find(|x| x.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown scheme {}, expected one of {:?}",
+                    s,
+                    Scheme::ALL.map(|x| x.name())
+                )
+            })
+    }
+}
+
+impl SignatureItem {
+    /// The data of the item, verified under its scheme
+    pub fn verifier(&self) -> &dyn SignatureVerifier {
+        match self {
+            SignatureItem::EcdsaSecp256k1(x) => x,
+            SignatureItem::SchnorrSecp256k1(x) => x,
+            SignatureItem::Ed25519(x) => x,
+            SignatureItem::P256Ecdsa(x) => x,
+            SignatureItem::WebAuthn(x) => x,
+            SignatureItem::Rsa(x) => x,
+            SignatureItem::ThresholdSchnorr(x) => x,
+            SignatureItem::MuSig2(x) => x,
+            SignatureItem::EthTx(x) => x,
+            SignatureItem::BitcoinTx(x) => x,
+            SignatureItem::SessionAuth(x) => x,
+            SignatureItem::CosignedSchnorr(x) => x,
+        }
+    }
+
+    /// The hash of what the signature authorizes: the message hash it carries, the client data
+    /// hash of a WebAuthn assertion, Keccak-256 of the message for Ed25519 and RSA, or the
+    /// transaction hash of an Ethereum transaction, the txid of a Bitcoin one, the handshake hash
+    /// of a session, over both of its ephemeral keys
+    pub fn message_hash(&self) -> [u8; 32] {
+        match self {
+            SignatureItem::EcdsaSecp256k1(x) => x.message_hash,
+            SignatureItem::SchnorrSecp256k1(x) => x.message_hash,
+            SignatureItem::Ed25519(x) => keccak256(&x.message),
+            SignatureItem::P256Ecdsa(x) => x.message_hash,
+            SignatureItem::WebAuthn(x) => x.client_data_hash,
+            SignatureItem::Rsa(x) => keccak256(&x.message),
+            SignatureItem::ThresholdSchnorr(x) => x.message_hash,
+            SignatureItem::MuSig2(x) => x.message_hash,
+            SignatureItem::EthTx(x) => x.tx_hash(),
+            SignatureItem::BitcoinTx(x) => x.txid().unwrap_or_default(),
+            SignatureItem::SessionAuth(x) => x.handshake_hash(),
+            SignatureItem::CosignedSchnorr(x) => x.message_hash,
+        }
+    }
+
+    /// The public key as the item carries it, the script of the output a Bitcoin input spends,
+    /// the identity key of the gateway of a session
+    pub fn public_key(&self) -> &[u8] {
+        match self {
+            SignatureItem::EcdsaSecp256k1(x) => &x.public_key,
+            SignatureItem::SchnorrSecp256k1(x) => &x.public_key,
+            SignatureItem::Ed25519(x) => &x.public_key,
+            SignatureItem::P256Ecdsa(x) => &x.public_key,
+            SignatureItem::WebAuthn(x) => &x.public_key,
+            SignatureItem::Rsa(x) => &x.public_key,
+            SignatureItem::ThresholdSchnorr(x) => &x.group_public_key,
+            SignatureItem::MuSig2(x) => &x.public_keys,
+            SignatureItem::EthTx(x) => &x.sender,
+            SignatureItem::BitcoinTx(x) => x.prevout().map_or(&[], |x| &x.script_pubkey),
+            SignatureItem::SessionAuth(x) => &x.identity_key,
+            SignatureItem::CosignedSchnorr(x) => &x.public_keys,
+        }
+    }
+
+    /// The Ethereum address of the signer of an ECDSA item, recovered without verifying: the
+    /// recovered key of a secp256k1 signature or the sender of a transaction, `None` if it
+    /// recovers no key or the scheme has no addresses
+    pub fn recover_address(&self) -> Option<[u8; 20]> {
+        match self {
+            SignatureItem::EcdsaSecp256k1(x) => x.recover_address(),
+            SignatureItem::EthTx(x) => x.recover_sender().ok(),
+            _ => None,
+        }
+    }
+
+    /// Leaf of the item in the authorization root:
+    /// keccak256(abi.encodePacked(uint8 scheme, bytes32 messageHash, bytes publicKey))
+    pub fn leaf(&self) -> [u8; 32] {
+        let public_key = self.public_key();
+        let mut packed = Vec::with_capacity(33 + public_key.len());
+        packed.push(self.scheme().id());
+        packed.extend_from_slice(&self.message_hash());
+        packed.extend_from_slice(public_key);
+        keccak256(&packed)
+    }
+
+    /// A valid sample signature of `scheme`, deterministic
+    pub fn sample(scheme: Scheme) -> Self {
+        match scheme {
+            Scheme::EcdsaSecp256k1 => SignatureItem::EcdsaSecp256k1(sample_ecdsa()),
+            Scheme::SchnorrSecp256k1 => SignatureItem::SchnorrSecp256k1(sample_schnorr()),
+            Scheme::Ed25519 => SignatureItem::Ed25519(sample_ed25519()),
+            Scheme::P256Ecdsa => SignatureItem::P256Ecdsa(sample_p256()),
+            Scheme::WebAuthn => SignatureItem::WebAuthn(sample_webauthn()),
+            Scheme::Rsa => SignatureItem::Rsa(sample_rsa(RsaPadding::Pkcs1v15)),
+            Scheme::ThresholdSchnorr => SignatureItem::ThresholdSchnorr(sample_threshold_schnorr()),
+            Scheme::MuSig2 => SignatureItem::MuSig2(sample_musig2()),
+            Scheme::EthTx => SignatureItem::EthTx(sample_eth_tx()),
+            Scheme::BitcoinTx => {
+                SignatureItem::BitcoinTx(sample_bitcoin_tx(BitcoinOutputKind::P2tr))
+            }
+            Scheme::SessionAuth => SignatureItem::SessionAuth(sample_session_auth()),
+            Scheme::CosignedSchnorr => SignatureItem::CosignedSchnorr(sample_cosigned_schnorr()),
+        }
+    }
+
+    /// A valid signature of `scheme` over a random message by a random key, drawn from `rng`
+    /// RSA signs under the sample key, see `sign_rsa`
+    pub fn random(scheme: Scheme, rng: &mut impl CryptoRngCore) -> Self {
+        let mut message = [0u8; 32];
+        rng.fill_bytes(&mut message);
+        // A valid secret key of the curve, any 32 bytes for Ed25519 and X25519, drawn straight
+        // into the buffer that wipes it once signed
+        let k256 = |x: &[u8]| k256::SecretKey::from_slice(x).is_ok();
+        let p256 = |x: &[u8]| p256::SecretKey::from_slice(x).is_ok();
+        let any = |_: &[u8]| true;
+        match scheme {
+            Scheme::EcdsaSecp256k1 => {
+                let secret_bytes = random_secret(k256, rng);
+                SignatureItem::EcdsaSecp256k1(sign_ecdsa(&secret_bytes, &message))
+            }
+            Scheme::SchnorrSecp256k1 => {
+                let secret_bytes = random_secret(k256, rng);
+                SignatureItem::SchnorrSecp256k1(sign_schnorr(&secret_bytes, &message))
+            }
+            Scheme::Ed25519 => {
+                let secret_bytes = random_secret(any, rng);
+                SignatureItem::Ed25519(sign_ed25519(&secret_bytes, &message))
+            }
+            Scheme::P256Ecdsa => {
+                let secret_bytes = random_secret(p256, rng);
+                SignatureItem::P256Ecdsa(sign_p256(&secret_bytes, &message))
+            }
+            Scheme::WebAuthn => {
+                let secret_bytes = random_secret(p256, rng);
+                let client_data_json = format!(
+                    r#"{{"type":"webauthn.get","challenge":"{}","origin":"https://example.com"}}"#,
+                    Base64UrlUnpadded::encode_string(&message)
+                );
+                SignatureItem::WebAuthn(sign_webauthn(&secret_bytes, client_data_json.as_bytes()))
+            }
+            Scheme::Rsa => SignatureItem::Rsa(sign_rsa(&message, RsaPadding::Pkcs1v15, rng)),
+            Scheme::ThresholdSchnorr => {
+                let group_secret = random_secret(k256, rng);
+                let frost = sign_threshold_schnorr(&group_secret, 2, &[1, 3], message, rng);
+                SignatureItem::ThresholdSchnorr(frost)
+            }
+            Scheme::MuSig2 => {
+                let secrets = [random_secret(k256, rng), random_secret(k256, rng)];
+                SignatureItem::MuSig2(sign_musig2(&secrets, message, rng))
+            }
+            Scheme::EthTx => {
+                let secret_bytes = random_secret(k256, rng);
+                let mut to = [0u8; 20];
+                rng.fill_bytes(&mut to);
+                let (nonce, value) = (rng.next_u32() as u64, rng.next_u64() as u128);
+                let tx = sign_eth_tx(
+                    &secret_bytes,
+                    EthTxKind::Eip1559,
+                    1,
+                    nonce,
+                    to,
+                    value,
+                    &message,
+                );
+                SignatureItem::EthTx(tx)
+            }
+            Scheme::BitcoinTx => {
+                let secret_bytes = random_secret(k256, rng);
+                let kind = [BitcoinOutputKind::P2wpkh, BitcoinOutputKind::P2tr]
+                    [rng.next_u32() as usize % 2];
+                SignatureItem::BitcoinTx(sign_bitcoin_tx(&secret_bytes, kind, &message))
+            }
+            Scheme::SessionAuth => {
+                let (client, gateway) = (random_secret(any, rng), random_secret(any, rng));
+                let identity = random_secret(any, rng);
+                SignatureItem::SessionAuth(sign_session_auth(
+                    &client, &gateway, &identity, &message,
+                ))
+            }
+            Scheme::CosignedSchnorr => {
+                let secrets = [random_secret(k256, rng), random_secret(k256, rng)];
+                SignatureItem::CosignedSchnorr(sign_cosigned_schnorr(&secrets, message, rng))
+            }
+        }
+    }
+}
+
+impl SignatureVerifier for SignatureItem {
+    fn scheme(&self) -> Scheme {
+        self.verifier().scheme()
+    }
+
+    fn try_verify(&self) -> Result<(), SigError> {
+        self.verifier().try_verify()
+    }
+
+    fn try_verify_pure(&self) -> Option<Result<(), SigError>> {
+        self.verifier().try_verify_pure()
+    }
+
+    fn cycle_label(&self) -> String {
+        self.verifier().cycle_label()
+    }
+
+    fn recoverable(&self) -> bool {
+        self.verifier().recoverable()
+    }
+
+    fn recover_public_key(&self) -> Option<[u8; 33]> {
+        self.verifier().recover_public_key()
+    }
+}
+
+// ============================================================================
+// Authorization Root
+// ============================================================================
+
+/// Keccak-256 of the pair, the smaller first, as OpenZeppelin `MerkleProof` hashes them
+fn hash_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+    let mut pair = [0u8; 64];
+    pair[..32].copy_from_slice(lo);
+    pair[32..].copy_from_slice(hi);
+    keccak256(&pair)
+}
+
+/// The level above `level`, an odd node moves up as is
+fn merkle_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [a, b] => hash_pair(a, b),
+            [a] => *a,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Merkle root of the leaves in order, zero if there are none
+/// Inclusion is checked on-chain with OpenZeppelin `MerkleProof.verify` and `merkle_proof`
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = merkle_level(&level);
+    }
+    level.first().copied().unwrap_or_default()
+}
+
+/// The sibling hashes from the leaf at `index` up to the root, `None` if out of range
+pub fn merkle_proof(leaves: &[[u8; 32]], index: usize) -> Option<Vec<[u8; 32]>> {
+    if index >= leaves.len() {
+        return None;
+    }
+    let mut proof = vec![];
+    let (mut level, mut index) = (leaves.to_vec(), index);
+    while level.len() > 1 {
+        // The odd node has no sibling on its level
+        if let Some(sibling) = level.get(index ^ 1) {
+            proof.push(*sibling);
+        }
+        level = merkle_level(&level);
+        index /= 2;
+    }
+    Some(proof)
+}
+
+/// Whether `leaf` is under `root` with the `merkle_proof`
+pub fn verify_merkle_proof(root: &[u8; 32], leaf: &[u8; 32], proof: &[[u8; 32]]) -> bool {
+    proof
+        .iter()
+        .fold(*leaf, |node, sibling| hash_pair(&node, sibling))
+        == *root
+}
+
+/// The addresses that signed the ECDSA items, ascending without duplicates, the other items and
+/// those that recover no key left out
+/// A recovered address signed the message, whichever key the item claims, so nothing is
+/// verified: the attestation is of who signed, not that it was who the items say
+pub fn recover_signers(items: &[SignatureItem]) -> Vec<[u8; 20]> {
+    let mut signers: Vec<_> = items.iter().filter_map(|x| x.recover_address()).collect();
+    signers.sort_unstable();
+    signers.dedup();
+    signers
+}
+
+/// Serialize the program input to bincode
+pub fn items_to_bincode(items: &[SignatureItem]) -> Result<Vec<u8>, bincode::Error> {
+    bincode::serialize(items)
+}
+
+/// Deserialize the program input from bincode
+pub fn items_from_bincode(bytes: &[u8]) -> Result<Vec<SignatureItem>, bincode::Error> {
+    bincode::deserialize(bytes)
+}
+
+// ============================================================================
+// Streamed Input
+// ============================================================================
+
+/// Items per frame of the streamed input by default, a frame is all the program holds at once
+pub const FRAME_ITEMS: usize = 256;
+
+/// The header of the items streamed to the program: `frames` frames of bincode items follow, see
+/// `item_frames`, so a batch of any size fits the memory of the guest
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StreamHeader {
+    /// Number of items over all frames
+    pub items: u32,
+    /// Number of frames
+    pub frames: u32,
+}
+
+/// The header and the frames of `items`, `frame_items` items each but the last, the program
+/// reads the header, then every frame as a `read_vec` buffer
+pub fn item_frames(
+    items: &[SignatureItem],
+    frame_items: usize,
+) -> Result<(StreamHeader, Vec<Vec<u8>>), bincode::Error> {
+    let frames = items
+        .chunks(frame_items.max(1))
+        .map(items_to_bincode)
+        .collect::<Result<Vec<_>, _>>()?;
+    let header = StreamHeader {
+        items: items.len() as u32,
+        frames: frames.len() as u32,
+    };
+    Ok((header, frames))
+}
+
+/// keccak256(abi.encodePacked(leaves)) of `items`, see `SignatureItem::leaf`
+pub fn items_hash(items: &[SignatureItem]) -> [u8; 32] {
+    let mut hasher = ItemsHasher::default();
+    items.iter().for_each(|x| hasher.push(x));
+    hasher.finish()
+}
+
+/// `items_hash` a frame at a time, the leaves are hashed in as the items are read
+#[derive(Clone)]
+pub struct ItemsHasher(tiny_keccak::Keccak);
+
+impl Default for ItemsHasher {
+    fn default() -> Self {
+        ItemsHasher(tiny_keccak::Keccak::v256())
+    }
+}
+
+impl std::fmt::Debug for ItemsHasher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ItemsHasher")
+    }
+}
+
+impl ItemsHasher {
+    /// Hash in the leaf of the next item
+    pub fn push(&mut self, item: &SignatureItem) {
+        use tiny_keccak::Hasher;
+        self.0.update(&item.leaf());
+    }
+
+    /// The hash of the items so far
+    pub fn finish(&self) -> [u8; 32] {
+        use tiny_keccak::Hasher;
+        let mut output = [0u8; 32];
+        self.0.clone().finalize(&mut output);
+        output
+    }
+}
+
+/// What the program keeps of the items it verifies a frame at a time, the frame itself dropped:
+/// the verdicts, the hash of the items, the leaves of the verified items if committed, and the
+/// tally per scheme
+#[derive(Debug, Clone, Default)]
+pub struct VerdictAccumulator {
+    /// The verdict of every item, in input order
+    pub verdicts: Vec<Result<(), SigError>>,
+    /// The leaves of the verified items in input order, empty unless collected
+    pub leaves: Vec<[u8; 32]>,
+    /// (verified, total) items of every scheme seen
+    pub tally: BTreeMap<Scheme, (usize, usize)>,
+    items: ItemsHasher,
+    collect_leaves: bool,
+    /// The item count of the stream header, the items past it fail
+    declared: usize,
+}
+
+impl VerdictAccumulator {
+    /// An empty accumulator for `items` items, collecting their leaves if `collect_leaves`
+    /// The count is the prover's, at most a frame of verdicts is reserved up front
+    pub fn new(items: usize, collect_leaves: bool) -> Self {
+        VerdictAccumulator {
+            verdicts: Vec::with_capacity(items.min(FRAME_ITEMS)),
+            collect_leaves,
+            declared: items,
+            ..Default::default()
+        }
+    }
+
+    /// Record the verdict of the next item, `SigError::MalformedFrame` in its place if the
+    /// frames hold more items than declared
+    pub fn push(&mut self, item: &SignatureItem, verdict: Result<(), SigError>) {
+        let verdict = if self.verdicts.len() < self.declared {
+            verdict
+        } else {
+            Err(SigError::MalformedFrame)
+        };
+        self.items.push(item);
+        let tally = self.tally.entry(item.scheme()).or_default();
+        tally.1 += 1;
+        if verdict.is_ok() {
+            tally.0 += 1;
+            if self.collect_leaves {
+                self.leaves.push(item.leaf());
+            }
+        }
+        self.verdicts.push(verdict);
+    }
+
+    /// Fail the declared items that were never read, after a malformed frame or when the frames
+    /// hold fewer, with `SigError::MalformedFrame`, they have no leaf so the items hash leaves
+    /// them out
+    pub fn fail_unread(&mut self) {
+        let unread = self.declared.saturating_sub(self.verdicts.len());
+        self.verdicts
+            .extend(std::iter::repeat_n(Err(SigError::MalformedFrame), unread));
+    }
+
+    /// The public values of the verdicts so far and the hash of their items, with the
+    /// authorization root if the leaves are collected
+    pub fn public_values(&self) -> PublicValuesStruct {
+        let mut public_values = PublicValuesStruct::from_verdicts(&self.verdicts);
+        public_values.items = self.items.finish().into();
+        if self.collect_leaves {
+            public_values.authorizations = merkle_root(&self.leaves).into();
+        }
+        public_values
+    }
+}
+
+// ============================================================================
+// Sample Data
+// ============================================================================
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+/// Reproducible sets of signature items for tests and benchmarks, every item a fresh key and
+/// message drawn from the seed, the items of each call in turn:
+/// `SampleGenerator::new(seed).ecdsa(10).schnorr(10).ed25519(10).build()`
+#[derive(Debug, Clone)]
+pub struct SampleGenerator {
+    seed: u64,
+    counts: Vec<(Scheme, usize)>,
+}
+
+impl SampleGenerator {
+    pub fn new(seed: u64) -> Self {
+        SampleGenerator {
+            seed,
+            counts: vec![],
+        }
+    }
+
+    /// `n` items of `scheme`, after those of the previous calls
+    pub fn scheme(mut self, scheme: Scheme, n: usize) -> Self {
+        self.counts.push((scheme, n));
+        self
+    }
+
+    pub fn ecdsa(self, n: usize) -> Self {
+        self.scheme(Scheme::EcdsaSecp256k1, n)
+    }
+
+    pub fn schnorr(self, n: usize) -> Self {
+        self.scheme(Scheme::SchnorrSecp256k1, n)
+    }
+
+    pub fn ed25519(self, n: usize) -> Self {
+        self.scheme(Scheme::Ed25519, n)
+    }
+
+    /// The items, the same for the same seed and calls, see `SignatureItem::random`
+    pub fn build(&self) -> Vec<SignatureItem> {
+        use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+        let mut rng = ChaCha20Rng::seed_from_u64(self.seed);
+        self.counts
+            .iter()
+            .flat_map(|(scheme, n)| std::iter::repeat_n(*scheme, *n))
+            .map(|scheme| SignatureItem::random(scheme, &mut rng))
+            .collect()
+    }
+}
+
+/// A 32-byte secret key, the `sign_*` helpers borrow it and it is wiped once dropped
+pub type SecretBytes = Zeroizing<[u8; 32]>;
+
+/// 32 random bytes `valid` takes as a secret key, redrawn in place until it does
+fn random_secret(valid: impl Fn(&[u8]) -> bool, rng: &mut impl CryptoRngCore) -> SecretBytes {
+    let mut secret = Zeroizing::new([0u8; 32]);
+    loop {
+        rng.fill_bytes(&mut *secret);
+        if valid(&secret[..]) {
+            return secret;
+        }
+    }
+}
+
+/// Create sample ECDSA data with a valid signature
+pub fn sample_ecdsa() -> EcdsaSecp256k1Data {
+    sign_ecdsa(
+        &Zeroizing::new([0x42u8; 32]),
+        b"Hello, ECDSA over secp256k1!",
+    )
+}
+
+/// Create ECDSA data of `message` signed by the secret key `secret_bytes`
+pub fn sign_ecdsa(secret_bytes: &SecretBytes, message: &[u8]) -> EcdsaSecp256k1Data {
+    use k256::ecdsa::{signature::Signer, SigningKey};
+
+    let signing_key = SigningKey::from_slice(&secret_bytes[..]).unwrap();
+    let verifying_key = signing_key.verifying_key();
+
+    // Hash the message
+    let message_hash = sha256(message);
+
+    // Sign the hash
+    let signature: k256::ecdsa::Signature = signing_key.sign(&message_hash);
+
+    // Get the public key in COMPRESSED format (33 bytes) - much cheaper to parse than uncompressed!
+    let public_key = verifying_key.to_encoded_point(true).as_bytes().to_vec();
+
+    let mut data = EcdsaSecp256k1Data {
+        message_hash,
+        // Signature bytes (64 bytes: r || s)
+        signature: signature.to_bytes().to_vec(),
+        public_key,
+        recovery_id: 0,
+        hashing: EcdsaHashing::Sha256,
+        message: None,
+    };
+
+    // Find the correct recovery_id by trying all possibilities
+    data.recovery_id = (0u8..4)
+        .find(|&i| {
+            let data = EcdsaSecp256k1Data {
+                recovery_id: i,
+                ..data.clone()
+            };
+            data.recovered_key().as_ref() == Some(verifying_key)
+        })
+        .unwrap();
+    data
+}
+
+/// Create sample ECDSA data of a wallet `personal_sign` (EIP-191) signature
+pub fn sample_personal_sign() -> EcdsaSecp256k1Data {
+    use k256::ecdsa::SigningKey;
+
+    // Create a deterministic signing key
+    let secret_bytes = [0x48u8; 32];
+    let signing_key = SigningKey::from_bytes(&secret_bytes.into()).unwrap();
+
+    // Sign the EIP-191 digest as the wallet does, r || s || v with v = 27 + recovery id
+    let message = b"Hello, personal_sign!";
+    let (signature, recovery_id) = signing_key
+        .sign_prehash_recoverable(&personal_sign_hash(message))
+        .unwrap();
+    let mut wallet_signature = signature.to_bytes().to_vec();
+    wallet_signature.push(27 + recovery_id.to_byte());
+
+    EcdsaSecp256k1Data::from_personal_sign(message, &wallet_signature).unwrap()
+}
+
+/// Create sample Schnorr data with a valid signature
+pub fn sample_schnorr() -> SchnorrSecp256k1Data {
+    sign_schnorr(
+        &Zeroizing::new([0x43u8; 32]),
+        b"Hello, Schnorr over secp256k1!",
+    )
+}
+
+/// Create Schnorr data of `message` signed by the secret key `secret_bytes`
+pub fn sign_schnorr(secret_bytes: &SecretBytes, message: &[u8]) -> SchnorrSecp256k1Data {
+    use k256::schnorr::{signature::Signer, SigningKey};
+
+    let signing_key = SigningKey::from_bytes(&secret_bytes[..]).unwrap();
+    let verifying_key = signing_key.verifying_key();
+
+    // Hash the message
+    let message_hash = sha256(message);
+
+    // Sign the hash
+    let signature: k256::schnorr::Signature = signing_key.sign(&message_hash);
+
+    SchnorrSecp256k1Data {
+        message_hash,
+        signature: signature.to_bytes().to_vec(),
+        // x-only public key (32 bytes)
+        public_key: verifying_key.to_bytes().into(),
+        hashing: SchnorrHashing::Sha256,
+        taproot: None,
+        message: None,
+    }
+}
+
+/// Create sample Schnorr data of a Taproot key path spend: a BIP-340 signature over a
+/// (stand-in) sighash by the output key tweaked from the internal key with `merkle_root`
+pub fn sample_taproot(merkle_root: Option<[u8; 32]>) -> SchnorrSecp256k1Data {
+    use k256::{elliptic_curve::PrimeField, schnorr::SigningKey, NonZeroScalar, Scalar};
+
+    // Create a deterministic internal key
+    let internal_key = SigningKey::from_bytes(&[0x49u8; 32]).unwrap();
+    let taproot = TaprootTweak {
+        internal_key: internal_key.verifying_key().to_bytes().into(),
+        merkle_root,
+    };
+
+    // The output secret is the internal one (of the even key) plus the tweak
+    let t = Scalar::from_repr(taproot.tweak().into()).unwrap();
+    let output_key =
+        SigningKey::from(NonZeroScalar::new(**internal_key.as_nonzero_scalar() + t).unwrap());
+
+    let message_hash = sha256(b"Hello, Taproot!");
+    let signature = output_key.sign_raw(&message_hash, &[0u8; 32]).unwrap();
+
+    SchnorrSecp256k1Data {
+        message_hash,
+        signature: signature.to_bytes().to_vec(),
+        public_key: output_key.verifying_key().to_bytes().into(),
+        hashing: SchnorrHashing::Bip340,
+        taproot: Some(taproot),
+        message: None,
+    }
+}
+
+/// Create sample FROST data with a valid signature, 2 of 3 signers
+pub fn sample_threshold_schnorr() -> ThresholdSchnorrData {
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+    // The shares and nonces are random, seeded for a deterministic sample
+    let mut rng = ChaCha20Rng::from_seed([0x4a; 32]);
+    let group_secret = Zeroizing::new([0x4au8; 32]);
+    sign_threshold_schnorr(
+        &group_secret,
+        2,
+        &[1, 3],
+        sha256(b"Hello, FROST!"),
+        &mut rng,
+    )
+}
+
+/// Create FROST data of `message_hash` signed by the `signers` (share indices from 1) of a
+/// `threshold` of n sharing of `group_secret`, by a trusted dealer
+/// The signers run the two FROST rounds: commit to nonces (d, e), then sign with the binding
+/// factor rho_i = H(i, m, commitments), R = sum(D_i + rho_i * E_i) and
+/// z_i = d_i + rho_i * e_i + lambda_i * s_i * c, c the BIP-340 challenge of R
+/// Fewer signers than `threshold` aggregate a signature that does not verify
+pub fn sign_threshold_schnorr(
+    group_secret: &SecretBytes,
+    threshold: usize,
+    signers: &[u64],
+    message_hash: [u8; 32],
+    rng: &mut impl CryptoRngCore,
+) -> ThresholdSchnorrData {
+    use k256::elliptic_curve::{ops::Reduce, point::AffineCoordinates, Field};
+    use k256::{ProjectivePoint, Scalar, U256};
+
+    // BIP-340 keys have an even y, negate the secret (so every share) if the group key is odd
+    let mut secret = Zeroizing::new(
+        *k256::SecretKey::from_slice(&group_secret[..])
+            .unwrap()
+            .to_nonzero_scalar(),
+    );
+    if bool::from(
+        (ProjectivePoint::GENERATOR * *secret)
+            .to_affine()
+            .y_is_odd(),
+    ) {
+        *secret = -*secret;
+    }
+    let group_key = (ProjectivePoint::GENERATOR * *secret).to_affine();
+
+    // Shares s_i = f(i) of f(x) = secret + a_1 * x + .. + a_{t-1} * x^{t-1}
+    let coefficients: Vec<Scalar> = (1..threshold).map(|_| Scalar::random(&mut *rng)).collect();
+    let share = |i: u64| -> Scalar {
+        let x = Scalar::from(i);
+        coefficients
+            .iter()
+            .rev()
+            .fold(Scalar::ZERO, |acc, a| (acc + a) * x)
+            + *secret
+    };
+
+    // Round one: every signer commits to its nonce pair
+    let nonces: Vec<(Scalar, Scalar)> = signers
+        .iter()
+        .map(|_| (Scalar::random(&mut *rng), Scalar::random(&mut *rng)))
+        .collect();
+    let mut commitments = message_hash.to_vec();
+    for (i, (d, e)) in signers.iter().zip(&nonces) {
+        commitments.extend_from_slice(&i.to_be_bytes());
+        commitments.extend_from_slice(&(ProjectivePoint::GENERATOR * d).to_affine().x());
+        commitments.extend_from_slice(&(ProjectivePoint::GENERATOR * e).to_affine().x());
+    }
+    let binding = |i: u64| -> Scalar {
+        let mut payload = i.to_be_bytes().to_vec();
+        payload.extend_from_slice(&commitments);
+        <Scalar as Reduce<U256>>::reduce_bytes(&tagged_hash("FROST/rho", &payload).into())
+    };
+    let mut nonces: Vec<Scalar> = signers
+        .iter()
+        .zip(&nonces)
+        .map(|(i, (d, e))| d + binding(*i) * e)
+        .collect();
+
+    // The group nonce R, negated with every signer's nonce if its y is odd
+    let mut r = nonces
+        .iter()
+        .fold(ProjectivePoint::IDENTITY, |acc, k| {
+            acc + ProjectivePoint::GENERATOR * k
+        })
+        .to_affine();
+    if bool::from(r.y_is_odd()) {
+        nonces.iter_mut().for_each(|k| *k = -*k);
+        r = (-ProjectivePoint::from(r)).to_affine();
+    }
+    let r_bytes: [u8; 32] = r.x().into();
+    let group_public_key: [u8; 32] = group_key.x().into();
+    let c = bip340_challenge(&r_bytes, &group_public_key, &message_hash);
+    let c = <Scalar as Reduce<U256>>::reduce_bytes(&c.into());
+
+    // Round two: z_i with the Lagrange coefficient of i over the signers, at 0
+    let lagrange = |i: u64| -> Scalar {
+        signers
+            .iter()
+            .filter(|j| **j != i)
+            .fold(Scalar::ONE, |acc, j| {
+                acc * Scalar::from(*j) * (Scalar::from(*j) - Scalar::from(i)).invert().unwrap()
+            })
+    };
+    let z = signers
+        .iter()
+        .zip(&nonces)
+        .fold(Scalar::ZERO, |acc, (i, k)| {
+            acc + k + lagrange(*i) * share(*i) * c
+        });
+
+    ThresholdSchnorrData {
+        message_hash,
+        signature: [r_bytes, z.to_bytes().into()].concat(),
+        group_public_key,
+    }
+}
+
+/// Create sample MuSig2 data with a valid signature, co-signed by a client and a vendor
+pub fn sample_musig2() -> MuSig2Data {
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+    // The nonces are random, seeded for a deterministic sample
+    let mut rng = ChaCha20Rng::from_seed([0x4c; 32]);
+    let secrets = [Zeroizing::new([0x4cu8; 32]), Zeroizing::new([0x4du8; 32])];
+    sign_musig2(&secrets, sha256(b"Hello, MuSig2!"), &mut rng)
+}
+
+/// Create MuSig2 data of `message_hash` co-signed by every secret key, as the signers do it
+/// in the two BIP-327 rounds: every signer commits to two nonces (k1, k2), then signs with
+/// b = H(aggregate nonce, Q, m), R = R1 + b * R2 and s_i = k1 + b * k2 + c * a_i * g * d_i,
+/// c the BIP-340 challenge and g = -1 if Q has an odd y (likewise for R, the nonces negated)
+pub fn sign_musig2(
+    secrets: &[SecretBytes],
+    message_hash: [u8; 32],
+    rng: &mut impl CryptoRngCore,
+) -> MuSig2Data {
+    use k256::elliptic_curve::Field;
+    use k256::elliptic_curve::{ops::Reduce, point::AffineCoordinates, sec1::ToEncodedPoint};
+    use k256::{ProjectivePoint, Scalar, U256};
+
+    let secrets: Zeroizing<Vec<Scalar>> = Zeroizing::new(
+        secrets
+            .iter()
+            .map(|x| {
+                *k256::SecretKey::from_slice(&x[..])
+                    .unwrap()
+                    .to_nonzero_scalar()
+            })
+            .collect(),
+    );
+    let public_keys: Vec<[u8; 33]> = secrets
+        .iter()
+        .map(|d| {
+            let point = (ProjectivePoint::GENERATOR * d).to_affine();
+            point.to_encoded_point(true).as_bytes().try_into().unwrap()
+        })
+        .collect();
+    let aggregate = musig2_aggregate(&public_keys).unwrap();
+    let aggregate_key: [u8; 32] = aggregate.x().into();
+    let g = if bool::from(aggregate.y_is_odd()) {
+        -Scalar::ONE
+    } else {
+        Scalar::ONE
+    };
+
+    // Round one: every signer's nonce pair, summed into the aggregate nonce
+    let nonces: Vec<(Scalar, Scalar)> = secrets
+        .iter()
+        .map(|_| (Scalar::random(&mut *rng), Scalar::random(&mut *rng)))
+        .collect();
+    let r1 = nonces
+        .iter()
+        .fold(ProjectivePoint::IDENTITY, |acc, (k1, _)| {
+            acc + ProjectivePoint::GENERATOR * k1
+        });
+    let r2 = nonces
+        .iter()
+        .fold(ProjectivePoint::IDENTITY, |acc, (_, k2)| {
+            acc + ProjectivePoint::GENERATOR * k2
+        });
+    let mut payload = r1.to_affine().to_encoded_point(true).as_bytes().to_vec();
+    payload.extend_from_slice(r2.to_affine().to_encoded_point(true).as_bytes());
+    payload.extend_from_slice(&aggregate_key);
+    payload.extend_from_slice(&message_hash);
+    let b =
+        <Scalar as Reduce<U256>>::reduce_bytes(&tagged_hash("MuSig/noncecoef", &payload).into());
+
+    // The final nonce, negated with every signer's nonce if its y is odd
+    let r = (r1 + r2 * b).to_affine();
+    let parity = if bool::from(r.y_is_odd()) {
+        -Scalar::ONE
+    } else {
+        Scalar::ONE
+    };
+    let r_bytes: [u8; 32] = r.x().into();
+    let c = bip340_challenge(&r_bytes, &aggregate_key, &message_hash);
+    let c = <Scalar as Reduce<U256>>::reduce_bytes(&c.into());
+
+    // Round two: the partial signatures, summed
+    let s = secrets
+        .iter()
+        .zip(&nonces)
+        .zip(musig2_coefficients(&public_keys))
+        .fold(Scalar::ZERO, |acc, ((d, (k1, k2)), a)| {
+            acc + parity * (k1 + b * k2) + c * a * g * d
+        });
+
+    MuSig2Data {
+        message_hash,
+        signature: [r_bytes, s.to_bytes().into()].concat(),
+        public_keys: public_keys.concat(),
+    }
+}
+
+pub fn sample_cosigned_schnorr() -> CosignedSchnorrData {
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+    // The nonces are random, seeded for a deterministic sample
+    let mut rng = ChaCha20Rng::from_seed([0x53; 32]);
+    let secrets = [Zeroizing::new([0x53u8; 32]), Zeroizing::new([0x54u8; 32])];
+    sign_cosigned_schnorr(&secrets, sha256(b"Hello, voucher!"), &mut rng)
+}
+
+/// Create co-signed data of `message_hash` by every secret key, each with its proof of
+/// possession: every signer commits to a nonce k_i, then signs with R = sum(k_i * G) and
+/// s_i = k_i + c * g * d_i, c the BIP-340 challenge under the sum Q of the keys and g = -1 if
+/// Q has an odd y (likewise for R, the nonces negated)
+pub fn sign_cosigned_schnorr(
+    secrets: &[SecretBytes],
+    message_hash: [u8; 32],
+    rng: &mut impl CryptoRngCore,
+) -> CosignedSchnorrData {
+    use k256::elliptic_curve::{ops::Reduce, point::AffineCoordinates, Field};
+    use k256::{ProjectivePoint, Scalar, U256};
+
+    let (public_keys, proofs): (Vec<_>, Vec<_>) = secrets.iter().map(prove_possession).unzip();
+    let aggregate = aggregate_public_keys(&public_keys, &proofs).unwrap();
+    let aggregate_key: [u8; 32] = aggregate[1..].try_into().unwrap();
+    let g = if aggregate[0] == 0x03 {
+        -Scalar::ONE
+    } else {
+        Scalar::ONE
+    };
+
+    // Round one: every signer's nonce, summed into the final one
+    let nonces: Vec<Scalar> = secrets.iter().map(|_| Scalar::random(&mut *rng)).collect();
+    let r = nonces
+        .iter()
+        .fold(ProjectivePoint::IDENTITY, |acc, k| {
+            acc + ProjectivePoint::GENERATOR * k
+        })
+        .to_affine();
+    let parity = if bool::from(r.y_is_odd()) {
+        -Scalar::ONE
+    } else {
+        Scalar::ONE
+    };
+    let r_bytes: [u8; 32] = r.x().into();
+    let c = bip340_challenge(&r_bytes, &aggregate_key, &message_hash);
+    let c = <Scalar as Reduce<U256>>::reduce_bytes(&c.into());
+
+    // Round two: the partial signatures, summed
+    let s = secrets
+        .iter()
+        .zip(&nonces)
+        .fold(Scalar::ZERO, |acc, (d, k)| {
+            let d = Zeroizing::new(
+                *k256::SecretKey::from_slice(&d[..])
+                    .unwrap()
+                    .to_nonzero_scalar(),
+            );
+            acc + parity * k + c * g * *d
+        });
+
+    CosignedSchnorrData {
+        message_hash,
+        signature: [r_bytes, s.to_bytes().into()].concat(),
+        public_keys: public_keys.concat(),
+        possession_proofs: proofs.concat(),
+    }
+}
+
+/// Create a sample EIP-1559 transaction with a valid signature, a transfer with calldata
+pub fn sample_eth_tx() -> EthTxData {
+    let value = 1_000_000_000_000_000_000;
+    let secret_bytes = Zeroizing::new([0x4eu8; 32]);
+    sign_eth_tx(
+        &secret_bytes,
+        EthTxKind::Eip1559,
+        1,
+        0,
+        [0x35; 20],
+        value,
+        b"Hello, Ethereum!",
+    )
+}
+
+/// Create a `kind` transaction on chain `chain_id` of `value` wei and calldata `data` to `to`,
+/// signed by the secret key `secret_bytes`, as a wallet does. The fees are 20 gwei (1 gwei to
+/// the validator) and the gas what the transfer and its calldata take at most
+pub fn sign_eth_tx(
+    secret_bytes: &SecretBytes,
+    kind: EthTxKind,
+    chain_id: u64,
+    nonce: u64,
+    to: [u8; 20],
+    value: u128,
+    data: &[u8],
+) -> EthTxData {
+    use k256::ecdsa::SigningKey;
+
+    let signing_key = SigningKey::from_slice(&secret_bytes[..]).unwrap();
+    let (gwei, gas) = (1_000_000_000, 21_000 + 16 * data.len() as u128);
+    let mut fields = vec![];
+    if kind == EthTxKind::Eip1559 {
+        fields.push(rlp::encode_uint(chain_id as u128));
+    }
+    fields.push(rlp::encode_uint(nonce as u128));
+    match kind {
+        EthTxKind::Legacy => fields.push(rlp::encode_uint(20 * gwei)),
+        EthTxKind::Eip1559 => fields.extend([rlp::encode_uint(gwei), rlp::encode_uint(20 * gwei)]),
+    }
+    fields.push(rlp::encode_uint(gas));
+    fields.extend([
+        rlp::encode_bytes(&to),
+        rlp::encode_uint(value),
+        rlp::encode_bytes(data),
+    ]);
+    if kind == EthTxKind::Eip1559 {
+        // The access list, empty
+        fields.push(rlp::encode_list(&[]));
+    }
+
+    // EIP-155 signs the chain id in place of the signature, EIP-1559 signs the type first
+    let sighash = match kind {
+        EthTxKind::Legacy => {
+            let chain = [
+                rlp::encode_uint(chain_id as u128),
+                rlp::encode_uint(0),
+                rlp::encode_uint(0),
+            ];
+            keccak256(&rlp::encode_list(&[&fields[..], &chain].concat()))
+        }
+        EthTxKind::Eip1559 => {
+            keccak256(&[&[EthTxData::EIP1559][..], &rlp::encode_list(&fields)].concat())
+        }
+    };
+    let (signature, recovery_id) = signing_key.sign_prehash_recoverable(&sighash).unwrap();
+    let y = recovery_id.to_byte() as u128;
+    let v = match kind {
+        EthTxKind::Legacy => chain_id as u128 * 2 + 35 + y,
+        EthTxKind::Eip1559 => y,
+    };
+    let (r, s) = (signature.r().to_bytes(), signature.s().to_bytes());
+    let word = |x: &[u8]| rlp::encode_bytes(&x[x.iter().take_while(|x| **x == 0).count()..]);
+    fields.extend([rlp::encode_uint(v), word(&r), word(&s)]);
+
+    let mut raw = rlp::encode_list(&fields);
+    if kind == EthTxKind::Eip1559 {
+        raw.insert(0, EthTxData::EIP1559);
+    }
+    EthTxData {
+        raw,
+        sender: eth_address(signing_key.verifying_key()),
+    }
+}
+
+/// Create a sample Bitcoin transaction spending a `kind` output with a valid signature
+pub fn sample_bitcoin_tx(kind: BitcoinOutputKind) -> BitcoinTxData {
+    sign_bitcoin_tx(&Zeroizing::new([0x4fu8; 32]), kind, b"Hello, Bitcoin!")
+}
+
+/// Create a transaction spending a `kind` output of 100,000 sats of the secret key
+/// `secret_bytes`, 99,000 back to it and `memo` (at most 75 bytes) in an OP_RETURN output,
+/// signed as a wallet does: SIGHASH_ALL for P2WPKH, SIGHASH_DEFAULT for Taproot
+pub fn sign_bitcoin_tx(
+    secret_bytes: &SecretBytes,
+    kind: BitcoinOutputKind,
+    memo: &[u8],
+) -> BitcoinTxData {
+    use bitcoin_hashes::{hash160, Hash};
+    use k256::{elliptic_curve::PrimeField, NonZeroScalar, Scalar};
+
+    assert!(memo.len() <= 75, "memo longer than a single push");
+    let signing_key = k256::ecdsa::SigningKey::from_slice(&secret_bytes[..]).unwrap();
+    let internal_key = k256::schnorr::SigningKey::from_bytes(&secret_bytes[..]).unwrap();
+    let tweak = TaprootTweak {
+        internal_key: internal_key.verifying_key().to_bytes().into(),
+        merkle_root: None,
+    };
+    let script_pubkey = match kind {
+        BitcoinOutputKind::P2wpkh => {
+            let public_key = signing_key.verifying_key().to_encoded_point(true);
+            [
+                &[0x00, 0x14][..],
+                &hash160::Hash::hash(public_key.as_bytes()).to_byte_array(),
+            ]
+            .concat()
+        }
+        BitcoinOutputKind::P2tr => [&[0x51, 0x20][..], &tweak.output_key().unwrap()].concat(),
+    };
+    let prevouts = vec![BitcoinPrevout {
+        amount: 100_000,
+        script_pubkey: script_pubkey.clone(),
+    }];
+
+    // One input, its outpoint made up from the memo, without a script_sig
+    let version = 2u32.to_le_bytes();
+    let input = [
+        &sha256(memo)[..],
+        &0u32.to_le_bytes(),
+        &[0x00],
+        &u32::MAX.to_le_bytes(),
+    ]
+    .concat();
+    let outputs = [
+        [
+            &99_000u64.to_le_bytes()[..],
+            &bitcoin::var_bytes(&script_pubkey),
+        ]
+        .concat(),
+        // OP_RETURN <memo>
+        [
+            &0u64.to_le_bytes()[..],
+            &bitcoin::var_bytes(&[&[0x6a, memo.len() as u8], memo].concat()),
+        ]
+        .concat(),
+    ];
+    let lock_time = 0u32.to_le_bytes();
+    let unsigned = [
+        &version[..],
+        &bitcoin::compact_size(1),
+        &input,
+        &bitcoin::compact_size(outputs.len()),
+        &outputs.concat(),
+        &lock_time,
+    ]
+    .concat();
+    let tx = bitcoin::parse(&unsigned).unwrap();
+
+    let witness = match kind {
+        BitcoinOutputKind::P2wpkh => {
+            let script_code = [&[0x76, 0xa9, 0x14], &script_pubkey[2..], &[0x88, 0xac]].concat();
+            let hash_type = BitcoinTxData::SIGHASH_ALL;
+            let sighash = segwit_v0_sighash(&tx, 0, &script_code, 100_000, hash_type);
+            let (signature, _) = signing_key.sign_prehash_recoverable(&sighash).unwrap();
+            let signature = [signature.to_der().as_bytes(), &[hash_type]].concat();
+            let public_key = signing_key.verifying_key().to_encoded_point(true);
+            vec![signature, public_key.as_bytes().to_vec()]
+        }
+        BitcoinOutputKind::P2tr => {
+            // The output secret is the internal one (of the even key) plus the tweak
+            let t = Scalar::from_repr(tweak.tweak().into()).unwrap();
+            let output_key = NonZeroScalar::new(**internal_key.as_nonzero_scalar() + t).unwrap();
+            let output_key = k256::schnorr::SigningKey::from(output_key);
+            let sighash =
+                taproot_sighash(&tx, &prevouts, 0, BitcoinTxData::SIGHASH_DEFAULT, None).unwrap();
+            vec![output_key
+                .sign_raw(&sighash, &[0u8; 32])
+                .unwrap()
+                .to_bytes()
+                .to_vec()]
+        }
+    };
+
+    // BIP-144: the marker and flag after the version, the witness before the lock time
+    let witness: Vec<_> = [bitcoin::compact_size(witness.len())]
+        .into_iter()
+        .chain(witness.iter().map(|x| bitcoin::var_bytes(x)))
+        .collect();
+    let (body, witness) = (&unsigned[4..unsigned.len() - 4], witness.concat());
+    let tx = [&version[..], &[0x00, 0x01], body, &witness, &lock_time].concat();
+    BitcoinTxData {
+        tx,
+        input: 0,
+        prevouts,
+    }
+}
+
+/// Create sample P-256 ECDSA data with a valid signature
+pub fn sample_p256() -> P256EcdsaData {
+    sign_p256(&Zeroizing::new([0x45u8; 32]), b"Hello, ECDSA over P-256!")
+}
+
+/// Create P-256 ECDSA data of `message` signed by the secret key `secret_bytes`
+pub fn sign_p256(secret_bytes: &SecretBytes, message: &[u8]) -> P256EcdsaData {
+    use p256::ecdsa::SigningKey;
+
+    let signing_key = SigningKey::from_slice(&secret_bytes[..]).unwrap();
+
+    // Hash the message
+    let message_hash = sha256(message);
+
+    // Sign the hash as the digest, the recovery id comes with it
+    // P-256 signing keeps a high s, negating s flips the parity of R the recovery id names
+    let (mut signature, mut recovery_id) =
+        signing_key.sign_prehash_recoverable(&message_hash).unwrap();
+    if let Some(normalized) = signature.normalize_s() {
+        signature = normalized;
+        recovery_id = ecdsa::RecoveryId::from_byte(recovery_id.to_byte() ^ 1).unwrap();
+    }
+
+    P256EcdsaData {
+        message_hash,
+        signature: signature.to_bytes().to_vec(),
+        public_key: signing_key
+            .verifying_key()
+            .to_encoded_point(true)
+            .as_bytes()
+            .to_vec(),
+        recovery_id: recovery_id.to_byte(),
+    }
+}
+
+/// Create a sample WebAuthn assertion with a valid signature, user present and verified
+pub fn sample_webauthn() -> WebAuthnAssertionData {
+    let client_data_json = br#"{"type":"webauthn.get","challenge":"SGVsbG8sIFdlYkF1dGhuIQ","origin":"https://example.com"}"#;
+    sign_webauthn(&Zeroizing::new([0x46u8; 32]), client_data_json)
+}
+
+/// Create a WebAuthn assertion of `client_data_json`, the relying party's challenge is what
+/// the passkey authorizes, signed by the credential key `secret_bytes`
+pub fn sign_webauthn(secret_bytes: &SecretBytes, client_data_json: &[u8]) -> WebAuthnAssertionData {
+    use p256::ecdsa::{signature::hazmat::PrehashSigner, Signature, SigningKey};
+
+    let signing_key = SigningKey::from_slice(&secret_bytes[..]).unwrap();
+
+    // rpIdHash || flags (UP | UV) || signCount
+    let mut authenticator_data = sha256(b"example.com").to_vec();
+    authenticator_data.push(WebAuthnAssertionData::FLAG_UP | WebAuthnAssertionData::FLAG_UV);
+    authenticator_data.extend_from_slice(&1u32.to_be_bytes());
+
+    let client_data_hash = sha256(client_data_json);
+
+    let mut assertion = WebAuthnAssertionData {
+        authenticator_data,
+        client_data_hash,
+        signature: vec![],
+        public_key: signing_key
+            .verifying_key()
+            .to_encoded_point(true)
+            .as_bytes()
+            .to_vec(),
+        require_user_verification: true,
+    };
+    let signature: Signature = signing_key
+        .sign_prehash(&sha256(&assertion.signed_payload()))
+        .unwrap();
+    assertion.signature = signature.to_der().as_bytes().to_vec();
+    assertion
+}
+
+/// Create sample RSA data with a valid signature, from a fixed 2048-bit test key
+pub fn sample_rsa(padding: RsaPadding) -> RsaSignatureData {
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+    // The PSS salt is random, seeded for a deterministic sample
+    sign_rsa(
+        b"Hello, RSA!",
+        padding,
+        &mut ChaCha20Rng::from_seed([0x47; 32]),
+    )
+}
+
+/// Create RSA data of `message` signed by the fixed 2048-bit test key, `rng` draws the PSS salt
+/// RSA keys are too slow to generate per signature, the message is what varies
+pub fn sign_rsa(
+    message: &[u8],
+    padding: RsaPadding,
+    rng: &mut impl CryptoRngCore,
+) -> RsaSignatureData {
+    use rsa::{
+        pkcs1::{DecodeRsaPrivateKey, EncodeRsaPublicKey},
+        Pkcs1v15Sign, Pss, RsaPrivateKey,
+    };
+    use sha2::Sha256;
+
+    // Not a secret, only signs the samples
+    let private_key = RsaPrivateKey::from_pkcs1_pem(include_str!("sample_rsa.pem")).unwrap();
+
+    let hashed = sha256(message);
+
+    let signature = match padding {
+        RsaPadding::Pkcs1v15 => private_key.sign(Pkcs1v15Sign::new::<Sha256>(), &hashed),
+        RsaPadding::Pss => private_key.sign_with_rng(rng, Pss::new::<Sha256>(), &hashed),
+    }
+    .unwrap();
+
+    RsaSignatureData {
+        message: message.to_vec(),
+        signature,
+        public_key: private_key
+            .to_public_key()
+            .to_pkcs1_der()
+            .unwrap()
+            .into_vec(),
+        padding,
+    }
+}
+
+/// Create sample Ed25519 data with a valid signature
+pub fn sample_ed25519() -> Ed25519Data {
+    sign_ed25519(&Zeroizing::new([0x44u8; 32]), b"Hello, Ed25519!")
+}
+
+/// Create Ed25519 data of `message` signed by the secret `secret_bytes`, signed with
+/// curve25519-dalek primitives
+pub fn sign_ed25519(secret_bytes: &SecretBytes, message: &[u8]) -> Ed25519Data {
+    sign_ed25519_with(secret_bytes, message, Ed25519Algorithm::Pure)
+}
+
+/// Create Ed25519 data of `message` signed by the secret `secret_bytes` under `algorithm`, ex.
+/// Ed25519ph as an HSM signs, panics on a context string the algorithm does not take
+pub fn sign_ed25519_with(
+    secret_bytes: &SecretBytes,
+    message: &[u8],
+    algorithm: Ed25519Algorithm,
+) -> Ed25519Data {
+    use curve25519_dalek::{constants::ED25519_BASEPOINT_TABLE, scalar::Scalar};
+    use sha2::{Digest, Sha512};
+
+    // The secret scalar
+    let secret_scalar = Zeroizing::new(Scalar::from_bytes_mod_order(**secret_bytes));
+
+    // Compute public key A = secret * B
+    let public_key = (ED25519_BASEPOINT_TABLE * &*secret_scalar)
+        .compress()
+        .to_bytes();
+    let mut data = Ed25519Data {
+        message: message.to_vec(),
+        signature: vec![],
+        public_key,
+        mode: Ed25519Mode::Strict,
+        algorithm,
+    };
+
+    // Generate nonce r = H(secret || dom2 || PH(M)) mod L
+    let mut nonce_hasher = Sha512::new();
+    nonce_hasher.update(&secret_bytes[..]);
+    nonce_hasher.update(data.dom2().unwrap());
+    nonce_hasher.update(data.prehashed());
+    let r = Scalar::from_bytes_mod_order_wide(&nonce_hasher.finalize().into());
+
+    // Compute R = r * B
+    let r_bytes = (ED25519_BASEPOINT_TABLE * &r).compress().to_bytes();
+
+    // Compute challenge H(dom2 || R || A || PH(M))
+    let h = Scalar::from_bytes_mod_order_wide(&data.challenge(r_bytes).unwrap());
+
+    // Compute s = r + h * secret (mod L)
+    let s = r + (h * *secret_scalar);
+
+    // Signature is (R, s)
+    data.signature = [r_bytes, s.to_bytes()].concat();
+    data
+}
+
+pub fn sample_session_auth() -> SessionAuthData {
+    let (client, gateway) = (Zeroizing::new([0x50u8; 32]), Zeroizing::new([0x51u8; 32]));
+    sign_session_auth(
+        &client,
+        &gateway,
+        &Zeroizing::new([0x52u8; 32]),
+        b"ddm session handshake",
+    )
+}
+
+/// Create the session of a client of ephemeral secret `client_secret` with a gateway of
+/// ephemeral secret `gateway_secret`, the handshake of `transcript` signed by the gateway's
+/// identity secret `identity_secret`
+pub fn sign_session_auth(
+    client_secret: &SecretBytes,
+    gateway_secret: &SecretBytes,
+    identity_secret: &SecretBytes,
+    transcript: &[u8],
+) -> SessionAuthData {
+    use curve25519_dalek::montgomery::MontgomeryPoint;
+
+    let mut session = SessionAuthData {
+        client_public_key: MontgomeryPoint::mul_base_clamped(**client_secret).0,
+        gateway_public_key: MontgomeryPoint::mul_base_clamped(**gateway_secret).0,
+        identity_key: [0u8; 32],
+        transcript: transcript.to_vec(),
+        signature: Vec::new(),
+    };
+    let signed = sign_ed25519(identity_secret, &session.handshake_hash());
+    session.identity_key = signed.public_key;
+    session.signature = signed.signature;
+    session
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One sample of every scheme
+    fn sample_items() -> Vec<SignatureItem> {
+        Scheme::ALL.into_iter().map(SignatureItem::sample).collect()
+    }
+
+    #[test]
+    fn test_ecdsa_data() {
+        let ecdsa = sample_ecdsa();
+        assert_eq!(ecdsa.message_hash.len(), 32);
+        assert_eq!(ecdsa.signature.len(), 64);
+        assert_eq!(ecdsa.public_key.len(), 33);
+        assert!(matches!(ecdsa.public_key[0], 0x02 | 0x03)); // Compressed format marker
+
+        // Test verification and recovery
+        assert!(ecdsa.verify());
+        assert_eq!(
+            ecdsa.recover().map(|x| x.to_vec()),
+            Some(ecdsa.public_key.clone())
+        );
+    }
+
+    #[test]
+    fn test_personal_sign() {
+        // The prefix counts the bytes of the message in decimal
+        assert_eq!(
+            personal_sign_payload(b"hello"),
+            b"\x19Ethereum Signed Message:\n5hello"
+        );
+        // As returned by ethers `hashMessage("hello")`
+        assert_eq!(
+            hex::encode(personal_sign_hash(b"hello")),
+            "50b2c43fd39106bafbba0da34fc430e1f91e3c96ea2acee2bc34119f92b37750"
+        );
+
+        let data = sample_personal_sign();
+        assert_eq!(data.hashing, EcdsaHashing::Prehashed);
+        assert!(data.verify());
+        assert_eq!(
+            data.recover().map(|x| x.to_vec()),
+            Some(data.public_key.clone())
+        );
+        assert!(data.address().is_some());
+        assert_eq!(data.recover_address(), data.address());
+
+        // v as 0/1 is accepted too
+        let mut wallet_signature = data.signature.clone();
+        wallet_signature.push(data.recovery_id);
+        let same =
+            EcdsaSecp256k1Data::from_personal_sign(b"Hello, personal_sign!", &wallet_signature);
+        assert_eq!(same, Some(data.clone()));
+
+        // Another message recovers another signer
+        let other =
+            EcdsaSecp256k1Data::from_personal_sign(b"Hello, world!", &wallet_signature).unwrap();
+        assert_ne!(other.recover_address(), data.address());
+        assert!(EcdsaSecp256k1Data::from_personal_sign(b"", &wallet_signature[..64]).is_none());
+
+        // The well known address of the secret key 1
+        let mut secret_bytes = [0u8; 32];
+        secret_bytes[31] = 1;
+        let signing_key = k256::ecdsa::SigningKey::from_bytes(&secret_bytes.into()).unwrap();
+        let public_key = signing_key
+            .verifying_key()
+            .to_encoded_point(true)
+            .as_bytes()
+            .to_vec();
+        let data = EcdsaSecp256k1Data { public_key, ..data };
+        assert_eq!(
+            data.address().map(hex::encode),
+            Some("7e5f4552091a69125d5dfcb7b8c2659029395bdf".to_string())
+        );
+    }
+
+    #[test]
+    fn test_eip712() {
+        use Eip712Value::{Address, String, Word};
+
+        // The `Mail` example of the EIP-712 spec, signed by the key keccak256("cow")
+        let address = |x: &str| -> [u8; 20] { hex::decode(x).unwrap().try_into().unwrap() };
+        let domain = Eip712Domain {
+            name: Some("Ether Mail".into()),
+            version: Some("1".into()),
+            chain_id: Some(1),
+            verifying_contract: Some(address("cccccccccccccccccccccccccccccccccccccccc")),
+            salt: None,
+        };
+        assert_eq!(
+            hex::encode(domain.separator()),
+            "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+        );
+
+        let person = "Person(string name,address wallet)";
+        let cow = address("cd2a3d9f938e13cd947ec05abc7fe734df8dd826");
+        let from = eip712_struct_hash(person, &[String("Cow"), Address(cow)]);
+        let bob = address("bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb");
+        let to = eip712_struct_hash(person, &[String("Bob"), Address(bob)]);
+        let mail = format!("Mail(Person from,Person to,string contents){}", person);
+        let struct_hash = eip712_struct_hash(&mail, &[Word(from), Word(to), String("Hello, Bob!")]);
+        assert_eq!(
+            hex::encode(struct_hash),
+            "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e"
+        );
+        assert_eq!(
+            hex::encode(eip712_hash(&domain, &struct_hash)),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+
+        let signature = hex::decode(concat!(
+            "4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d",
+            "07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562",
+            "1c"
+        ))
+        .unwrap();
+        let data = EcdsaSecp256k1Data::from_eip712(&domain, struct_hash, &signature).unwrap();
+        assert!(data.verify());
+        assert_eq!(data.recover_address(), Some(cow));
+
+        // Under another domain the signature recovers another signer
+        let other = Eip712Domain {
+            chain_id: Some(2),
+            ..domain
+        };
+        let data = EcdsaSecp256k1Data::from_eip712(&other, struct_hash, &signature).unwrap();
+        assert_ne!(data.recover_address(), Some(cow));
+    }
+
+    #[test]
+    fn test_schnorr_data() {
+        let schnorr = sample_schnorr();
+        assert_eq!(schnorr.message_hash.len(), 32);
+        assert_eq!(schnorr.signature.len(), 64);
+        assert_eq!(schnorr.public_key.len(), 32);
+
+        // Test verification
+        assert!(schnorr.verify());
+    }
+
+    #[test]
+    fn test_raw_message() {
+        // The message hashed in the guest is the one signed
+        let message = b"Hello, ECDSA on secp256k1!";
+        let ecdsa = sign_ecdsa(&Zeroizing::new([0x42; 32]), message)
+            .with_message(message, MessageDigest::Sha256);
+        assert_eq!(ecdsa.try_verify(), Ok(()));
+        assert_eq!(pure::ecdsa_secp256k1(&ecdsa), Ok(()));
+        let schnorr = sign_schnorr(&Zeroizing::new([0x43; 32]), message)
+            .with_message(message, MessageDigest::Sha256);
+        assert_eq!(schnorr.try_verify(), Ok(()));
+        assert_eq!(pure::schnorr_secp256k1(&schnorr), Ok(()));
+
+        // An EIP-191 signature carries the payload, its keccak is the signed hash
+        let payload = personal_sign_payload(b"Hello, personal_sign!");
+        let wallet = sample_personal_sign().with_message(&payload, MessageDigest::Keccak256);
+        assert_eq!(wallet.try_verify(), Ok(()));
+        let wrong_digest = sample_personal_sign().with_message(&payload, MessageDigest::Sha256);
+        assert_eq!(wrong_digest.try_verify(), Err(SigError::MessageMismatch));
+
+        // Another message fails before the signature is checked, even with a valid signature
+        let other = ecdsa
+            .clone()
+            .with_message(b"Hello, other message!", MessageDigest::Sha256);
+        assert_eq!(other.try_verify(), Err(SigError::MessageMismatch));
+        assert_eq!(
+            pure::ecdsa_secp256k1(&other),
+            Err(SigError::MessageMismatch)
+        );
+        let other = schnorr
+            .clone()
+            .with_message(b"Hello, other message!", MessageDigest::Sha256);
+        assert_eq!(other.try_verify(), Err(SigError::MessageMismatch));
+        assert_eq!(
+            pure::schnorr_secp256k1(&other),
+            Err(SigError::MessageMismatch)
+        );
+
+        let items = [
+            SignatureItem::EcdsaSecp256k1(ecdsa),
+            SignatureItem::SchnorrSecp256k1(schnorr),
+        ];
+        assert_eq!(
+            items_from_bincode(&items_to_bincode(&items).unwrap()).unwrap(),
+            items
+        );
+        assert_eq!(
+            SigError::from_code(SigError::MessageMismatch.code()),
+            Some(SigError::MessageMismatch)
+        );
+    }
+
+    #[test]
+    fn test_bip340_taproot() {
+        use k256::elliptic_curve::{ops::Reduce, point::AffineCoordinates};
+        use k256::{schnorr::VerifyingKey, ProjectivePoint, Scalar, U256};
+
+        let decode = |x: &str| -> [u8; 32] { hex::decode(x).unwrap().try_into().unwrap() };
+
+        // Test vector 1 of BIP-340
+        let bip340 = SchnorrSecp256k1Data {
+            message_hash: decode(
+                "243f6a8885a308d313198a2e03707344a4093822299f31d0082efa98ec4e6c89",
+            ),
+            signature: hex::decode(concat!(
+                "6896bd60eeae296db48a229ff71dfe071bde413e6d43f917dc8dcf8c78de3341",
+                "8906d11ac976abccb20b091292bff4ea897efcb639ea871cfa95f6de339e4b0a"
+            ))
+            .unwrap(),
+            public_key: decode("dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba659"),
+            hashing: SchnorrHashing::Bip340,
+            taproot: None,
+            message: None,
+        };
+        assert!(bip340.verify());
+        // Not over SHA-256 of the message
+        let sha256_hashed = SchnorrSecp256k1Data {
+            hashing: SchnorrHashing::Sha256,
+            ..bip340.clone()
+        };
+        assert_eq!(sha256_hashed.try_verify(), Err(SigError::InvalidSignature));
+
+        // The challenge is the one the signature satisfies: x(s * G - e * P) == r
+        let r: [u8; 32] = bip340.signature[..32].try_into().unwrap();
+        let s: [u8; 32] = bip340.signature[32..].try_into().unwrap();
+        let e = bip340_challenge(&r, &bip340.public_key, &bip340.message_hash);
+        let e = <Scalar as Reduce<U256>>::reduce_bytes(&e.into());
+        let s = <Scalar as Reduce<U256>>::reduce_bytes(&s.into());
+        let p = ProjectivePoint::from(
+            *VerifyingKey::from_bytes(&bip340.public_key)
+                .unwrap()
+                .as_affine(),
+        );
+        let nonce: [u8; 32] = (ProjectivePoint::GENERATOR * s - p * e)
+            .to_affine()
+            .x()
+            .into();
+        assert_eq!(nonce, r);
+
+        // Output keys of the BIP-341 wallet test vectors
+        let key_path = TaprootTweak {
+            internal_key: decode(
+                "d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d",
+            ),
+            merkle_root: None,
+        };
+        assert_eq!(
+            key_path.output_key().map(hex::encode),
+            Ok("53a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343".to_string())
+        );
+        let script_path = TaprootTweak {
+            internal_key: decode(
+                "187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27",
+            ),
+            merkle_root: Some(decode(
+                "5b75adecf53548f3ec6ad7d78383bf84cc57b55a3127c72b9a2481752dd88b21",
+            )),
+        };
+        assert_eq!(
+            script_path.output_key().map(hex::encode),
+            Ok("147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3".to_string())
+        );
+
+        // A spend verifies under the output key it is tweaked to, not under another tree
+        for merkle_root in [None, Some([0x5a; 32])] {
+            let spend = sample_taproot(merkle_root);
+            assert!(spend.verify());
+            let mut bad = spend.clone();
+            let other_tree = TaprootTweak {
+                merkle_root: Some([0x5b; 32]),
+                ..spend.taproot.clone().unwrap()
+            };
+            bad.taproot = Some(other_tree);
+            assert_eq!(bad.try_verify(), Err(SigError::TweakMismatch));
+        }
+    }
+
+    #[test]
+    fn test_threshold_schnorr() {
+        use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+        let frost = sample_threshold_schnorr();
+        assert_eq!(frost.signature.len(), 64);
+        assert!(frost.verify());
+
+        // Any 3 of 5 signers sign under the same group key, 2 of them can't
+        let mut rng = ChaCha20Rng::seed_from_u64(5);
+        let message_hash = sha256(b"voucher");
+        let sign = |signers: &[u64], rng: &mut ChaCha20Rng| {
+            sign_threshold_schnorr(&Zeroizing::new([0x4bu8; 32]), 3, signers, message_hash, rng)
+        };
+        let (a, b) = (sign(&[1, 2, 3], &mut rng), sign(&[2, 4, 5], &mut rng));
+        assert!(a.verify() && b.verify());
+        assert_eq!(a.group_public_key, b.group_public_key);
+        assert_ne!(a.signature, b.signature);
+        assert_eq!(
+            sign(&[1, 4], &mut rng).try_verify(),
+            Err(SigError::InvalidSignature)
+        );
+
+        let mut bad = frost.clone();
+        bad.message_hash[0] ^= 1;
+        assert_eq!(bad.try_verify(), Err(SigError::InvalidSignature));
+    }
+
+    #[test]
+    fn test_musig2() {
+        let key = |x: &str| -> [u8; 33] { hex::decode(x).unwrap().try_into().unwrap() };
+        let agg = |keys: &[[u8; 33]]| musig2_key_agg(keys).map(hex::encode);
+
+        // Key aggregation vectors of BIP-327
+        let x = [
+            key("02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9"),
+            key("03dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba659"),
+            key("023590a94e768f8e1815c2f24b4d80a8e3149316c3518ce7b7ad338368d038ca66"),
+        ];
+        assert_eq!(
+            agg(&[x[0], x[1], x[2]]),
+            Ok("90539eede565f5d054f32cc0c220126889ed1e5d193baf15aef344fe59d4610c".to_string())
+        );
+        assert_eq!(
+            agg(&[x[2], x[1], x[0]]),
+            Ok("6204de8b083426dc6eaf9502d27024d53fc826bf7d2012148a0575435df54b2b".to_string())
+        );
+        assert_eq!(
+            agg(&[x[0], x[0], x[0]]),
+            Ok("b436e3bad62b8cd409969a224731c193d051162d8c5ae8b109306127da3aa935".to_string())
+        );
+        assert_eq!(
+            agg(&[x[0], x[0], x[1], x[1]]),
+            Ok("69bc22bfa5d106306e48a20679de1d7389386124d07571d0d872686028c26a3e".to_string())
+        );
+        assert_eq!(agg(&[]), Err(SigError::MalformedKey));
+
+        let musig2 = sample_musig2();
+        let keys = musig2.keys().unwrap();
+        assert_eq!(keys.len(), 2);
+        assert!(musig2.verify());
+
+        // The signature binds the signers: without one, or in another order, it fails
+        let mut bad = musig2.clone();
+        bad.public_keys = keys[0].to_vec();
+        assert_eq!(bad.try_verify(), Err(SigError::InvalidSignature));
+        bad.public_keys = [keys[1], keys[0]].concat();
+        assert_eq!(bad.try_verify(), Err(SigError::InvalidSignature));
+        bad.public_keys = [keys[0], keys[1]].concat();
+        bad.public_keys[0] = 0x08;
+        assert_eq!(bad.try_verify(), Err(SigError::MalformedKey));
+        bad.public_keys.truncate(40);
+        assert_eq!(bad.try_verify(), Err(SigError::MalformedKey));
+    }
+
+    #[test]
+    fn test_cosigned_schnorr() {
+        use k256::elliptic_curve::{sec1::ToEncodedPoint, PrimeField};
+        use k256::{schnorr::SigningKey, ProjectivePoint, PublicKey, Scalar};
+
+        let cosigned = sample_cosigned_schnorr();
+        let (keys, proofs) = (cosigned.keys().unwrap(), cosigned.proofs().unwrap());
+        assert_eq!((keys.len(), proofs.len()), (2, 2));
+        assert_eq!(cosigned.try_verify(), Ok(()));
+        assert_eq!(pure::cosigned_schnorr(&cosigned), Ok(()));
+
+        // The aggregate is the plain sum, in any order
+        let point = |x: &[u8; 33]| PublicKey::from_sec1_bytes(x).unwrap().to_projective();
+        let sum = (point(&keys[0]) + point(&keys[1]))
+            .to_affine()
+            .to_encoded_point(true);
+        assert_eq!(
+            aggregate_public_keys(&keys, &proofs).unwrap()[..],
+            *sum.as_bytes()
+        );
+        let swapped = CosignedSchnorrData {
+            public_keys: [keys[1], keys[0]].concat(),
+            possession_proofs: [proofs[1], proofs[0]].concat(),
+            ..cosigned.clone()
+        };
+        assert_eq!(swapped.try_verify(), Ok(()));
+
+        // Without a signer the signature fails, a repeated key or a missing proof is rejected
+        let (vendor, vendor_proof) = prove_possession(&Zeroizing::new([0x54; 32]));
+        assert_eq!((vendor, vendor_proof), (keys[1], proofs[1]));
+        assert_eq!(
+            verify_possession(&keys[0], &proofs[1]),
+            Err(SigError::InvalidPossession)
+        );
+        let alone = CosignedSchnorrData {
+            public_keys: keys[0].to_vec(),
+            possession_proofs: proofs[0].to_vec(),
+            ..cosigned.clone()
+        };
+        assert_eq!(alone.try_verify(), Err(SigError::InvalidSignature));
+        let repeated = CosignedSchnorrData {
+            public_keys: [keys[0], keys[0]].concat(),
+            ..cosigned.clone()
+        };
+        assert_eq!(repeated.try_verify(), Err(SigError::MalformedKey));
+        let mut missing = cosigned.clone();
+        missing.possession_proofs.truncate(64);
+        assert_eq!(missing.try_verify(), Err(SigError::InvalidPossession));
+        missing.possession_proofs.truncate(40);
+        assert_eq!(missing.try_verify(), Err(SigError::InvalidPossession));
+
+        // A rogue key x * G - P cancels the client's, the attacker alone signs under the sum but
+        // cannot prove possession of it
+        let attacker = [0x55u8; 32];
+        let x = Scalar::from_repr(attacker.into()).unwrap();
+        let rogue = (ProjectivePoint::GENERATOR * x - point(&keys[0])).to_affine();
+        let rogue: [u8; 33] = rogue.to_encoded_point(true).as_bytes().try_into().unwrap();
+        let signing_key = SigningKey::from_bytes(&attacker).unwrap();
+        let signature = signing_key
+            .sign_raw(&cosigned.message_hash, &[0u8; 32])
+            .unwrap();
+        let forged = CosignedSchnorrData {
+            signature: signature.to_bytes().to_vec(),
+            public_keys: [keys[0], rogue].concat(),
+            possession_proofs: [proofs[0], prove_possession(&Zeroizing::new(attacker)).1].concat(),
+            ..cosigned.clone()
+        };
+        assert_eq!(forged.try_verify(), Err(SigError::InvalidPossession));
+        assert_eq!(
+            pure::cosigned_schnorr(&forged),
+            Err(SigError::InvalidPossession)
+        );
+        assert_eq!(
+            SigError::from_code(SigError::InvalidPossession.code()),
+            Some(SigError::InvalidPossession)
+        );
+    }
+
+    #[test]
+    fn test_eth_tx() {
+        // The example of EIP-155: nonce 9, 20 gwei, 1 ether to 0x3535.., chain 1
+        let raw = "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a0\
+                   28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b\
+                   3800ccf555c9f3dc64214b297fb1966a3b6d83";
+        let value = 1_000_000_000_000_000_000;
+        let tx = sign_eth_tx(
+            &Zeroizing::new([0x46; 32]),
+            EthTxKind::Legacy,
+            1,
+            9,
+            [0x35; 20],
+            value,
+            &[],
+        );
+        assert_eq!(hex::encode(&tx.raw), raw);
+        assert_eq!(
+            hex::encode(tx.sender),
+            "9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f"
+        );
+        let signature = tx.signature().unwrap();
+        assert_eq!(
+            hex::encode(signature.sighash),
+            "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+        );
+        assert_eq!(signature.chain_id, Some(1));
+        assert!(tx.verify());
+
+        let tx = sample_eth_tx();
+        assert_eq!(tx.raw[0], EthTxData::EIP1559);
+        assert_eq!(tx.recover_sender(), Ok(tx.sender));
+        assert_eq!(
+            SignatureItem::EthTx(tx.clone()).message_hash(),
+            keccak256(&tx.raw)
+        );
+        assert!(tx.verify());
+        let polygon = sign_eth_tx(
+            &Zeroizing::new([0x4e; 32]),
+            EthTxKind::Legacy,
+            137,
+            0,
+            [0x35; 20],
+            0,
+            b"data",
+        );
+        assert_eq!(polygon.signature().unwrap().chain_id, Some(137));
+        assert!(polygon.verify());
+
+        // Signed by someone else, or another transaction
+        let mut bad = tx.clone();
+        bad.sender[0] ^= 1;
+        assert_eq!(bad.try_verify(), Err(SigError::InvalidSignature));
+        let mut bad = tx.clone();
+        bad.raw[8] ^= 1;
+        assert_eq!(bad.try_verify(), Err(SigError::InvalidSignature));
+
+        // Not a transaction, or a v that is no recovery id
+        let mut bad = tx.clone();
+        bad.raw.truncate(20);
+        assert_eq!(bad.try_verify(), Err(SigError::MalformedTransaction));
+        bad.raw = [&[0x01][..], &tx.raw[1..]].concat();
+        assert_eq!(bad.try_verify(), Err(SigError::MalformedTransaction));
+        let mut fields: Vec<_> = rlp::list(&polygon.raw)
+            .unwrap()
+            .iter()
+            .map(|x| x.to_vec())
+            .collect();
+        fields[6] = rlp::encode_uint(30);
+        bad.raw = rlp::encode_list(&fields);
+        assert_eq!(bad.try_verify(), Err(SigError::MalformedSignature));
+    }
+
+    #[test]
+    fn test_bitcoin_tx() {
+        // The native P2WPKH example of BIP-143: its second input, of 6 BTC
+        let tx = "01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f000000004948\
+                  30450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9\
+                  281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e\
+                  815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99\
+                  f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa8159\
+                  88ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a95\
+                  4c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7a\
+                  cafcdb3566bb0ad253f62fc70f07aeee635711000000";
+        let prevout = |amount, script| BitcoinPrevout {
+            amount,
+            script_pubkey: hex::decode(script).unwrap(),
+        };
+        let vector = BitcoinTxData {
+            tx: hex::decode(tx).unwrap(),
+            input: 1,
+            prevouts: vec![
+                prevout(
+                    625_000_000,
+                    "2103c9f4836b9a4f77fc0d81f7bcb01b7f1b35916864b9476c241ce9fc198bd25432ac",
+                ),
+                prevout(600_000_000, "00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1"),
+            ],
+        };
+        let BitcoinSignature::Ecdsa { sighash, .. } = vector.signature().unwrap() else {
+            panic!("not a P2WPKH spend");
+        };
+        assert_eq!(
+            hex::encode(sighash),
+            "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"
+        );
+        assert!(vector.verify());
+        // The first input spends a P2PK output, no witness program
+        let mut bad = vector.clone();
+        bad.input = 0;
+        assert_eq!(bad.try_verify(), Err(SigError::UnsupportedScript));
+
+        // Both kinds round trip, the Taproot one signs every amount
+        for kind in [BitcoinOutputKind::P2wpkh, BitcoinOutputKind::P2tr] {
+            let tx = sample_bitcoin_tx(kind);
+            assert!(tx.verify(), "{kind:?}");
+            assert_eq!(
+                SignatureItem::BitcoinTx(tx.clone()).message_hash(),
+                tx.txid().unwrap()
+            );
+
+            // Another amount spent, another key, or an output of the transaction changed
+            let mut bad = tx.clone();
+            bad.prevouts[0].amount -= 1;
+            assert_eq!(bad.try_verify(), Err(SigError::InvalidSignature));
+            let mut bad = tx.clone();
+            let len = bad.prevouts[0].script_pubkey.len();
+            bad.prevouts[0].script_pubkey[len - 1] ^= 1;
+            assert!(!bad.verify());
+            let mut bad = tx.clone();
+            bad.tx[60] ^= 1;
+            assert_eq!(bad.try_verify(), Err(SigError::InvalidSignature));
+            // Not a transaction, or one of more inputs than outputs spent
+            let mut bad = tx.clone();
+            bad.tx.push(0);
+            assert_eq!(bad.try_verify(), Err(SigError::MalformedTransaction));
+            let mut bad = tx.clone();
+            bad.prevouts.push(tx.prevouts[0].clone());
+            assert_eq!(bad.try_verify(), Err(SigError::MalformedTransaction));
+        }
+
+        // A script path spend, or a Taproot signature with an explicit SIGHASH_DEFAULT
+        let tx = sample_bitcoin_tx(BitcoinOutputKind::P2tr);
+        let witness_at = tx.tx.len() - 4 - 66;
+        assert_eq!(tx.tx[witness_at..witness_at + 2], [1, 64]);
+        let with_witness = |items: &[&[u8]]| {
+            let witness: Vec<_> = items.iter().flat_map(|x| bitcoin::var_bytes(x)).collect();
+            let lock_time = &tx.tx[tx.tx.len() - 4..];
+            let tx = [
+                &tx.tx[..witness_at],
+                &bitcoin::compact_size(items.len()),
+                &witness,
+                lock_time,
+            ]
+            .concat();
+            BitcoinTxData {
+                tx,
+                ..sample_bitcoin_tx(BitcoinOutputKind::P2tr)
+            }
+            .try_verify()
+        };
+        let signature = &tx.tx[witness_at + 2..witness_at + 66];
+        assert_eq!(with_witness(&[signature]), Ok(()));
+        assert_eq!(
+            with_witness(&[signature, &[0x51], &[0xc0; 33]]),
+            Err(SigError::UnsupportedScript)
+        );
+        assert_eq!(
+            with_witness(&[&[signature, &[0x00]].concat()]),
+            Err(SigError::MalformedSignature)
+        );
+        // An annex is signed, the signature without it no longer holds
+        assert_eq!(
+            with_witness(&[signature, &[0x50, 1]]),
+            Err(SigError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_p256_data() {
+        let p256 = sample_p256();
+        assert_eq!(p256.signature.len(), 64);
+        assert_eq!(p256.public_key.len(), 33);
+
+        // Test verification and recovery
+        assert!(p256.verify());
+        assert_eq!(
+            p256.recover().map(|x| x.to_vec()),
+            Some(p256.public_key.clone())
+        );
+
+        // Tampered or malformed data fails without panicking
+        let mut bad = p256.clone();
+        bad.message_hash[0] ^= 1;
+        assert!(!bad.verify());
+        assert_ne!(
+            bad.recover().map(|x| x.to_vec()),
+            Some(p256.public_key.clone())
+        );
+        bad.signature.truncate(10);
+        assert!(!bad.verify());
+        assert_eq!(bad.recover(), None);
+    }
+
+    #[test]
+    fn test_webauthn_data() {
+        let webauthn = sample_webauthn();
+        assert_eq!(webauthn.authenticator_data.len(), 37);
+        assert_eq!(webauthn.sign_count(), Some(1));
+
+        // Test verification
+        assert!(webauthn.verify());
+
+        // The authenticator data is signed
+        let mut bad = webauthn.clone();
+        bad.authenticator_data[36] = 2;
+        assert!(!bad.verify());
+
+        // So is the client data
+        let mut bad = webauthn.clone();
+        bad.client_data_hash[0] ^= 1;
+        assert!(!bad.verify());
+
+        // Without user presence the assertion fails, even with a valid signature
+        let mut bad = webauthn.clone();
+        bad.authenticator_data[32] = WebAuthnAssertionData::FLAG_UV;
+        assert!(!bad.verify());
+
+        // Truncated authenticator data fails without panicking
+        let mut bad = webauthn.clone();
+        bad.authenticator_data.truncate(33);
+        assert!(!bad.verify());
+    }
+
+    #[test]
+    fn test_ed25519_data() {
+        let ed25519 = sample_ed25519();
+        assert!(!ed25519.message.is_empty());
+        assert_eq!(ed25519.signature.len(), 64);
+        assert_eq!(ed25519.public_key.len(), 32);
+
+        // Test verification
+        assert!(ed25519.verify());
+    }
+
+    #[test]
+    fn test_rsa_data() {
+        for padding in [RsaPadding::Pkcs1v15, RsaPadding::Pss] {
+            let rsa = sample_rsa(padding);
+            assert_eq!(rsa.signature.len(), 256);
+
+            // Test verification
+            assert!(rsa.verify());
+
+            // Under the other padding the signature fails
+            let other = match padding {
+                RsaPadding::Pkcs1v15 => RsaPadding::Pss,
+                RsaPadding::Pss => RsaPadding::Pkcs1v15,
+            };
+            assert!(!RsaSignatureData {
+                padding: other,
+                ..rsa.clone()
+            }
+            .verify());
+
+            let mut bad = rsa.clone();
+            bad.message.push(0);
+            assert!(!bad.verify());
+
+            // A malformed key fails without panicking
+            let mut bad = rsa.clone();
+            bad.public_key.truncate(10);
+            assert!(!bad.verify());
+        }
+        assert_eq!(sample_rsa(RsaPadding::Pss).cycle_label(), "rsa2048_pss");
+    }
+
+    #[test]
+    fn test_ed25519_modes() {
+        use curve25519_dalek::{
+            constants::{ED25519_BASEPOINT_TABLE, EIGHT_TORSION},
+            scalar::Scalar,
+        };
+        use sha2::{Digest, Sha512};
+
+        let modes = [
+            Ed25519Mode::Strict,
+            Ed25519Mode::Zip215,
+            Ed25519Mode::Cofactored,
+        ];
+        let verdicts = |x: &Ed25519Data| -> Vec<bool> {
+            modes
+                .iter()
+                .map(|mode| {
+                    Ed25519Data {
+                        mode: *mode,
+                        ..x.clone()
+                    }
+                    .verify()
+                })
+                .collect()
+        };
+        let ed25519 = sample_ed25519();
+        assert_eq!(verdicts(&ed25519), [true, true, true]);
+
+        // s + L is the same scalar, but not canonical. s < L < 2^253 so the sum fits
+        const L: [u8; 32] = [
+            0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9,
+            0xde, 0x14, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+        ];
+        let mut bad = ed25519.clone();
+        let mut carry = 0u16;
+        for (x, l) in bad.signature[32..].iter_mut().zip(L) {
+            let sum = *x as u16 + l as u16 + carry;
+            *x = sum as u8;
+            carry = sum >> 8;
+        }
+        assert_eq!(verdicts(&bad), [false, false, false]);
+
+        // R offset by a point of small order, signed as such: only the cofactored modes accept
+        let secret = Scalar::from_bytes_mod_order([0x44u8; 32]);
+        let r = Scalar::from_bytes_mod_order([0x55u8; 32]);
+        let r_bytes = (ED25519_BASEPOINT_TABLE * &r + EIGHT_TORSION[1])
+            .compress()
+            .to_bytes();
+        let mut hasher = Sha512::new();
+        hasher.update(r_bytes);
+        hasher.update(ed25519.public_key);
+        hasher.update(&ed25519.message);
+        let h = Scalar::from_bytes_mod_order_wide(&hasher.finalize().into());
+        let mut torsion = ed25519.clone();
+        torsion.signature = [r_bytes, (r + h * secret).to_bytes()].concat();
+        assert_eq!(verdicts(&torsion), [false, true, true]);
+
+        // A malformed signature fails without panicking
+        let mut bad = ed25519.clone();
+        bad.signature.truncate(10);
+        assert_eq!(verdicts(&bad), [false, false, false]);
+    }
+
+    #[test]
+    fn test_ed25519_algorithms() {
+        // The Ed25519ctx example of RFC 8032, context "foo"
+        let rfc8032 = Ed25519Data {
+            message: hex::decode("f726936d19c800494e3fdaff20b276a8").unwrap(),
+            signature: hex::decode(
+                "55a4cc2f70a54e04288c5f4cd1e45a7bb520b36292911876cada7323198dd87a\
+                 8b36950b95130022907a7fb7c4e9b2d5f6cca685a587b4b21f4b888e4e7edb0d",
+            )
+            .unwrap(),
+            public_key: hex::decode(
+                "dfc9425e4f968f7f0c29f0259cf5f9aed6851c2bb4ad8bfb860cfee0ab248292",
+            )
+            .unwrap()
+            .try_into()
+            .unwrap(),
+            mode: Ed25519Mode::Strict,
+            algorithm: Ed25519Algorithm::Ctx(b"foo".to_vec()),
+        };
+        assert_eq!(rfc8032.try_verify(), Ok(()));
+        assert_eq!(rfc8032.cycle_label(), "ed25519ctx");
+        // Another context, or none
+        let bar = Ed25519Data {
+            algorithm: Ed25519Algorithm::Ctx(b"bar".to_vec()),
+            ..rfc8032.clone()
+        };
+        assert_eq!(bar.try_verify(), Err(SigError::InvalidSignature));
+        let pure = Ed25519Data {
+            algorithm: Ed25519Algorithm::Pure,
+            ..rfc8032.clone()
+        };
+        assert_eq!(pure.try_verify(), Err(SigError::InvalidSignature));
+
+        // Every algorithm signs and verifies, and fails as any other
+        let message = b"Hello, HSM!";
+        let algorithms = [
+            Ed25519Algorithm::Pure,
+            Ed25519Algorithm::Ctx(b"payments".to_vec()),
+            Ed25519Algorithm::Ph(vec![]),
+            Ed25519Algorithm::Ph(b"payments".to_vec()),
+        ];
+        for (i, algorithm) in algorithms.iter().enumerate() {
+            let signed = sign_ed25519_with(&Zeroizing::new([0x44; 32]), message, algorithm.clone());
+            for (j, other) in algorithms.iter().enumerate() {
+                let data = Ed25519Data {
+                    algorithm: other.clone(),
+                    ..signed.clone()
+                };
+                assert_eq!(data.verify(), i == j, "{algorithm:?} as {other:?}");
+            }
+        }
+        let pure = sign_ed25519_with(&Zeroizing::new([0x44; 32]), message, Ed25519Algorithm::Pure);
+        assert_eq!(pure, sign_ed25519(&Zeroizing::new([0x44; 32]), message));
+
+        // Ed25519ph signs the digest, the message is hashed in the guest
+        let ph = sign_ed25519_with(
+            &Zeroizing::new([0x44; 32]),
+            message,
+            Ed25519Algorithm::Ph(vec![]),
+        );
+        let mut bad = ph.clone();
+        bad.message.push(0);
+        assert_eq!(bad.try_verify(), Err(SigError::InvalidSignature));
+        assert_eq!(ph.cycle_label(), "ed25519ph");
+
+        // A context Ed25519ctx must have, or longer than its length byte
+        let empty = Ed25519Data {
+            algorithm: Ed25519Algorithm::Ctx(vec![]),
+            ..ph.clone()
+        };
+        assert_eq!(empty.try_verify(), Err(SigError::InvalidContext));
+        let long = Ed25519Data {
+            algorithm: Ed25519Algorithm::Ph(vec![0; 256]),
+            ..ph.clone()
+        };
+        assert_eq!(long.try_verify(), Err(SigError::InvalidContext));
+        assert_eq!(
+            SigError::from_code(SigError::InvalidContext.code()),
+            Some(SigError::InvalidContext)
+        );
+    }
+
+    #[test]
+    fn test_session_auth() {
+        use curve25519_dalek::montgomery::MontgomeryPoint;
+
+        // RFC 7748 section 6.1: Alice the client, Bob the gateway
+        let decode = |x: &str| -> [u8; 32] { hex::decode(x).unwrap().try_into().unwrap() };
+        let alice = decode("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        let bob = decode("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+        let (alice, bob) = (Zeroizing::new(alice), Zeroizing::new(bob));
+        let identity = Zeroizing::new([0x53u8; 32]);
+        let session = sign_session_auth(&alice, &bob, &identity, b"hello gateway");
+        let alice_public =
+            decode("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a");
+        let bob_public = decode("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f");
+        assert_eq!(
+            (session.client_public_key, session.gateway_public_key),
+            (alice_public, bob_public)
+        );
+        let shared = MontgomeryPoint(session.gateway_public_key)
+            .mul_clamped(*alice)
+            .0;
+        assert_eq!(
+            shared,
+            decode("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742")
+        );
+        assert_eq!(session.try_verify(), Ok(()));
+        assert_eq!(pure::session_auth(&session), Ok(()));
+
+        // The signature binds the transcript and both ephemeral keys, and so does the leaf
+        let mut bad = session.clone();
+        bad.transcript.push(0);
+        assert_eq!(bad.try_verify(), Err(SigError::InvalidSignature));
+        let other = sign_session_auth(
+            &Zeroizing::new([0x54; 32]),
+            &bob,
+            &identity,
+            b"hello gateway",
+        );
+        for swapped in [
+            SessionAuthData {
+                client_public_key: other.client_public_key,
+                ..session.clone()
+            },
+            SessionAuthData {
+                gateway_public_key: other.client_public_key,
+                ..session.clone()
+            },
+        ] {
+            assert_eq!(swapped.try_verify(), Err(SigError::InvalidSignature));
+            let leaf = |x: &SessionAuthData| SignatureItem::SessionAuth(x.clone()).leaf();
+            assert_ne!(leaf(&swapped), leaf(&session));
+        }
+        let impostor =
+            sign_session_auth(&alice, &bob, &Zeroizing::new([0x55; 32]), b"hello gateway");
+        let forged = SessionAuthData {
+            identity_key: session.identity_key,
+            ..impostor
+        };
+        assert_eq!(forged.try_verify(), Err(SigError::InvalidSignature));
+
+        // A small order ephemeral key shares nothing
+        let small = SessionAuthData {
+            client_public_key: [0; 32],
+            ..session.clone()
+        };
+        assert_eq!(small.try_verify(), Err(SigError::MalformedKey));
+        assert_eq!(pure::session_auth(&small), Err(SigError::MalformedKey));
+
+        // Off the guest the gateway checks the client's MAC under their session key
+        let key = SessionAuthData::session_key(&shared, &alice_public, &bob_public);
+        let mac = SessionAuthData::transcript_mac(&key, b"hello gateway");
+        assert_eq!(session.verify_mac(&bob, &mac), Ok(()));
+        assert_eq!(bad.verify_mac(&bob, &mac), Err(SigError::InvalidSignature));
+        assert_eq!(
+            session.verify_mac(&alice, &mac),
+            Err(SigError::MalformedKey)
+        );
+        assert_eq!(small.verify_mac(&bob, &mac), Err(SigError::MalformedKey));
+    }
+
+    #[test]
+    fn test_signature_items() {
+        let items = sample_items();
+        let schemes: Vec<_> = items.iter().map(|x| x.scheme()).collect();
+        assert_eq!(schemes, Scheme::ALL);
+        assert!(items.iter().all(|x| x.verify()));
+
+        // Labeled and recovered through the scheme's own verifier
+        let labels: Vec<_> = items.iter().map(|x| x.cycle_label()).collect();
+        let expected = [
+            "ecdsa",
+            "schnorr",
+            "ed25519",
+            "p256",
+            "webauthn",
+            "rsa2048_pkcs1v15",
+        ];
+        let more = ["frost", "musig2", "ethtx", "btctx", "session", "cosign"];
+        assert_eq!(labels, [&expected[..], &more[..]].concat());
+        for x in &items {
+            let recovered = x.recover_public_key().map(|key| key.to_vec());
+            assert_eq!(recovered, x.recoverable().then(|| x.public_key().to_vec()));
+        }
+
+        // A tampered item fails under its own scheme only
+        let mut items = items;
+        if let SignatureItem::Ed25519(x) = &mut items[2] {
+            x.message[0] ^= 1;
+        }
+        let verdicts: Vec<_> = items.iter().map(|x| x.verify()).collect();
+        assert_eq!(
+            verdicts,
+            [true, true, false, true, true, true, true, true, true, true, true, true]
+        );
+    }
+
+    #[test]
+    fn test_random_items() {
+        use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+        let draw = |seed: u64| -> Vec<SignatureItem> {
+            let mut rng = ChaCha20Rng::seed_from_u64(seed);
+            Scheme::ALL
+                .into_iter()
+                .chain(Scheme::ALL)
+                .map(|scheme| SignatureItem::random(scheme, &mut rng))
+                .collect()
+        };
+        let items = draw(1);
+        assert!(items.iter().all(|x| x.verify()));
+
+        // Every draw is a fresh signature, the same seed draws the same ones
+        let (first, second) = items.split_at(Scheme::ALL.len());
+        assert!(first.iter().zip(second).all(|(a, b)| a != b));
+        assert_eq!(items, draw(1));
+        assert_ne!(items, draw(2));
+
+        for scheme in Scheme::ALL {
+            assert_eq!(scheme.name().parse(), Ok(scheme));
+        }
+        assert!("dsa".parse::<Scheme>().is_err());
+    }
+
+    #[test]
+    fn test_sample_generator() {
+        let generator = SampleGenerator::new(7).ecdsa(3).schnorr(2).ed25519(1);
+        let items = generator.build();
+        let schemes: Vec<_> = items.iter().map(|x| x.scheme().name()).collect();
+        assert_eq!(
+            schemes,
+            ["ecdsa", "ecdsa", "ecdsa", "schnorr", "schnorr", "ed25519"]
+        );
+        assert!(items.iter().all(|x| x.verify()));
+
+        // Distinct keys and messages, the same ones for the same seed only
+        let mut keys: Vec<_> = items.iter().map(|x| x.public_key()).collect();
+        let mut messages: Vec<_> = items.iter().map(|x| x.message_hash()).collect();
+        keys.sort();
+        keys.dedup();
+        messages.sort();
+        messages.dedup();
+        assert_eq!((keys.len(), messages.len()), (6, 6));
+        assert_eq!(items, generator.build());
+        assert_ne!(
+            items,
+            SampleGenerator::new(8)
+                .ecdsa(3)
+                .schnorr(2)
+                .ed25519(1)
+                .build()
+        );
+
+        let mixed = SampleGenerator::new(7)
+            .scheme(Scheme::MuSig2, 2)
+            .scheme(Scheme::BitcoinTx, 2)
+            .build();
+        assert_eq!(mixed.len(), 4);
+        assert!(mixed.iter().all(|x| x.verify()));
+        assert!(SampleGenerator::new(7).build().is_empty());
+    }
+
+    #[test]
+    fn test_pure_verifiers() {
+        // A byte of the signature flipped, the verdict is invalid or malformed
+        let tampered = |mut item: SignatureItem, i: usize| {
+            match &mut item {
+                SignatureItem::EcdsaSecp256k1(x) => x.signature[i] ^= 1,
+                SignatureItem::SchnorrSecp256k1(x) => x.signature[i] ^= 1,
+                SignatureItem::Ed25519(x) => x.signature[i] ^= 1,
+                SignatureItem::P256Ecdsa(x) => x.signature[i] ^= 1,
+                SignatureItem::WebAuthn(x) => x.signature[i] ^= 1,
+                SignatureItem::Rsa(x) => x.signature[i] ^= 1,
+                SignatureItem::ThresholdSchnorr(x) => x.signature[i] ^= 1,
+                SignatureItem::MuSig2(x) => x.signature[i] ^= 1,
+                // r and s are last
+                SignatureItem::EthTx(x) => {
+                    let len = x.raw.len();
+                    x.raw[len - 1 - i] ^= 1
+                }
+                // The witness is last but the lock time
+                SignatureItem::BitcoinTx(x) => {
+                    let len = x.tx.len();
+                    x.tx[len - 5 - i] ^= 1
+                }
+                SignatureItem::SessionAuth(x) => x.signature[i % 64] ^= 1,
+                SignatureItem::CosignedSchnorr(x) => x.signature[i] ^= 1,
+            }
+            item
+        };
+        let mut items = sample_items();
+        items.push(SignatureItem::SchnorrSecp256k1(sample_taproot(Some(
+            [7; 32],
+        ))));
+        items.push(SignatureItem::Rsa(sample_rsa(RsaPadding::Pss)));
+        items.push(SignatureItem::BitcoinTx(sample_bitcoin_tx(
+            BitcoinOutputKind::P2wpkh,
+        )));
+        for algorithm in [
+            Ed25519Algorithm::Ctx(b"payments".to_vec()),
+            Ed25519Algorithm::Ph(vec![]),
+        ] {
+            items.push(SignatureItem::Ed25519(sign_ed25519_with(
+                &Zeroizing::new([0x44; 32]),
+                b"Hello, HSM!",
+                algorithm,
+            )));
+        }
+        let mut mismatch = sample_taproot(None);
+        mismatch.taproot.as_mut().unwrap().internal_key[0] ^= 1;
+        items.push(SignatureItem::SchnorrSecp256k1(mismatch));
+        for item in items.clone() {
+            items.push(tampered(item.clone(), 0));
+            items.push(tampered(item, 40));
+        }
+
+        // The upstream crates give the same verdict, with the same reason
+        for item in &items {
+            assert_eq!(
+                item.try_verify_pure(),
+                Some(item.try_verify()),
+                "{:?}",
+                item.scheme()
+            );
+        }
+        assert!(items.iter().any(|x| x.verify()) && items.iter().any(|x| !x.verify()));
+    }
+
+    #[test]
+    fn test_sig_errors() {
+        // Malformed inputs fail with their reason instead of panicking
+        let mut ecdsa = sample_ecdsa();
+        ecdsa.message_hash[0] ^= 1;
+        assert_eq!(ecdsa.try_verify(), Err(SigError::InvalidSignature));
+        ecdsa.public_key = vec![0x02; 10];
+        assert_eq!(ecdsa.try_verify(), Err(SigError::MalformedKey));
+        ecdsa.signature.truncate(10);
+        assert_eq!(ecdsa.try_verify(), Err(SigError::MalformedSignature));
+        assert_eq!(ecdsa.recover(), None);
+
+        let mut schnorr = sample_schnorr();
+        schnorr.signature.clear();
+        assert_eq!(schnorr.try_verify(), Err(SigError::MalformedSignature));
+        assert_eq!(
+            SchnorrSecp256k1Data::batch_verify(&[schnorr, sample_schnorr()]),
+            1
+        );
+
+        let mut webauthn = sample_webauthn();
+        webauthn.authenticator_data[32] = WebAuthnAssertionData::FLAG_UP;
+        assert_eq!(webauthn.try_verify(), Err(SigError::MissingUserFlags));
+        webauthn.authenticator_data.truncate(33);
+        assert_eq!(
+            webauthn.try_verify(),
+            Err(SigError::MalformedAuthenticatorData)
+        );
+
+        let mut ed25519 = sample_ed25519();
+        ed25519.signature[0..32].copy_from_slice(&[0; 32]);
+        ed25519.signature[0] = 1; // the identity, of small order
+        assert_eq!(ed25519.try_verify(), Err(SigError::RejectedByMode));
+
+        let mut rsa = sample_rsa(RsaPadding::Pss);
+        rsa.public_key.truncate(10);
+        assert_eq!(rsa.try_verify(), Err(SigError::MalformedKey));
+
+        let items = [SignatureItem::Ed25519(ed25519), SignatureItem::Rsa(rsa)];
+        let results: Vec<_> = items.iter().map(|x| x.try_verify()).collect();
+        assert_eq!(
+            results,
+            [Err(SigError::RejectedByMode), Err(SigError::MalformedKey)]
+        );
+    }
+
+    #[test]
+    fn test_malleability() {
+        use k256::elliptic_curve::{ops::Reduce, point::AffineCoordinates, PrimeField};
+        use k256::{ProjectivePoint, Scalar, U256};
+
+        // The high-s twin of a valid ECDSA signature
+        let ecdsa = sample_ecdsa();
+        let s = Scalar::from_repr(<[u8; 32]>::try_from(&ecdsa.signature[32..]).unwrap().into())
+            .unwrap();
+        let mut high = ecdsa.clone();
+        high.signature[32..].copy_from_slice(&(-s).to_repr());
+        assert_eq!(high.try_verify(), Err(SigError::HighS));
+        assert_eq!(high.try_verify_pure(), Some(Err(SigError::HighS)));
+
+        // Over P-256 as well, the signer normalizes s and still recovers its key
+        let p256 = sample_p256();
+        assert_eq!(
+            p256.recover(),
+            Some(p256.public_key.clone().try_into().unwrap())
+        );
+        let s =
+            p256::Scalar::from_repr(<[u8; 32]>::try_from(&p256.signature[32..]).unwrap().into())
+                .unwrap();
+        let mut high = p256.clone();
+        high.signature[32..].copy_from_slice(&(-s).to_repr());
+        assert_eq!(high.try_verify(), Err(SigError::HighS));
+        assert_eq!(high.try_verify_pure(), Some(Err(SigError::HighS)));
+
+        // A signed transaction with s flipped, and v with it so it still recovers the sender:
+        // y parity, then r and s as 32 byte strings at the end
+        let eth = sample_eth_tx();
+        let mut high = eth.clone();
+        let n = high.raw.len();
+        assert_eq!((high.raw[n - 66], high.raw[n - 33]), (0xa0, 0xa0));
+        let s =
+            Scalar::from_repr(<[u8; 32]>::try_from(&high.raw[n - 32..]).unwrap().into()).unwrap();
+        high.raw[n - 32..].copy_from_slice(&(-s).to_repr());
+        high.raw[n - 67] ^= 0x80 ^ 0x01;
+        assert_eq!(high.recover_sender(), Err(SigError::HighS));
+        assert_eq!(high.try_verify(), Err(SigError::HighS));
+        assert_eq!(high.try_verify_pure(), Some(Err(SigError::HighS)));
+
+        // The P2WPKH witness signature re-encoded with the high s
+        let btc = sample_bitcoin_tx(BitcoinOutputKind::P2wpkh);
+        let BitcoinSignature::Ecdsa { signature, .. } = btc.signature().unwrap() else {
+            panic!("P2WPKH signs with ECDSA");
+        };
+        let (r, s) = k256::ecdsa::Signature::from_der(&signature)
+            .unwrap()
+            .split_scalars();
+        let twin = k256::ecdsa::Signature::from_scalars(r.to_repr(), (-*s).to_repr())
+            .unwrap()
+            .to_der();
+        let at = btc
+            .tx
+            .windows(signature.len())
+            .position(|x| x == signature)
+            .unwrap();
+        let mut high = btc.clone();
+        // The push length counts the hash type byte after the signature
+        high.tx.splice(
+            at - 1..at + signature.len(),
+            [&[twin.len() as u8 + 1][..], twin.as_bytes()].concat(),
+        );
+        assert_eq!(high.try_verify(), Err(SigError::HighS));
+        assert_eq!(high.try_verify_pure(), Some(Err(SigError::HighS)));
+
+        // BIP-340 signatures of a signer that skips the even y: R or the key of odd y
+        let message_hash = sha256(b"odd");
+        let odd = |x: &Scalar| bool::from((ProjectivePoint::GENERATOR * x).to_affine().y_is_odd());
+        let scalars = || (1u64..).map(Scalar::from);
+        let sign = |d: Scalar, k: Scalar| {
+            let public_key: [u8; 32] = (ProjectivePoint::GENERATOR * d).to_affine().x().into();
+            let r: [u8; 32] = (ProjectivePoint::GENERATOR * k).to_affine().x().into();
+            let challenge = tagged_hash(
+                "BIP0340/challenge",
+                &[&r[..], &public_key, &message_hash].concat(),
+            );
+            let e = <Scalar as Reduce<U256>>::reduce_bytes(&challenge.into());
+            SchnorrSecp256k1Data {
+                message_hash,
+                signature: [r, (k + e * d).to_repr().into()].concat(),
+                public_key,
+                hashing: SchnorrHashing::Bip340,
+                taproot: None,
+                message: None,
+            }
+        };
+        let (even_d, odd_d) = (
+            scalars().find(|x| !odd(x)).unwrap(),
+            scalars().find(odd).unwrap(),
+        );
+        // Another nonce than the key's, or it signs with the key's own secret
+        let even_k = scalars().skip(99).find(|x| !odd(x)).unwrap();
+        let odd_k = scalars().skip(99).find(odd).unwrap();
+        assert_eq!(sign(even_d, even_k).try_verify(), Ok(()));
+        for bad in [sign(even_d, odd_k), sign(odd_d, even_k)] {
+            assert_eq!(bad.try_verify(), Err(SigError::OddY));
+            assert_eq!(bad.try_verify_pure(), Some(Err(SigError::OddY)));
+        }
+        let mut bad = sign(even_d, even_k);
+        bad.message_hash[0] ^= 1;
+        assert_eq!(bad.try_verify(), Err(SigError::InvalidSignature));
+
+        // Ed25519 s not below L, and R encoded as y = p + 1, the identity
+        let mut ed25519 = sample_ed25519();
+        ed25519.signature[32..].copy_from_slice(&[0xff; 32]);
+        assert_eq!(ed25519.try_verify(), Err(SigError::NonCanonical));
+        let mut ed25519 = sample_ed25519();
+        let p_plus_one = [&[0xee][..], &[0xff; 30], &[0x7f]].concat();
+        ed25519.signature[..32].copy_from_slice(&p_plus_one);
+        for (mode, verdict) in [
+            (Ed25519Mode::Strict, Err(SigError::NonCanonical)),
+            (Ed25519Mode::Cofactored, Err(SigError::NonCanonical)),
+            (Ed25519Mode::Zip215, Err(SigError::InvalidSignature)),
+        ] {
+            let data = Ed25519Data {
+                mode,
+                ..ed25519.clone()
+            };
+            assert_eq!(
+                (data.try_verify(), data.try_verify_pure()),
+                (verdict, Some(verdict))
+            );
+        }
+        for code in 12..=14 {
+            assert_eq!(SigError::from_code(code).map(|x| x.code()), Some(code));
+        }
+    }
+
+    #[test]
+    fn test_public_values() {
+        use alloy_sol_types::SolValue;
+
+        // Nine items span two bytes of the bitmap
+        let mut verdicts = vec![Ok(()); 9];
+        verdicts[1] = Err(SigError::InvalidSignature);
+        verdicts[8] = Err(SigError::MalformedKey);
+        let public_values = PublicValuesStruct::from_verdicts(&verdicts);
+        assert_eq!(public_values.bitmap.to_vec(), [0b1111_1101, 0]);
+        assert_eq!(public_values.failures.to_vec(), [7, 2]);
+        assert!(public_values.verified(0));
+        assert!(!public_values.verified(1));
+        assert!(!public_values.verified(9));
+
+        // The struct is what the program commits, ABI encoded
+        let decoded = PublicValuesStruct::abi_decode(&public_values.abi_encode()).unwrap();
+        assert_eq!(decoded.verdicts(), Some(verdicts));
+
+        // Failures that don't match the bitmap are rejected
+        let mut bad = decoded.clone();
+        bad.failures = vec![7].into();
+        assert_eq!(bad.verdicts(), None);
+        bad.failures = vec![7, 2, 2].into();
+        assert_eq!(bad.verdicts(), None);
+    }
+
+    #[test]
+    fn test_recover_signers() {
+        use alloy_sol_types::SolValue;
+
+        let alice = sign_ecdsa(&Zeroizing::new([0x41; 32]), b"first");
+        let bob = sign_eth_tx(
+            &Zeroizing::new([0x42; 32]),
+            EthTxKind::Eip1559,
+            1,
+            0,
+            [0x35; 20],
+            1,
+            b"",
+        );
+        let items = [
+            SignatureItem::EcdsaSecp256k1(alice.clone()),
+            SignatureItem::EthTx(bob.clone()),
+            SignatureItem::EcdsaSecp256k1(sign_ecdsa(&Zeroizing::new([0x41; 32]), b"second")),
+            SignatureItem::Ed25519(sample_ed25519()),
+        ];
+        let mut expected = vec![alice.address().unwrap(), bob.sender];
+        expected.sort();
+        let signers = recover_signers(&items);
+        assert_eq!(signers, expected);
+        assert_eq!(items[3].recover_address(), None);
+
+        // Not verified: a tampered message recovers someone else, a malformed signature no one
+        let mut tampered = alice.clone();
+        tampered.message_hash[0] ^= 1;
+        let mut malformed = alice.clone();
+        malformed.signature.truncate(10);
+        let items = [
+            SignatureItem::EcdsaSecp256k1(tampered),
+            SignatureItem::EcdsaSecp256k1(malformed),
+        ];
+        let recovered = recover_signers(&items);
+        assert_eq!(recovered.len(), 1);
+        assert_ne!(recovered[0], alice.address().unwrap());
+
+        // The hash is the same with or without the list, as Solidity packs the addresses
+        let public_values = SignersStruct::new(4, &signers, false, [7; 32]);
+        let packed: Vec<u8> = public_values
+            .signers
+            .iter()
+            .flat_map(|x| x.into_word().0)
+            .collect();
+        assert_eq!(public_values.hash, keccak256(&packed));
+        assert_eq!(public_values.items, [7; 32]);
+        let hash_only = SignersStruct::new(4, &signers, true, [7; 32]);
+        assert!(hash_only.signers.is_empty());
+        assert_eq!(hash_only.hash, public_values.hash);
+        let decoded = SignersStruct::abi_decode(&public_values.abi_encode()).unwrap();
+        assert_eq!(decoded.signers, public_values.signers);
+        assert_eq!(signers_hash(&[]), keccak256(&[]));
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        let leaves: Vec<[u8; 32]> = (0..5u8).map(|i| keccak256(&[i])).collect();
+        let root = merkle_root(&leaves);
+        assert_eq!(merkle_root(&leaves[..1]), leaves[0]);
+        assert_eq!(merkle_root(&[]), [0; 32]);
+        assert_eq!(merkle_root(&leaves[..2]), hash_pair(&leaves[1], &leaves[0]));
+
+        // Every leaf proves against the root, the odd last one with a shorter proof
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = merkle_proof(&leaves, i).unwrap();
+            assert_eq!(proof.len(), if i == 4 { 1 } else { 3 });
+            assert!(verify_merkle_proof(&root, leaf, &proof));
+            assert!(!verify_merkle_proof(&root, &keccak256(b"other"), &proof));
+        }
+        assert_eq!(merkle_proof(&leaves, 5), None);
+
+        // The leaf binds the scheme, the message and the key
+        let items = sample_items();
+        let leaves: Vec<_> = items.iter().map(|x| x.leaf()).collect();
+        let mut bad = sample_ed25519();
+        bad.message[0] ^= 1;
+        assert_ne!(SignatureItem::Ed25519(bad).leaf(), leaves[2]);
+        assert!(leaves
+            .iter()
+            .enumerate()
+            .all(|(i, x)| !leaves[..i].contains(x)));
+    }
+
+    #[test]
+    fn test_bincode_serialization() {
+        // An arbitrary mix, schemes repeated and out of order
+        let items: Vec<_> = [Scheme::Ed25519, Scheme::EcdsaSecp256k1, Scheme::Ed25519]
+            .into_iter()
+            .map(SignatureItem::sample)
+            .collect();
+
+        // Serialize
+        let serialized = items_to_bincode(&items).expect("Failed to serialize");
+        assert!(!serialized.is_empty());
+
+        // Deserialize
+        let deserialized = items_from_bincode(&serialized).expect("Failed to deserialize");
+
+        // Verify they are equal
+        assert_eq!(items, deserialized);
+
+        // Verify the deserialized data still works
+        assert!(deserialized.iter().all(|x| x.verify()));
+    }
+
+    #[test]
+    fn test_streamed_input() {
+        use alloy_sol_types::SolValue;
+
+        let mut items = SampleGenerator::new(7)
+            .ecdsa(5)
+            .schnorr(3)
+            .ed25519(2)
+            .build();
+        if let SignatureItem::SchnorrSecp256k1(x) = &mut items[6] {
+            x.signature[0] ^= 1;
+        }
+
+        // Frames of 4 items, the last one short, read back in order
+        let (header, frames) = item_frames(&items, 4).unwrap();
+        assert_eq!(
+            header,
+            StreamHeader {
+                items: 10,
+                frames: 3
+            }
+        );
+        let read: Vec<_> = frames
+            .iter()
+            .flat_map(|x| items_from_bincode(x).unwrap())
+            .collect();
+        assert_eq!(read, items);
+        assert_eq!(
+            item_frames(&[], FRAME_ITEMS).unwrap(),
+            (StreamHeader::default(), vec![])
+        );
+
+        // Verified a frame at a time, the public values are those of the whole batch
+        let mut accumulator = VerdictAccumulator::new(items.len(), true);
+        for frame in &frames {
+            for item in items_from_bincode(frame).unwrap() {
+                let verdict = item.try_verify();
+                accumulator.push(&item, verdict);
+            }
+        }
+        let verdicts: Vec<_> = items.iter().map(|x| x.try_verify()).collect();
+        let mut expected = PublicValuesStruct::from_verdicts(&verdicts);
+        let leaves: Vec<_> = items
+            .iter()
+            .filter(|x| x.verify())
+            .map(|x| x.leaf())
+            .collect();
+        expected.authorizations = merkle_root(&leaves).into();
+        expected.items = items_hash(&items).into();
+        assert_eq!(
+            accumulator.public_values().abi_encode(),
+            expected.abi_encode()
+        );
+        assert_eq!(accumulator.tally[&Scheme::SchnorrSecp256k1], (2, 3));
+        assert_eq!(accumulator.tally[&Scheme::EcdsaSecp256k1], (5, 5));
+
+        // Without the leaves the root is left zero
+        let mut accumulator = VerdictAccumulator::new(items.len(), false);
+        items
+            .iter()
+            .for_each(|x| accumulator.push(x, x.try_verify()));
+        assert!(accumulator.leaves.is_empty());
+        let mut expected = PublicValuesStruct::from_verdicts(&verdicts);
+        expected.items = items_hash(&items).into();
+        assert_eq!(
+            accumulator.public_values().abi_encode(),
+            expected.abi_encode()
+        );
+
+        // A malformed frame fails its items and those after it, the items read still count
+        assert!(items_from_bincode(&frames[1][..frames[1].len() - 1]).is_err());
+        let huge = VerdictAccumulator::new(u32::MAX as usize, false);
+        assert_eq!(huge.verdicts.capacity(), FRAME_ITEMS);
+        let mut accumulator = VerdictAccumulator::new(items.len(), false);
+        let read = items_from_bincode(&frames[0]).unwrap();
+        read.iter()
+            .for_each(|x| accumulator.push(x, x.try_verify()));
+        accumulator.fail_unread();
+        let public_values = accumulator.public_values();
+        let verdicts = public_values.verdicts().unwrap();
+        assert_eq!(verdicts.len(), items.len());
+        assert_eq!(
+            verdicts[..read.len()],
+            items[..read.len()]
+                .iter()
+                .map(|x| x.try_verify())
+                .collect::<Vec<_>>()
+        );
+        assert!(verdicts[read.len()..]
+            .iter()
+            .all(|x| *x == Err(SigError::MalformedFrame)));
+        assert_eq!(public_values.items, items_hash(&read));
+
+        // Frames holding more items than the header declares fail the extra ones
+        let mut accumulator = VerdictAccumulator::new(2, false);
+        items[..3]
+            .iter()
+            .for_each(|x| accumulator.push(x, x.try_verify()));
+        accumulator.fail_unread();
+        assert_eq!(
+            accumulator.verdicts[..2],
+            items[..2]
+                .iter()
+                .map(|x| x.try_verify())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(accumulator.verdicts[2..], [Err(SigError::MalformedFrame)]);
+        assert_eq!(accumulator.public_values().items, items_hash(&items[..3]));
+    }
+
+    #[test]
+    fn test_items_hash() {
+        let items: Vec<_> = Scheme::ALL
+            .iter()
+            .map(|x| SignatureItem::sample(*x))
+            .collect();
+        let packed: Vec<u8> = items.iter().flat_map(|x| x.leaf()).collect();
+        assert_eq!(items_hash(&items), keccak256(&packed));
+        assert_eq!(items_hash(&[]), keccak256(&[]));
+
+        // The verdicts commit to the items: another message, key or order is another hash,
+        // even where the verdicts stay the same
+        let mut other_message = items.clone();
+        let SignatureItem::EcdsaSecp256k1(x) = &mut other_message[0] else {
+            unreachable!("the first scheme is ECDSA")
+        };
+        x.message_hash[0] ^= 1;
+        assert_ne!(items_hash(&other_message), items_hash(&items));
+        let mut other_key = items.clone();
+        let SignatureItem::EcdsaSecp256k1(x) = &mut other_key[0] else {
+            unreachable!("the first scheme is ECDSA")
+        };
+        x.public_key[1] ^= 1;
+        assert_ne!(items_hash(&other_key), items_hash(&items));
+        let mut reordered = items.clone();
+        reordered.swap(0, 1);
+        assert_ne!(items_hash(&reordered), items_hash(&items));
+
+        let mut accumulator = VerdictAccumulator::new(items.len(), false);
+        items
+            .iter()
+            .for_each(|x| accumulator.push(x, Err(SigError::InvalidSignature)));
+        let mut tampered = VerdictAccumulator::new(items.len(), false);
+        other_message
+            .iter()
+            .for_each(|x| tampered.push(x, Err(SigError::InvalidSignature)));
+        let (a, b) = (accumulator.public_values(), tampered.public_values());
+        assert_eq!(
+            (a.bitmap.clone(), a.failures.clone()),
+            (b.bitmap, b.failures)
+        );
+        assert_ne!(a.items, b.items);
     }
 }
@@ -0,0 +1,333 @@
+//! The verifiers of the signature items on the upstream crates instead of the SP1-patched ones,
+//! the same checks in the same order, so both give the same verdict and only the cycles differ.
+//! The program runs both over the same items in its benchmark mode, what the upstream crates
+//! cost over the patched ones is what the precompiles save.
+use crate::{
+    check_message, keccak256, musig2_coefficients, possession_data, sha256, tagged_hash,
+    BitcoinSignature, BitcoinTxData, CosignedSchnorrData, EcdsaSecp256k1Data, Ed25519Data,
+    Ed25519Mode, EthTxData, MuSig2Data, P256EcdsaData, RsaPadding, RsaSignatureData,
+    SchnorrHashing, SchnorrSecp256k1Data, SessionAuthData, SigError, TaprootTweak,
+    ThresholdSchnorrData, WebAuthnAssertionData,
+};
+
+pub fn ecdsa_secp256k1(data: &EcdsaSecp256k1Data) -> Result<(), SigError> {
+    use k256_pure::ecdsa::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey};
+
+    check_message(&data.message, &data.message_hash)?;
+    let signature =
+        Signature::from_slice(&data.signature).map_err(|_| SigError::MalformedSignature)?;
+    if signature.normalize_s().is_some() {
+        return Err(SigError::HighS);
+    }
+    let verifying_key =
+        VerifyingKey::from_sec1_bytes(&data.public_key).map_err(|_| SigError::MalformedKey)?;
+
+    verifying_key
+        .verify_prehash(&data.digest(), &signature)
+        .map_err(|_| SigError::InvalidSignature)
+}
+
+pub fn schnorr_secp256k1(data: &SchnorrSecp256k1Data) -> Result<(), SigError> {
+    use k256_pure::schnorr::{signature::Verifier, Signature, VerifyingKey};
+
+    check_message(&data.message, &data.message_hash)?;
+    if let Some(tweak) = &data.taproot {
+        if taproot_output_key(tweak)? != data.public_key {
+            return Err(SigError::TweakMismatch);
+        }
+    }
+
+    if data.signature.len() != 64 {
+        return Err(SigError::MalformedSignature);
+    }
+    let signature =
+        Signature::try_from(&data.signature[..]).map_err(|_| SigError::MalformedSignature)?;
+    let verifying_key =
+        VerifyingKey::from_bytes(&data.public_key).map_err(|_| SigError::MalformedKey)?;
+
+    match data.hashing {
+        SchnorrHashing::Sha256 => verifying_key.verify(&data.message_hash, &signature),
+        SchnorrHashing::Bip340 => verifying_key.verify_raw(&data.message_hash, &signature),
+    }
+    .map_err(|_| schnorr_failure(data, &verifying_key))
+}
+
+/// See `SchnorrSecp256k1Data::failure`
+fn schnorr_failure(
+    data: &SchnorrSecp256k1Data,
+    key: &k256_pure::schnorr::VerifyingKey,
+) -> SigError {
+    use k256_pure::elliptic_curve::{ops::Reduce, point::AffineCoordinates};
+    use k256_pure::{ProjectivePoint, Scalar, U256};
+
+    let (r, s) = data.signature.split_at(32);
+    let message = [r, &data.public_key, &data.bip340_message()].concat();
+    let challenge = tagged_hash("BIP0340/challenge", &message);
+    let e = <Scalar as Reduce<U256>>::reduce_bytes(&challenge.into());
+    let s = <Scalar as Reduce<U256>>::reduce_bytes(&<[u8; 32]>::try_from(s).unwrap().into());
+    let (s_g, e_p) = (
+        ProjectivePoint::GENERATOR * s,
+        ProjectivePoint::from(*key.as_affine()) * e,
+    );
+    match [s_g - e_p, s_g + e_p]
+        .iter()
+        .any(|x| <[u8; 32]>::from(x.to_affine().x()) == r)
+    {
+        true => SigError::OddY,
+        false => SigError::InvalidSignature,
+    }
+}
+
+/// See `TaprootTweak::output_key`
+fn taproot_output_key(tweak: &TaprootTweak) -> Result<[u8; 32], SigError> {
+    use k256_pure::elliptic_curve::{point::AffineCoordinates, PrimeField};
+    use k256_pure::{schnorr::VerifyingKey, ProjectivePoint, Scalar};
+
+    let internal_key =
+        VerifyingKey::from_bytes(&tweak.internal_key).map_err(|_| SigError::MalformedKey)?;
+    let t = Option::<Scalar>::from(Scalar::from_repr(tweak.tweak().into()))
+        .ok_or(SigError::TweakMismatch)?;
+    let internal_point = ProjectivePoint::from(*internal_key.as_affine());
+    let output_key = (internal_point + ProjectivePoint::GENERATOR * t).to_affine();
+    if output_key == k256_pure::AffinePoint::IDENTITY {
+        return Err(SigError::TweakMismatch);
+    }
+    Ok(output_key.x().into())
+}
+
+pub fn threshold_schnorr(data: &ThresholdSchnorrData) -> Result<(), SigError> {
+    schnorr_secp256k1(&SchnorrSecp256k1Data {
+        message_hash: data.message_hash,
+        signature: data.signature.clone(),
+        public_key: data.group_public_key,
+        hashing: SchnorrHashing::Bip340,
+        taproot: None,
+        message: None,
+    })
+}
+
+pub fn musig2(data: &MuSig2Data) -> Result<(), SigError> {
+    schnorr_secp256k1(&SchnorrSecp256k1Data {
+        message_hash: data.message_hash,
+        signature: data.signature.clone(),
+        public_key: musig2_key_agg(&data.keys()?)?,
+        hashing: SchnorrHashing::Bip340,
+        taproot: None,
+        message: None,
+    })
+}
+
+/// See `crate::aggregate_public_keys`, the proofs of possession are BIP-340 signatures as well
+pub fn cosigned_schnorr(data: &CosignedSchnorrData) -> Result<(), SigError> {
+    use k256_pure::elliptic_curve::point::AffineCoordinates;
+    use k256_pure::{ProjectivePoint, PublicKey};
+
+    let (public_keys, proofs) = (data.keys()?, data.proofs()?);
+    if public_keys.is_empty()
+        || public_keys
+            .iter()
+            .enumerate()
+            .any(|(i, x)| public_keys[..i].contains(x))
+    {
+        return Err(SigError::MalformedKey);
+    }
+    if proofs.len() != public_keys.len() {
+        return Err(SigError::InvalidPossession);
+    }
+    let mut aggregate = ProjectivePoint::IDENTITY;
+    for (x, proof) in public_keys.iter().zip(&proofs) {
+        let point = PublicKey::from_sec1_bytes(x).map_err(|_| SigError::MalformedKey)?;
+        schnorr_secp256k1(&possession_data(x, proof)).map_err(|_| SigError::InvalidPossession)?;
+        aggregate += point.to_projective();
+    }
+    let aggregate = aggregate.to_affine();
+    if aggregate == k256_pure::AffinePoint::IDENTITY {
+        return Err(SigError::MalformedKey);
+    }
+    schnorr_secp256k1(&SchnorrSecp256k1Data {
+        message_hash: data.message_hash,
+        signature: data.signature.clone(),
+        public_key: aggregate.x().into(),
+        hashing: SchnorrHashing::Bip340,
+        taproot: None,
+        message: None,
+    })
+}
+
+/// See `crate::musig2_key_agg`, the coefficients are hashes, the same whatever the crate
+fn musig2_key_agg(public_keys: &[[u8; 33]]) -> Result<[u8; 32], SigError> {
+    use k256_pure::elliptic_curve::{ops::Reduce, point::AffineCoordinates};
+    use k256_pure::{ProjectivePoint, PublicKey, Scalar, U256};
+
+    if public_keys.is_empty() {
+        return Err(SigError::MalformedKey);
+    }
+    let mut aggregate = ProjectivePoint::IDENTITY;
+    for (x, a) in public_keys.iter().zip(musig2_coefficients(public_keys)) {
+        let point = PublicKey::from_sec1_bytes(x).map_err(|_| SigError::MalformedKey)?;
+        aggregate += point.to_projective() * <Scalar as Reduce<U256>>::reduce_bytes(&a.to_bytes());
+    }
+    let aggregate = aggregate.to_affine();
+    if aggregate == k256_pure::AffinePoint::IDENTITY {
+        return Err(SigError::MalformedKey);
+    }
+    Ok(aggregate.x().into())
+}
+
+pub fn p256_ecdsa(data: &P256EcdsaData) -> Result<(), SigError> {
+    use p256_pure::ecdsa::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey};
+
+    let signature =
+        Signature::from_slice(&data.signature).map_err(|_| SigError::MalformedSignature)?;
+    if signature.normalize_s().is_some() {
+        return Err(SigError::HighS);
+    }
+    let verifying_key =
+        VerifyingKey::from_sec1_bytes(&data.public_key).map_err(|_| SigError::MalformedKey)?;
+
+    verifying_key
+        .verify_prehash(&data.message_hash, &signature)
+        .map_err(|_| SigError::InvalidSignature)
+}
+
+pub fn webauthn(data: &WebAuthnAssertionData) -> Result<(), SigError> {
+    use p256_pure::ecdsa::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey};
+
+    data.check_flags()?;
+    let signature =
+        Signature::from_der(&data.signature).map_err(|_| SigError::MalformedSignature)?;
+    let verifying_key =
+        VerifyingKey::from_sec1_bytes(&data.public_key).map_err(|_| SigError::MalformedKey)?;
+
+    verifying_key
+        .verify_prehash(&sha256(&data.signed_payload()), &signature)
+        .map_err(|_| SigError::InvalidSignature)
+}
+
+pub fn rsa(data: &RsaSignatureData) -> Result<(), SigError> {
+    use rsa_pure::{
+        pkcs1::DecodeRsaPublicKey, traits::PublicKeyParts, Pkcs1v15Sign, Pss, RsaPublicKey,
+    };
+    use sha2::Sha256;
+
+    let key = RsaPublicKey::from_pkcs1_der(&data.public_key).map_err(|_| SigError::MalformedKey)?;
+    if !(RsaSignatureData::MIN_BITS..=RsaSignatureData::MAX_BITS).contains(&key.n().bits()) {
+        return Err(SigError::UnsupportedKeySize);
+    }
+    let hashed = sha256(&data.message);
+    match data.padding {
+        RsaPadding::Pkcs1v15 => key.verify(Pkcs1v15Sign::new::<Sha256>(), &hashed, &data.signature),
+        RsaPadding::Pss => key.verify(Pss::new::<Sha256>(), &hashed, &data.signature),
+    }
+    .map_err(|_| SigError::InvalidSignature)
+}
+
+pub fn ed25519(data: &Ed25519Data) -> Result<(), SigError> {
+    use curve25519_dalek_pure::{
+        constants::ED25519_BASEPOINT_TABLE, edwards::CompressedEdwardsY, scalar::Scalar,
+        traits::IsIdentity,
+    };
+
+    if data.signature.len() != 64 {
+        return Err(SigError::MalformedSignature);
+    }
+    let r_bytes: [u8; 32] = data.signature[0..32].try_into().unwrap();
+    let s_bytes: [u8; 32] = data.signature[32..64].try_into().unwrap();
+
+    let r_point = CompressedEdwardsY(r_bytes)
+        .decompress()
+        .ok_or(SigError::MalformedSignature)?;
+    let s = Option::<Scalar>::from(Scalar::from_canonical_bytes(s_bytes))
+        .ok_or(SigError::NonCanonical)?;
+    let a_point = CompressedEdwardsY(data.public_key)
+        .decompress()
+        .ok_or(SigError::MalformedKey)?;
+
+    if data.mode != Ed25519Mode::Zip215
+        && (r_point.compress().0 != r_bytes || a_point.compress().0 != data.public_key)
+    {
+        return Err(SigError::NonCanonical);
+    }
+    if data.mode == Ed25519Mode::Strict && (r_point.is_small_order() || a_point.is_small_order()) {
+        return Err(SigError::RejectedByMode);
+    }
+
+    let h = Scalar::from_bytes_mod_order_wide(&data.challenge(r_bytes)?);
+
+    let diff = ED25519_BASEPOINT_TABLE * &s - h * a_point - r_point;
+    let ok = match data.mode {
+        Ed25519Mode::Strict => diff.is_identity(),
+        Ed25519Mode::Zip215 | Ed25519Mode::Cofactored => diff.mul_by_cofactor().is_identity(),
+    };
+    ok.then_some(()).ok_or(SigError::InvalidSignature)
+}
+
+/// See `SessionAuthData::try_verify`, the handshake signature is checked as `ed25519` does
+pub fn session_auth(data: &SessionAuthData) -> Result<(), SigError> {
+    use curve25519_dalek_pure::montgomery::MontgomeryPoint;
+
+    let small = |u: &[u8; 32]| {
+        MontgomeryPoint(*u)
+            .to_edwards(0)
+            .is_some_and(|x| x.is_small_order())
+    };
+    if small(&data.client_public_key) || small(&data.gateway_public_key) {
+        return Err(SigError::MalformedKey);
+    }
+    ed25519(&data.signed_data())
+}
+
+/// See `EthTxData::recover_sender`, the transaction is parsed and hashed as there
+pub fn eth_tx(data: &EthTxData) -> Result<(), SigError> {
+    use k256_pure::ecdsa::{RecoveryId, Signature, VerifyingKey};
+
+    let tx = data.signature()?;
+    let signature =
+        Signature::from_slice(&tx.signature).map_err(|_| SigError::MalformedSignature)?;
+    if signature.normalize_s().is_some() {
+        return Err(SigError::HighS);
+    }
+    let recovery_id = RecoveryId::from_byte(tx.recovery_id).ok_or(SigError::MalformedSignature)?;
+    let public_key = VerifyingKey::recover_from_prehash(&tx.sighash, &signature, recovery_id)
+        .map_err(|_| SigError::InvalidSignature)?;
+    let point = public_key.to_encoded_point(false);
+    (keccak256(&point.as_bytes()[1..])[12..] == data.sender)
+        .then_some(())
+        .ok_or(SigError::InvalidSignature)
+}
+
+/// See `BitcoinTxData::try_verify`, the transaction is parsed and hashed as there
+pub fn bitcoin_tx(data: &BitcoinTxData) -> Result<(), SigError> {
+    use k256_pure::ecdsa::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey};
+
+    match data.signature()? {
+        BitcoinSignature::Ecdsa {
+            sighash,
+            signature,
+            public_key,
+        } => {
+            let signature =
+                Signature::from_der(&signature).map_err(|_| SigError::MalformedSignature)?;
+            if signature.normalize_s().is_some() {
+                return Err(SigError::HighS);
+            }
+            let verifying_key =
+                VerifyingKey::from_sec1_bytes(&public_key).map_err(|_| SigError::MalformedKey)?;
+            verifying_key
+                .verify_prehash(&sighash, &signature)
+                .map_err(|_| SigError::InvalidSignature)
+        }
+        BitcoinSignature::Schnorr {
+            sighash,
+            signature,
+            output_key,
+        } => schnorr_secp256k1(&SchnorrSecp256k1Data {
+            message_hash: sighash,
+            signature,
+            public_key: output_key,
+            hashing: SchnorrHashing::Bip340,
+            taproot: None,
+            message: None,
+        }),
+    }
+}
@@ -0,0 +1,140 @@
+//! Just enough RLP for the signed Ethereum transactions of `EthTxData`: the items of a list are
+//! kept encoded, so the unsigned prefix of a transaction re-encodes as it was signed. Only the
+//! canonical encoding decodes, as Ethereum clients reject any other.
+
+/// Whether the item `data` starts with is a list, its payload and what follows it, `None` if
+/// it is truncated or not canonical
+fn split(data: &[u8]) -> Option<(bool, &[u8], &[u8])> {
+    let (&prefix, rest) = data.split_first()?;
+    let (is_list, len, rest) = match prefix {
+        0x00..=0x7f => return Some((false, &data[..1], rest)),
+        0x80..=0xb7 => (false, (prefix - 0x80) as usize, rest),
+        0xb8..=0xbf => {
+            let (len, rest) = long_len(rest, prefix - 0xb7)?;
+            (false, len, rest)
+        }
+        0xc0..=0xf7 => (true, (prefix - 0xc0) as usize, rest),
+        0xf8..=0xff => {
+            let (len, rest) = long_len(rest, prefix - 0xf7)?;
+            (true, len, rest)
+        }
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let (payload, rest) = rest.split_at(len);
+    // A single byte below 0x80 is its own encoding
+    if !is_list && len == 1 && payload[0] < 0x80 {
+        return None;
+    }
+    Some((is_list, payload, rest))
+}
+
+/// A length of `size` big-endian bytes and what follows it, over 55 or it would be in the
+/// prefix, without leading zeros
+fn long_len(data: &[u8], size: u8) -> Option<(usize, &[u8])> {
+    let size = size as usize;
+    if size > size_of::<usize>() || data.len() < size || data[0] == 0 {
+        return None;
+    }
+    let (len, rest) = data.split_at(size);
+    let len = len.iter().fold(0, |acc, x| acc << 8 | *x as usize);
+    (len > 55).then_some((len, rest))
+}
+
+/// The byte string `data` encodes, `None` unless it is exactly one canonical string
+pub fn bytes(data: &[u8]) -> Option<&[u8]> {
+    match split(data)? {
+        (false, payload, []) => Some(payload),
+        _ => None,
+    }
+}
+
+/// The unsigned integer `data` encodes, `None` unless it is a canonical string of at most 8
+/// bytes without leading zeros
+pub fn uint(data: &[u8]) -> Option<u64> {
+    let bytes = bytes(data)?;
+    if bytes.len() > 8 || bytes.first() == Some(&0) {
+        return None;
+    }
+    Some(bytes.iter().fold(0, |acc, x| acc << 8 | *x as u64))
+}
+
+/// The items of the list `data` encodes, each still encoded, `None` unless it is exactly one
+/// canonical list of canonical items
+pub fn list(data: &[u8]) -> Option<Vec<&[u8]>> {
+    let (true, mut payload, []) = split(data)? else {
+        return None;
+    };
+    let mut items = vec![];
+    while !payload.is_empty() {
+        let (_, _, rest) = split(payload)?;
+        items.push(&payload[..payload.len() - rest.len()]);
+        payload = rest;
+    }
+    Some(items)
+}
+
+fn header(offset: u8, len: usize) -> Vec<u8> {
+    if len <= 55 {
+        return vec![offset + len as u8];
+    }
+    let len = (len as u64).to_be_bytes();
+    let len = &len[len.iter().take_while(|x| **x == 0).count()..];
+    [&[offset + 55 + len.len() as u8][..], len].concat()
+}
+
+pub fn encode_bytes(data: &[u8]) -> Vec<u8> {
+    match data {
+        [x] if *x < 0x80 => vec![*x],
+        _ => [header(0x80, data.len()), data.to_vec()].concat(),
+    }
+}
+
+/// Big-endian without leading zeros, zero is the empty string
+pub fn encode_uint(x: u128) -> Vec<u8> {
+    let bytes = x.to_be_bytes();
+    encode_bytes(&bytes[bytes.iter().take_while(|x| **x == 0).count()..])
+}
+
+/// The list of the encoded `items`
+pub fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload = items.concat();
+    [header(0xc0, payload.len()), payload].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rlp() {
+        // The examples of the Ethereum yellow paper and wiki
+        let dog = encode_bytes(b"dog");
+        assert_eq!(dog, b"\x83dog");
+        let cat_dog = encode_list(&[encode_bytes(b"cat"), dog.clone()]);
+        assert_eq!(cat_dog, b"\xc8\x83cat\x83dog");
+        assert_eq!(encode_uint(0), [0x80]);
+        assert_eq!(encode_uint(15), [0x0f]);
+        assert_eq!(encode_uint(1024), [0x82, 0x04, 0x00]);
+        let lorem = b"Lorem ipsum dolor sit amet, consectetur adipisicing elit";
+        assert_eq!(encode_bytes(lorem)[..2], [0xb8, 0x38]);
+
+        assert_eq!(bytes(&dog), Some(&b"dog"[..]));
+        assert_eq!(bytes(&encode_bytes(lorem)), Some(&lorem[..]));
+        assert_eq!(list(&cat_dog), Some(vec![&b"\x83cat"[..], &dog[..]]));
+        assert_eq!(list(&[0xc0]), Some(vec![]));
+        assert_eq!(uint(&encode_uint(1024)), Some(1024));
+        assert_eq!(uint(&encode_uint(u64::MAX as u128 + 1)), None);
+
+        // Truncated, trailing bytes, the wrong kind and non-canonical encodings
+        assert_eq!(bytes(&dog[..3]), None);
+        assert_eq!(bytes(b"\x83dogs"), None);
+        assert_eq!(bytes(&cat_dog), None);
+        assert_eq!(list(&dog), None);
+        assert_eq!(bytes(&[0x81, 0x05]), None);
+        assert_eq!(bytes(&[0xb8, 0x03, 1, 2, 3]), None);
+        assert_eq!(uint(&[0x82, 0x00, 0x01]), None);
+        assert_eq!(list(b"\xc3\x83ca"), None);
+    }
+}
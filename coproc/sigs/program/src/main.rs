@@ -1,117 +1,164 @@
-//! Signature verification program that verifies ECDSA, Schnorr, and EdDSA signatures
-//! with cycle tracking for performance benchmarking.
+//! Signature verification program that verifies any mix of ECDSA (secp256k1 and P-256), Schnorr,
+//! EdDSA, RSA signatures, WebAuthn assertions, signed Ethereum and Bitcoin transactions and
+//! gateway session handshakes with cycle tracking for performance benchmarking, or only recovers
+//! who signed the ECDSA ones.
 
 #![no_main]
 sp1_zkvm::entrypoint!(main);
 
-use fibonacci_lib::SignatureTestData;
+use alloy_sol_types::SolValue;
+use sigs_lib::{
+    items_from_bincode, recover_signers, ItemsHasher, ProgramMode, Scheme, SchnorrSecp256k1Data,
+    SignatureItem, SignatureVerifier, SignersStruct, StreamHeader, VerdictAccumulator,
+};
 
 pub fn main() {
-    // Read the vector of signature test data from stdin
-    let signature_test_data: Vec<SignatureTestData> = sp1_zkvm::io::read::<Vec<SignatureTestData>>();
-
-    println!("cycle-tracker-start: total");
-
-    let num_signatures = signature_test_data.len();
-    println!("Received {} signature test data instances", num_signatures);
-
-    // Track overall statistics
-    let mut ecdsa_success = 0;
-    let mut schnorr_success = 0;
-    let mut ed25519_success = 0;
-
-    println!("\n=== Individual Verification Mode ===");
-
-    // Verify each signature with individual cycle tracking
-    for (i, test_data) in signature_test_data.iter().enumerate() {
-        println!("\n--- Signature set {} ---", i + 1);
-
-        // Track ECDSA verification
-        println!("cycle-tracker-start: ecdsa_verify_individual");
-        if test_data.ecdsa.verify() {
-            ecdsa_success += 1;
-            println!("✓ ECDSA verified");
-        } else {
-            println!("✗ ECDSA failed");
-        }
-        println!("cycle-tracker-end: ecdsa_verify_individual");
-
-        // Track ECDSA recovery (for comparison)
-        println!("cycle-tracker-start: ecdsa_recover_individual");
-        let _recovered_pubkey = test_data.ecdsa.recover();
-        println!("✓ ECDSA recovered");
-        println!("cycle-tracker-end: ecdsa_recover_individual");
-
-        // Track Schnorr verification (individual)
-        println!("cycle-tracker-start: schnorr_verify_individual");
-        if test_data.schnorr.verify() {
-            schnorr_success += 1;
-            println!("✓ Schnorr verified");
-        } else {
-            println!("✗ Schnorr failed");
+    // Whether to commit the Merkle root of the verified items
+    let commit_authorizations = sp1_zkvm::io::read::<bool>();
+    // Whether to verify every item again on the upstream crates, to compare the cycles
+    let compare_precompiles = sp1_zkvm::io::read::<bool>();
+    // Whether to verify the items, or only recover who signed them
+    let mode = sp1_zkvm::io::read::<ProgramMode>();
+    // The scheme-tagged signature items are streamed in frames, one frame is held at a time
+    // A frame that is not bincode of signature items ends the reading, the items from it on fail
+    let header = sp1_zkvm::io::read::<StreamHeader>();
+    let frames =
+        (0..header.frames).map_while(|i| match items_from_bincode(&sp1_zkvm::io::read_vec()) {
+            Ok(items) => Some(items),
+            Err(e) => {
+                println!("✗ frame {}: {}", i + 1, e);
+                None
+            }
+        });
+
+    if mode != ProgramMode::Verify {
+        println!("\n=== Signer Recovery Mode ===");
+
+        // The address of every ECDSA item recovered, none verified
+        println!("cycle-tracker-start: recover_signers");
+        let mut signers = vec![];
+        let mut count = 0;
+        // The signers are of these items, the hash commits to them
+        let mut hasher = ItemsHasher::default();
+        for items in frames {
+            signers.extend(recover_signers(&items));
+            items.iter().for_each(|x| hasher.push(x));
+            count += items.len();
         }
-        println!("cycle-tracker-end: schnorr_verify_individual");
-
-        // Track Ed25519 verification
-        println!("cycle-tracker-start: ed25519_verify_individual");
-        if test_data.ed25519.verify() {
-            ed25519_success += 1;
-            println!("✓ Ed25519 verified");
-        } else {
-            println!("✗ Ed25519 failed");
+        signers.sort_unstable();
+        signers.dedup();
+        println!("cycle-tracker-end: recover_signers");
+        // The count and the items hash are of the items read, whatever the header declares
+        if count != header.items as usize {
+            println!("✗ {} items read of {} declared", count, header.items);
         }
-        println!("cycle-tracker-end: ed25519_verify_individual");
+        println!("Recovered {} signers of {} items", signers.len(), count);
+
+        let hash_only = mode == ProgramMode::RecoverSignersHash;
+        let public_values = SignersStruct::new(count, &signers, hash_only, hasher.finish());
+        sp1_zkvm::io::commit_slice(&public_values.abi_encode());
+        return;
     }
 
-    println!("\n=== Batch Verification Mode ===");
-
-    // Schnorr batch verification using proper API
-    println!("cycle-tracker-start: schnorr_verify_batch");
-    let schnorr_sigs: Vec<_> = signature_test_data.iter().map(|t| t.schnorr.clone()).collect();
-    let schnorr_batch_success = fibonacci_lib::SchnorrSecp256k1Data::batch_verify(&schnorr_sigs);
-    println!("cycle-tracker-end: schnorr_verify_batch");
-    println!("Schnorr batch: {}/{} verified", schnorr_batch_success, num_signatures);
-
-    // ECDSA batch verification
-    let mut ecdsa_batch_success = 0;
-    println!("cycle-tracker-start: ecdsa_verify_batch");
-    for test_data in signature_test_data.iter() {
-        if test_data.ecdsa.verify() {
-            ecdsa_batch_success += 1;
+    println!(
+        "Receiving {} signature items in {} frames",
+        header.items, header.frames
+    );
+
+    let mut accumulator = VerdictAccumulator::new(header.items as usize, commit_authorizations);
+    let (mut schnorr_batch_success, mut schnorr_batch_count) = (0, 0);
+    for items in frames {
+        // The total is summed over the frames, the comparison left out
+        println!("cycle-tracker-report-start: total");
+
+        println!("\n=== Individual Verification Mode ===");
+
+        // Verify each item under its scheme, tracked per scheme
+        // A malformed item fails with its reason, the others are still verified
+        for item in &items {
+            let name = item.cycle_label();
+
+            println!("cycle-tracker-start: {}_verify_individual", name);
+            let verdict = item.try_verify();
+            println!("cycle-tracker-end: {}_verify_individual", name);
+
+            // Track key recovery where the scheme has it (for comparison)
+            if item.recoverable() {
+                println!("cycle-tracker-start: {}_recover_individual", name);
+                let _recovered_pubkey = item.recover_public_key();
+                println!("cycle-tracker-end: {}_recover_individual", name);
+            }
+
+            // The leaf of a verified item is hashed now, while the frame is held
+            // An item past the count of the header fails instead
+            accumulator.push(item, verdict);
+            match accumulator.verdicts.last().unwrap() {
+                Ok(()) => println!("✓ item {} {}", accumulator.verdicts.len(), name),
+                Err(e) => println!("✗ item {} {}: {}", accumulator.verdicts.len(), name, e),
+            }
         }
-    }
-    println!("cycle-tracker-end: ecdsa_verify_batch");
-    println!("ECDSA batch: {}/{} verified", ecdsa_batch_success, num_signatures);
 
-    // ECDSA batch recovery (for comparison)
-    println!("cycle-tracker-start: ecdsa_recover_batch");
-    for test_data in signature_test_data.iter() {
-        let _recovered = test_data.ecdsa.recover();
-    }
-    println!("cycle-tracker-end: ecdsa_recover_batch");
-    println!("ECDSA batch: 10/10 recovered");
-
-    // Ed25519 batch verification
-    let mut ed25519_batch_success = 0;
-    println!("cycle-tracker-start: ed25519_verify_batch");
-    for test_data in signature_test_data.iter() {
-        if test_data.ed25519.verify() {
-            ed25519_batch_success += 1;
+        println!("\n=== Batch Verification Mode ===");
+
+        // Schnorr batch verification over the Schnorr items of the frame
+        let schnorr_sigs: Vec<_> = items
+            .iter()
+            .filter_map(|item| match item {
+                SignatureItem::SchnorrSecp256k1(x) => Some(x.clone()),
+                _ => None,
+            })
+            .collect();
+        println!("cycle-tracker-start: schnorr_verify_batch");
+        schnorr_batch_success += SchnorrSecp256k1Data::batch_verify(&schnorr_sigs);
+        println!("cycle-tracker-end: schnorr_verify_batch");
+        schnorr_batch_count += schnorr_sigs.len();
+
+        println!("cycle-tracker-report-end: total");
+
+        // The same items on the SP1-patched crates and on the upstream ones, the cycles of both
+        // are summed per section in the execution report
+        if compare_precompiles {
+            println!("\n=== Precompile vs Pure-Rust Mode ===");
+            let first = accumulator.verdicts.len() - items.len();
+            for (i, item) in items.iter().enumerate() {
+                let name = item.cycle_label();
+
+                println!("cycle-tracker-report-start: {}_precompile", name);
+                let verdict = item.try_verify();
+                println!("cycle-tracker-report-end: {}_precompile", name);
+
+                println!("cycle-tracker-report-start: {}_pure", name);
+                let pure = item.try_verify_pure();
+                println!("cycle-tracker-report-end: {}_pure", name);
+
+                if pure.is_some_and(|pure| pure != verdict) {
+                    println!(
+                        "✗ item {} {}: pure-Rust verdict differs",
+                        first + i + 1,
+                        name
+                    );
+                }
+            }
         }
     }
-    println!("cycle-tracker-end: ed25519_verify_batch");
-    println!("Ed25519 batch: {}/{} verified", ed25519_batch_success, num_signatures);
-
-    println!("cycle-tracker-end: total");
+    // The items a malformed frame or a short stream left out fail, none is missing a verdict
+    accumulator.fail_unread();
+    println!(
+        "Schnorr batch: {}/{} verified",
+        schnorr_batch_success, schnorr_batch_count
+    );
 
     // Print summary
     println!("\n=== Final Summary ===");
-    println!("ECDSA:   {}/{} succeeded", ecdsa_success, num_signatures);
-    println!("Schnorr: {}/{} succeeded", schnorr_success, num_signatures);
-    println!("Ed25519: {}/{} succeeded", ed25519_success, num_signatures);
-
-    // Commit the results as public values
-    sp1_zkvm::io::commit(&ecdsa_success);
-    sp1_zkvm::io::commit(&schnorr_success);
-    sp1_zkvm::io::commit(&ed25519_success);
+    for scheme in Scheme::ALL {
+        let (ok, count) = accumulator.tally.get(&scheme).copied().unwrap_or_default();
+        println!("{:8} {}/{} succeeded", scheme.name(), ok, count);
+    }
+
+    // Commit the verdict of every item as a bitmap by input index, with the reason of a failure
+    // and the Merkle root of the leaves of the verified items if asked
+    println!("cycle-tracker-start: authorizations_root");
+    let public_values = accumulator.public_values();
+    println!("cycle-tracker-end: authorizations_root");
+    sp1_zkvm::io::commit_slice(&public_values.abi_encode());
 }
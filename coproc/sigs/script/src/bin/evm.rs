@@ -12,22 +12,26 @@
 
 use alloy_sol_types::SolType;
 use clap::{Parser, ValueEnum};
-use fibonacci_lib::PublicValuesStruct;
 use serde::{Deserialize, Serialize};
+use sigs_lib::{item_frames, ProgramMode, PublicValuesStruct, Scheme, SignatureItem, FRAME_ITEMS};
 use sp1_sdk::{
     include_elf, HashableKey, ProverClient, SP1ProofWithPublicValues, SP1Stdin, SP1VerifyingKey,
 };
 use std::path::PathBuf;
 
 /// The ELF (executable and linkable format) file for the Succinct RISC-V zkVM.
-pub const FIBONACCI_ELF: &[u8] = include_elf!("fibonacci-program");
+pub const SIGS_ELF: &[u8] = include_elf!("sigs-program");
 
 /// The arguments for the EVM command.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct EVMArgs {
-    #[arg(long, default_value = "20")]
-    n: u32,
+    /// Number of sample signature items, every scheme in turn
+    #[arg(long, default_value = "8")]
+    count: usize,
+    /// Also commit the Merkle root of the verified (scheme, message hash, public key)
+    #[arg(long)]
+    authorizations: bool,
     #[arg(long, value_enum, default_value = "groth16")]
     system: ProofSystem,
 }
@@ -42,10 +46,12 @@ enum ProofSystem {
 /// A fixture that can be used to test the verification of SP1 zkVM proofs inside Solidity.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct SP1FibonacciProofFixture {
-    a: u32,
-    b: u32,
-    n: u32,
+struct SP1SigsProofFixture {
+    count: u32,
+    bitmap: String,
+    failures: String,
+    authorizations: String,
+    items: String,
     vkey: String,
     public_values: String,
     proof: String,
@@ -62,13 +68,21 @@ fn main() {
     let client = ProverClient::from_env();
 
     // Setup the program.
-    let (pk, vk) = client.setup(FIBONACCI_ELF);
+    let (pk, vk) = client.setup(SIGS_ELF);
 
     // Setup the inputs.
+    let items: Vec<SignatureItem> = (0..args.count)
+        .map(|i| SignatureItem::sample(Scheme::ALL[i % Scheme::ALL.len()]))
+        .collect();
     let mut stdin = SP1Stdin::new();
-    stdin.write(&args.n);
-
-    println!("n: {}", args.n);
+    stdin.write(&args.authorizations);
+    stdin.write(&false);
+    stdin.write(&ProgramMode::Verify);
+    let (header, frames) = item_frames(&items, FRAME_ITEMS).expect("failed to serialize the items");
+    stdin.write(&header);
+    frames.into_iter().for_each(|frame| stdin.write_vec(frame));
+
+    println!("count: {}", args.count);
     println!("Proof System: {:?}", args.system);
 
     // Generate the proof based on the selected proof system.
@@ -89,13 +103,21 @@ fn create_proof_fixture(
 ) {
     // Deserialize the public values.
     let bytes = proof.public_values.as_slice();
-    let PublicValuesStruct { n, a, b } = PublicValuesStruct::abi_decode(bytes).unwrap();
+    let PublicValuesStruct {
+        count,
+        bitmap,
+        failures,
+        authorizations,
+        items,
+    } = PublicValuesStruct::abi_decode(bytes).unwrap();
 
     // Create the testing fixture so we can test things end-to-end.
-    let fixture = SP1FibonacciProofFixture {
-        a,
-        b,
-        n,
+    let fixture = SP1SigsProofFixture {
+        count,
+        bitmap: format!("0x{}", hex::encode(bitmap)),
+        failures: format!("0x{}", hex::encode(failures)),
+        authorizations: authorizations.to_string(),
+        items: items.to_string(),
         vkey: vk.bytes32().to_string(),
         public_values: format!("0x{}", hex::encode(bytes)),
         proof: format!("0x{}", hex::encode(proof.bytes())),
@@ -9,11 +9,23 @@
 //! ```shell
 //! RUST_LOG=info cargo run --release -- --prove
 //! ```
+//! or, for a Groth16 proof written to a fixture of the Foundry tests
+//! ```shell
+//! RUST_LOG=info cargo run --release -- --prove --fixture
+//! ```
 
 use alloy_sol_types::SolType;
 use clap::Parser;
-use fibonacci_lib::{create_sample_signature_test_data, PublicValuesStruct, SignatureTestData};
-use sp1_sdk::{include_elf, ProverClient, SP1Stdin};
+use serde::Serialize;
+use sigs_lib::{
+    item_frames, ProgramMode, PublicValuesStruct, SampleGenerator, Scheme, SignatureItem,
+    SignatureVerifier, SignersStruct, FRAME_ITEMS,
+};
+use sp1_sdk::{
+    include_elf, ExecutionReport, HashableKey, ProverClient, SP1ProofWithPublicValues, SP1Stdin,
+    SP1VerifyingKey,
+};
+use std::path::PathBuf;
 
 // Cryptographic imports
 use k256::{
@@ -35,12 +47,13 @@ use ed25519_dalek::{
     SigningKey as EdSigningKey,
     VerifyingKey as EdVerifyingKey,
 };
-use rand_core::OsRng;
+use rand_core::{OsRng, RngCore};
 use sha2::{Digest, Sha256};
 use tiny_keccak::{Hasher, Keccak};
+use zeroize::ZeroizeOnDrop;
 
 /// The ELF (executable and linkable format) file for the Succinct RISC-V zkVM.
-pub const FIBONACCI_ELF: &[u8] = include_elf!("fibonacci-program");
+pub const SIGS_ELF: &[u8] = include_elf!("sigs-program");
 
 /// The arguments for the command.
 #[derive(Parser, Debug)]
@@ -54,6 +67,58 @@ struct Args {
 
     #[arg(long, default_value = "20")]
     n: u32,
+
+    /// Number of signature items in the mix, spread evenly over the schemes
+    #[arg(long, default_value = "80")]
+    count: usize,
+
+    /// Schemes in the mix, comma separated: ecdsa, schnorr, ed25519, p256, webauthn, rsa, frost,
+    /// musig2, ethtx, btctx, session, cosign
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "ecdsa,schnorr,ed25519,p256,webauthn,rsa,frost,musig2,ethtx,btctx,session,cosign"
+    )]
+    schemes: Vec<Scheme>,
+
+    /// Seed of the random keys and messages, a random one (printed) if not set
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Also commit the Merkle root of the verified (scheme, message hash, public key)
+    #[arg(long)]
+    authorizations: bool,
+
+    /// Also verify every item on the upstream crates, and report the cycles of both per section
+    #[arg(long)]
+    compare_precompiles: bool,
+
+    /// Instead of verifying, recover and commit the addresses of the signers of the ECDSA items
+    #[arg(long)]
+    recover_signers: bool,
+
+    /// With --recover-signers, commit only the hash of the addresses
+    #[arg(long, requires = "recover_signers")]
+    signers_hash_only: bool,
+
+    /// Items per frame of the input, the program holds one frame at a time
+    #[arg(long, default_value_t = FRAME_ITEMS)]
+    frame_items: usize,
+
+    /// Prove with Groth16, verifiable on-chain, and write the proof to a fixture of the Foundry
+    /// tests in contracts/src/fixtures
+    #[arg(long, requires = "prove")]
+    fixture: bool,
+}
+
+/// A proof of the program for the Foundry tests, what the SP1 verifier contract takes: the
+/// public values are a `PublicValuesStruct`, or a `SignersStruct` when recovering signers
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SP1SigsFixture {
+    vkey: String,
+    public_values: String,
+    proof: String,
 }
 
 fn main() {
@@ -72,47 +137,106 @@ fn main() {
     // Setup the prover client.
     let client = ProverClient::from_env();
 
-    // Create a vector of 10 signature test data instances
-    println!("Creating 10 signature test data instances...");
-    let signature_test_data: Vec<SignatureTestData> = (0..10)
-        .map(|_| create_sample_signature_test_data())
-        .collect();
-
-    println!("Created {} signature test data instances", signature_test_data.len());
+    if args.schemes.is_empty() {
+        eprintln!("Error: --schemes must select at least one scheme");
+        std::process::exit(1);
+    }
 
-    // Setup the inputs - serialize the vector with bincode
+    // Create the mix of signature items, random keys and messages, spread evenly over the schemes
+    let seed = args.seed.unwrap_or_else(|| OsRng.next_u64());
+    let n = args.schemes.len();
+    let items: Vec<SignatureItem> = args
+        .schemes
+        .iter()
+        .enumerate()
+        .fold(SampleGenerator::new(seed), |generator, (i, scheme)| {
+            generator.scheme(*scheme, args.count / n + (i < args.count % n) as usize)
+        })
+        .build();
+
+    println!("Created {} signature items (seed {})", items.len(), seed);
+
+    // Setup the inputs - the flags, then the items streamed in bincode frames
     let mut stdin = SP1Stdin::new();
-    stdin.write(&signature_test_data);
+    stdin.write(&args.authorizations);
+    stdin.write(&args.compare_precompiles);
+    let mode = match (args.recover_signers, args.signers_hash_only) {
+        (false, _) => ProgramMode::Verify,
+        (true, false) => ProgramMode::RecoverSigners,
+        (true, true) => ProgramMode::RecoverSignersHash,
+    };
+    stdin.write(&mode);
+    let (header, frames) =
+        item_frames(&items, args.frame_items).expect("failed to serialize the items");
+    stdin.write(&header);
+    frames.into_iter().for_each(|frame| stdin.write_vec(frame));
 
     if args.execute {
         // Execute the program
         println!("Executing signature verification program...");
-        let (output, report) = client.execute(FIBONACCI_ELF, &stdin).run().unwrap();
+        let (output, report) = client.execute(SIGS_ELF, &stdin).run().unwrap();
         println!("Program executed successfully.");
 
+        if mode != ProgramMode::Verify {
+            // Who signed the items, not whether they verify
+            let public_values = SignersStruct::abi_decode(output.as_slice()).unwrap();
+            println!(
+                "Signers of {} items: {}",
+                public_values.count, public_values.hash
+            );
+            for signer in &public_values.signers {
+                println!("  {}", signer);
+            }
+            println!("Total cycles: {}", report.total_instruction_count());
+            return;
+        }
+
+        // The verdict of every item, in input order
+        let public_values = PublicValuesStruct::abi_decode(output.as_slice()).unwrap();
+        let verdicts = public_values
+            .verdicts()
+            .expect("failures don't match the bitmap");
+        let verified = verdicts.iter().filter(|verdict| verdict.is_ok()).count();
+        println!("Verified {}/{} items", verified, verdicts.len());
+        if args.authorizations {
+            println!("Authorizations root: {}", public_values.authorizations);
+        }
+        for (i, verdict) in verdicts.iter().enumerate() {
+            if let Err(e) = verdict {
+                println!("Item {} ({}) failed: {}", i + 1, items[i].cycle_label(), e);
+            }
+        }
+
         // The program will output cycle tracking information
-        // Read the output (empty for now, but we can add public values later)
         println!("\n=== Execution Report ===");
         println!("Total instruction count: {}", report.total_instruction_count());
         println!("Total cycles: {}", report.total_instruction_count());
 
+        if args.compare_precompiles {
+            print_precompile_report(&report);
+        }
     } else {
         // Setup the program for proving.
         println!("Setting up program for proving...");
-        let (pk, vk) = client.setup(FIBONACCI_ELF);
+        let (pk, vk) = client.setup(SIGS_ELF);
 
-        // Generate the proof
+        // Generate the proof, wrapped in Groth16 for the EVM if it goes to the fixture
         println!("Generating proof...");
-        let proof = client
-            .prove(&pk, &stdin)
-            .run()
-            .expect("failed to generate proof");
+        let proof = match args.fixture {
+            true => client.prove(&pk, &stdin).groth16().run(),
+            false => client.prove(&pk, &stdin).run(),
+        }
+        .expect("failed to generate proof");
 
         println!("Successfully generated proof!");
 
         // Verify the proof.
         client.verify(&proof, &vk).expect("failed to verify proof");
         println!("Successfully verified proof!");
+
+        if args.fixture {
+            write_fixture(&proof, &vk);
+        }
     }
 }
 
@@ -120,6 +244,91 @@ fn main() {
 // Helper Functions
 // ============================================================================
 
+/// Cycles per signature of every section verified on the SP1-patched crates and on the upstream
+/// ones, from the `{section}_precompile` and `{section}_pure` trackers of the program
+fn print_precompile_report(report: &ExecutionReport) {
+    let (cycles, invocations) = (&report.cycle_tracker, &report.invocation_tracker);
+    let per_signature = |key: &str| Some(cycles.get(key)? / invocations.get(key)?.max(&1));
+    let mut sections: Vec<_> = cycles
+        .keys()
+        .filter_map(|x| x.strip_suffix("_precompile"))
+        .collect();
+    sections.sort();
+
+    println!("\n=== Precompile vs Pure-Rust ===");
+    println!(
+        "{:20} {:>8} {:>14} {:>14} {:>8}",
+        "section", "items", "precompile", "pure-Rust", "saved"
+    );
+    for section in sections {
+        let precompile_key = format!("{}_precompile", section);
+        let count = invocations
+            .get(&precompile_key)
+            .copied()
+            .unwrap_or_default();
+        let Some(precompile) = per_signature(&precompile_key) else {
+            continue;
+        };
+        match per_signature(&format!("{}_pure", section)) {
+            Some(pure) => println!(
+                "{:20} {:>8} {:>14} {:>14} {:>7.1}x",
+                section,
+                count,
+                precompile,
+                pure,
+                pure as f64 / precompile.max(1) as f64
+            ),
+            None => println!(
+                "{:20} {:>8} {:>14} {:>14} {:>8}",
+                section, count, precompile, "-", "-"
+            ),
+        }
+    }
+}
+
+/// A keypair of the helpers below: the signing key is only lent out and its secret is zeroized
+/// when the keypair goes out of scope, keys that don't zeroize on drop don't type check
+/// Some deployments sign real vouchers with these helpers
+pub struct Keypair<S: ZeroizeOnDrop, V: Copy> {
+    signing_key: S,
+    verifying_key: V,
+}
+
+impl<S: ZeroizeOnDrop, V: Copy> Keypair<S, V> {
+    pub fn signing_key(&self) -> &S {
+        &self.signing_key
+    }
+
+    pub fn verifying_key(&self) -> V {
+        self.verifying_key
+    }
+}
+
+impl<S: ZeroizeOnDrop, V: Copy> std::fmt::Debug for Keypair<S, V> {
+    /// The secret left out, a keypair may be logged
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keypair").finish_non_exhaustive()
+    }
+}
+
+/// Write the Groth16 `proof` to contracts/src/fixtures/sigs-groth16-fixture.json
+fn write_fixture(proof: &SP1ProofWithPublicValues, vk: &SP1VerifyingKey) {
+    let fixture = SP1SigsFixture {
+        vkey: vk.bytes32().to_string(),
+        public_values: format!("0x{}", hex::encode(proof.public_values.as_slice())),
+        proof: format!("0x{}", hex::encode(proof.bytes())),
+    };
+    println!("Verification Key: {}", fixture.vkey);
+    println!("Public Values: {}", fixture.public_values);
+
+    let fixture_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../contracts/src/fixtures");
+    std::fs::create_dir_all(&fixture_path).expect("failed to create fixture path");
+    let file = fixture_path.join("sigs-groth16-fixture.json");
+    std::fs::write(&file, serde_json::to_string_pretty(&fixture).unwrap())
+        .expect("failed to write fixture");
+    println!("Fixture written to {}", file.display());
+}
+
 /// Hash a message using Keccak-256
 pub fn keccak256(data: &[u8]) -> [u8; 32] {
     let mut hasher = Keccak::v256();
@@ -141,10 +350,13 @@ pub fn sha256(data: &[u8]) -> [u8; 32] {
 // ============================================================================
 
 /// Generate a new ECDSA keypair
-pub fn ecdsa_generate_keypair() -> (EcdsaSigningKey, EcdsaVerifyingKey) {
+pub fn ecdsa_generate_keypair() -> Keypair<EcdsaSigningKey, EcdsaVerifyingKey> {
     let signing_key = EcdsaSigningKey::random(&mut OsRng);
     let verifying_key = *signing_key.verifying_key();
-    (signing_key, verifying_key)
+    Keypair {
+        signing_key,
+        verifying_key,
+    }
 }
 
 /// Sign a message using ECDSA over secp256k1
@@ -192,10 +404,13 @@ pub fn ecdsa_verify_prehashed(
 // ============================================================================
 
 /// Generate a new Schnorr keypair
-pub fn schnorr_generate_keypair() -> (SchnorrSigningKey, SchnorrVerifyingKey) {
+pub fn schnorr_generate_keypair() -> Keypair<SchnorrSigningKey, SchnorrVerifyingKey> {
     let signing_key = SchnorrSigningKey::random(&mut OsRng);
     let verifying_key = *signing_key.verifying_key();
-    (signing_key, verifying_key)
+    Keypair {
+        signing_key,
+        verifying_key,
+    }
 }
 
 /// Sign a message using Schnorr signatures over secp256k1
@@ -243,10 +458,13 @@ pub fn schnorr_verify_prehashed(
 // ============================================================================
 
 /// Generate a new Ed25519 keypair
-pub fn eddsa_generate_keypair() -> (EdSigningKey, EdVerifyingKey) {
+pub fn eddsa_generate_keypair() -> Keypair<EdSigningKey, EdVerifyingKey> {
     let signing_key = EdSigningKey::generate(&mut OsRng);
     let verifying_key = signing_key.verifying_key();
-    (signing_key, verifying_key)
+    Keypair {
+        signing_key,
+        verifying_key,
+    }
 }
 
 /// Sign a message using EdDSA (Ed25519)
@@ -275,33 +493,33 @@ mod tests {
 
     #[test]
     fn test_ecdsa_sign_verify() {
-        let (signing_key, verifying_key) = ecdsa_generate_keypair();
+        let keypair = ecdsa_generate_keypair();
         let message = b"Hello, ECDSA!";
 
-        let signature = ecdsa_sign(&signing_key, message);
-        let result = ecdsa_verify(&verifying_key, message, &signature);
+        let signature = ecdsa_sign(keypair.signing_key(), message);
+        let result = ecdsa_verify(&keypair.verifying_key(), message, &signature);
 
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_schnorr_sign_verify() {
-        let (signing_key, verifying_key) = schnorr_generate_keypair();
+        let keypair = schnorr_generate_keypair();
         let message = b"Hello, Schnorr!";
 
-        let signature = schnorr_sign(&signing_key, message);
-        let result = schnorr_verify(&verifying_key, message, &signature);
+        let signature = schnorr_sign(keypair.signing_key(), message);
+        let result = schnorr_verify(&keypair.verifying_key(), message, &signature);
 
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_eddsa_sign_verify() {
-        let (signing_key, verifying_key) = eddsa_generate_keypair();
+        let keypair = eddsa_generate_keypair();
         let message = b"Hello, EdDSA!";
 
-        let signature = eddsa_sign(&signing_key, message);
-        let result = eddsa_verify(&verifying_key, message, &signature);
+        let signature = eddsa_sign(keypair.signing_key(), message);
+        let result = eddsa_verify(&keypair.verifying_key(), message, &signature);
 
         assert!(result.is_ok());
     }
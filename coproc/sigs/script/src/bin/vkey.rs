@@ -1,10 +1,10 @@
 use sp1_sdk::{include_elf, HashableKey, Prover, ProverClient};
 
 /// The ELF (executable and linkable format) file for the Succinct RISC-V zkVM.
-pub const FIBONACCI_ELF: &[u8] = include_elf!("fibonacci-program");
+pub const SIGS_ELF: &[u8] = include_elf!("sigs-program");
 
 fn main() {
     let prover = ProverClient::builder().cpu().build();
-    let (_, vk) = prover.setup(FIBONACCI_ELF);
+    let (_, vk) = prover.setup(SIGS_ELF);
     println!("{}", vk.bytes32());
 }
\ No newline at end of file
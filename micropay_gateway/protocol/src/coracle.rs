@@ -10,6 +10,8 @@ pub trait ClientOracleRecord<VendorId> {
     fn collateral_now(&self) -> u64;
     /// returns subscriptions now
     fn subscriptions_now(&self) -> u64;
+    /// the key the client signs its vouchers with (ex. an erc20 address), empty if none is registered
+    fn voucher_key(&self) -> &[u8];
 }
 
 pub trait ClientOracleRead<Ci, Vi, COR: ClientOracleRecord<Vi>> {
@@ -19,7 +21,8 @@ pub trait ClientOracleRead<Ci, Vi, COR: ClientOracleRecord<Vi>> {
         f: F,
     ) -> impl std::future::Future<Output = Result<R, std::io::Error>> + Send
     where
-        F: FnOnce(&COR) -> R;
+        F: FnOnce(&COR) -> R,
+        R: Send;
 }
 
 #[derive(Clone)]
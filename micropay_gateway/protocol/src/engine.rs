@@ -175,6 +175,13 @@ where
     T1: ClientOracleRead<Ci, Vi, COR>,
     T2: ClientOutstandingBalanceOp<Ci, OBR>,
 {
+    pub fn new(
+        va: VoucherAuth<Ci, Vi, V, COR, T0, T1>,
+        ob: OutstandingBalanceTracker<T2, Ci, OBR>,
+        cr: ClientRiskConfig,
+    ) -> Self {
+        Self { va, ob, cr }
+    }
     pub async fn accept_session(&self, v: &V) -> Result<(), EngineErr> {
         Ok(self
             .va
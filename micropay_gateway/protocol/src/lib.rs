@@ -5,4 +5,10 @@ mod settle;
 mod vauth;
 mod voucher;
 
-pub use engine::{ApiEngine, CronEngine};
+pub use coracle::{ClientOracle, ClientOracleRead, ClientOracleRecord};
+pub use engine::{ApiEngine, ClientRiskConfig, CronEngine, EngineErr, QueryCont};
+pub use obalance::{
+    ClientOutstandingBalanceOp, OutstandingBalanceRecord, OutstandingBalanceTracker,
+};
+pub use vauth::{StaticVAuthErr, VAuthErr, VolatileVAuthErr, VoucherAuth};
+pub use voucher::{ClientUnspentVouchers, UnspentVoucherTracker, UnspentVouchersOp, Voucher};
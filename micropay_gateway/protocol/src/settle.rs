@@ -9,7 +9,8 @@ pub trait SettleVouchersOp<Ci, Vi, V: Voucher<Ci, Vi>> {
         f: F,
     ) -> impl std::future::Future<Output = Result<R, std::io::Error>> + Send
     where
-        F: FnOnce(&mut ClientSettleVouchers<Ci, Vi, V>) -> R;
+        F: FnOnce(&mut ClientSettleVouchers<Ci, Vi, V>) -> R,
+        R: Send;
 }
 
 pub struct SettleVouchers<Ci, Vi, V, T> {
@@ -10,11 +10,11 @@ use thiserror::Error;
 /// The voucher is valid if:
 /// - insert voucher if next in seq
 /// STATIC:
-/// - the voucher sig is valid
 /// - the voucher is in the name of the vendor
 /// VOLATILE:
 /// - the voucher is unspent (subject to change based on usage)
 /// - the client is subscribed to vendor (subject to change based on client changes)
+/// - the voucher sig is valid under the key the client registered (subject to key rotation)
 /// - the client collateral is >= voucher size
 ///     (subject to change on client withdrawing or settling against other vendors)
 pub struct VoucherAuth<Ci, Vi, V, COR, T0, T1> {
@@ -102,7 +102,10 @@ where
             .b
             .r_on_client_oracle(&v.client_identifier(), |r| {
                 if !r.is_subscribed_to_be(&self.vendor) {
-                    return Err(VolatileVAuthErr::ClientIsNotSubscribed);
+                    return Err(VolatileVAuthErr::ClientIsNotSubscribed.into());
+                }
+                if !v.is_valid_signature(r.voucher_key()) {
+                    return Err(StaticVAuthErr::InvalidSig.into());
                 }
                 let collat = r.collateral_to_be();
                 let va = v.voucher_atoms();
@@ -111,9 +114,10 @@ where
                     return Err(VolatileVAuthErr::ClientHasInsufficientBalance {
                         seen_balance: collat,
                         voucher_atoms: va,
-                    });
+                    }
+                    .into());
                 }
-                Ok(())
+                Ok::<_, VAuthErr>(())
             })
             .await??; // notice unwrap both errs
         Ok(())
@@ -121,9 +125,6 @@ where
 
     /// Called whenever new voucher is seen.
     fn is_auth_static(&self, v: &V) -> Result<(), StaticVAuthErr> {
-        if !v.is_valid_signature() {
-            return Err(StaticVAuthErr::InvalidSig);
-        }
         if v.voucher_atoms() == 0 {
             return Err(StaticVAuthErr::VoucherHasZeroAtoms);
         }
@@ -2,9 +2,10 @@ use std::marker::PhantomData;
 
 /// Abstraction over a voucher
 /// Ci = ClientId, Vi = VendorId
-pub trait Voucher<Ci, Vi>: Clone {
-    /// returns `true` if the cryptographic signature on the voucher is valid
-    fn is_valid_signature(&self) -> bool;
+pub trait Voucher<Ci, Vi>: Clone + Send {
+    /// returns `true` if the cryptographic signature on the voucher is valid under `key`,
+    /// the key the client registered on chain, see `ClientOracleRecord::voucher_key`
+    fn is_valid_signature(&self, key: &[u8]) -> bool;
     /// nonce of the voucher, this value increases with each next voucher signed
     /// like a blockchain transaction
     fn nonce(&self) -> u64;
@@ -30,7 +31,8 @@ pub trait UnspentVouchersOp<Ci, Vi, V: Voucher<Ci, Vi>> {
         f: F,
     ) -> impl std::future::Future<Output = Result<R, std::io::Error>> + Send
     where
-        F: FnOnce(&mut ClientUnspentVouchers<Ci, Vi, V>) -> R;
+        F: FnOnce(&mut ClientUnspentVouchers<Ci, Vi, V>) -> R,
+        R: Send;
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,337 @@
+//! Gateway configuration, loaded once at startup.
+//!
+//! Every setting comes from, highest precedence first: a command line flag, its `GATEWAY_*` env var,
+//! the toml file given by `--config` / `GATEWAY_CONFIG`, the default.
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::Parser;
+use micropay_gateway::meter::{
+    DEFAULT_ATOMS_PER_MIB, DEFAULT_ATOMS_PER_ROW, DEFAULT_ATOMS_PER_SEC, DEFAULT_QUERY_ESTIMATE,
+    Price,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::pgwire::{MAX_MESSAGE_LEN, READ_CHUNK};
+
+pub const DEFAULT_LISTEN_ADDR: &str = "0.0.0.0:5433"; // where clients connect
+pub const DEFAULT_BACKEND_ADDR: &str = "127.0.0.1:5432"; // real postgres
+pub const DEFAULT_MAX_CONNECTIONS: usize = 1024;
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+/// TLS, startup packet and voucher check all have to fit in this
+pub const DEFAULT_HANDSHAKE_TIMEOUT_SECS: u64 = 30;
+/// smallest read buffer accepted, a startup packet should fit in a couple of reads
+pub const MIN_READ_BUFFER: usize = 1024;
+
+#[derive(Parser, Debug)]
+#[command(about = "PostgreSQL gateway that admits and charges clients by voucher")]
+pub struct Args {
+    /// toml file with any of the settings below, flags and env vars take precedence
+    #[arg(long, env = "GATEWAY_CONFIG")]
+    pub config: Option<PathBuf>,
+    #[command(flatten)]
+    pub settings: Settings,
+}
+
+/// Settings as given by one source, unset fields fall through to the next.
+#[derive(clap::Args, Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// address clients connect to [default: 0.0.0.0:5433]
+    #[arg(long, env = "GATEWAY_LISTEN_ADDR")]
+    pub listen_addr: Option<String>,
+    /// host:port of the real postgres [default: 127.0.0.1:5432]
+    #[arg(long, env = "GATEWAY_BACKEND_ADDR")]
+    pub backend_addr: Option<String>,
+    /// connections served at once, the rest are turned away [default: 1024]
+    #[arg(long, env = "GATEWAY_MAX_CONNECTIONS")]
+    pub max_connections: Option<usize>,
+    /// bytes read off a socket at a time [default: 8192]
+    #[arg(long, env = "GATEWAY_READ_BUFFER")]
+    pub read_buffer: Option<usize>,
+    /// largest protocol message relayed, in bytes [default: 1073741823]
+    #[arg(long, env = "GATEWAY_MAX_MESSAGE_LEN")]
+    pub max_message_len: Option<usize>,
+    /// seconds to wait for the backend to accept a connection [default: 10]
+    #[arg(long, env = "GATEWAY_CONNECT_TIMEOUT_SECS")]
+    pub connect_timeout_secs: Option<u64>,
+    /// seconds a client gets to complete TLS and send a startup packet with a voucher [default: 30]
+    #[arg(long, env = "GATEWAY_HANDSHAKE_TIMEOUT_SECS")]
+    pub handshake_timeout_secs: Option<u64>,
+    /// seconds without a message either way before the connection is closed, 0 never [default: 0]
+    #[arg(long, env = "GATEWAY_IDLE_TIMEOUT_SECS")]
+    pub idle_timeout_secs: Option<u64>,
+    /// pem certificate chain for client facing TLS
+    #[arg(long, env = "GATEWAY_TLS_CERT")]
+    pub tls_cert: Option<PathBuf>,
+    /// pem private key of `tls_cert`
+    #[arg(long, env = "GATEWAY_TLS_KEY")]
+    pub tls_key: Option<PathBuf>,
+    /// pem CA the backend certificate must chain to, enables TLS to the backend
+    #[arg(long, env = "GATEWAY_BACKEND_CA")]
+    pub backend_ca: Option<PathBuf>,
+    /// toml with `[[client]]` collateral and subscriptions, stands in for the chain
+    #[arg(long, env = "GATEWAY_ORACLE_FILE")]
+    pub oracle_file: Option<PathBuf>,
+    /// voucher atoms charged per MiB sent to the client [default: 1000]
+    #[arg(long, env = "GATEWAY_PRICE_PER_MIB")]
+    pub price_per_mib: Option<u64>,
+    /// voucher atoms charged per second a query runs [default: 100]
+    #[arg(long, env = "GATEWAY_PRICE_PER_SEC")]
+    pub price_per_sec: Option<u64>,
+    /// voucher atoms charged per row returned or affected [default: 0]
+    #[arg(long, env = "GATEWAY_PRICE_PER_ROW")]
+    pub price_per_row: Option<u64>,
+    /// atoms a client must have available to start a query [default: 1000]
+    #[arg(long, env = "GATEWAY_QUERY_ESTIMATE")]
+    pub query_estimate: Option<u64>,
+}
+
+impl Settings {
+    /// Field by field, `self` wins over `other`.
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            listen_addr: self.listen_addr.or(other.listen_addr),
+            backend_addr: self.backend_addr.or(other.backend_addr),
+            max_connections: self.max_connections.or(other.max_connections),
+            read_buffer: self.read_buffer.or(other.read_buffer),
+            max_message_len: self.max_message_len.or(other.max_message_len),
+            connect_timeout_secs: self.connect_timeout_secs.or(other.connect_timeout_secs),
+            handshake_timeout_secs: self.handshake_timeout_secs.or(other.handshake_timeout_secs),
+            idle_timeout_secs: self.idle_timeout_secs.or(other.idle_timeout_secs),
+            tls_cert: self.tls_cert.or(other.tls_cert),
+            tls_key: self.tls_key.or(other.tls_key),
+            backend_ca: self.backend_ca.or(other.backend_ca),
+            oracle_file: self.oracle_file.or(other.oracle_file),
+            price_per_mib: self.price_per_mib.or(other.price_per_mib),
+            price_per_sec: self.price_per_sec.or(other.price_per_sec),
+            price_per_row: self.price_per_row.or(other.price_per_row),
+            query_estimate: self.query_estimate.or(other.query_estimate),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub listen_addr: SocketAddr,
+    /// host:port
+    pub backend_addr: String,
+    /// host part of `backend_addr`, what its TLS certificate is checked against
+    pub backend_host: String,
+    pub max_connections: usize,
+    pub read_buffer: usize,
+    pub max_message_len: usize,
+    pub connect_timeout: Duration,
+    pub handshake_timeout: Duration,
+    pub idle_timeout: Option<Duration>,
+    /// (certificate chain, private key)
+    pub tls: Option<(PathBuf, PathBuf)>,
+    pub backend_ca: Option<PathBuf>,
+    pub oracle_file: Option<PathBuf>,
+    pub price: Price,
+}
+
+impl Config {
+    /// Parse flags and env, read the toml file if any and validate the lot.
+    pub fn load() -> Result<Self, ConfigErr> {
+        let args = Args::parse();
+        let file = match &args.config {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| ConfigErr::Read(path.display().to_string(), e))?;
+                toml::from_str(&text)?
+            }
+            None => Settings::default(),
+        };
+        Self::try_from(args.settings.or(file))
+    }
+}
+
+impl TryFrom<Settings> for Config {
+    type Error = ConfigErr;
+
+    fn try_from(s: Settings) -> Result<Self, ConfigErr> {
+        let listen_addr = s.listen_addr.as_deref().unwrap_or(DEFAULT_LISTEN_ADDR);
+        let listen_addr = listen_addr
+            .parse()
+            .map_err(|_| ConfigErr::Address(listen_addr.to_string()))?;
+        let backend_addr = s
+            .backend_addr
+            .unwrap_or_else(|| DEFAULT_BACKEND_ADDR.to_string());
+        let backend_host = match backend_addr.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string(),
+            _ => return Err(ConfigErr::Address(backend_addr)),
+        };
+
+        let max_connections = s.max_connections.unwrap_or(DEFAULT_MAX_CONNECTIONS);
+        if max_connections == 0 {
+            return Err(ConfigErr::Invalid("max_connections must be at least 1"));
+        }
+        let max_message_len = s.max_message_len.unwrap_or(MAX_MESSAGE_LEN);
+        if !(MIN_READ_BUFFER..=MAX_MESSAGE_LEN).contains(&max_message_len) {
+            return Err(ConfigErr::Invalid(
+                "max_message_len must be between 1KiB and 1GiB",
+            ));
+        }
+        let read_buffer = s.read_buffer.unwrap_or(READ_CHUNK);
+        if !(MIN_READ_BUFFER..=max_message_len).contains(&read_buffer) {
+            return Err(ConfigErr::Invalid(
+                "read_buffer must be between 1KiB and max_message_len",
+            ));
+        }
+
+        let connect_timeout = s
+            .connect_timeout_secs
+            .unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS);
+        let handshake_timeout = s
+            .handshake_timeout_secs
+            .unwrap_or(DEFAULT_HANDSHAKE_TIMEOUT_SECS);
+        if connect_timeout == 0 || handshake_timeout == 0 {
+            return Err(ConfigErr::Invalid(
+                "connect and handshake timeouts must be at least 1s",
+            ));
+        }
+        let idle_timeout = s
+            .idle_timeout_secs
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs);
+
+        let tls = match (s.tls_cert, s.tls_key) {
+            (Some(cert), Some(key)) => Some((cert, key)),
+            (None, None) => None,
+            _ => return Err(ConfigErr::Invalid("tls_cert and tls_key go together")),
+        };
+
+        Ok(Self {
+            listen_addr,
+            backend_addr,
+            backend_host,
+            max_connections,
+            read_buffer,
+            max_message_len,
+            connect_timeout: Duration::from_secs(connect_timeout),
+            handshake_timeout: Duration::from_secs(handshake_timeout),
+            idle_timeout,
+            tls,
+            backend_ca: s.backend_ca,
+            oracle_file: s.oracle_file,
+            price: Price {
+                atoms_per_mib: s.price_per_mib.unwrap_or(DEFAULT_ATOMS_PER_MIB),
+                atoms_per_sec: s.price_per_sec.unwrap_or(DEFAULT_ATOMS_PER_SEC),
+                atoms_per_row: s.price_per_row.unwrap_or(DEFAULT_ATOMS_PER_ROW),
+                query_estimate: s.query_estimate.unwrap_or(DEFAULT_QUERY_ESTIMATE),
+            },
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigErr {
+    #[error("Can't read config file {0}: {1}")]
+    Read(String, std::io::Error),
+    #[error("Config file {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Not a host:port address '{0}'")]
+    Address(String),
+    #[error("Invalid config: {0}")]
+    Invalid(&'static str),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Settings {
+        Args::try_parse_from([&["gateway"], args].concat())
+            .unwrap()
+            .settings
+    }
+
+    #[test]
+    fn test_defaults() {
+        let c = Config::try_from(Settings::default()).unwrap();
+        assert_eq!(c.listen_addr, DEFAULT_LISTEN_ADDR.parse().unwrap());
+        assert_eq!(
+            (c.backend_addr.as_str(), c.backend_host.as_str()),
+            (DEFAULT_BACKEND_ADDR, "127.0.0.1")
+        );
+        assert_eq!(
+            (c.read_buffer, c.max_message_len),
+            (READ_CHUNK, MAX_MESSAGE_LEN)
+        );
+        assert_eq!(c.idle_timeout, None);
+        assert!(c.tls.is_none() && c.backend_ca.is_none());
+        assert_eq!(c.price, Price::default());
+    }
+
+    #[test]
+    fn test_precedence() {
+        let file: Settings = toml::from_str(
+            r#"
+            backend_addr = "db.internal:6432"
+            max_connections = 10
+            idle_timeout_secs = 300
+            price_per_sec = 0
+            "#,
+        )
+        .unwrap();
+        let cli = parse(&[
+            "--max-connections",
+            "20",
+            "--listen-addr",
+            "127.0.0.1:7000",
+            "--price-per-row",
+            "2",
+        ]);
+        let c = Config::try_from(cli.or(file)).unwrap();
+        assert_eq!(c.max_connections, 20);
+        assert_eq!(c.listen_addr, "127.0.0.1:7000".parse().unwrap());
+        assert_eq!(
+            (c.backend_addr.as_str(), c.backend_host.as_str()),
+            ("db.internal:6432", "db.internal")
+        );
+        assert_eq!(c.idle_timeout, Some(Duration::from_secs(300)));
+        assert_eq!(
+            c.connect_timeout,
+            Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS)
+        );
+        assert_eq!(
+            c.price,
+            Price {
+                atoms_per_sec: 0,
+                atoms_per_row: 2,
+                ..Price::default()
+            }
+        );
+
+        assert!(toml::from_str::<Settings>("listen = \"0.0.0.0:1\"").is_err());
+    }
+
+    #[test]
+    fn test_validation() {
+        let bad = [
+            (&["--listen-addr", "localhost"][..], "Not a host:port"),
+            (&["--backend-addr", "db.internal"], "Not a host:port"),
+            (&["--backend-addr", "db.internal:99999"], "Not a host:port"),
+            (&["--max-connections", "0"], "max_connections"),
+            (&["--read-buffer", "10"], "read_buffer"),
+            (
+                &["--read-buffer", "4096", "--max-message-len", "2048"],
+                "read_buffer",
+            ),
+            (&["--handshake-timeout-secs", "0"], "timeouts"),
+            (&["--tls-cert", "cert.pem"], "go together"),
+        ];
+        for (args, why) in bad {
+            let err = Config::try_from(parse(args)).unwrap_err().to_string();
+            assert!(err.contains(why), "{args:?}: {err}");
+        }
+        let c = Config::try_from(parse(&["--backend-addr", "[::1]:5432"])).unwrap();
+        assert_eq!(c.backend_host, "::1");
+    }
+}
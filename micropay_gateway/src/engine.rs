@@ -1,248 +1,321 @@
+//! The protocol `ApiEngine` over in-memory state.
+//!
+//! Vouchers and outstanding balances live in process memory (lost on restart), the client oracle is seeded from
+//! a toml file until an on-chain reader exists.
 use parking_lot::Mutex;
-use protocol::traits::*;
+use protocol::*;
+use serde::Deserialize;
 use std::collections::HashMap;
+use std::future::{Future, ready};
+use std::io;
+use std::marker::PhantomData;
 use std::sync::Arc;
 
-pub type ClientId = u64;
-pub type VendorId = u64;
+use crate::voucher::{Address, ClientId, GatewayVoucher, VendorId};
 
-#[derive(Clone, Debug)]
-pub struct TestVoucher {
-    pub ci: u64,
-    pub vi: u64,
-    pub nonce: u64,
-    pub atoms: u64,
-}
+pub type GatewayEngine = ApiEngine<
+    ClientId,
+    VendorId,
+    GatewayVoucher,
+    OracleRecord,
+    Balance,
+    MemVouchers,
+    MemOracle,
+    MemBalances,
+>;
 
-impl Voucher<ClientId, VendorId> for TestVoucher {
-    const DECIMALS: u32 = 6;
-    fn client_identifier(&self) -> ClientId {
-        self.ci
-    }
-    fn vendor_identifier(&self) -> VendorId {
-        self.vi
-    }
-    fn nonce(&self) -> u64 {
-        self.nonce
-    }
-    fn is_valid_signature(&self) -> bool {
-        true
-    }
-    fn voucher_atoms(&self) -> u64 {
-        self.atoms
-    }
+pub fn mem_engine(vendor: VendorId, oracle: MemOracle, cr: ClientRiskConfig) -> GatewayEngine {
+    let va = VoucherAuth::new(
+        vendor,
+        UnspentVoucherTracker::new(MemVouchers::default()),
+        ClientOracle::new(Arc::new(oracle)),
+    );
+    ApiEngine::new(
+        va,
+        OutstandingBalanceTracker::new(MemBalances::default()),
+        cr,
+    )
 }
 
-#[derive(Debug, Clone)]
-pub struct TestVTracker {
-    /// these would be in db
-    pub client_to_v: Arc<Mutex<HashMap<ClientId, ClientVouchers>>>,
-}
+type Unspent = ClientUnspentVouchers<ClientId, VendorId, GatewayVoucher>;
 
-#[derive(Default, Debug)]
-pub struct ClientVouchers {
-    pub vouchers: Vec<TestVoucher>,
-    /// None if vouchers.len()==0
-    pub spent_nonce: Option<u64>,
+#[derive(Default, Clone)]
+pub struct MemVouchers {
+    pub client_to_v: Arc<Mutex<HashMap<ClientId, Unspent>>>,
 }
 
-impl VoucherTracker<TestVoucher, ClientId> for TestVTracker {
-    fn get_first_unspent_voucher(&self, ci: &VendorId) -> Result<TestVoucher, VTrackErr> {
-        let mut g = self.client_to_v.lock();
-        let e = g.entry(*ci).or_default();
-        match e.spent_nonce.map(|x| x + 1) {
-            Some(unspent) => {
-                for v in e.vouchers.iter().rev() {
-                    if v.nonce == unspent {
-                        return Ok(v.clone());
-                    }
-                }
-                Err(VTrackErr::NoVoucher)
-            }
-            None => {
-                if e.vouchers.len() == 0 {
-                    return Err(VTrackErr::NoVoucher);
-                }
-                Ok(e.vouchers[0].clone())
-            }
-        }
-    }
-    fn get_latest_voucher_nonce(&self, ci: &VendorId) -> Result<u64, VTrackErr> {
+impl UnspentVouchersOp<ClientId, VendorId, GatewayVoucher> for MemVouchers {
+    fn rw_on_unspent_vouchers<F, R>(
+        &self,
+        ci: &ClientId,
+        f: F,
+    ) -> impl Future<Output = io::Result<R>> + Send
+    where
+        F: FnOnce(&mut Unspent) -> R,
+        R: Send,
+    {
         let mut g = self.client_to_v.lock();
-        let e = g.entry(*ci).or_default();
-        e.vouchers
-            .last()
-            .map(|x| x.nonce())
-            .ok_or(VTrackErr::NoVoucher)
+        let e = g.entry(*ci).or_insert_with(|| ClientUnspentVouchers {
+            spent_vouchers: vec![],
+            unspent_vouchers: vec![],
+            last_known_nonce: None,
+            _ci: PhantomData,
+            _vi: PhantomData,
+        });
+        ready(Ok(f(e)))
     }
-    fn get_unspent_atoms(&self, ci: &VendorId) -> Result<u64, VTrackErr> {
-        let mut g = self.client_to_v.lock();
-        let e = g.entry(*ci).or_default();
-        match e.spent_nonce {
-            Some(spent_nonce) => {
-                let mut sm = 0;
-                for v in e.vouchers.iter() {
-                    if v.nonce > spent_nonce {
-                        sm += v.atoms;
-                    }
-                }
-                Ok(sm)
-            }
-            None => Ok(e.vouchers.iter().map(|x| x.atoms).sum()),
-        }
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Balance {
+    pub outstanding: u64,
+    pub locked: u64,
+}
+
+impl OutstandingBalanceRecord for Balance {
+    fn outstanding(&mut self) -> &mut u64 {
+        &mut self.outstanding
     }
-    fn insert_voucher(&self, v: TestVoucher) -> Result<(), VTrackErr> {
-        let mut g = self.client_to_v.lock();
-        let e = g.entry(v.ci).or_default();
-        e.vouchers.push(v);
-        Ok(())
+    fn lock_value(&mut self) -> &mut u64 {
+        &mut self.locked
     }
-    fn mark_spent(&self, ci: &VendorId, nonce: u64) {
-        let mut g = self.client_to_v.lock();
-        let e = g.entry(*ci).or_default();
-        e.spent_nonce = Some(nonce);
+}
+
+#[derive(Default, Clone)]
+pub struct MemBalances {
+    pub client_to_b: Arc<Mutex<HashMap<ClientId, Balance>>>,
+}
+
+impl ClientOutstandingBalanceOp<ClientId, Balance> for MemBalances {
+    async fn rw_on_client_o_balance<F, R>(&self, ci: &ClientId, f: F) -> io::Result<R>
+    where
+        F: FnOnce(&mut Balance) -> R + Send,
+    {
+        Ok(f(self.client_to_b.lock().entry(*ci).or_default()))
     }
 }
 
-pub struct CostTrack {
-    pub client_to_v: Mutex<HashMap<ClientId, ClientCost>>,
+/// What the chain says about a client.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OracleRecord {
+    pub id: ClientId,
+    pub collateral: u64,
+    /// after queued withdrawals go through, defaults to `collateral`
+    #[serde(default)]
+    pub collateral_to_be: Option<u64>,
+    /// vendors subscribed to, less any queued unsubscribes
+    #[serde(default)]
+    pub vendors: Vec<VendorId>,
+    /// subscriptions now, defaults to the number of `vendors`
+    #[serde(default)]
+    pub subscriptions: Option<u64>,
+    /// `0x` hex address of the key the client signs vouchers with, every voucher is refused without one
+    #[serde(default, deserialize_with = "hex_address")]
+    pub signer: Option<Address>,
 }
 
-#[derive(Default)]
-pub struct ClientCost {
-    pub unmarked: u64,
-    pub lockv: u64,
+fn hex_address<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<Address>, D::Error> {
+    use serde::de::Error;
+    let s = String::deserialize(d)?;
+    let bytes = hex::decode(s.strip_prefix("0x").unwrap_or(&s)).map_err(D::Error::custom)?;
+    bytes
+        .try_into()
+        .map(Some)
+        .map_err(|_| D::Error::custom("address is not 20 bytes"))
 }
 
-impl UnmarkedCostTracker<ClientId> for CostTrack {
-    fn add_cost(&self, ci: &ClientId, atoms: u64) {
-        let mut g = self.client_to_v.lock();
-        let e = g.entry(*ci).or_default();
-        e.unmarked += atoms;
+impl ClientOracleRecord<VendorId> for OracleRecord {
+    fn collateral_to_be(&self) -> u64 {
+        self.collateral_to_be.unwrap_or(self.collateral)
     }
-    fn reduce(&self, ci: &ClientId, atoms: u64) {
-        let mut g = self.client_to_v.lock();
-        let e = g.entry(*ci).or_default();
-        e.unmarked = e.unmarked.saturating_sub(atoms);
+    fn is_subscribed_to_be(&self, vi: &VendorId) -> bool {
+        self.vendors.contains(vi)
     }
-    fn lock(&self, ci: &ClientId, atoms: u64) {
-        let mut g = self.client_to_v.lock();
-        let e = g.entry(*ci).or_default();
-        e.lockv += atoms;
+    fn collateral_now(&self) -> u64 {
+        self.collateral
     }
-    fn unlock(&self, ci: &ClientId, atoms: u64) {
-        let mut g = self.client_to_v.lock();
-        let e = g.entry(*ci).or_default();
-        e.lockv = e.lockv.saturating_sub(atoms);
+    fn subscriptions_now(&self) -> u64 {
+        self.subscriptions.unwrap_or(self.vendors.len() as u64)
     }
-    fn unmarked_cost(&self, ci: &ClientId) -> u64 {
-        let mut g = self.client_to_v.lock();
-        let e = g.entry(*ci).or_default();
-        e.unmarked
-    }
-    fn locked_cost(&self, ci: &ClientId) -> u64 {
-        let mut g = self.client_to_v.lock();
-        let e = g.entry(*ci).or_default();
-        e.lockv
+    fn voucher_key(&self) -> &[u8] {
+        self.signer.as_ref().map_or(&[], |a| a)
     }
 }
 
-#[derive(Clone)]
-pub struct Chain {}
+/// Unknown clients have no collateral and no subscriptions.
+#[derive(Default, Debug)]
+pub struct MemOracle {
+    pub clients: HashMap<ClientId, OracleRecord>,
+}
 
-impl ChainOracle<ClientId, VendorId> for Chain {
-    fn get_client_collateral(&self, ci: &ClientId) -> Result<u64, OracleErr> {
-        Ok(3 * 10u64.pow(TestVoucher::DECIMALS))
-    }
-    fn get_total_subscribed(&self, ci: &ClientId) -> Result<u64, OracleErr> {
-        Ok(2)
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OracleFile {
+    #[serde(default)]
+    client: Vec<OracleRecord>,
+}
+
+impl MemOracle {
+    /// ```toml
+    /// [[client]]
+    /// id = 7
+    /// collateral = 3000000
+    /// vendors = [1]
+    /// ```
+    pub fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        let file: OracleFile = toml::from_str(text)?;
+        Ok(Self {
+            clients: file.client.into_iter().map(|r| (r.id, r)).collect(),
+        })
     }
-    fn is_client_subscribed(&self, ci: &ClientId, vi: &VendorId) -> Result<bool, OracleErr> {
-        Ok(true)
+}
+
+impl ClientOracleRead<ClientId, VendorId, OracleRecord> for MemOracle {
+    fn r_on_client_oracle<F, R>(
+        &self,
+        ci: &ClientId,
+        f: F,
+    ) -> impl Future<Output = io::Result<R>> + Send
+    where
+        F: FnOnce(&OracleRecord) -> R,
+        R: Send,
+    {
+        let r = match self.clients.get(ci) {
+            Some(r) => f(r),
+            None => f(&OracleRecord {
+                id: *ci,
+                ..Default::default()
+            }),
+        };
+        ready(Ok(r))
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use assert_matches::assert_matches;
-    use protocol::*;
+    use crate::voucher::address;
+    use k256::ecdsa::SigningKey;
 
     const VENDOR: u64 = 42;
     const CLIENT: u64 = 30;
+    const DECIMALS: u32 = 6;
 
-    fn setup() -> (TestVoucher, TestVTracker, Engine<TestVoucher, u64, u64>) {
-        let vendor = 42;
-        let o = Box::new(Chain {});
-        let cr = ClientRisk::new(o.clone(), None);
-        let vtc = TestVTracker {
-            client_to_v: Arc::new(Mutex::new(HashMap::new())),
-        };
-        let vt = Box::new(vtc.clone());
-        let u = Box::new(CostTrack {
-            client_to_v: Mutex::new(HashMap::new()),
-        });
-        let ct = CreditTrack::new(cr, vt.clone(), u);
-        let va = VoucherAuth::new(vendor, vt, o);
-        let mut v = TestVoucher {
-            ci: CLIENT,
-            vi: VENDOR,
+    fn key() -> SigningKey {
+        SigningKey::from_slice(&[0x11; 32]).unwrap()
+    }
+
+    fn signed(v: &GatewayVoucher) -> GatewayVoucher {
+        v.clone().sign(&key())
+    }
+
+    fn setup() -> (GatewayVoucher, GatewayEngine) {
+        let oracle = MemOracle::from_toml(&format!(
+            "[[client]]\nid = {CLIENT}\ncollateral = {}\nvendors = [{VENDOR}]\nsubscriptions = 2\nsigner = \"0x{}\"",
+            3 * 10u64.pow(DECIMALS),
+            hex::encode(address(key().verifying_key()))
+        ))
+        .unwrap();
+        let v = GatewayVoucher {
+            client: CLIENT,
+            vendor: VENDOR,
             nonce: 1,
-            atoms: 10 * 10u64.pow(TestVoucher::DECIMALS as u32),
+            atoms: 10 * 10u64.pow(DECIMALS),
+            expiry: u64::MAX,
+            signature: String::new(),
         };
-        (v, vtc, Engine { ct, va })
+        (v, mem_engine(VENDOR, oracle, ClientRiskConfig::new()))
     }
 
-    #[test]
-    fn test_engine() -> Result<(), EngineErr> {
-        let (mut v, vt, e) = setup();
+    #[tokio::test]
+    async fn test_engine() -> Result<(), EngineErr> {
+        let (mut v, e) = setup();
 
-        assert_matches!(
-            e.accept_session(v.clone()),
+        assert!(matches!(
+            e.accept_session(&signed(&v)).await,
             Err(EngineErr::VAuth(VAuthErr::Volatile(
                 VolatileVAuthErr::ClientHasInsufficientBalance { .. }
             )))
-        );
-        v.atoms = 1 * 10u64.pow(TestVoucher::DECIMALS as u32);
-        assert_matches!(
-            e.accept_session(v.clone()),
+        ));
+        v.atoms = 10u64.pow(DECIMALS);
+        assert!(matches!(
+            e.accept_session(&signed(&v)).await,
             Err(EngineErr::VAuth(VAuthErr::FirstVoucherNonceInvalid))
-        );
+        ));
         // user can sign more than they have because they haven't spent it and
         // vendor hasn't used it.
         v.nonce = 0;
-        assert_matches!(e.accept_session(v.clone()), Ok(()));
-        v.nonce = 1;
-        assert_matches!(e.accept_session(v.clone()), Ok(()));
-        v.nonce = 2;
-        assert_matches!(e.accept_session(v.clone()), Ok(()));
-        println!("{:#?}", vt);
-
-        let aprx_cost = 1000;
-        let qc = e.accept_query(CLIENT, aprx_cost)?;
-        assert_matches!(
-            qc,
-            QueryCont {
-                case: QueryCase::Continue { locked_cost: 1000 },
-                ..
-            }
-        );
-        println!("{:#?}", qc);
-        let sq = SettleQuery {
-            hour_price: 0.1 * 10f64.powf(TestVoucher::DECIMALS as f64),
-            data_bytes: (1.0 * 1e3) as u64,
-            gb_price: 0.2 * 10f64.powf(TestVoucher::DECIMALS as f64),
+        // signed by another key, or for other atoms than it carries
+        let forged = v
+            .clone()
+            .sign(&SigningKey::from_slice(&[0x22; 32]).unwrap());
+        let tampered = GatewayVoucher {
+            atoms: v.atoms / 2,
+            ..signed(&v)
         };
-        println!("sq {:?}", sq);
-        println!("cost {}", sq.cost(qc.start));
-        let hour = qc.start.elapsed().as_secs_f64() / 3600.0;
-        let giga_bytes = sq.data_bytes as f64 / (8.0 * 1e9);
-        let v = (hour * sq.hour_price) + (giga_bytes * sq.gb_price);
-        println!("cost f64 {}", v);
+        for bad in [forged, tampered, v.clone()] {
+            let res = e.accept_session(&bad).await;
+            assert!(matches!(
+                res,
+                Err(EngineErr::VAuth(VAuthErr::Static(
+                    StaticVAuthErr::InvalidSig
+                )))
+            ));
+        }
+        e.accept_session(&signed(&v)).await?;
+        v.nonce = 1;
+        e.accept_session(&signed(&v)).await?;
+        v.nonce = 3;
+        let spent = e.accept_session(&signed(&v)).await;
+        assert!(matches!(
+            spent,
+            Err(EngineErr::VAuth(VAuthErr::VoucherSpentOrNonceTooHigh))
+        ));
 
-        let res = e.settle_query(&CLIENT, &qc, sq)?;
-        println!("{:#?}", res);
+        // 2 vouchers unspent, but only collateral / (subscriptions + expand risk) is safe to spend
+        let safe_cap = 3 * 10u64.pow(DECIMALS) / (2 + 5);
+        assert!(!e.query(&CLIENT, safe_cap + 1).await?.should_continue);
+        let qc = e.query(&CLIENT, 1000).await?;
+        assert!(qc.should_continue);
+        e.settle_query(&CLIENT, &qc, 800).await?;
 
+        let stranger = signed(&GatewayVoucher {
+            client: CLIENT + 1,
+            nonce: 0,
+            ..v
+        });
+        assert!(matches!(
+            e.accept_session(&stranger).await,
+            Err(EngineErr::VAuth(VAuthErr::Volatile(
+                VolatileVAuthErr::ClientIsNotSubscribed
+            )))
+        ));
         Ok(())
     }
+
+    #[test]
+    fn test_oracle_file() {
+        let text = "[[client]]\nid = 1\ncollateral = 10\ncollateral_to_be = 4\nvendors = [2, 3]";
+        let oracle = MemOracle::from_toml(text).unwrap();
+        let r = &oracle.clients[&1];
+        assert_eq!(
+            (
+                r.collateral_now(),
+                r.collateral_to_be(),
+                r.subscriptions_now()
+            ),
+            (10, 4, 2)
+        );
+        assert!(r.is_subscribed_to_be(&3) && !r.is_subscribed_to_be(&4));
+        assert!(r.voucher_key().is_empty());
+        let signed =
+            MemOracle::from_toml(&format!("{text}\nsigner = \"0x{}\"", "ab".repeat(20))).unwrap();
+        assert_eq!(signed.clients[&1].voucher_key(), [0xab; 20]);
+        assert!(
+            MemOracle::from_toml(&format!("{text}\nsigner = \"0x{}\"", "ab".repeat(19))).is_err()
+        );
+        assert!(MemOracle::from_toml("[[client]]\nid = 1").is_err());
+        assert!(MemOracle::from_toml("").unwrap().clients.is_empty());
+    }
 }
@@ -1 +1,3 @@
 pub mod engine;
+pub mod meter;
+pub mod voucher;
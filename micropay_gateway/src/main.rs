@@ -1,40 +1,235 @@
-use std::collections::HashSet;
 use std::io::ErrorKind;
+use std::sync::Arc;
+use std::time::Duration;
 
-use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::io::{self, AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
-
-const LISTEN_ADDR: &str = "0.0.0.0:5433"; // where clients connect
-const BACKEND_ADDR: &str = "127.0.0.1:5432"; // real postgres
-
-/// application_name=init_voucher; (strip app_name in msg, set to 'psql')
-/// set voucher = next_voucher; (strip set from sql, update voucher)
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+
+use config::Config;
+use micropay_gateway::engine::{GatewayEngine, MemOracle, mem_engine};
+use micropay_gateway::voucher::{GatewayVoucher, VendorId, VoucherErr};
+use pgwire::{Message, MessageReader};
+use protocol::ClientRiskConfig;
+use session::Session;
+use tls::Tls;
+
+mod config;
+mod pgwire;
+mod session;
+mod tls;
+
+const VENDOR_ID: VendorId = 1; // vouchers must be signed for us
+
+/// application_name=init_voucher:<voucher> or voucher=<voucher>; (strip from startup msg, app_name -> 'psql')
+/// set voucher = '<next voucher>'; (answered by the gateway, never reaches the backend)
 #[tokio::main]
-async fn main() -> io::Result<()> {
-    let listener = TcpListener::bind(LISTEN_ADDR).await?;
-    println!("pg proxy listening on {LISTEN_ADDR}, forwarding to {BACKEND_ADDR}");
-
+async fn main() -> anyhow::Result<()> {
+    let cfg = Arc::new(Config::load()?);
+    let tls = Tls::from_config(&cfg)?;
+    let oracle = match &cfg.oracle_file {
+        Some(path) => MemOracle::from_toml(&std::fs::read_to_string(path)?)?,
+        None => {
+            eprintln!("no oracle file, every client is unsubscribed");
+            MemOracle::default()
+        }
+    };
+    let engine = Arc::new(mem_engine(VENDOR_ID, oracle, ClientRiskConfig::new()));
+    let listener = TcpListener::bind(cfg.listen_addr).await?;
+    println!(
+        "pg proxy listening on {} (client tls {}), forwarding to {} (backend tls {}), max {} connections",
+        cfg.listen_addr,
+        tls.acceptor.is_some(),
+        cfg.backend_addr,
+        tls.connector.is_some(),
+        cfg.max_connections
+    );
+
+    let slots = Arc::new(Semaphore::new(cfg.max_connections));
     loop {
-        let (client, addr) = listener.accept().await?;
+        let (mut client, addr) = listener.accept().await?;
+        let Ok(slot) = slots.clone().try_acquire_owned() else {
+            eprintln!("turning away {addr}, at max connections");
+            tokio::spawn(async move {
+                // too_many_connections
+                reject(
+                    &mut client,
+                    "53300",
+                    "sorry, too many clients already".into(),
+                )
+                .await
+            });
+            continue;
+        };
         println!("new connection from {addr}");
 
+        let (cfg, tls, engine) = (cfg.clone(), tls.clone(), engine.clone());
         tokio::spawn(async move {
-            if let Err(e) = handle_conn2(client).await {
+            if let Err(e) = handle_conn2(client, &cfg, tls, engine).await {
                 eprintln!("connection from {addr} ended with error: {e}");
             }
+            drop(slot);
         });
     }
 }
 
-async fn handle_conn2(mut client: TcpStream) -> io::Result<()> {
-    let mut server = TcpStream::connect(BACKEND_ADDR).await?;
-    println!("connected to backend {BACKEND_ADDR}");
-    logged_copy_bidirectional(client, server).await?;
+fn timed_out(what: &str) -> io::Error {
+    io::Error::new(ErrorKind::TimedOut, format!("{what} timed out"))
+}
+
+async fn handle_conn2(
+    client: TcpStream,
+    cfg: &Config,
+    tls: Tls,
+    engine: Arc<GatewayEngine>,
+) -> io::Result<()> {
+    let handshake = async {
+        let Some((client, first)) = tls::negotiate_client(client, &tls).await? else {
+            return Ok(None);
+        };
+        let (cr, mut cw) = tokio::io::split(client); // client read/write
+        let mut cr = MessageReader::with_limits(cr, cfg.read_buffer, cfg.max_message_len);
+        // voucher is checked before the backend is ever touched
+        let startup = voucher_handshake(&mut cr, &mut cw, first, &engine).await?;
+        Ok::<_, io::Error>(startup.map(|startup| (cr, cw, startup)))
+    };
+    let handshake = timeout(cfg.handshake_timeout, handshake)
+        .await
+        .map_err(|_| timed_out("handshake"))?;
+    let Some((cr, cw, startup)) = handshake? else {
+        return Ok(());
+    };
+
+    let connect =
+        async { tls::negotiate_backend(TcpStream::connect(&cfg.backend_addr).await?, &tls).await };
+    let mut server = timeout(cfg.connect_timeout, connect)
+        .await
+        .map_err(|_| timed_out("backend connect"))??;
+    println!("connected to backend {}", cfg.backend_addr);
+    let voucher = match startup {
+        Startup::Session { voucher, packet } => {
+            server.write_all(&packet).await?;
+            voucher
+        }
+        // backend closes the connection right after a cancel
+        Startup::Cancel(packet) => return server.write_all(&packet).await,
+    };
+    println!(
+        "session for client {} on voucher nonce={} atoms={}",
+        voucher.client, voucher.nonce, voucher.atoms
+    );
+
+    let mut session = Session::new(voucher, VENDOR_ID, engine, cfg.price);
+    let proxied = proxy_messages(cr, cw, server, &mut session, cfg).await;
+    session.finish().await;
+    let (c2s, s2c) = proxied?;
+    println!("connection closed, {c2s} bytes client → server, {s2c} bytes server → client");
     Ok(())
 }
 
+/// What the client asked for in its startup packet.
+#[derive(Debug)]
+enum Startup {
+    /// a regular session, with the packet rewritten for the backend
+    Session {
+        voucher: GatewayVoucher,
+        packet: Vec<u8>,
+    },
+    /// cancel a running query of another session, forwarded as is
+    Cancel(Vec<u8>),
+}
+
+/// Reads the client's startup packet (unless TLS negotiation already did) and admits it only with a voucher
+/// the engine accepts. Rejected clients are sent a FATAL ErrorResponse. `Ok(None)` if the client went away.
+async fn voucher_handshake<R, W>(
+    cr: &mut MessageReader<R>,
+    cw: &mut W,
+    first: Option<Vec<u8>>,
+    engine: &GatewayEngine,
+) -> io::Result<Option<Startup>>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let packet = match first {
+        Some(packet) => packet,
+        None => match cr.read_startup().await? {
+            Some(packet) => packet,
+            None => return Ok(None),
+        },
+    };
+    let pver = u32::from_be_bytes(packet[4..8].try_into().unwrap());
+    match pver {
+        startup::CANCEL_REQUEST => return Ok(Some(Startup::Cancel(packet))),
+        startup::PROTOCOL_V3 => {}
+        // encryption requests are only valid as the very first packet
+        _ => {
+            return Err(reject(
+                cw,
+                "08P01",
+                format!("unsupported protocol version {pver:#x}"),
+            )
+            .await);
+        }
+    }
+
+    let (pver, mut kv) = startup::parse_startup_message(&packet, packet.len())?;
+    let blob = startup::take_voucher(&mut kv);
+    println!("client sent '{} {:?}'", pver, kv);
+    let voucher = async {
+        let voucher = GatewayVoucher::decode(&blob.ok_or(VoucherErr::Missing)?)?;
+        voucher.validate(VENDOR_ID)?;
+        engine.accept_session(&voucher).await?;
+        Ok::<_, VoucherErr>(voucher)
+    };
+    match voucher.await {
+        Ok(voucher) => {
+            let packet = startup::build_startup_message(pver, &kv);
+            Ok(Some(Startup::Session { voucher, packet }))
+        }
+        Err(e) => Err(reject(cw, e.sqlstate(), e.to_string()).await),
+    }
+}
+
+/// Tells the client why it is turned away and closes the connection.
+async fn reject<W: AsyncWrite + Unpin>(cw: &mut W, code: &str, msg: String) -> io::Error {
+    let _ = cw
+        .write_all(&Message::error_response("FATAL", code, &msg).encode())
+        .await;
+    let _ = cw.shutdown().await;
+    io::Error::new(ErrorKind::PermissionDenied, msg)
+}
+
 mod startup {
     use std::io::{self, ErrorKind};
+
+    pub const PROTOCOL_V3: u32 = 0x0003_0000;
+    pub const CANCEL_REQUEST: u32 = 80877102;
+    pub const SSL_REQUEST: u32 = 80877103;
+    pub const GSSENC_REQUEST: u32 = 80877104;
+    /// application_name prefix that carries a voucher
+    pub const INIT_VOUCHER: &str = "init_voucher:";
+    /// what the backend sees as application_name instead of the voucher
+    pub const APPLICATION_NAME: &str = "psql";
+
+    /// Remove the voucher from startup params, either `voucher=<blob>` or
+    /// `application_name=init_voucher:<blob>`. The dedicated param wins if both are set.
+    /// application_name is rewritten so the voucher never reaches the backend.
+    pub fn take_voucher(params: &mut Vec<(String, String)>) -> Option<String> {
+        let mut from_app_name = None;
+        for (k, v) in params.iter_mut() {
+            if k == "application_name" && v.starts_with(INIT_VOUCHER) {
+                from_app_name = Some(v[INIT_VOUCHER.len()..].to_string());
+                *v = APPLICATION_NAME.to_string();
+            }
+        }
+        let dedicated = params
+            .iter()
+            .position(|(k, _)| k == "voucher")
+            .map(|i| params.remove(i).1);
+        dedicated.or(from_app_name)
+    }
     /// Parse a PostgreSQL StartupMessage from `buf[..n]`.
     /// Returns (protocol_version, Vec<(key, value)>).
     ///
@@ -138,83 +333,284 @@ mod startup {
     }
 }
 
-/// Pumps bytes both ways with logging.
-/// Returns (bytes_client_to_server, bytes_server_to_client).
-pub async fn logged_copy_bidirectional(
-    mut client: TcpStream,
-    mut server: TcpStream,
-) -> io::Result<(u64, u64)> {
-    let (mut cr, mut cw) = tokio::io::split(client); // client read/write
-    let (mut sr, mut sw) = tokio::io::split(server); // server read/write
-
-    let c2s = {
-        let mut buf = [0u8; 8192];
-        // read startup msg
-        let n = cr.read(&mut buf).await?;
-        let (pver, kv) = startup::parse_startup_message(&buf, n)?;
-        println!("client sent '{} {:?}'", pver, kv);
-
-        sw.write_all(&buf[..n]).await?;
-
-        // ---- TASK: client → server ----
-        tokio::spawn(async move {
-            let mut total = 0u64;
-
-            loop {
-                let n = match cr.read(&mut buf).await {
-                    Ok(0) => break, // client closed
-                    Ok(n) => n,
-                    Err(e) => return Err(e),
-                };
-
-                // LOG BYTES SENT FROM CLIENT
-                println!(
-                    "CLIENT → SERVER  ({} bytes): {:02x?}",
-                    n,
-                    &String::from_utf8_lossy(&buf[..n])
-                );
+/// What happens to a client message after the hook saw it.
+pub enum Flow {
+    Forward,
+    /// swallow the message and answer the client with these instead
+    Reply(Vec<Message>),
+}
 
-                total += n as u64;
+/// Observes every framed message passing through the proxy.
+pub trait MessageHook {
+    /// client → server
+    fn frontend(&mut self, _msg: &Message) -> impl Future<Output = Flow> + Send {
+        async { Flow::Forward }
+    }
+    /// server → client, the messages returned are sent to the client right after it
+    fn backend(&mut self, _msg: &Message) -> impl Future<Output = Vec<Message>> + Send {
+        async { vec![] }
+    }
+}
 
-                sw.write_all(&buf[..n]).await?;
+/// Relays whole protocol messages both ways once the startup packet is through,
+/// handing each to `hook` before it is forwarded.
+/// Returns (bytes_client_to_server, bytes_server_to_client).
+pub async fn proxy_messages<CR, CW, S>(
+    mut cr: MessageReader<CR>,
+    mut cw: CW,
+    server: S,
+    hook: &mut impl MessageHook,
+    cfg: &Config,
+) -> io::Result<(u64, u64)>
+where
+    CR: AsyncRead + Unpin,
+    CW: AsyncWrite + Unpin,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (sr, mut sw) = tokio::io::split(server); // server read/write
+    let mut sr = MessageReader::with_limits(sr, cfg.read_buffer, cfg.max_message_len);
+
+    let mut c2s = 0u64;
+    let mut s2c = 0u64;
+    // both reads are cancel safe, a message is only taken off a reader once it is whole
+    loop {
+        tokio::select! {
+            msg = cr.read_message() => {
+                let Some(msg) = msg? else {
+                    let _ = sw.shutdown().await; // client closed
+                    break;
+                };
+                match hook.frontend(&msg).await {
+                    Flow::Forward => {
+                        sw.write_all(&msg.encode()).await?;
+                        c2s += msg.wire_len() as u64;
+                    }
+                    Flow::Reply(replies) => {
+                        for reply in replies {
+                            cw.write_all(&reply.encode()).await?;
+                        }
+                    }
+                }
             }
+            msg = sr.read_message() => {
+                let Some(msg) = msg? else {
+                    let _ = cw.shutdown().await; // server closed
+                    break;
+                };
+                let held = hook.backend(&msg).await;
+                cw.write_all(&msg.encode()).await?;
+                s2c += msg.wire_len() as u64;
+                for reply in held {
+                    cw.write_all(&reply.encode()).await?;
+                }
+            }
+            _ = idle(cfg.idle_timeout) => {
+                let _ = sw.shutdown().await;
+                let _ = cw.shutdown().await;
+                return Err(timed_out("idle connection"));
+            }
+        }
+    }
 
-            let _ = sw.shutdown().await;
-            Ok(total)
-        })
-    };
+    Ok((c2s, s2c))
+}
 
-    // ---- TASK: server → client ----
-    let s2c = tokio::spawn(async move {
-        let mut buf = [0u8; 8192];
-        let mut total = 0u64;
+/// Resolves after `limit` of quiet, never without a limit. Recreated per message, so it restarts on traffic.
+async fn idle(limit: Option<Duration>) {
+    match limit {
+        Some(limit) => tokio::time::sleep(limit).await,
+        None => std::future::pending().await,
+    }
+}
 
-        loop {
-            let n = match sr.read(&mut buf).await {
-                Ok(0) => break, // server closed
-                Ok(n) => n,
-                Err(e) => return Err(e),
-            };
+#[cfg(test)]
+mod test {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+    use micropay_gateway::voucher::address;
+    use tokio::io::AsyncReadExt;
+
+    fn kv(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
 
-            // LOG BYTES SENT FROM SERVER
-            println!(
-                "SERVER → CLIENT  ({} bytes): {:02x?}",
-                n,
-                &String::from_utf8_lossy(&buf[..n])
-            );
+    fn key() -> SigningKey {
+        SigningKey::from_slice(&[0x11; 32]).unwrap()
+    }
 
-            total += n as u64;
+    fn voucher() -> GatewayVoucher {
+        let v = GatewayVoucher {
+            client: 7,
+            vendor: VENDOR_ID,
+            nonce: 0,
+            atoms: 5000,
+            expiry: u64::MAX,
+            signature: "".into(),
+        };
+        v.sign(&key())
+    }
 
-            cw.write_all(&buf[..n]).await?;
-        }
+    fn engine() -> GatewayEngine {
+        let signer = hex::encode(address(key().verifying_key()));
+        let oracle = format!(
+            "[[client]]\nid = 7\ncollateral = 10000\nvendors = [{VENDOR_ID}]\nsigner = \"0x{signer}\""
+        );
+        mem_engine(
+            VENDOR_ID,
+            MemOracle::from_toml(&oracle).unwrap(),
+            ClientRiskConfig::new(),
+        )
+    }
 
-        let _ = cw.shutdown().await;
-        Ok(total)
-    });
+    #[test]
+    fn test_take_voucher() {
+        let mut params = kv(&[("user", "alice"), ("application_name", "init_voucher:abc")]);
+        assert_eq!(startup::take_voucher(&mut params).as_deref(), Some("abc"));
+        assert_eq!(
+            params,
+            kv(&[("user", "alice"), ("application_name", "psql")])
+        );
+
+        // dedicated param wins, application_name left alone unless it carries a voucher
+        let mut params = kv(&[
+            ("voucher", "def"),
+            ("user", "alice"),
+            ("application_name", "init_voucher:abc"),
+        ]);
+        assert_eq!(startup::take_voucher(&mut params).as_deref(), Some("def"));
+        assert_eq!(
+            params,
+            kv(&[("user", "alice"), ("application_name", "psql")])
+        );
+        let mut params = kv(&[("user", "alice"), ("application_name", "pgbench")]);
+        assert_eq!(startup::take_voucher(&mut params), None);
+        assert_eq!(
+            params,
+            kv(&[("user", "alice"), ("application_name", "pgbench")])
+        );
+    }
 
-    // Wait for both directions
-    let c2s_res = c2s.await.unwrap()?;
-    let s2c_res = s2c.await.unwrap()?;
+    /// Runs the handshake against `packets`, returns its outcome and what the client was sent.
+    async fn handshake(
+        engine: &GatewayEngine,
+        packets: &[Vec<u8>],
+    ) -> (io::Result<Option<Startup>>, Vec<u8>) {
+        let (mut client, gateway) = tokio::io::duplex(1 << 16);
+        for p in packets {
+            client.write_all(p).await.unwrap();
+        }
+        let (gr, mut gw) = tokio::io::split(gateway);
+        let res = voucher_handshake(&mut MessageReader::new(gr), &mut gw, None, engine).await;
+        drop(gw);
+        let mut sent = Vec::new();
+        client.read_to_end(&mut sent).await.unwrap();
+        (res, sent)
+    }
 
-    Ok((c2s_res, s2c_res))
+    #[tokio::test]
+    async fn test_voucher_handshake() {
+        let blob = format!("init_voucher:{}", voucher().encode());
+        let params = kv(&[("user", "alice"), ("application_name", &blob)]);
+        let engine = engine();
+        let build = |params: &[(String, String)]| {
+            startup::build_startup_message(startup::PROTOCOL_V3, params)
+        };
+        let (res, sent) = handshake(&engine, &[build(&params)]).await;
+        assert!(sent.is_empty());
+        let Ok(Some(Startup::Session { voucher: v, packet })) = res else {
+            panic!("session not admitted")
+        };
+        assert_eq!(v, voucher());
+        let (_, forwarded) = startup::parse_startup_message(&packet, packet.len()).unwrap();
+        assert_eq!(
+            forwarded,
+            kv(&[("user", "alice"), ("application_name", "psql")])
+        );
+
+        let encoded = |v: GatewayVoucher| kv(&[("voucher", &v.encode())]);
+        let signed = |v: GatewayVoucher| encoded(v.sign(&key()));
+        let bad = [
+            (kv(&[("user", "alice")]), "No voucher", "28000"),
+            (kv(&[("voucher", "!!")]), "not base64", "28000"),
+            (
+                signed(GatewayVoucher {
+                    vendor: 2,
+                    ..voucher()
+                }),
+                "different vendor",
+                "28000",
+            ),
+            (
+                signed(GatewayVoucher {
+                    expiry: 1,
+                    ..voucher()
+                }),
+                "expired",
+                "28000",
+            ),
+            // the engine's say: nonce gap, more than the collateral, unknown to the oracle
+            (
+                signed(GatewayVoucher {
+                    nonce: 2,
+                    ..voucher()
+                }),
+                "nonce",
+                "28000",
+            ),
+            (
+                signed(GatewayVoucher {
+                    nonce: 1,
+                    atoms: 20000,
+                    ..voucher()
+                }),
+                "balance=10000",
+                "28000",
+            ),
+            (
+                signed(GatewayVoucher {
+                    client: 8,
+                    ..voucher()
+                }),
+                "subscription",
+                "28000",
+            ),
+            // not signed by the client's registered key, or not for these atoms
+            (
+                encoded(voucher().sign(&SigningKey::from_slice(&[0x22; 32]).unwrap())),
+                "signature",
+                "28P01",
+            ),
+            (
+                encoded(GatewayVoucher {
+                    atoms: 6000,
+                    ..voucher()
+                }),
+                "signature",
+                "28P01",
+            ),
+        ];
+        for (params, why, code) in bad {
+            let (res, sent) = handshake(&engine, &[build(&params)]).await;
+            assert_eq!(res.unwrap_err().kind(), ErrorKind::PermissionDenied);
+            let msg = MessageReader::new(sent.as_slice())
+                .read_message()
+                .await
+                .unwrap()
+                .unwrap();
+            let pgwire::Backend::ErrorResponse(fields) = msg.backend() else {
+                panic!("no ErrorResponse")
+            };
+            assert!(
+                fields.contains(&(b'S', "FATAL")) && fields.contains(&(b'C', code)),
+                "{fields:?}"
+            );
+            assert!(
+                fields.iter().any(|(f, m)| *f == b'M' && m.contains(why)),
+                "{fields:?}"
+            );
+        }
+    }
 }
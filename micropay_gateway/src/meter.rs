@@ -0,0 +1,120 @@
+//! What a query costs, in voucher atoms.
+//!
+//! A query is metered from the client message that starts it to the backend's ReadyForQuery.
+use std::time::Duration;
+
+/// usdc has 6 decimals, this is 0.1 cent
+pub const DEFAULT_ATOMS_PER_MIB: u64 = 1_000;
+pub const DEFAULT_ATOMS_PER_SEC: u64 = 100;
+/// rows aren't charged for unless configured, bytes already scale with them
+pub const DEFAULT_ATOMS_PER_ROW: u64 = 0;
+pub const DEFAULT_QUERY_ESTIMATE: u64 = 1_000;
+
+/// What one query used.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    /// server → client, whole messages
+    pub bytes: u64,
+    pub elapsed: Duration,
+    /// returned or affected, as the CommandComplete tags report
+    pub rows: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Price {
+    pub atoms_per_mib: u64,
+    pub atoms_per_sec: u64,
+    pub atoms_per_row: u64,
+    /// locked with the engine when a query starts, the actual cost is only known once it is done
+    pub query_estimate: u64,
+}
+
+impl Default for Price {
+    fn default() -> Self {
+        Self {
+            atoms_per_mib: DEFAULT_ATOMS_PER_MIB,
+            atoms_per_sec: DEFAULT_ATOMS_PER_SEC,
+            atoms_per_row: DEFAULT_ATOMS_PER_ROW,
+            query_estimate: DEFAULT_QUERY_ESTIMATE,
+        }
+    }
+}
+
+impl Price {
+    /// Bytes and time are each rounded up to the next atom.
+    pub fn cost(&self, u: &Usage) -> u64 {
+        let bytes = (u.bytes as u128 * self.atoms_per_mib as u128).div_ceil(1 << 20);
+        let time = (u.elapsed.as_millis() * self.atoms_per_sec as u128).div_ceil(1000);
+        let rows = u.rows as u128 * self.atoms_per_row as u128;
+        u64::try_from(bytes + time + rows).unwrap_or(u64::MAX)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cost() {
+        let p = Price {
+            atoms_per_mib: 1000,
+            atoms_per_sec: 100,
+            atoms_per_row: 0,
+            query_estimate: 0,
+        };
+        assert_eq!(p.cost(&Usage::default()), 0);
+        assert_eq!(
+            p.cost(&Usage {
+                bytes: 1 << 20,
+                elapsed: Duration::from_secs(2),
+                rows: 5
+            }),
+            1200
+        );
+        // a single byte or millisecond is not free
+        assert_eq!(
+            p.cost(&Usage {
+                bytes: 1,
+                elapsed: Duration::from_millis(1),
+                rows: 0
+            }),
+            2
+        );
+        let flat = Price {
+            atoms_per_mib: 0,
+            ..p
+        };
+        assert_eq!(
+            flat.cost(&Usage {
+                bytes: 1 << 30,
+                elapsed: Duration::from_millis(1500),
+                rows: 0
+            }),
+            150
+        );
+        let per_row = Price {
+            atoms_per_row: 3,
+            ..p
+        };
+        assert_eq!(
+            per_row.cost(&Usage {
+                bytes: 0,
+                elapsed: Duration::ZERO,
+                rows: 7
+            }),
+            21
+        );
+        let max = Price {
+            atoms_per_mib: u64::MAX,
+            ..p
+        };
+        assert_eq!(
+            max.cost(&Usage {
+                bytes: u64::MAX,
+                elapsed: Duration::ZERO,
+                rows: 0
+            }),
+            u64::MAX
+        );
+    }
+}
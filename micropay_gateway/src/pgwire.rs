@@ -0,0 +1,473 @@
+//! PostgreSQL wire protocol framing.
+//!
+//! Every message after the startup packet is framed as:
+//!   Byte1 tag
+//!   Int32 length                (includes this Int32, excludes the tag)
+//!   Byte[length - 4] body
+//!
+//! TCP gives no message boundaries, so a single read may carry several messages or only part of one.
+//! `MessageReader` buffers until a full frame is available.
+use std::io::{self, Error, ErrorKind};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Upper bound on a single frame, mirrors the backend's own `PQ_LARGE_MESSAGE_LIMIT`.
+pub const MAX_MESSAGE_LEN: usize = 0x3fff_ffff;
+/// Startup packets are tiny, the backend rejects anything over 10000 bytes.
+pub const MAX_STARTUP_LEN: usize = 10_000;
+pub const READ_CHUNK: usize = 8192;
+
+/// One tagged protocol message, body excludes the tag and length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub tag: u8,
+    pub body: Vec<u8>,
+}
+
+impl Message {
+    pub fn new(tag: u8, body: Vec<u8>) -> Self {
+        Self { tag, body }
+    }
+
+    /// Number of bytes this message occupies on the wire.
+    pub fn wire_len(&self) -> usize {
+        1 + 4 + self.body.len()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.wire_len());
+        out.push(self.tag);
+        out.extend_from_slice(&((self.body.len() + 4) as u32).to_be_bytes());
+        out.extend_from_slice(&self.body);
+        out
+    }
+
+    /// ErrorResponse carrying severity, SQLSTATE code and message.
+    pub fn error_response(severity: &str, code: &str, msg: &str) -> Self {
+        let mut body = Vec::new();
+        for (field, value) in [
+            (b'S', severity),
+            (b'V', severity),
+            (b'C', code),
+            (b'M', msg),
+        ] {
+            body.push(field);
+            body.extend_from_slice(value.as_bytes());
+            body.push(0);
+        }
+        body.push(0);
+        Self::new(b'E', body)
+    }
+
+    /// Interpret as a message sent by the client.
+    pub fn frontend(&self) -> Frontend<'_> {
+        let body = self.body.as_slice();
+        let typed = match self.tag {
+            b'Q' => cstr(body).map(|(sql, _)| Frontend::Query(sql)),
+            b'P' => cstr(body).and_then(|(name, rest)| {
+                let (query, _) = cstr(rest)?;
+                Some(Frontend::Parse { name, query })
+            }),
+            b'B' => Some(Frontend::Bind),
+            b'E' => cstr(body).map(|(portal, _)| Frontend::Execute(portal)),
+            b'D' => Some(Frontend::Describe),
+            b'C' => Some(Frontend::Close),
+            b'H' => Some(Frontend::Flush),
+            b'S' => Some(Frontend::Sync),
+            b'F' => Some(Frontend::FunctionCall),
+            b'X' => Some(Frontend::Terminate),
+            _ => None,
+        };
+        typed.unwrap_or(Frontend::Other {
+            tag: self.tag,
+            len: body.len(),
+        })
+    }
+
+    /// Interpret as a message sent by the backend.
+    pub fn backend(&self) -> Backend<'_> {
+        let body = self.body.as_slice();
+        let typed = match self.tag {
+            b'R' => be_u32(body).map(Backend::Authentication),
+            b'S' => cstr(body).and_then(|(name, rest)| {
+                let (value, _) = cstr(rest)?;
+                Some(Backend::ParameterStatus { name, value })
+            }),
+            b'Z' => body.first().map(|status| Backend::ReadyForQuery(*status)),
+            b'C' => cstr(body).map(|(tag, _)| Backend::CommandComplete(tag)),
+            b'T' => be_u16(body).map(Backend::RowDescription),
+            b'D' => be_u16(body).map(Backend::DataRow),
+            b'E' => Some(Backend::ErrorResponse(fields(body))),
+            b'N' => Some(Backend::NoticeResponse(fields(body))),
+            _ => None,
+        };
+        typed.unwrap_or(Backend::Other {
+            tag: self.tag,
+            len: body.len(),
+        })
+    }
+}
+
+/// Client → server messages the gateway cares about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frontend<'a> {
+    /// simple query protocol
+    Query(&'a str),
+    /// extended query protocol, prepared statement
+    Parse {
+        name: &'a str,
+        query: &'a str,
+    },
+    Bind,
+    Execute(&'a str),
+    Describe,
+    Close,
+    /// ask the backend for what it has buffered, ahead of Sync
+    Flush,
+    Sync,
+    /// fast path function call, answered like a Query
+    FunctionCall,
+    Terminate,
+    Other {
+        tag: u8,
+        len: usize,
+    },
+}
+
+/// Server → client messages the gateway cares about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Backend<'a> {
+    /// auth request code, 0 is AuthenticationOk
+    Authentication(u32),
+    ParameterStatus {
+        name: &'a str,
+        value: &'a str,
+    },
+    /// transaction status: b'I' idle, b'T' in transaction, b'E' failed transaction
+    ReadyForQuery(u8),
+    /// command tag, e.g. "SELECT 3"
+    CommandComplete(&'a str),
+    /// column count
+    RowDescription(u16),
+    /// column count
+    DataRow(u16),
+    /// (field code, value) pairs, e.g. (b'M', "message")
+    ErrorResponse(Vec<(u8, &'a str)>),
+    NoticeResponse(Vec<(u8, &'a str)>),
+    Other {
+        tag: u8,
+        len: usize,
+    },
+}
+
+/// Split off a NUL terminated string.
+fn cstr(buf: &[u8]) -> Option<(&str, &[u8])> {
+    let end = buf.iter().position(|b| *b == 0)?;
+    let s = std::str::from_utf8(&buf[..end]).ok()?;
+    Some((s, &buf[end + 1..]))
+}
+
+fn be_u32(buf: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(buf.get(..4)?.try_into().ok()?))
+}
+
+fn be_u16(buf: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes(buf.get(..2)?.try_into().ok()?))
+}
+
+/// ErrorResponse / NoticeResponse body: (Byte1 code, String value)* terminated by a zero code.
+fn fields(mut buf: &[u8]) -> Vec<(u8, &str)> {
+    let mut out = Vec::new();
+    while let Some((&code, rest)) = buf.split_first() {
+        if code == 0 {
+            break;
+        }
+        let Some((value, rest)) = cstr(rest) else {
+            break;
+        };
+        out.push((code, value));
+        buf = rest;
+    }
+    out
+}
+
+/// Rows a CommandComplete tag reports, `None` for commands that don't count rows (e.g. "CREATE TABLE").
+/// "INSERT oid rows", "SELECT rows", "UPDATE rows", ...
+pub fn command_rows(tag: &str) -> Option<u64> {
+    let (command, rest) = tag.split_once(' ')?;
+    let rows = match command {
+        "INSERT" => rest.split_once(' ')?.1,
+        "SELECT" | "UPDATE" | "DELETE" | "MERGE" | "MOVE" | "FETCH" | "COPY" => rest,
+        _ => return None,
+    };
+    rows.parse().ok()
+}
+
+/// Reads whole frames off a byte stream.
+///
+/// Both `read_startup` and `read_message` are cancel safe: partial frames stay
+/// buffered, so they can be raced in `tokio::select!`.
+pub struct MessageReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    /// bytes asked for per read
+    chunk: usize,
+    max_len: usize,
+}
+
+impl<R: AsyncRead + Unpin> MessageReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_limits(inner, READ_CHUNK, MAX_MESSAGE_LEN)
+    }
+
+    /// `max_len` caps the length field of tagged messages, the startup packet has its own cap.
+    pub fn with_limits(inner: R, chunk: usize, max_len: usize) -> Self {
+        Self {
+            inner,
+            buf: Vec::with_capacity(chunk),
+            chunk,
+            max_len,
+        }
+    }
+
+    /// Bytes received but not yet handed out as a frame.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Read the untagged startup packet, returned whole (length prefix included).
+    /// `Ok(None)` if the peer closed before sending anything.
+    pub async fn read_startup(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if self.buf.len() >= 4 {
+                let len = u32::from_be_bytes(self.buf[0..4].try_into().unwrap()) as usize;
+                if !(8..=MAX_STARTUP_LEN).contains(&len) {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("invalid startup length {len}"),
+                    ));
+                }
+                if self.buf.len() >= len {
+                    return Ok(Some(self.buf.drain(..len).collect()));
+                }
+            }
+            if !self.fill().await? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Read the next tagged message.
+    /// `Ok(None)` on a clean close between messages.
+    pub async fn read_message(&mut self) -> io::Result<Option<Message>> {
+        loop {
+            if self.buf.len() >= 5 {
+                let len = u32::from_be_bytes(self.buf[1..5].try_into().unwrap()) as usize;
+                if !(4..=self.max_len).contains(&len) {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("invalid length {len} for message '{}'", self.buf[0] as char),
+                    ));
+                }
+                if self.buf.len() > len {
+                    let tag = self.buf[0];
+                    let body = self.buf[5..1 + len].to_vec();
+                    self.buf.drain(..1 + len);
+                    return Ok(Some(Message { tag, body }));
+                }
+            }
+            if !self.fill().await? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Pull more bytes into the buffer, false on EOF at a frame boundary.
+    async fn fill(&mut self) -> io::Result<bool> {
+        self.buf.reserve(self.chunk);
+        if self.inner.read_buf(&mut self.buf).await? > 0 {
+            return Ok(true);
+        }
+        if self.buf.is_empty() {
+            return Ok(false);
+        }
+        Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            format!(
+                "peer closed mid message ({} bytes buffered)",
+                self.buf.len()
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    fn startup_packet() -> Vec<u8> {
+        let mut body = 0x0003_0000u32.to_be_bytes().to_vec();
+        body.extend_from_slice(b"user\0alice\0\0");
+        let mut out = ((body.len() + 4) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(&body);
+        out
+    }
+
+    #[tokio::test]
+    async fn test_split_across_reads() {
+        let msgs = vec![
+            Message::new(b'Q', b"select 1;\0".to_vec()),
+            Message::new(b'S', vec![]),
+            Message::new(b'X', vec![]),
+        ];
+        let mut wire = startup_packet();
+        for m in &msgs {
+            wire.extend_from_slice(&m.encode());
+        }
+
+        let (mut tx, rx) = tokio::io::duplex(64);
+        let writer = tokio::spawn(async move {
+            // one byte per write, every frame straddles many reads
+            for b in wire {
+                tx.write_all(&[b]).await.unwrap();
+                tx.flush().await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        });
+
+        let mut reader = MessageReader::new(rx);
+        assert_eq!(
+            reader.read_startup().await.unwrap().unwrap(),
+            startup_packet()
+        );
+        for m in &msgs {
+            assert_eq!(&reader.read_message().await.unwrap().unwrap(), m);
+        }
+        writer.await.unwrap();
+        assert!(reader.read_message().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_many_per_read() {
+        let mut wire = Vec::new();
+        for i in 0..10u8 {
+            wire.extend_from_slice(&Message::new(b'D', vec![0, 1, 0, 0, 0, 1, i]).encode());
+        }
+        let mut reader = MessageReader::new(wire.as_slice());
+        for i in 0..10u8 {
+            let m = reader.read_message().await.unwrap().unwrap();
+            assert_eq!(m.body[6], i);
+            assert_eq!(m.backend(), Backend::DataRow(1));
+        }
+        assert!(reader.read_message().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bad_frames() {
+        // truncated body
+        let wire = Message::new(b'Q', b"select 1;\0".to_vec()).encode();
+        let mut reader = MessageReader::new(&wire[..8]);
+        let err = reader.read_message().await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        // length smaller than itself
+        let wire = [b'Q', 0, 0, 0, 3];
+        let mut reader = MessageReader::new(&wire[..]);
+        assert_eq!(
+            reader.read_message().await.unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+
+        // over the configured cap
+        let wire = Message::new(b'Q', vec![1; 100]).encode();
+        let mut reader = MessageReader::with_limits(&wire[..], 16, 99);
+        assert_eq!(
+            reader.read_message().await.unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        let mut reader = MessageReader::with_limits(&wire[..], 16, 104);
+        assert_eq!(
+            reader.read_message().await.unwrap().unwrap().body.len(),
+            100
+        );
+
+        // oversized startup
+        let wire = 100_000u32.to_be_bytes();
+        let mut reader = MessageReader::new(&wire[..]);
+        assert_eq!(
+            reader.read_startup().await.unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_typed_views() {
+        let q = Message::new(b'Q', b"set voucher = 'x';\0".to_vec());
+        assert_eq!(q.frontend(), Frontend::Query("set voucher = 'x';"));
+        let p = Message::new(b'P', b"s1\0select $1\0\0\0".to_vec());
+        assert_eq!(
+            p.frontend(),
+            Frontend::Parse {
+                name: "s1",
+                query: "select $1"
+            }
+        );
+        assert_eq!(Message::new(b'X', vec![]).frontend(), Frontend::Terminate);
+        assert_eq!(Message::new(b'H', vec![]).frontend(), Frontend::Flush);
+        assert_eq!(
+            Message::new(b'F', vec![0; 8]).frontend(),
+            Frontend::FunctionCall
+        );
+        assert_eq!(
+            Message::new(b'd', vec![1, 2]).frontend(),
+            Frontend::Other { tag: b'd', len: 2 }
+        );
+
+        assert_eq!(
+            Message::new(b'R', vec![0, 0, 0, 0]).backend(),
+            Backend::Authentication(0)
+        );
+        assert_eq!(
+            Message::new(b'Z', vec![b'I']).backend(),
+            Backend::ReadyForQuery(b'I')
+        );
+        assert_eq!(
+            Message::new(b'C', b"SELECT 3\0".to_vec()).backend(),
+            Backend::CommandComplete("SELECT 3")
+        );
+        let s = Message::new(b'S', b"server_version\x0016.1\0".to_vec());
+        assert_eq!(
+            s.backend(),
+            Backend::ParameterStatus {
+                name: "server_version",
+                value: "16.1"
+            }
+        );
+        let e = Message::error_response("ERROR", "28000", "bad voucher");
+        let fields = vec![
+            (b'S', "ERROR"),
+            (b'V', "ERROR"),
+            (b'C', "28000"),
+            (b'M', "bad voucher"),
+        ];
+        assert_eq!(e.backend(), Backend::ErrorResponse(fields));
+        // malformed body falls back to Other
+        assert_eq!(
+            Message::new(b'R', vec![0]).backend(),
+            Backend::Other { tag: b'R', len: 1 }
+        );
+    }
+
+    #[test]
+    fn test_command_rows() {
+        assert_eq!(command_rows("SELECT 3"), Some(3));
+        assert_eq!(command_rows("INSERT 0 12"), Some(12));
+        assert_eq!(command_rows("UPDATE 0"), Some(0));
+        assert_eq!(command_rows("COPY 1000000"), Some(1000000));
+        assert_eq!(command_rows("CREATE TABLE"), None);
+        assert_eq!(command_rows("BEGIN"), None);
+        assert_eq!(command_rows("INSERT 5"), None);
+        assert_eq!(command_rows("SELECT x"), None);
+    }
+}
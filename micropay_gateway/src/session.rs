@@ -0,0 +1,608 @@
+//! Per connection state: the voucher the client is currently paying with and the queries it is paying for.
+//!
+//! Clients top up mid session with `SET voucher = '<voucher>';` which never reaches the backend.
+//! Every Query, FunctionCall or extended query up to its Sync locks `Price::query_estimate` with the engine
+//! and is settled at its metered cost when the backend answers ReadyForQuery. The backend answers in order,
+//! so what it sends is metered to the oldest request not answered yet, Flush results included.
+//! What the gateway answers itself waits for the ReadyForQuery of everything pipelined before it.
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Instant;
+
+use micropay_gateway::engine::GatewayEngine;
+use micropay_gateway::meter::{Price, Usage};
+use micropay_gateway::voucher::{GatewayVoucher, VendorId, VoucherErr};
+use protocol::QueryCont;
+
+use crate::pgwire::{Backend, Frontend, Message, command_rows};
+use crate::{Flow, MessageHook};
+
+pub struct Session {
+    pub voucher: GatewayVoucher,
+    vendor: VendorId,
+    engine: Arc<GatewayEngine>,
+    price: Price,
+    /// last ReadyForQuery status from the backend, echoed when we answer on its behalf
+    tx_status: u8,
+    /// extended query messages since the last Sync, metered in `pending` from the first
+    extended: Extended,
+    /// sent to the backend or answered by the gateway, oldest first
+    pending: VecDeque<Pending>,
+}
+
+enum Pending {
+    /// answered by one ReadyForQuery
+    Backend(Metered),
+    /// the gateway's answer, ReadyForQuery left off, sent once everything before it is answered
+    Reply(Vec<Message>),
+}
+
+/// One request the backend answers with a single ReadyForQuery.
+struct Metered {
+    /// `None` for a bare Sync, nothing to charge
+    qc: Option<QueryCont>,
+    started: Instant,
+    usage: Usage,
+}
+
+enum Extended {
+    Idle,
+    Running,
+    /// the engine said no, swallow everything up to Sync and answer it with this error
+    Refused(Message),
+}
+
+impl Session {
+    pub fn new(
+        voucher: GatewayVoucher,
+        vendor: VendorId,
+        engine: Arc<GatewayEngine>,
+        price: Price,
+    ) -> Self {
+        Self {
+            voucher,
+            vendor,
+            engine,
+            price,
+            tx_status: b'I',
+            extended: Extended::Idle,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Swap in another voucher of the same client, once the engine accepts it (nonce progression, oracle).
+    pub async fn refresh(&mut self, blob: &str) -> Result<(), VoucherErr> {
+        let next = GatewayVoucher::decode(blob)?;
+        next.validate(self.vendor)?;
+        if next.client != self.voucher.client {
+            return Err(VoucherErr::OtherClient);
+        }
+        self.engine.accept_session(&next).await?;
+        self.voucher = next;
+        Ok(())
+    }
+
+    /// Answer an intercepted `SET voucher` as the backend would, ReadyForQuery left to `reply`.
+    async fn set_voucher(&mut self, blob: &str) -> Message {
+        match self.refresh(blob).await {
+            Ok(()) => {
+                println!(
+                    "client {} refreshed voucher nonce={}",
+                    self.voucher.client, self.voucher.nonce
+                );
+                Message::new(b'C', b"SET\0".to_vec())
+            }
+            Err(e) => Message::error_response("ERROR", e.sqlstate(), &e.to_string()),
+        }
+    }
+
+    /// Answer the client with `out` and a ReadyForQuery, right away if nothing is in flight, else
+    /// held until the backend answered everything sent before.
+    fn reply(&mut self, mut out: Vec<Message>) -> Flow {
+        if self.pending.is_empty() {
+            out.push(self.ready());
+            return Flow::Reply(out);
+        }
+        self.pending.push_back(Pending::Reply(out));
+        Flow::Reply(vec![])
+    }
+
+    /// Lock the estimate with the engine, the ErrorResponse to answer with if the client can't cover it.
+    async fn start_query(&self) -> Result<Metered, Message> {
+        let metered = |qc| Metered {
+            qc,
+            started: Instant::now(),
+            usage: Usage::default(),
+        };
+        match self
+            .engine
+            .query(&self.voucher.client, self.price.query_estimate)
+            .await
+        {
+            Ok(qc) if qc.should_continue => Ok(metered(Some(qc))),
+            // insufficient_resources
+            Ok(_) => Err(Message::error_response(
+                "ERROR",
+                "53000",
+                "Out of credit, SET voucher to top up",
+            )),
+            Err(e) => Err(Message::error_response(
+                "ERROR",
+                "58000",
+                &format!("Can't check credit {e}"),
+            )),
+        }
+    }
+
+    /// Forward a request answered by one ReadyForQuery once its estimate is locked, refuse it otherwise.
+    async fn forward_metered(&mut self) -> Flow {
+        match self.start_query().await {
+            Ok(m) => {
+                self.pending.push_back(Pending::Backend(m));
+                Flow::Forward
+            }
+            Err(refused) => self.reply(vec![refused]),
+        }
+    }
+
+    /// Charge the client what the query used, releasing the locked estimate.
+    async fn settle(&self, m: Metered) {
+        let Some(qc) = m.qc else {
+            return;
+        };
+        let usage = Usage {
+            elapsed: m.started.elapsed(),
+            ..m.usage
+        };
+        let cost = self.price.cost(&usage);
+        match self
+            .engine
+            .settle_query(&self.voucher.client, &qc, cost)
+            .await
+        {
+            Ok(()) => println!("client {} query {usage:?} cost {cost}", self.voucher.client),
+            Err(e) => eprintln!(
+                "client {} query cost {cost} not settled: {e}",
+                self.voucher.client
+            ),
+        }
+    }
+
+    /// Settle whatever is still running once the connection is gone.
+    pub async fn finish(&mut self) {
+        while let Some(p) = self.pending.pop_front() {
+            if let Pending::Backend(m) = p {
+                self.settle(m).await;
+            }
+        }
+    }
+
+    fn ready(&self) -> Message {
+        Message::new(b'Z', vec![self.tx_status])
+    }
+}
+
+impl MessageHook for Session {
+    async fn frontend(&mut self, msg: &Message) -> Flow {
+        let typed = msg.frontend();
+        println!("CLIENT → SERVER  ({} bytes): {:?}", msg.wire_len(), typed);
+        match typed {
+            Frontend::Query(sql) => {
+                if let Some(blob) = parse_set_voucher(sql) {
+                    let out = self.set_voucher(blob).await;
+                    return self.reply(vec![out]);
+                }
+                self.forward_metered().await
+            }
+            Frontend::Sync => match std::mem::replace(&mut self.extended, Extended::Idle) {
+                Extended::Refused(refused) => self.reply(vec![refused]),
+                // metered since its first message
+                Extended::Running => Flow::Forward,
+                Extended::Idle => {
+                    let started = Instant::now();
+                    let bare = Metered {
+                        qc: None,
+                        started,
+                        usage: Usage::default(),
+                    };
+                    self.pending.push_back(Pending::Backend(bare));
+                    Flow::Forward
+                }
+            },
+            Frontend::Terminate => Flow::Forward,
+            _ if matches!(self.extended, Extended::Refused(_)) => Flow::Reply(vec![]),
+            Frontend::FunctionCall => self.forward_metered().await,
+            Frontend::Parse { .. }
+            | Frontend::Bind
+            | Frontend::Execute(_)
+            | Frontend::Describe
+            | Frontend::Close => {
+                if !matches!(self.extended, Extended::Idle) {
+                    return Flow::Forward;
+                }
+                match self.start_query().await {
+                    Ok(m) => {
+                        self.pending.push_back(Pending::Backend(m));
+                        self.extended = Extended::Running;
+                        Flow::Forward
+                    }
+                    Err(refused) => {
+                        self.extended = Extended::Refused(refused);
+                        Flow::Reply(vec![])
+                    }
+                }
+            }
+            _ => Flow::Forward,
+        }
+    }
+
+    async fn backend(&mut self, msg: &Message) -> Vec<Message> {
+        let typed = msg.backend();
+        println!("SERVER → CLIENT  ({} bytes): {:?}", msg.wire_len(), typed);
+        // the front is always sent to the backend, held replies go out as soon as they reach it
+        if let Some(Pending::Backend(m)) = self.pending.front_mut() {
+            m.usage.bytes += msg.wire_len() as u64;
+            if let Backend::CommandComplete(tag) = typed {
+                m.usage.rows += command_rows(tag).unwrap_or(0);
+            }
+        }
+        let Backend::ReadyForQuery(status) = typed else {
+            return vec![];
+        };
+        self.tx_status = status;
+        if let Some(Pending::Backend(m)) = self.pending.pop_front() {
+            self.settle(m).await;
+        }
+        let mut out = vec![];
+        while matches!(self.pending.front(), Some(Pending::Reply(_))) {
+            if let Some(Pending::Reply(reply)) = self.pending.pop_front() {
+                out.extend(reply);
+                out.push(self.ready());
+            }
+        }
+        out
+    }
+}
+
+/// The blob of `SET voucher = '<blob>'` (or `TO`), case insensitive, one statement only.
+pub fn parse_set_voucher(sql: &str) -> Option<&str> {
+    let sql = sql.trim().trim_end_matches(';').trim_end();
+    let rest = strip_keyword(sql, "set")?;
+    let rest = strip_keyword(rest.trim_start(), "voucher")?.trim_start();
+    let rest = match rest.strip_prefix('=') {
+        Some(rest) => rest,
+        None => strip_keyword(rest, "to")?,
+    };
+    let blob = rest.trim_start().strip_prefix('\'')?.strip_suffix('\'')?;
+    (!blob.contains('\'')).then_some(blob)
+}
+
+/// `s` without its leading keyword, the keyword must end at a word boundary.
+fn strip_keyword<'a>(s: &'a str, kw: &str) -> Option<&'a str> {
+    let head = s.get(..kw.len())?;
+    let rest = &s[kw.len()..];
+    let boundary = rest
+        .chars()
+        .next()
+        .is_none_or(|c| !(c.is_alphanumeric() || c == '_'));
+    (head.eq_ignore_ascii_case(kw) && boundary).then_some(rest)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+    use micropay_gateway::engine::{MemOracle, mem_engine};
+    use micropay_gateway::voucher::address;
+    use protocol::ClientRiskConfig;
+
+    fn key() -> SigningKey {
+        SigningKey::from_slice(&[0x11; 32]).unwrap()
+    }
+
+    fn voucher(nonce: u64) -> GatewayVoucher {
+        let atoms = 5000 * (nonce + 1);
+        GatewayVoucher {
+            client: 7,
+            vendor: 1,
+            nonce,
+            atoms,
+            expiry: u64::MAX,
+            signature: String::new(),
+        }
+        .sign(&key())
+    }
+
+    fn set(v: &GatewayVoucher) -> Message {
+        Message::new(
+            b'Q',
+            format!("SET voucher = '{}';\0", v.encode()).into_bytes(),
+        )
+    }
+
+    fn engine() -> Arc<GatewayEngine> {
+        let signer = hex::encode(address(key().verifying_key()));
+        let oracle = format!(
+            "[[client]]\nid = 7\ncollateral = 100000\nvendors = [1]\nsigner = \"0x{signer}\""
+        );
+        let oracle = MemOracle::from_toml(&oracle).unwrap();
+        Arc::new(mem_engine(1, oracle, ClientRiskConfig::new()))
+    }
+
+    fn is_error(out: &[Message], code: &str) -> bool {
+        let Some(Backend::ErrorResponse(fields)) = out.first().map(Message::backend) else {
+            return false;
+        };
+        fields.contains(&(b'C', code)) && out[1] == Message::new(b'Z', vec![b'I'])
+    }
+
+    #[test]
+    fn test_parse_set_voucher() {
+        assert_eq!(parse_set_voucher("set voucher = 'abc';"), Some("abc"));
+        assert_eq!(parse_set_voucher("  SET Voucher TO 'abc'  ; "), Some("abc"));
+        assert_eq!(parse_set_voucher("set voucher='abc'"), Some("abc"));
+        assert_eq!(parse_set_voucher("set vouchers = 'abc'"), None);
+        assert_eq!(parse_set_voucher("set voucher = abc"), None);
+        assert_eq!(
+            parse_set_voucher("set voucher = 'a'; drop table x; select 'b'"),
+            None
+        );
+        assert_eq!(parse_set_voucher("set search_path = 'abc'"), None);
+        assert_eq!(parse_set_voucher("select 'set voucher = 1'"), None);
+    }
+
+    #[tokio::test]
+    async fn test_set_voucher() {
+        let engine = engine();
+        engine.accept_session(&voucher(0)).await.unwrap();
+        let mut s = Session::new(voucher(0), 1, engine, Price::default());
+        let select = Message::new(b'Q', b"select 1;\0".to_vec());
+        assert!(matches!(s.frontend(&select).await, Flow::Forward));
+        s.backend(&Message::new(b'Z', vec![b'I'])).await;
+
+        // accepted, answered as if the backend did it
+        let Flow::Reply(out) = s.frontend(&set(&voucher(1))).await else {
+            panic!("SET reached the backend")
+        };
+        assert_eq!(
+            out,
+            vec![
+                Message::new(b'C', b"SET\0".to_vec()),
+                Message::new(b'Z', vec![b'I'])
+            ]
+        );
+        assert_eq!(s.voucher, voucher(1));
+
+        // refused by the engine, tx status follows the backend
+        s.backend(&Message::new(b'Z', vec![b'T'])).await;
+        let Flow::Reply(out) = s.frontend(&set(&voucher(3))).await else {
+            panic!("SET reached the backend")
+        };
+        let Backend::ErrorResponse(fields) = out[0].backend() else {
+            panic!("no ErrorResponse")
+        };
+        assert!(fields.contains(&(b'C', "28000")), "{fields:?}");
+        assert_eq!(out[1], Message::new(b'Z', vec![b'T']));
+        assert_eq!(s.voucher, voucher(1));
+
+        let other = GatewayVoucher {
+            client: 8,
+            ..voucher(2)
+        };
+        assert!(matches!(
+            s.refresh(&other.encode()).await,
+            Err(VoucherErr::OtherClient)
+        ));
+        let vendor = GatewayVoucher {
+            vendor: 2,
+            ..voucher(2)
+        };
+        assert!(matches!(
+            s.refresh(&vendor.encode()).await,
+            Err(VoucherErr::InvalidVendor)
+        ));
+        let rich = GatewayVoucher {
+            atoms: 1_000_000,
+            ..voucher(2)
+        }
+        .sign(&key());
+        assert!(matches!(
+            s.refresh(&rich.encode()).await,
+            Err(VoucherErr::Engine(_))
+        ));
+        // more atoms than it was signed for
+        let tampered = GatewayVoucher {
+            atoms: 20000,
+            ..voucher(2)
+        };
+        let Err(e) = s.refresh(&tampered.encode()).await else {
+            panic!("tampered voucher accepted")
+        };
+        assert_eq!(e.sqlstate(), "28P01");
+        assert!(s.refresh(&voucher(2).encode()).await.is_ok());
+
+        // pipelined behind a query, the answer waits for the query's ReadyForQuery
+        assert!(matches!(s.frontend(&select).await, Flow::Forward));
+        assert!(matches!(s.frontend(&set(&voucher(3))).await, Flow::Reply(out) if out.is_empty()));
+        assert_eq!(s.voucher, voucher(3));
+        assert!(
+            s.backend(&Message::new(b'C', b"SELECT 1\0".to_vec()))
+                .await
+                .is_empty()
+        );
+        let out = s.backend(&Message::new(b'Z', vec![b'I'])).await;
+        assert_eq!(
+            out,
+            vec![
+                Message::new(b'C', b"SET\0".to_vec()),
+                Message::new(b'Z', vec![b'I'])
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metering() {
+        let engine = engine();
+        engine.accept_session(&voucher(0)).await.unwrap();
+        // a byte an atom
+        let price = Price {
+            atoms_per_mib: 1 << 20,
+            atoms_per_sec: 0,
+            atoms_per_row: 0,
+            query_estimate: 2000,
+        };
+        let mut s = Session::new(voucher(0), 1, engine.clone(), price);
+        let select = Message::new(b'Q', b"select 1;\0".to_vec());
+        let done = [
+            Message::new(b'C', b"SELECT 1\0".to_vec()),
+            Message::new(b'Z', vec![b'I']),
+        ];
+
+        assert!(matches!(s.frontend(&select).await, Flow::Forward));
+        for msg in &done {
+            assert!(s.backend(msg).await.is_empty());
+        }
+        // 20 bytes spent of the 5000 atom voucher
+        assert!(!engine.query(&7, 4981).await.unwrap().should_continue);
+
+        let extended = [
+            Message::new(b'P', b"\0select 1\0\0\0".to_vec()),
+            Message::new(b'B', b"\0\0\0\0\0\0\0\0".to_vec()),
+            Message::new(b'E', b"\0\0\0\0\0".to_vec()),
+            Message::new(b'S', vec![]),
+        ];
+        for msg in &extended {
+            assert!(matches!(s.frontend(msg).await, Flow::Forward));
+        }
+        s.backend(&Message::new(b'1', vec![])).await;
+        s.backend(&Message::new(b'2', vec![])).await;
+        for msg in &done {
+            s.backend(msg).await;
+        }
+        assert!(!engine.query(&7, 4951).await.unwrap().should_continue);
+
+        // nothing in flight, refused right away
+        let mut broke = Session::new(
+            voucher(0),
+            1,
+            engine.clone(),
+            Price {
+                query_estimate: 5000,
+                ..price
+            },
+        );
+        let Flow::Reply(out) = broke.frontend(&select).await else {
+            panic!("forwarded without credit")
+        };
+        assert!(is_error(&out, "53000"));
+
+        // two queries in flight lock 4000 of the 4950 left, a third is refused
+        assert!(matches!(s.frontend(&select).await, Flow::Forward));
+        assert!(matches!(s.frontend(&select).await, Flow::Forward));
+        assert!(matches!(s.frontend(&select).await, Flow::Reply(out) if out.is_empty()));
+        // extended: swallowed up to Sync, which gets the error
+        for msg in &extended {
+            assert!(matches!(s.frontend(msg).await, Flow::Reply(out) if out.is_empty()));
+        }
+
+        // both refusals follow the results of the two in flight, in order
+        for msg in &done {
+            assert!(s.backend(msg).await.is_empty());
+        }
+        assert!(s.backend(&done[0]).await.is_empty());
+        let out = s.backend(&done[1]).await;
+        assert_eq!(out.len(), 4);
+        assert!(is_error(&out[..2], "53000") && is_error(&out[2..], "53000"));
+
+        // the two in flight cost 20 each
+        s.finish().await;
+        assert!(!engine.query(&7, 4911).await.unwrap().should_continue);
+        assert!(engine.query(&7, 4910).await.unwrap().should_continue);
+    }
+
+    #[tokio::test]
+    async fn test_flush_and_function_call_metering() {
+        let engine = engine();
+        engine.accept_session(&voucher(0)).await.unwrap();
+        // a byte an atom
+        let price = Price {
+            atoms_per_mib: 1 << 20,
+            atoms_per_sec: 0,
+            atoms_per_row: 0,
+            query_estimate: 100,
+        };
+        let mut s = Session::new(voucher(0), 1, engine.clone(), price);
+        let select = Message::new(b'Q', b"select 1;\0".to_vec());
+        let complete = Message::new(b'C', b"SELECT 1\0".to_vec());
+        let ready = Message::new(b'Z', vec![b'I']);
+
+        // a Query in flight, then an extended query flushed before its Sync
+        assert!(matches!(s.frontend(&select).await, Flow::Forward));
+        let extended = [
+            Message::new(b'P', b"\0select 1\0\0\0".to_vec()),
+            Message::new(b'B', b"\0\0\0\0\0\0\0\0".to_vec()),
+            Message::new(b'E', b"\0\0\0\0\0".to_vec()),
+            Message::new(b'H', vec![]),
+        ];
+        for msg in &extended {
+            assert!(matches!(s.frontend(msg).await, Flow::Forward));
+        }
+        // 20 bytes for the Query, the extended query still locks its estimate
+        s.backend(&complete).await;
+        s.backend(&ready).await;
+        assert!(!engine.query(&7, 4881).await.unwrap().should_continue);
+        // the flushed results are the extended query's, with its ReadyForQuery 30 bytes
+        for msg in [
+            Message::new(b'1', vec![]),
+            Message::new(b'2', vec![]),
+            complete.clone(),
+        ] {
+            s.backend(&msg).await;
+        }
+        assert!(matches!(
+            s.frontend(&Message::new(b'S', vec![])).await,
+            Flow::Forward
+        ));
+        s.backend(&ready).await;
+        assert!(!engine.query(&7, 4951).await.unwrap().should_continue);
+
+        // a FunctionCall is metered on its own, its ReadyForQuery settles it and not the Query after it
+        let call = Message::new(b'F', b"\0\0\0\x2a\0\0\0\0\0\0".to_vec());
+        assert!(matches!(s.frontend(&call).await, Flow::Forward));
+        assert!(matches!(s.frontend(&select).await, Flow::Forward));
+        s.backend(&Message::new(b'V', b"\0\0\0\x01\x07".to_vec()))
+            .await;
+        s.backend(&ready).await;
+        // 16 bytes for the call, the Query still locks its estimate
+        assert!(!engine.query(&7, 4835).await.unwrap().should_continue);
+        s.backend(&complete).await;
+        s.backend(&ready).await;
+        assert!(!engine.query(&7, 4915).await.unwrap().should_continue);
+        assert!(engine.query(&7, 4914).await.unwrap().should_continue);
+    }
+
+    #[tokio::test]
+    async fn test_row_pricing() {
+        let engine = engine();
+        engine.accept_session(&voucher(0)).await.unwrap();
+        let price = Price {
+            atoms_per_mib: 0,
+            atoms_per_sec: 0,
+            atoms_per_row: 10,
+            query_estimate: 100,
+        };
+        let mut s = Session::new(voucher(0), 1, engine.clone(), price);
+        let sql = Message::new(
+            b'Q',
+            b"select * from t limit 3; insert into t values (1), (2);\0".to_vec(),
+        );
+        assert!(matches!(s.frontend(&sql).await, Flow::Forward));
+        // rows of every statement in the query count, tags without rows don't
+        for tag in [&b"SELECT 3\0"[..], b"INSERT 0 2\0", b"CREATE TABLE\0"] {
+            s.backend(&Message::new(b'C', tag.to_vec())).await;
+        }
+        s.backend(&Message::new(b'Z', vec![b'I'])).await;
+        assert!(!engine.query(&7, 4951).await.unwrap().should_continue);
+        assert!(engine.query(&7, 4950).await.unwrap().should_continue);
+    }
+}
@@ -0,0 +1,260 @@
+//! TLS toward clients (SSLRequest answered with 'S') and optionally toward the backend.
+//!
+//! Clients ask for TLS with an untagged SSLRequest before their startup packet, the server answers a single
+//! byte, 'S' then TLS handshake or 'N' and the client carries on in plaintext (or gives up).
+use std::fs::File;
+use std::io::{self, BufReader, Error, ErrorKind};
+use std::sync::Arc;
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+use crate::config::Config;
+use crate::pgwire::MessageReader;
+use crate::startup;
+
+/// Either side of the proxy, plain or TLS.
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+#[derive(Default, Clone)]
+pub struct Tls {
+    /// terminate TLS for clients that ask, else SSLRequest is declined
+    pub acceptor: Option<TlsAcceptor>,
+    /// always talk TLS to the backend, verified against the configured CA
+    pub connector: Option<(TlsConnector, ServerName<'static>)>,
+}
+
+impl Tls {
+    /// Load the configured certificates, keys and CAs.
+    pub fn from_config(cfg: &Config) -> io::Result<Self> {
+        let acceptor = match &cfg.tls {
+            Some((cert, key)) => Some(acceptor(&cert.to_string_lossy(), &key.to_string_lossy())?),
+            None => None,
+        };
+        let connector = match &cfg.backend_ca {
+            Some(ca) => Some(connector(&ca.to_string_lossy(), &cfg.backend_host)?),
+            None => None,
+        };
+        Ok(Self {
+            acceptor,
+            connector,
+        })
+    }
+}
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg.into())
+}
+
+fn load_certs(path: &str) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(path)?))
+        .collect::<io::Result<Vec<_>>>()?;
+    if certs.is_empty() {
+        return Err(invalid(format!("no certificate in {path}")));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> io::Result<PrivateKeyDer<'static>> {
+    rustls_pemfile::private_key(&mut BufReader::new(File::open(path)?))?
+        .ok_or_else(|| invalid(format!("no private key in {path}")))
+}
+
+/// Server side TLS from a pem certificate chain and its private key.
+pub fn acceptor(cert_path: &str, key_path: &str) -> io::Result<TlsAcceptor> {
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(load_certs(cert_path)?, load_key(key_path)?)
+        .map_err(|e| invalid(format!("{cert_path}: {e}")))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Client side TLS trusting only the CA(s) in `ca_path`, `host` is what the backend cert must be for.
+pub fn connector(ca_path: &str, host: &str) -> io::Result<(TlsConnector, ServerName<'static>)> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(ca_path)? {
+        roots
+            .add(cert)
+            .map_err(|e| invalid(format!("{ca_path}: {e}")))?;
+    }
+    let name =
+        ServerName::try_from(host.to_string()).map_err(|e| invalid(format!("{host}: {e}")))?;
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok((TlsConnector::from(Arc::new(config)), name))
+}
+
+/// Answer the client's encryption requests, upgrading to TLS if it asks and we can.
+/// Returns the stream to carry on with and the startup packet if it was already read.
+/// `Ok(None)` if the client went away.
+pub async fn negotiate_client(
+    mut client: TcpStream,
+    tls: &Tls,
+) -> io::Result<Option<(Box<dyn Stream>, Option<Vec<u8>>)>> {
+    loop {
+        let mut reader = MessageReader::new(&mut client);
+        let Some(packet) = reader.read_startup().await? else {
+            return Ok(None);
+        };
+        // the client must wait for our answer, anything pipelined would bypass TLS
+        if reader.buffered() > 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "data received before the startup answer",
+            ));
+        }
+        let pver = u32::from_be_bytes(packet[4..8].try_into().unwrap());
+        match (pver, &tls.acceptor) {
+            (startup::SSL_REQUEST, Some(acceptor)) => {
+                client.write_all(b"S").await?;
+                let client = acceptor.accept(client).await?;
+                return Ok(Some((Box::new(client), None)));
+            }
+            // no encryption offered, the client carries on in plaintext
+            (startup::SSL_REQUEST | startup::GSSENC_REQUEST, _) => client.write_all(b"N").await?,
+            _ => return Ok(Some((Box::new(client), Some(packet)))),
+        }
+    }
+}
+
+/// Upgrade the backend connection when configured, refusing to go on in plaintext.
+pub async fn negotiate_backend(mut server: TcpStream, tls: &Tls) -> io::Result<Box<dyn Stream>> {
+    let Some((connector, name)) = &tls.connector else {
+        return Ok(Box::new(server));
+    };
+    let mut request = 8u32.to_be_bytes().to_vec();
+    request.extend_from_slice(&startup::SSL_REQUEST.to_be_bytes());
+    server.write_all(&request).await?;
+    match server.read_u8().await? {
+        b'S' => Ok(Box::new(connector.connect(name.clone(), server).await?)),
+        answer => Err(Error::new(
+            ErrorKind::ConnectionRefused,
+            format!("backend declined TLS (answered {:?})", answer as char),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    // testdata: ca.pem signs cert.pem (CN=localhost, SAN localhost + 127.0.0.1), key.pem is its key
+    const CA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/ca.pem");
+    const CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/cert.pem");
+    const KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/key.pem");
+
+    fn ssl_request() -> Vec<u8> {
+        [0, 0, 0, 8]
+            .into_iter()
+            .chain(startup::SSL_REQUEST.to_be_bytes())
+            .collect()
+    }
+
+    async fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        (client, listener.accept().await.unwrap().0)
+    }
+
+    #[tokio::test]
+    async fn test_client_tls() {
+        let tls = Tls {
+            acceptor: Some(acceptor(CERT, KEY).unwrap()),
+            connector: None,
+        };
+        let (connector, name) = connector(CA, "localhost").unwrap();
+        let (mut client, gateway) = pair().await;
+
+        let client = tokio::spawn(async move {
+            client.write_all(&ssl_request()).await.unwrap();
+            assert_eq!(client.read_u8().await.unwrap(), b'S');
+            let mut client = connector.connect(name, client).await.unwrap();
+            client.write_all(b"over tls").await.unwrap();
+            client.shutdown().await.unwrap();
+        });
+        let (mut stream, packet) = negotiate_client(gateway, &tls).await.unwrap().unwrap();
+        assert!(packet.is_none());
+        let mut got = Vec::new();
+        stream.read_to_end(&mut got).await.unwrap();
+        assert_eq!(got, b"over tls");
+        client.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_declined() {
+        let startup = startup::build_startup_message(
+            startup::PROTOCOL_V3,
+            &[("user".into(), "alice".into())],
+        );
+        let (mut client, gateway) = pair().await;
+        client.write_all(&ssl_request()).await.unwrap();
+        let sent = startup.clone();
+        let client = tokio::spawn(async move {
+            assert_eq!(client.read_u8().await.unwrap(), b'N');
+            client.write_all(&sent).await.unwrap();
+            client
+        });
+        let (_, packet) = negotiate_client(gateway, &Tls::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet, Some(startup.clone()));
+        client.await.unwrap();
+
+        // pipelining the startup behind the SSLRequest is refused
+        let (mut client, gateway) = pair().await;
+        client
+            .write_all(&[ssl_request(), startup].concat())
+            .await
+            .unwrap();
+        let err = negotiate_client(gateway, &Tls::default())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn test_backend_tls() {
+        let tls = Tls {
+            acceptor: None,
+            connector: Some(connector(CA, "localhost").unwrap()),
+        };
+        let backend = Tls {
+            acceptor: Some(acceptor(CERT, KEY).unwrap()),
+            connector: None,
+        };
+        let (gateway, server) = pair().await;
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = negotiate_client(server, &backend).await.unwrap().unwrap();
+            stream.write_all(b"hello").await.unwrap();
+            stream.shutdown().await.unwrap();
+        });
+        let mut stream = negotiate_backend(gateway, &tls).await.unwrap();
+        let mut got = Vec::new();
+        stream.read_to_end(&mut got).await.unwrap();
+        assert_eq!(got, b"hello");
+        server.await.unwrap();
+
+        // a backend without TLS is not silently used in plaintext
+        let (gateway, mut server) = pair().await;
+        tokio::spawn(async move {
+            server.read_exact(&mut [0u8; 8]).await.unwrap();
+            server.write_all(b"N").await.unwrap();
+        });
+        let err = negotiate_backend(gateway, &tls).await.err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+
+        // not a server name, not a key
+        assert!(connector(CA, "not a host").is_err());
+        assert!(acceptor(CA, CA).is_err());
+    }
+}
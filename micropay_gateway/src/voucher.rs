@@ -0,0 +1,318 @@
+//! Vouchers as presented by clients on the wire.
+//!
+//! A voucher travels as url-safe base64 (no padding) of its json so it fits in a startup parameter.
+//! The client signs it as EIP-712 typed data with the key it registered, see `GatewayVoucher::signing_hash`.
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use k256::ecdsa::{RecoveryId, Signature, SigningKey, VerifyingKey};
+use protocol::{EngineErr, StaticVAuthErr, VAuthErr, Voucher};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use thiserror::Error;
+
+pub type ClientId = u64;
+pub type VendorId = u64;
+/// Ethereum address, what a client registers to sign its vouchers with
+pub type Address = [u8; 20];
+
+const DOMAIN_TYPE: &str = "EIP712Domain(string name,string version)";
+const VOUCHER_TYPE: &str =
+    "Voucher(uint64 client,uint64 vendor,uint64 nonce,uint64 atoms,uint64 expiry)";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GatewayVoucher {
+    pub client: ClientId,
+    pub vendor: VendorId,
+    /// increases by one with each voucher the client signs
+    pub nonce: u64,
+    /// atoms the voucher is signed for
+    pub atoms: u64,
+    /// unix seconds, the voucher is refused from then on
+    pub expiry: u64,
+    /// `0x` hex of the 65 byte r || s || v signature of `signing_hash`, as `eth_signTypedData_v4` returns it
+    pub signature: String,
+}
+
+impl GatewayVoucher {
+    /// What a client sends.
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).expect("voucher serializes"))
+    }
+
+    pub fn decode(blob: &str) -> Result<Self, VoucherErr> {
+        let json = URL_SAFE_NO_PAD.decode(blob.trim())?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+
+    /// Checks that need no state, done before a backend connection is opened.
+    /// The signature is checked by the engine, against the key in the client's oracle record.
+    pub fn validate(&self, vendor: VendorId) -> Result<(), VoucherErr> {
+        if self.vendor != vendor {
+            return Err(VoucherErr::InvalidVendor);
+        }
+        if self.atoms == 0 {
+            return Err(VoucherErr::ZeroAtoms);
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        if self.expiry <= now {
+            return Err(VoucherErr::Expired);
+        }
+        if self.signature.is_empty() {
+            return Err(VoucherErr::MissingSignature);
+        }
+        Ok(())
+    }
+
+    /// EIP-712 hash of the voucher under the `ddm micropay` version 1 domain, what the client signs.
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let domain = [
+            keccak(DOMAIN_TYPE.as_bytes()),
+            keccak(b"ddm micropay"),
+            keccak(b"1"),
+        ]
+        .concat();
+        let mut data = keccak(VOUCHER_TYPE.as_bytes()).to_vec();
+        for word in [
+            self.client,
+            self.vendor,
+            self.nonce,
+            self.atoms,
+            self.expiry,
+        ] {
+            data.extend_from_slice(&[0; 24]);
+            data.extend_from_slice(&word.to_be_bytes());
+        }
+        keccak(&[&b"\x19\x01"[..], &keccak(&domain), &keccak(&data)].concat())
+    }
+
+    /// The voucher signed by `key`, as a wallet would.
+    pub fn sign(mut self, key: &SigningKey) -> Self {
+        let (signature, recovery) = key
+            .sign_prehash_recoverable(&self.signing_hash())
+            .expect("32 byte hash");
+        let mut rsv = signature.to_bytes().to_vec();
+        rsv.push(27 + recovery.to_byte());
+        self.signature = format!("0x{}", hex::encode(rsv));
+        self
+    }
+
+    /// Address of the key that signed the voucher, `None` if the signature is malformed, has the high s of a
+    /// malleated one, or recovers no key.
+    pub fn signer(&self) -> Option<Address> {
+        let rsv = hex::decode(self.signature.strip_prefix("0x").unwrap_or(&self.signature)).ok()?;
+        let [rs @ .., v] = <[u8; 65]>::try_from(rsv).ok()?;
+        let signature = Signature::from_slice(&rs).ok()?;
+        if signature.normalize_s().is_some() {
+            return None;
+        }
+        let recovery = RecoveryId::from_byte(v.checked_sub(27)?)?;
+        let key =
+            VerifyingKey::recover_from_prehash(&self.signing_hash(), &signature, recovery).ok()?;
+        Some(address(&key))
+    }
+}
+
+/// Ethereum address of `key`: the last 20 bytes of the keccak of its uncompressed point.
+pub fn address(key: &VerifyingKey) -> Address {
+    let point = key.to_encoded_point(false);
+    keccak(&point.as_bytes()[1..])[12..]
+        .try_into()
+        .expect("20 bytes")
+}
+
+fn keccak(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+impl Voucher<ClientId, VendorId> for GatewayVoucher {
+    /// `key` is the address the client registered, which has to be the one that signed
+    fn is_valid_signature(&self, key: &[u8]) -> bool {
+        self.signer().is_some_and(|signer| signer[..] == *key)
+    }
+    fn nonce(&self) -> u64 {
+        self.nonce
+    }
+    fn voucher_atoms(&self) -> u64 {
+        self.atoms
+    }
+    fn client_identifier(&self) -> ClientId {
+        self.client
+    }
+    fn vendor_identifier(&self) -> VendorId {
+        self.vendor
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum VoucherErr {
+    #[error(
+        "No voucher, connect with application_name=init_voucher:<voucher> or voucher=<voucher>"
+    )]
+    Missing,
+    #[error("Voucher is not base64 {0}")]
+    Encoding(#[from] base64::DecodeError),
+    #[error("Voucher is malformed {0}")]
+    Format(#[from] serde_json::Error),
+    #[error("Voucher is signed for a different vendor")]
+    InvalidVendor,
+    #[error("Voucher has zero atoms. It has no value")]
+    ZeroAtoms,
+    #[error("Voucher has expired")]
+    Expired,
+    #[error("Voucher is not signed")]
+    MissingSignature,
+    #[error("Voucher is signed by a different client than the session")]
+    OtherClient,
+    #[error("Voucher refused {0}")]
+    Engine(#[from] EngineErr),
+}
+
+impl VoucherErr {
+    /// SQLSTATE for the ErrorResponse the client gets.
+    pub fn sqlstate(&self) -> &'static str {
+        // invalid_authorization_specification
+        const INVALID_AUTH: &str = "28000";
+        let VoucherErr::Engine(e) = self else {
+            return INVALID_AUTH;
+        };
+        match e {
+            // system_error, nothing wrong with the voucher
+            EngineErr::IO(_) | EngineErr::VAuth(VAuthErr::IO(_)) => "58000",
+            // internal_error
+            EngineErr::VAuth(VAuthErr::NewVoucherRace | VAuthErr::InternalFailure) => "XX000",
+            // invalid_password, the voucher is the credential
+            EngineErr::VAuth(VAuthErr::Static(StaticVAuthErr::InvalidSig)) => "28P01",
+            _ => INVALID_AUTH,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key() -> SigningKey {
+        SigningKey::from_slice(&[0x11; 32]).unwrap()
+    }
+
+    fn voucher() -> GatewayVoucher {
+        let v = GatewayVoucher {
+            client: 7,
+            vendor: 1,
+            nonce: 0,
+            atoms: 5000,
+            expiry: u64::MAX,
+            signature: "".into(),
+        };
+        v.sign(&key())
+    }
+
+    #[test]
+    fn test_voucher_blob() {
+        let v = voucher();
+        let blob = v.encode();
+        assert!(!blob.contains(['=', '+', '/']));
+        assert_eq!(GatewayVoucher::decode(&blob).unwrap(), v);
+        assert!(v.validate(1).is_ok());
+
+        assert!(matches!(
+            GatewayVoucher::decode("not base64!"),
+            Err(VoucherErr::Encoding(_))
+        ));
+        assert!(matches!(
+            GatewayVoucher::decode(&URL_SAFE_NO_PAD.encode("{}")),
+            Err(VoucherErr::Format(_))
+        ));
+        assert!(matches!(v.validate(2), Err(VoucherErr::InvalidVendor)));
+        let zero = GatewayVoucher {
+            atoms: 0,
+            ..voucher()
+        };
+        assert!(matches!(zero.validate(1), Err(VoucherErr::ZeroAtoms)));
+        let expired = GatewayVoucher {
+            expiry: 1,
+            ..voucher()
+        };
+        assert!(matches!(expired.validate(1), Err(VoucherErr::Expired)));
+        let unsigned = GatewayVoucher {
+            signature: String::new(),
+            ..voucher()
+        };
+        assert!(matches!(
+            unsigned.validate(1),
+            Err(VoucherErr::MissingSignature)
+        ));
+        assert!(!unsigned.is_valid_signature(&address(key().verifying_key())));
+
+        assert_eq!(VoucherErr::Missing.sqlstate(), "28000");
+        let io = VoucherErr::from(EngineErr::IO(std::io::Error::other("db down")));
+        assert_eq!(io.sqlstate(), "58000");
+    }
+
+    #[test]
+    fn test_voucher_signature() {
+        let v = voucher();
+        let registered = address(key().verifying_key());
+        assert_eq!(v.signer(), Some(registered));
+        assert!(v.is_valid_signature(&registered));
+        assert!(!v.is_valid_signature(&[]));
+
+        // every signed field is bound, a tampered voucher recovers someone else
+        for tampered in [
+            GatewayVoucher {
+                client: 8,
+                ..v.clone()
+            },
+            GatewayVoucher {
+                vendor: 2,
+                ..v.clone()
+            },
+            GatewayVoucher {
+                nonce: 1,
+                ..v.clone()
+            },
+            GatewayVoucher {
+                atoms: 50000,
+                ..v.clone()
+            },
+            GatewayVoucher {
+                expiry: 1 << 40,
+                ..v.clone()
+            },
+        ] {
+            assert_ne!(tampered.signing_hash(), v.signing_hash());
+            assert!(!tampered.is_valid_signature(&registered));
+        }
+
+        // signed by a key the client did not register
+        let other = SigningKey::from_slice(&[0x22; 32]).unwrap();
+        let forged = v.clone().sign(&other);
+        assert_eq!(forged.signer(), Some(address(other.verifying_key())));
+        assert!(!forged.is_valid_signature(&registered));
+
+        // the high s twin of the signature, a bad recovery byte, garbage
+        let rsv = hex::decode(&v.signature[2..]).unwrap();
+        use k256::elliptic_curve::PrimeField;
+        let s =
+            k256::Scalar::from_repr(<[u8; 32]>::try_from(&rsv[32..64]).unwrap().into()).unwrap();
+        let twin = [&rsv[..32], &(-s).to_bytes()[..], &[rsv[64] ^ 1]].concat();
+        let malleated = GatewayVoucher {
+            signature: format!("0x{}", hex::encode(twin)),
+            ..v.clone()
+        };
+        assert_eq!(malleated.signer(), None);
+        let bad_v = GatewayVoucher {
+            signature: format!("{}00", &v.signature[..v.signature.len() - 2]),
+            ..v.clone()
+        };
+        assert_eq!(bad_v.signer(), None);
+        let garbage = GatewayVoucher {
+            signature: "sig".into(),
+            ..v
+        };
+        assert!(!garbage.is_valid_signature(&registered));
+    }
+}
@@ -0,0 +1,268 @@
+use crate::pay::{GPayment, PaymentSigner};
+use std::collections::HashMap;
+use std::hash::Hash;
+use thiserror::Error;
+
+/// coproc atoms are i64, so this is the biggest amount that settles on every path
+pub const MAX_PAYMENT_AMOUNT: u64 = i64::MAX as u64;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BatchError {
+    #[error("Batch has no payments")]
+    Empty,
+    #[error("Payment {idx} chain_id differs from the rest of the batch")]
+    ChainIdMismatch { idx: usize },
+    #[error("Payment {idx} amount={amount} outside of (0, {MAX_PAYMENT_AMOUNT}]")]
+    AmountOutOfBounds { idx: usize, amount: u64 },
+    #[error("Payment {idx} reuses nonce={nonce} of the same signer")]
+    DuplicateNonce { idx: usize, nonce: u64 },
+    #[error("Payment {idx} nonce={got} but signer is at expected={expected}")]
+    NonceGap { idx: usize, expected: u64, got: u64 },
+    #[error("Payment {idx} follows nonce u64::MAX of the same signer")]
+    NonceOverflow { idx: usize },
+    #[error("Batch total overflows u64")]
+    TotalOverflow,
+}
+
+/// nonce range seen in the batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonceRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// aggregate over the payments of one vendor/product/signer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Totals {
+    pub count: usize,
+    /// saturates, `validate_batch` rejects batches that would overflow
+    pub sum: u64,
+    /// (min, max) nonce
+    pub nonces: NonceRange,
+}
+
+impl Totals {
+    fn new(amount: u64, nonce: u64) -> Self {
+        Self {
+            count: 1,
+            sum: amount,
+            nonces: NonceRange {
+                start: nonce,
+                end: nonce,
+            },
+        }
+    }
+    fn add(&mut self, amount: u64, nonce: u64) {
+        self.count += 1;
+        self.sum = self.sum.saturating_add(amount);
+        self.nonces.start = self.nonces.start.min(nonce);
+        self.nonces.end = self.nonces.end.max(nonce);
+    }
+}
+
+/// Summary of a batch (or any payment stream) per vendor, product and signer.
+/// Cheap to compute, so dashboards and settlement triggers don't need the circuit or prover.
+#[derive(Debug, Clone)]
+pub struct BatchStats<A, P, Sg> {
+    pub count: usize,
+    pub total: u64,
+    pub vendors: HashMap<A, Totals>,
+    pub products: HashMap<P, Totals>,
+    pub signers: HashMap<Sg, Totals>,
+}
+
+impl<A: Eq + Hash + Clone, P: Eq + Hash + Clone, Sg: Eq + Hash> Default for BatchStats<A, P, Sg> {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            total: 0,
+            vendors: HashMap::new(),
+            products: HashMap::new(),
+            signers: HashMap::new(),
+        }
+    }
+}
+
+impl<A: Eq + Hash + Clone, P: Eq + Hash + Clone, Sg: Eq + Hash> BatchStats<A, P, Sg> {
+    /// aggregate without any validation
+    pub fn from_payments<N, C, AM, S>(payments: &[GPayment<A, N, C, P, AM, S>]) -> Self
+    where
+        N: Copy + Into<u64>,
+        AM: Copy + Into<u64>,
+        GPayment<A, N, C, P, AM, S>: PaymentSigner<Signer = Sg>,
+    {
+        let mut stats = Self::default();
+        for p in payments {
+            stats.add(p);
+        }
+        stats
+    }
+
+    pub fn add<N, C, AM, S>(&mut self, p: &GPayment<A, N, C, P, AM, S>)
+    where
+        N: Copy + Into<u64>,
+        AM: Copy + Into<u64>,
+        GPayment<A, N, C, P, AM, S>: PaymentSigner<Signer = Sg>,
+    {
+        let (amount, nonce): (u64, u64) = (p.amount.into(), p.nonce.into());
+        self.count += 1;
+        self.total = self.total.saturating_add(amount);
+        self.vendors
+            .entry(p.vendor.clone())
+            .and_modify(|t| t.add(amount, nonce))
+            .or_insert(Totals::new(amount, nonce));
+        self.products
+            .entry(p.product_id.clone())
+            .and_modify(|t| t.add(amount, nonce))
+            .or_insert(Totals::new(amount, nonce));
+        self.signers
+            .entry(p.signer())
+            .and_modify(|t| t.add(amount, nonce))
+            .or_insert(Totals::new(amount, nonce));
+    }
+}
+
+/// Checks that the batch can be settled as is:
+/// - all payments are on one chain
+/// - amounts are non zero and fit the coproc atoms
+/// - no signer reuses a nonce
+/// - each signer nonces go strictly +1 in batch order
+pub fn validate_batch<A, N, C, P, AM, S, Sg>(
+    payments: &[GPayment<A, N, C, P, AM, S>],
+) -> Result<BatchStats<A, P, Sg>, BatchError>
+where
+    A: Eq + Hash + Clone,
+    N: Copy + Into<u64>,
+    C: PartialEq,
+    P: Eq + Hash + Clone,
+    AM: Copy + Into<u64>,
+    Sg: Eq + Hash,
+    GPayment<A, N, C, P, AM, S>: PaymentSigner<Signer = Sg>,
+{
+    let chain_id = &payments.first().ok_or(BatchError::Empty)?.chain_id;
+    let mut stats = BatchStats::default();
+    for (idx, p) in payments.iter().enumerate() {
+        if p.chain_id != *chain_id {
+            return Err(BatchError::ChainIdMismatch { idx });
+        }
+        let amount: u64 = p.amount.into();
+        if amount == 0 || amount > MAX_PAYMENT_AMOUNT {
+            return Err(BatchError::AmountOutOfBounds { idx, amount });
+        }
+        let nonce: u64 = p.nonce.into();
+        if let Some(t) = stats.signers.get(&p.signer()) {
+            let r = t.nonces;
+            if (r.start..=r.end).contains(&nonce) {
+                return Err(BatchError::DuplicateNonce { idx, nonce });
+            }
+            let expected = r
+                .end
+                .checked_add(1)
+                .ok_or(BatchError::NonceOverflow { idx })?;
+            if expected != nonce {
+                return Err(BatchError::NonceGap {
+                    idx,
+                    expected,
+                    got: nonce,
+                });
+            }
+        }
+        if stats.total.checked_add(amount).is_none() {
+            return Err(BatchError::TotalOverflow);
+        }
+        stats.add(p);
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pay::TestPayment;
+
+    fn pay(signer: u64, nonce: u64, amount: u64) -> TestPayment {
+        GPayment {
+            vendor: 1,
+            nonce,
+            chain_id: 10,
+            product_id: 0,
+            amount,
+            signature: signer,
+        }
+    }
+
+    #[test]
+    fn test_validate_batch() {
+        let b = vec![pay(7, 3, 100), pay(8, 0, 5), pay(7, 4, 50)];
+        let stats = validate_batch(&b).unwrap();
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.total, 155);
+        assert_eq!(stats.signers[&7].nonces, NonceRange { start: 3, end: 4 });
+        assert_eq!(stats.signers[&8].nonces, NonceRange { start: 0, end: 0 });
+    }
+
+    #[test]
+    fn test_batch_stats() {
+        let mut b = vec![pay(7, 3, 100), pay(8, 9, 5), pay(7, 1, 50)];
+        b[1].vendor = 2;
+        b[2].product_id = 4;
+        let stats = BatchStats::from_payments(&b);
+        assert_eq!((stats.count, stats.total), (3, 155));
+        assert_eq!(
+            stats.vendors[&1],
+            Totals {
+                count: 2,
+                sum: 150,
+                nonces: NonceRange { start: 1, end: 3 }
+            }
+        );
+        assert_eq!(stats.vendors[&2].sum, 5);
+        assert_eq!(stats.products[&0].count, 2);
+        assert_eq!(stats.products[&4].sum, 50);
+        assert_eq!(stats.signers[&8].nonces, NonceRange { start: 9, end: 9 });
+    }
+
+    #[test]
+    fn test_validate_batch_errors() {
+        assert_eq!(
+            validate_batch::<u64, u64, u64, u64, u64, u64, u64>(&[]).err(),
+            Some(BatchError::Empty)
+        );
+
+        let mut other_chain = pay(7, 1, 1);
+        other_chain.chain_id = 11;
+        assert_eq!(
+            validate_batch(&[pay(7, 0, 1), other_chain]).err(),
+            Some(BatchError::ChainIdMismatch { idx: 1 })
+        );
+        assert_eq!(
+            validate_batch(&[pay(7, 0, 0)]).err(),
+            Some(BatchError::AmountOutOfBounds { idx: 0, amount: 0 })
+        );
+        assert_eq!(
+            validate_batch(&[pay(7, 0, 1), pay(7, 1, 1), pay(7, 0, 1)]).err(),
+            Some(BatchError::DuplicateNonce { idx: 2, nonce: 0 })
+        );
+        assert_eq!(
+            validate_batch(&[pay(7, 0, 1), pay(7, 2, 1)]).err(),
+            Some(BatchError::NonceGap {
+                idx: 1,
+                expected: 1,
+                got: 2
+            })
+        );
+        assert_eq!(
+            validate_batch(&[pay(7, u64::MAX, 1), pay(7, 0, 1)]).err(),
+            Some(BatchError::NonceOverflow { idx: 1 })
+        );
+        assert_eq!(
+            validate_batch(&[
+                pay(7, 0, MAX_PAYMENT_AMOUNT),
+                pay(8, 0, MAX_PAYMENT_AMOUNT),
+                pay(9, 0, 2)
+            ])
+            .err(),
+            Some(BatchError::TotalOverflow)
+        );
+    }
+}
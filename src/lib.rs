@@ -1,5 +1,7 @@
+pub mod batch;
 pub mod hash;
 pub mod pay;
+pub mod router;
 use bellman::{
     Circuit, ConstraintSystem, LinearCombination, SynthesisError,
     gadgets::{boolean::Boolean, num::AllocatedNum},
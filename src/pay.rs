@@ -26,3 +26,19 @@ pub struct GPayment<A, N, C, P, AM, S> {
 /// OR that the payments sources are somehow abstracted and everything is paid out from one 'pot'
 
 pub type TestNoSigPayment = GPayment<u64, u64, u64, u64, u64, ()>;
+
+/// Recovering who signed the payment is up to the signature scheme
+pub trait PaymentSigner {
+    type Signer;
+    fn signer(&self) -> Self::Signer;
+}
+
+/// payment where the 'signature' is just the signer id, for tests
+pub type TestPayment = GPayment<u64, u64, u64, u64, u64, u64>;
+
+impl PaymentSigner for TestPayment {
+    type Signer = u64;
+    fn signer(&self) -> u64 {
+        self.signature
+    }
+}
@@ -0,0 +1,197 @@
+use crate::pay::GPayment;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// when a batch is closed and handed to settlement
+#[derive(Debug, Clone, Copy)]
+pub struct BatchConfig {
+    /// close the batch once it holds this many payments, also the most a batch holds
+    pub max_payments: usize,
+    /// close the batch once its first payment is this old, so slow chains still settle
+    pub max_age: Duration,
+}
+
+/// Accumulates the payments of a single chain
+pub struct Batcher<A, N, C, P, AM, S> {
+    payments: Vec<GPayment<A, N, C, P, AM, S>>,
+    /// None while empty
+    opened_at: Option<Instant>,
+}
+
+impl<A, N, C, P, AM, S> Default for Batcher<A, N, C, P, AM, S> {
+    fn default() -> Self {
+        Self {
+            payments: vec![],
+            opened_at: None,
+        }
+    }
+}
+
+impl<A, N, C, P, AM, S> Batcher<A, N, C, P, AM, S> {
+    pub fn push(&mut self, p: GPayment<A, N, C, P, AM, S>, now: Instant) {
+        self.opened_at.get_or_insert(now);
+        self.payments.push(p);
+    }
+    pub fn len(&self) -> usize {
+        self.payments.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.payments.is_empty()
+    }
+    pub fn is_ready(&self, cfg: &BatchConfig, now: Instant) -> bool {
+        match self.opened_at {
+            None => false,
+            Some(t) => self.len() >= cfg.max_payments || now.duration_since(t) >= cfg.max_age,
+        }
+    }
+    /// empty the batcher
+    pub fn take(&mut self) -> Vec<GPayment<A, N, C, P, AM, S>> {
+        self.opened_at = None;
+        std::mem::take(&mut self.payments)
+    }
+    /// take the oldest `max` payments (at least 1), the rest stay and keep the batcher age
+    pub fn take_up_to(&mut self, max: usize) -> Vec<GPayment<A, N, C, P, AM, S>> {
+        let max = max.max(1);
+        if self.payments.len() <= max {
+            return self.take();
+        }
+        let rest = self.payments.split_off(max);
+        std::mem::replace(&mut self.payments, rest)
+    }
+}
+
+pub struct ReadyBatch<A, N, C, P, AM, S> {
+    pub chain_id: C,
+    pub payments: Vec<GPayment<A, N, C, P, AM, S>>,
+}
+
+/// Splits a payment stream from clients paying on several chains into per chain batches,
+/// so one gateway process can settle on all of them.
+pub struct ChainRouter<A, N, C, P, AM, S> {
+    cfg: BatchConfig,
+    /// ordered so batches come out in chain id order, run to run
+    batchers: BTreeMap<C, Batcher<A, N, C, P, AM, S>>,
+}
+
+impl<A, N, C: Ord + Clone, P, AM, S> ChainRouter<A, N, C, P, AM, S> {
+    pub fn new(cfg: BatchConfig) -> Self {
+        Self {
+            cfg,
+            batchers: BTreeMap::new(),
+        }
+    }
+
+    pub fn route(&mut self, p: GPayment<A, N, C, P, AM, S>) {
+        self.route_at(p, Instant::now());
+    }
+
+    pub fn route_at(&mut self, p: GPayment<A, N, C, P, AM, S>, now: Instant) {
+        self.batchers
+            .entry(p.chain_id.clone())
+            .or_default()
+            .push(p, now);
+    }
+
+    /// payments waiting per chain
+    pub fn pending(&self, chain_id: &C) -> usize {
+        self.batchers.get(chain_id).map(|b| b.len()).unwrap_or(0)
+    }
+
+    /// take every batch that is full or old enough, across all chains in chain id order,
+    /// a chain with more than `max_payments` waiting yields several batches
+    pub fn poll_ready_batches(&mut self) -> Vec<ReadyBatch<A, N, C, P, AM, S>> {
+        self.poll_ready_batches_at(Instant::now())
+    }
+
+    pub fn poll_ready_batches_at(&mut self, now: Instant) -> Vec<ReadyBatch<A, N, C, P, AM, S>> {
+        let mut ready = vec![];
+        for (chain_id, b) in self.batchers.iter_mut() {
+            while b.is_ready(&self.cfg, now) {
+                ready.push(ReadyBatch {
+                    chain_id: chain_id.clone(),
+                    payments: b.take_up_to(self.cfg.max_payments),
+                });
+            }
+        }
+        ready
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pay::TestPayment;
+
+    fn pay(chain_id: u64, nonce: u64) -> TestPayment {
+        GPayment {
+            vendor: 1,
+            nonce,
+            chain_id,
+            product_id: 0,
+            amount: 10,
+            signature: 7,
+        }
+    }
+
+    #[test]
+    fn test_router_splits_by_chain() {
+        let mut r = ChainRouter::new(BatchConfig {
+            max_payments: 2,
+            max_age: Duration::from_secs(60),
+        });
+        let t0 = Instant::now();
+        r.route_at(pay(1, 0), t0);
+        r.route_at(pay(2, 0), t0);
+        assert!(r.poll_ready_batches_at(t0).is_empty());
+
+        r.route_at(pay(1, 1), t0);
+        let ready = r.poll_ready_batches_at(t0);
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].chain_id, 1);
+        assert_eq!(ready[0].payments.len(), 2);
+        assert_eq!(r.pending(&1), 0);
+        assert_eq!(r.pending(&2), 1);
+
+        // chain 2 is slow but still settles after max_age
+        let ready = r.poll_ready_batches_at(t0 + Duration::from_secs(60));
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].chain_id, 2);
+        assert_eq!(r.pending(&2), 0);
+    }
+
+    #[test]
+    fn test_router_order_and_cut() {
+        let mut r = ChainRouter::new(BatchConfig {
+            max_payments: 2,
+            max_age: Duration::from_secs(60),
+        });
+        let t0 = Instant::now();
+        for chain_id in [9, 3, 5] {
+            for nonce in 0..5 {
+                r.route_at(pay(chain_id, nonce), t0);
+            }
+        }
+        let ready = r.poll_ready_batches_at(t0);
+        let batches: Vec<_> = ready
+            .iter()
+            .map(|b| {
+                (
+                    b.chain_id,
+                    b.payments.iter().map(|p| p.nonce).collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        let per_chain = |c| [(c, vec![0, 1]), (c, vec![2, 3])];
+        assert_eq!(batches, [per_chain(3), per_chain(5), per_chain(9)].concat());
+        // the leftover of each chain is as old as its batch
+        assert_eq!(r.pending(&3), 1);
+        let ready = r.poll_ready_batches_at(t0 + Duration::from_secs(60));
+        let chains: Vec<_> = ready.iter().map(|b| b.chain_id).collect();
+        assert_eq!(chains, [3, 5, 9]);
+        assert!(
+            ready
+                .iter()
+                .all(|b| b.payments.len() == 1 && b.payments[0].nonce == 4)
+        );
+    }
+}